@@ -0,0 +1,499 @@
+//! # Arbitime Macros
+//!
+//! Attribute macros for [arbitime](https://docs.rs/arbitime).
+//!
+//! These are re-exported by `arbitime` behind its default `macros` feature,
+//! so you usually want `use arbitime::timed;` rather than depending on this
+//! crate directly.
+
+use proc_macro::{Delimiter, Group, Literal, Spacing, Span, TokenStream, TokenTree};
+
+/// Times every call of the annotated function and logs the duration.
+///
+/// The report uses the same format as `log_time!`, labelled with the function
+/// name. Every exit path is measured, including early returns, `?` and panics.
+/// On an `async fn` the time is measured from the first poll until the
+/// returned future completes.
+///
+/// On an `impl` block, every method in it is timed, labelled `Type::method`.
+/// A method can override the options of the block with its own
+/// `#[timed(...)]`, or opt out with `#[timed(skip)]`. `const fn`s are never
+/// timed.
+///
+/// # Attributes
+///
+/// - `name = "..."` - Use a custom label instead of the function name. On an
+///   `impl` block, use it instead of the type name, as in `"name::method"`.
+/// - `threshold = "10ms"` - Only log calls that take at least this long.
+///   Accepts the units `ns`, `us`/`µs`, `ms`, `s` and `min`.
+/// - `skip` - Leave the function as it is
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::timed;
+///
+/// #[timed]
+/// fn parse(input: &str) -> usize {
+///     input.len()
+/// }
+///
+/// #[timed(name = "database query", threshold = "10ms")]
+/// fn query(id: u32) -> Result<u32, String> {
+///     if id == 0 {
+///         return Err("invalid id".to_string());
+///     }
+///     Ok(id * 2)
+/// }
+///
+/// #[timed]
+/// async fn fetch() -> u32 {
+///     42
+/// }
+///
+/// assert_eq!(parse("hello"), 5); // Prints: "parse - Execution time: ..."
+/// assert_eq!(query(21), Ok(42)); // Only prints if it took 10ms or more
+///
+/// struct Cache(Vec<u32>);
+///
+/// #[timed(threshold = "1ms")]
+/// impl Cache {
+///     fn get(&self, index: usize) -> Option<u32> {
+///         self.0.get(index).copied()
+///     }
+///
+///     #[timed(name = "cache insert")]
+///     fn insert(&mut self, value: u32) {
+///         self.0.push(value);
+///     }
+///
+///     #[timed(skip)]
+///     fn len(&self) -> usize {
+///         self.0.len()
+///     }
+/// }
+///
+/// let mut cache = Cache(Vec::new());
+/// cache.insert(7); // Prints: "cache insert - Execution time: ..." if it took 1ms or more
+/// assert_eq!(cache.get(0), Some(7)); // Prints: "Cache::get - Execution time: ..." if it took 1ms or more
+/// assert_eq!(cache.len(), 1);
+/// ```
+#[proc_macro_attribute]
+pub fn timed(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if is_skip(&attr) {
+        return item;
+    }
+    let args = match TimedArgs::parse(attr, &["name", "threshold"]) {
+        Ok(args) => args,
+        Err(error) => return error,
+    };
+    let item: Vec<TokenTree> = item.into_iter().collect();
+    let result = match first_keyword(&item, &["impl", "fn"]) {
+        Some("impl") => instrument_impl(item, &args),
+        _ => instrument_fn(item.into_iter().collect(), &args, "timed"),
+    };
+    match result {
+        Ok(tokens) => tokens,
+        Err(error) => error,
+    }
+}
+
+/// Turns the annotated function into a test that logs its duration, and
+/// fails if it takes longer than its budget.
+///
+/// Adds `#[test]` to the function and times it like `#[timed]`, so slow tests
+/// show up in the output of `cargo test -- --nocapture` and in the registry
+/// without an external test runner. A test that finishes, or returns an
+/// error, after more than its `budget` panics with a message such as
+/// `"parses_config - Execution time: 1.2 s, exceeding its budget of 500 ms"`.
+/// A test that already failed is not reported twice. Like `assert_time!`,
+/// the budget is enforced even while output is turned off with
+/// `set_enabled(false)`; with the `disable` feature, nothing is measured and
+/// every test is within its budget.
+///
+/// Async tests are not supported: combine `#[timed]` with the test attribute
+/// of the async runtime instead.
+///
+/// # Attributes
+///
+/// - `budget = "500ms"` - Fail the test if it takes longer than this
+/// - `name = "..."` - Use a custom label instead of the function name
+/// - `threshold = "10ms"` - Only log the test if it takes at least this long
+///
+/// Durations accept the units `ns`, `us`/`µs`, `ms`, `s` and `min`.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::timed_test;
+///
+/// #[timed_test(budget = "1s")]
+/// fn parses_numbers() {
+///     assert_eq!("42".parse::<u32>(), Ok(42));
+/// }
+///
+/// #[timed_test(threshold = "100ms")]
+/// fn sums_numbers() -> Result<(), String> {
+///     assert_eq!((1..=100).sum::<u32>(), 5050);
+///     Ok(())
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn timed_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = match TimedArgs::parse(attr, &["name", "threshold", "budget"]) {
+        Ok(args) => args,
+        Err(error) => return error,
+    };
+    let item: Vec<TokenTree> = item.into_iter().collect();
+    let is_async = item
+        .iter()
+        .take_while(|token| !matches!(token, TokenTree::Ident(ident) if ident.to_string() == "fn"))
+        .any(|token| matches!(token, TokenTree::Ident(ident) if ident.to_string() == "async"));
+    if is_async {
+        return error(
+            "`#[timed_test]` does not support async functions, use `#[timed]` with the runtime's test attribute",
+            Span::call_site(),
+        );
+    }
+    let mut tokens: TokenStream = "#[test]".parse().expect("`#[test]` is valid Rust");
+    match instrument_fn(item.into_iter().collect(), &args, "timed_test") {
+        Ok(function) => tokens.extend(function),
+        Err(error) => return error,
+    }
+    tokens
+}
+
+/// Options accepted by `#[timed(...)]` and `#[timed_test(...)]`.
+#[derive(Default, Clone)]
+struct TimedArgs {
+    /// A string literal token to use as the label, verbatim.
+    name: Option<Literal>,
+    /// The minimum duration to log, in nanoseconds.
+    threshold: Option<u64>,
+    /// The duration over which a test fails, in nanoseconds.
+    budget: Option<u64>,
+}
+
+impl TimedArgs {
+    /// Parses the options, accepting only the given `keys`.
+    fn parse(attr: TokenStream, keys: &[&str]) -> Result<Self, TokenStream> {
+        let mut args = TimedArgs::default();
+        let mut tokens = attr.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            let TokenTree::Ident(key) = token else {
+                return Err(error("expected an attribute name", token.span()));
+            };
+            match tokens.next() {
+                Some(TokenTree::Punct(p)) if p.as_char() == '=' => {}
+                _ => return Err(error("expected `=` after attribute name", key.span())),
+            }
+            let value = match tokens.next() {
+                Some(TokenTree::Literal(lit)) => lit,
+                Some(other) => return Err(error("expected a string literal", other.span())),
+                None => return Err(error("expected a string literal", key.span())),
+            };
+            let Some(text) = string_value(&value) else {
+                return Err(error("expected a string literal", value.span()));
+            };
+            let duration = || {
+                parse_duration(&text)
+                    .ok_or_else(|| error("invalid duration, expected something like \"10ms\"", value.span()))
+            };
+            match key.to_string().as_str() {
+                other if !keys.contains(&other) => {
+                    return Err(error(
+                        &format!("unknown attribute `{}`, expected {}", other, expected(keys)),
+                        key.span(),
+                    ));
+                }
+                "name" => args.name = Some(value),
+                "threshold" => args.threshold = Some(duration()?),
+                _ => args.budget = Some(duration()?),
+            }
+            match tokens.next() {
+                None => break,
+                Some(TokenTree::Punct(p)) if p.as_char() == ',' => {}
+                Some(other) => return Err(error("expected `,`", other.span())),
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Lists attribute names for an error message, e.g. `` `a`, `b` or `c` ``.
+fn expected(keys: &[&str]) -> String {
+    let quoted: Vec<String> = keys.iter().map(|key| format!("`{}`", key)).collect();
+    match quoted.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/// Returns whether the options are just `skip`.
+fn is_skip(attr: &TokenStream) -> bool {
+    let tokens: Vec<TokenTree> = attr.clone().into_iter().collect();
+    matches!(tokens.as_slice(), [TokenTree::Ident(ident)] if ident.to_string() == "skip")
+}
+
+/// Returns the first of `keywords` among the top-level identifiers of an item,
+/// e.g. `fn` for a function or `impl` for an `impl` block.
+fn first_keyword<'a>(tokens: &[TokenTree], keywords: &[&'a str]) -> Option<&'a str> {
+    tokens.iter().find_map(|token| match token {
+        TokenTree::Ident(ident) => {
+            let ident = ident.to_string();
+            keywords.iter().copied().find(|keyword| *keyword == ident)
+        }
+        _ => None,
+    })
+}
+
+/// Times every method of an `impl` block, labelled `Type::method`.
+fn instrument_impl(mut tokens: Vec<TokenTree>, args: &TimedArgs) -> Result<TokenStream, TokenStream> {
+    let body = match tokens.pop() {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => group,
+        _ => return Err(error("`#[timed]` requires an `impl` block with a body", Span::call_site())),
+    };
+    let prefix = match &args.name {
+        Some(name) => string_value(name).ok_or_else(|| error("expected a string literal", name.span()))?,
+        None => self_type(&tokens).ok_or_else(|| error("`#[timed]` could not find the type of the `impl` block", Span::call_site()))?,
+    };
+
+    let mut items = TokenStream::new();
+    for item in impl_items(body.stream()) {
+        if first_keyword(&item, &["fn"]).is_none() || first_keyword(&item, &["const", "fn"]) == Some("const") {
+            items.extend(item);
+            continue;
+        }
+        let (item, method) = take_timed_attr(item)?;
+        let mut method_args = match method {
+            MethodAttr::None => TimedArgs { name: None, ..args.clone() },
+            MethodAttr::Skip => {
+                items.extend(item);
+                continue;
+            }
+            MethodAttr::Options(options) => TimedArgs {
+                threshold: options.threshold.or(args.threshold),
+                ..options
+            },
+        };
+        if method_args.name.is_none() {
+            let method = first_keyword_after(&item, "fn").unwrap_or_default();
+            let label = format!("{}::{}", prefix, method.trim_start_matches("r#"));
+            method_args.name = Some(Literal::string(&label));
+        }
+        items.extend(instrument_fn(item.into_iter().collect(), &method_args, "timed")?);
+    }
+
+    let mut group = Group::new(Delimiter::Brace, items);
+    group.set_span(body.span());
+    tokens.push(TokenTree::Group(group));
+    Ok(tokens.into_iter().collect())
+}
+
+/// Returns the identifier following the top-level `keyword` of an item.
+fn first_keyword_after(tokens: &[TokenTree], keyword: &str) -> Option<String> {
+    tokens.windows(2).find_map(|pair| match pair {
+        [TokenTree::Ident(kw), TokenTree::Ident(name)] if kw.to_string() == keyword => Some(name.to_string()),
+        _ => None,
+    })
+}
+
+/// Returns the name of the type implemented by an `impl` block header, e.g.
+/// `Cache` for `impl<T> Display for Cache<T> where T: Display`.
+fn self_type(header: &[TokenTree]) -> Option<String> {
+    let start = header
+        .iter()
+        .position(|token| matches!(token, TokenTree::Ident(ident) if ident.to_string() == "impl"))?;
+    let mut depth = 0usize;
+    let mut arrow = false;
+    let mut name = None;
+    for token in &header[start + 1..] {
+        match token {
+            TokenTree::Punct(punct) => {
+                match punct.as_char() {
+                    '<' => depth += 1,
+                    '>' if !arrow => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                arrow = punct.as_char() == '-' && punct.spacing() == Spacing::Joint;
+            }
+            TokenTree::Ident(ident) if depth == 0 => {
+                arrow = false;
+                match ident.to_string().as_str() {
+                    "where" => break,
+                    "for" => name = None,
+                    "dyn" | "mut" | "const" | "unsafe" => {}
+                    ident => name = Some(ident.to_string()),
+                }
+            }
+            _ => arrow = false,
+        }
+    }
+    name
+}
+
+/// Splits the body of an `impl` block into its items.
+fn impl_items(body: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut items = Vec::new();
+    let mut item: Vec<TokenTree> = Vec::new();
+    for token in body {
+        let ends_item = match &token {
+            TokenTree::Punct(punct) => punct.as_char() == ';',
+            // The body of a method or of a macro invocation such as `m! { ... }`
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                first_keyword(&item, &["fn"]).is_some()
+                    || matches!(item.last(), Some(TokenTree::Punct(punct)) if punct.as_char() == '!')
+            }
+            _ => false,
+        };
+        item.push(token);
+        if ends_item {
+            items.push(std::mem::take(&mut item));
+        }
+    }
+    if !item.is_empty() {
+        items.push(item);
+    }
+    items
+}
+
+/// The `#[timed]` attribute of a method in an `impl` block.
+enum MethodAttr {
+    /// No attribute: use the options of the block
+    None,
+    /// `#[timed(skip)]`
+    Skip,
+    /// `#[timed(...)]`, overriding the options of the block
+    Options(TimedArgs),
+}
+
+/// Removes a `#[timed]` or `#[timed(...)]` attribute from a method in an
+/// `impl` block, returning what it asked for.
+fn take_timed_attr(mut item: Vec<TokenTree>) -> Result<(Vec<TokenTree>, MethodAttr), TokenStream> {
+    let mut index = 0;
+    while let [TokenTree::Punct(pound), TokenTree::Group(attr), ..] = &item[index..] {
+        if pound.as_char() != '#' || attr.delimiter() != Delimiter::Bracket {
+            break;
+        }
+        let tokens: Vec<TokenTree> = attr.stream().into_iter().collect();
+        let path_len = tokens
+            .iter()
+            .take_while(|token| match token {
+                TokenTree::Ident(_) => true,
+                TokenTree::Punct(punct) => punct.as_char() == ':',
+                _ => false,
+            })
+            .count();
+        let is_timed = matches!(tokens[..path_len].last(), Some(TokenTree::Ident(ident)) if ident.to_string() == "timed");
+        let options = match &tokens[path_len..] {
+            [] => Some(TokenStream::new()),
+            [TokenTree::Group(group)] if group.delimiter() == Delimiter::Parenthesis => Some(group.stream()),
+            _ => None,
+        };
+        if let (true, Some(options)) = (is_timed, options) {
+            item.drain(index..index + 2);
+            if is_skip(&options) {
+                return Ok((item, MethodAttr::Skip));
+            }
+            return Ok((item, MethodAttr::Options(TimedArgs::parse(options, &["name", "threshold"])?)));
+        }
+        index += 2;
+    }
+    Ok((item, MethodAttr::None))
+}
+
+/// Wraps the body of a function so that a timer guard lives for the whole call.
+fn instrument_fn(item: TokenStream, args: &TimedArgs, attribute: &str) -> Result<TokenStream, TokenStream> {
+    let mut tokens: Vec<TokenTree> = item.into_iter().collect();
+
+    let fn_name = tokens
+        .windows(2)
+        .find_map(|pair| match pair {
+            [TokenTree::Ident(kw), TokenTree::Ident(name)] if kw.to_string() == "fn" => {
+                Some(name.to_string())
+            }
+            _ => None,
+        })
+        .ok_or_else(|| error(&format!("`#[{}]` can only be applied to functions", attribute), Span::call_site()))?;
+
+    let body = match tokens.pop() {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => group,
+        Some(other) => {
+            return Err(error(&format!("`#[{}]` requires a function with a body", attribute), other.span()));
+        }
+        None => {
+            return Err(error(&format!("`#[{}]` can only be applied to functions", attribute), Span::call_site()));
+        }
+    };
+
+    let label = args
+        .name
+        .clone()
+        .unwrap_or_else(|| Literal::string(fn_name.trim_start_matches("r#")));
+    let mut options = String::new();
+    if let Some(nanos) = args.threshold {
+        options.push_str(&format!(".threshold(::core::time::Duration::from_nanos({}))", nanos));
+    }
+    if let Some(nanos) = args.budget {
+        options.push_str(&format!(".__budget(::core::time::Duration::from_nanos({}))", nanos));
+    }
+    let mut new_body: TokenStream = format!(
+        "let __arbitime_timer = ::arbitime::ScopeTimer::new({}){}\
+            .__callsite({{\
+                static SITE: ::arbitime::callsite::CallSite = ::arbitime::callsite::CallSite::new(\
+                    ::core::file!(), ::core::line!(), ::core::module_path!());\
+                &SITE\
+            }});",
+        label, options
+    )
+    .parse()
+    .expect("generated timer statement is valid Rust");
+    let body_span = body.span();
+    new_body.extend([TokenTree::Group(body)]);
+
+    let mut group = Group::new(Delimiter::Brace, new_body);
+    group.set_span(body_span);
+    tokens.push(TokenTree::Group(group));
+    Ok(tokens.into_iter().collect())
+}
+
+/// Returns the contents of a plain (non-raw) string literal.
+fn string_value(lit: &Literal) -> Option<String> {
+    let repr = lit.to_string();
+    let inner = repr.strip_prefix('"')?.strip_suffix('"')?;
+    Some(inner.replace("\\\"", "\"").replace("\\\\", "\\"))
+}
+
+/// Parses a duration such as `"10ms"` or `"1.5 s"` into nanoseconds.
+fn parse_duration(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let value: f64 = number.parse().ok()?;
+    let nanos_per_unit = match unit.trim() {
+        "ns" => 1.0,
+        "us" | "µs" => 1e3,
+        "ms" => 1e6,
+        "s" => 1e9,
+        "min" => 60e9,
+        _ => return None,
+    };
+    Some((value * nanos_per_unit).round() as u64)
+}
+
+/// Builds a `compile_error!` invocation pointing at `span`.
+fn error(message: &str, span: Span) -> TokenStream {
+    let tokens: TokenStream = format!("::core::compile_error!({});", Literal::string(message))
+        .parse()
+        .expect("generated compile_error! is valid Rust");
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            token.set_span(span);
+            token
+        })
+        .collect()
+}
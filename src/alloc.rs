@@ -0,0 +1,194 @@
+//! Allocation tracking.
+//!
+//! Latency spikes are often caused by allocation churn rather than by the
+//! work itself. [`CountingAlloc`] wraps a global allocator and counts the
+//! allocations made by each thread, and [`time_alloc!`](crate::time_alloc)
+//! reports them next to the elapsed time of a block.
+//!
+//! Counting only happens once `CountingAlloc` is installed as the
+//! `#[global_allocator]`; otherwise all counts stay zero.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::alloc::CountingAlloc;
+//! use arbitime::time_alloc;
+//!
+//! #[global_allocator]
+//! static ALLOC: CountingAlloc = CountingAlloc::new();
+//!
+//! let (timings, words) = time_alloc!({
+//!     "four words to split".split(' ').map(String::from).collect::<Vec<_>>()
+//! });
+//! println!("{}", timings); // "11.2 µs, 7 allocations, 304 bytes"
+//! # #[cfg(not(feature = "disable"))]
+//! assert!(timings.allocations >= 5);
+//! assert_eq!(words.len(), 4);
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fmt;
+use std::ops::Sub;
+use std::time::Duration;
+
+use crate::HumanDuration;
+
+thread_local! {
+    /// Allocations made by the current thread so far.
+    static COUNTERS: Cell<AllocStats> = const { Cell::new(AllocStats { allocations: 0, bytes: 0 }) };
+}
+
+/// A global allocator that counts the allocations of each thread.
+///
+/// Wraps [`System`] by default, or any other allocator with [`CountingAlloc::wrap`].
+/// Counting costs a thread-local increment per allocation.
+#[derive(Debug, Default)]
+pub struct CountingAlloc<A = System> {
+    inner: A,
+}
+
+impl CountingAlloc<System> {
+    /// Creates a counting wrapper around the system allocator.
+    pub const fn new() -> Self {
+        CountingAlloc { inner: System }
+    }
+}
+
+impl<A> CountingAlloc<A> {
+    /// Creates a counting wrapper around another allocator.
+    pub const fn wrap(inner: A) -> Self {
+        CountingAlloc { inner }
+    }
+}
+
+/// Counts an allocation of `size` bytes on the current thread.
+fn count(size: usize) {
+    // Ignore allocations made while the thread-local is being destroyed
+    let _ = COUNTERS.try_with(|counters| {
+        let stats = counters.get();
+        counters.set(AllocStats {
+            allocations: stats.allocations + 1,
+            bytes: stats.bytes + size as u64,
+        });
+    });
+}
+
+// SAFETY: all allocation requests are forwarded to the wrapped allocator
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        // SAFETY: the caller upholds the contract of `GlobalAlloc::alloc`
+        unsafe { self.inner.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        // SAFETY: the caller upholds the contract of `GlobalAlloc::alloc_zeroed`
+        unsafe { self.inner.alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: the caller upholds the contract of `GlobalAlloc::dealloc`
+        unsafe { self.inner.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        // SAFETY: the caller upholds the contract of `GlobalAlloc::realloc`
+        unsafe { self.inner.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// Numbers of allocations and allocated bytes.
+///
+/// Reallocations count as an allocation of the new size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AllocStats {
+    /// Number of allocations
+    pub allocations: u64,
+    /// Number of bytes allocated
+    pub bytes: u64,
+}
+
+impl AllocStats {
+    /// Returns the allocations made by the current thread so far.
+    ///
+    /// Always zero unless [`CountingAlloc`] is the global allocator.
+    pub fn now() -> Self {
+        if cfg!(feature = "disable") {
+            return AllocStats::default();
+        }
+        COUNTERS.try_with(Cell::get).unwrap_or_default()
+    }
+}
+
+impl Sub for AllocStats {
+    type Output = AllocStats;
+
+    /// Returns the allocations made between two readings.
+    fn sub(self, earlier: AllocStats) -> AllocStats {
+        AllocStats {
+            allocations: self.allocations.saturating_sub(earlier.allocations),
+            bytes: self.bytes.saturating_sub(earlier.bytes),
+        }
+    }
+}
+
+/// Elapsed time and allocations of a block, returned by [`time_alloc!`](crate::time_alloc).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AllocTimings {
+    /// Elapsed wall-clock time
+    pub duration: Duration,
+    /// Number of allocations made by the current thread
+    pub allocations: u64,
+    /// Number of bytes allocated by the current thread
+    pub bytes: u64,
+}
+
+impl AllocTimings {
+    /// Combines a duration with the allocations made in the same period.
+    pub fn new(duration: Duration, stats: AllocStats) -> Self {
+        AllocTimings {
+            duration,
+            allocations: stats.allocations,
+            bytes: stats.bytes,
+        }
+    }
+}
+
+impl fmt::Display for AllocTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, {} allocations, {} bytes",
+            HumanDuration(self.duration),
+            self.allocations,
+            self.bytes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_allocations() {
+        let alloc = CountingAlloc::new();
+        let start = AllocStats::now();
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        // SAFETY: the layout has a non-zero size and the pointer is freed with it
+        unsafe {
+            let ptr = alloc.alloc(layout);
+            let ptr = alloc.realloc(ptr, layout, 128);
+            alloc.dealloc(ptr, Layout::from_size_align(128, 8).unwrap());
+        }
+        let used = AllocStats::now() - start;
+        if cfg!(feature = "disable") {
+            assert_eq!(used, AllocStats::default());
+        } else {
+            assert_eq!(used, AllocStats { allocations: 2, bytes: 192 });
+        }
+    }
+}
@@ -0,0 +1,501 @@
+//! Timing baselines stored between runs, to catch performance regressions.
+//!
+//! A [`Baseline`] holds the call count and mean duration of each label of a
+//! [`Report`]. It is saved as a JSON object keyed by label:
+//!
+//! ```text
+//! {
+//!   "parse": {"count":3,"mean_ns":400,"min_ns":300,"max_ns":500},
+//!   "render": {"count":60,"mean_ns":16200000,"min_ns":15900000,"max_ns":18000000}
+//! }
+//! ```
+//!
+//! On later runs, [`Baseline::compare`] lists the labels whose mean got
+//! slower than the baseline by more than a given percentage. [`check`] does
+//! all of this in one call, which turns a test or benchmark binary into a
+//! lightweight continuous performance check: commit the baseline file, and
+//! fail the build when a label regresses.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use arbitime::{baseline, log_time};
+//!
+//! for _ in 0..100 {
+//!     log_time!("parse" => (1..=1000).sum::<u32>());
+//! }
+//!
+//! // Writes the baseline on the first run, and compares against it afterwards
+//! let regressions = baseline::check("timings.json", 10.0).unwrap();
+//! for regression in &regressions {
+//!     eprintln!("{}", regression); // "parse - mean 5 µs, 25.0% slower than the baseline of 4 µs"
+//! }
+//! assert!(regressions.is_empty());
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::HumanDuration;
+use crate::record::escape_json;
+use crate::registry::{self, Report};
+
+/// The stored statistics of a single label.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BaselineEntry {
+    /// Number of measurements
+    pub count: u64,
+    /// Mean duration of the measurements
+    pub mean: Duration,
+    /// Shortest duration
+    pub min: Duration,
+    /// Longest duration
+    pub max: Duration,
+}
+
+/// Aggregated timings by label, to compare later runs against.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Baseline {
+    entries: BTreeMap<String, BaselineEntry>,
+}
+
+impl Baseline {
+    /// Creates an empty baseline.
+    pub fn new() -> Self {
+        Baseline::default()
+    }
+
+    /// Creates a baseline of every label in `report`.
+    pub fn from_report(report: &Report) -> Self {
+        let entries = report
+            .entries()
+            .iter()
+            .map(|(label, stats)| {
+                let entry = BaselineEntry {
+                    count: stats.count,
+                    mean: stats.mean(),
+                    min: stats.min,
+                    max: stats.max,
+                };
+                (label.clone(), entry)
+            })
+            .collect();
+        Baseline { entries }
+    }
+
+    /// Returns the entry of a single label.
+    pub fn get(&self, label: &str) -> Option<&BaselineEntry> {
+        self.entries.get(label)
+    }
+
+    /// Sets the entry of a label, replacing any previous one.
+    pub fn insert(&mut self, label: impl Into<String>, entry: BaselineEntry) {
+        self.entries.insert(label.into(), entry);
+    }
+
+    /// Returns all labels and their entries, ordered by label.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &BaselineEntry)> {
+        self.entries.iter().map(|(label, entry)| (label.as_str(), entry))
+    }
+
+    /// Returns the number of labels.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the baseline has no labels.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the labels of `report` whose mean is more than
+    /// `max_regression` percent slower than their baseline, ordered by how
+    /// much they regressed (most first).
+    ///
+    /// Labels that are missing from either side are ignored.
+    pub fn compare(&self, report: &Report, max_regression: f64) -> Vec<Regression> {
+        let mut regressions: Vec<Regression> = report
+            .entries()
+            .iter()
+            .filter_map(|(label, stats)| {
+                let baseline = self.entries.get(label)?.mean;
+                let regression = Regression {
+                    label: label.clone(),
+                    baseline,
+                    current: stats.mean(),
+                };
+                (regression.change() > max_regression).then_some(regression)
+            })
+            .collect();
+        regressions.sort_by(|a, b| b.change().total_cmp(&a.change()));
+        regressions
+    }
+
+    /// Formats the baseline as a JSON object keyed by label, with one label
+    /// per line.
+    pub fn to_json(&self) -> String {
+        let lines: Vec<String> = self
+            .entries
+            .iter()
+            .map(|(label, entry)| {
+                format!(
+                    r#"  {}: {{"count":{},"mean_ns":{},"min_ns":{},"max_ns":{}}}"#,
+                    escape_json(label),
+                    entry.count,
+                    entry.mean.as_nanos(),
+                    entry.min.as_nanos(),
+                    entry.max.as_nanos()
+                )
+            })
+            .collect();
+        if lines.is_empty() {
+            return "{}\n".to_string();
+        }
+        format!("{{\n{}\n}}\n", lines.join(",\n"))
+    }
+
+    /// Parses a baseline in the format of [`to_json`](Self::to_json).
+    ///
+    /// Fields other than `count`, `mean_ns`, `min_ns` and `max_ns` are
+    /// ignored, so that files edited by other tools still load.
+    pub fn from_json(json: &str) -> Result<Self, BaselineError> {
+        let mut parser = Parser { json, pos: 0 };
+        let mut baseline = Baseline::new();
+        parser.object(|parser, label| {
+            let mut entry = BaselineEntry::default();
+            parser.object(|parser, field| {
+                match field.as_str() {
+                    "count" => entry.count = parser.integer()?,
+                    "mean_ns" => entry.mean = Duration::from_nanos(parser.integer()?),
+                    "min_ns" => entry.min = Duration::from_nanos(parser.integer()?),
+                    "max_ns" => entry.max = Duration::from_nanos(parser.integer()?),
+                    _ => parser.skip_value()?,
+                }
+                Ok(())
+            })?;
+            baseline.insert(label, entry);
+            Ok(())
+        })?;
+        parser.whitespace();
+        if parser.pos < json.len() {
+            return Err(parser.error("end of input"));
+        }
+        Ok(baseline)
+    }
+
+    /// Reads a baseline from a file written by [`save`](Self::save).
+    ///
+    /// A malformed file is reported as an [`io::ErrorKind::InvalidData`]
+    /// error wrapping a [`BaselineError`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Baseline::from_json(&json).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Writes the baseline to a file, typically committed next to the code.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
+
+/// A label that got slower than its baseline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Regression {
+    /// The label
+    pub label: String,
+    /// The mean duration in the baseline
+    pub baseline: Duration,
+    /// The mean duration of the current run
+    pub current: Duration,
+}
+
+impl Regression {
+    /// Returns how much slower the current run is, in percent of the baseline.
+    pub fn change(&self) -> f64 {
+        registry::change(self.baseline, self.current)
+    }
+}
+
+impl fmt::Display for Regression {
+    /// Formats the regression as e.g.
+    /// `"parse - mean 5 µs, 25.0% slower than the baseline of 4 µs"`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - mean {}, {:.1}% slower than the baseline of {}",
+            self.label,
+            HumanDuration(self.current),
+            self.change(),
+            HumanDuration(self.baseline)
+        )
+    }
+}
+
+/// A malformed baseline file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaselineError {
+    offset: usize,
+    expected: &'static str,
+}
+
+impl BaselineError {
+    /// Returns the byte offset of the error in the input.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for BaselineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid baseline at byte {}: expected {}", self.offset, self.expected)
+    }
+}
+
+impl Error for BaselineError {}
+
+/// Compares the current [registry snapshot](registry::snapshot) against the
+/// baseline at `path`, returning the labels more than `max_regression`
+/// percent slower than their baseline.
+///
+/// If there is no file at `path` yet, the snapshot is saved there as the new
+/// baseline and there are no regressions.
+pub fn check(path: impl AsRef<Path>, max_regression: f64) -> io::Result<Vec<Regression>> {
+    let path = path.as_ref();
+    let report = registry::snapshot();
+    match Baseline::load(path) {
+        Ok(baseline) => Ok(baseline.compare(&report, max_regression)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            Baseline::from_report(&report).save(path)?;
+            Ok(Vec::new())
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// A minimal JSON reader for baseline files.
+struct Parser<'a> {
+    json: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, expected: &'static str) -> BaselineError {
+        BaselineError {
+            offset: self.pos,
+            expected,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.json.as_bytes().get(self.pos).copied()
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips whitespace and consumes `byte` if it comes next.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.whitespace();
+        let found = self.peek() == Some(byte);
+        self.pos += usize::from(found);
+        found
+    }
+
+    fn expect(&mut self, byte: u8, expected: &'static str) -> Result<(), BaselineError> {
+        if !self.eat(byte) {
+            return Err(self.error(expected));
+        }
+        Ok(())
+    }
+
+    /// Reads an object, calling `field` with each key to read its value.
+    fn object(
+        &mut self,
+        mut field: impl FnMut(&mut Self, String) -> Result<(), BaselineError>,
+    ) -> Result<(), BaselineError> {
+        self.expect(b'{', "`{`")?;
+        if self.eat(b'}') {
+            return Ok(());
+        }
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.expect(b':', "`:`")?;
+            field(self, key)?;
+            if self.eat(b'}') {
+                return Ok(());
+            }
+            self.expect(b',', "`,` or `}`")?;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, BaselineError> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error("a string"));
+        }
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            let rest = &self.json[self.pos..];
+            let Some(end) = rest.find(['"', '\\']) else {
+                return Err(self.error("`\"`"));
+            };
+            text.push_str(&rest[..end]);
+            self.pos += end + 1;
+            if rest.as_bytes()[end] == b'"' {
+                return Ok(text);
+            }
+            let escaped = match self.peek() {
+                Some(b'"') => '"',
+                Some(b'\\') => '\\',
+                Some(b'/') => '/',
+                Some(b'b') => '\u{8}',
+                Some(b'f') => '\u{c}',
+                Some(b'n') => '\n',
+                Some(b'r') => '\r',
+                Some(b't') => '\t',
+                Some(b'u') => {
+                    self.pos += 1;
+                    let high = self.hex()?;
+                    let code = match high {
+                        0xd800..0xdc00 if self.json[self.pos..].starts_with("\\u") => {
+                            self.pos += 2;
+                            let low = self.hex()?;
+                            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+                        }
+                        code => code,
+                    };
+                    text.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    continue;
+                }
+                _ => return Err(self.error("an escape sequence")),
+            };
+            text.push(escaped);
+            self.pos += 1;
+        }
+    }
+
+    /// Reads the four hex digits of a `\u` escape.
+    fn hex(&mut self) -> Result<u32, BaselineError> {
+        let digits = self.json.get(self.pos..self.pos + 4).ok_or_else(|| self.error("four hex digits"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("four hex digits"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn integer(&mut self) -> Result<u64, BaselineError> {
+        self.whitespace();
+        let digits = self.json[self.pos..].bytes().take_while(u8::is_ascii_digit).count();
+        let value = self.json[self.pos..self.pos + digits]
+            .parse()
+            .map_err(|_| self.error("an unsigned integer"))?;
+        self.pos += digits;
+        Ok(value)
+    }
+
+    /// Skips a value of any type.
+    fn skip_value(&mut self) -> Result<(), BaselineError> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(|parser, _| parser.skip_value()),
+            Some(b'[') => {
+                self.pos += 1;
+                if self.eat(b']') {
+                    return Ok(());
+                }
+                loop {
+                    self.skip_value()?;
+                    if self.eat(b']') {
+                        return Ok(());
+                    }
+                    self.expect(b',', "`,` or `]`")?;
+                }
+            }
+            Some(b'"') => self.string().map(drop),
+            _ => {
+                let literal = self.json[self.pos..]
+                    .bytes()
+                    .take_while(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'+' | b'.'))
+                    .count();
+                if literal == 0 {
+                    return Err(self.error("a value"));
+                }
+                self.pos += literal;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_json() {
+        registry::record("baseline-\"quoted\"", Duration::from_micros(300));
+        registry::record("baseline-\"quoted\"", Duration::from_micros(500));
+        let report = registry::snapshot();
+        let mut baseline = Baseline::from_report(&report);
+        baseline.insert("baseline-\u{e9}", BaselineEntry::default());
+
+        let json = baseline.to_json();
+        assert!(json.contains(r#"  "baseline-\"quoted\"": {"count":2,"mean_ns":400000,"min_ns":300000,"max_ns":500000}"#));
+        assert_eq!(Baseline::from_json(&json), Ok(baseline));
+        assert_eq!(Baseline::from_json("{}"), Ok(Baseline::new()));
+
+        let edited = r#" { "aé\n": { "mean_ns": 7, "note": [1, {"x": null}], "count": 1 } } "#;
+        let entry = BaselineEntry {
+            count: 1,
+            mean: Duration::from_nanos(7),
+            ..BaselineEntry::default()
+        };
+        assert_eq!(Baseline::from_json(edited).unwrap().get("a\u{e9}\n"), Some(&entry));
+
+        let error = Baseline::from_json(r#"{"a": {"count": -1}}"#).unwrap_err();
+        assert_eq!(error.to_string(), "invalid baseline at byte 16: expected an unsigned integer");
+        assert!(Baseline::from_json(r#"{"a": {}} x"#).is_err());
+    }
+
+    #[test]
+    fn flags_regressions() {
+        registry::record("baseline-slower", Duration::from_millis(15));
+        registry::record("baseline-steady", Duration::from_millis(10));
+        registry::record("baseline-new", Duration::from_millis(10));
+        let report = registry::snapshot();
+
+        let mut baseline = Baseline::new();
+        for label in ["baseline-slower", "baseline-steady", "baseline-gone"] {
+            let entry = BaselineEntry {
+                count: 1,
+                mean: Duration::from_millis(10),
+                ..BaselineEntry::default()
+            };
+            baseline.insert(label, entry);
+        }
+
+        let regressions = baseline.compare(&report, 10.0);
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(
+            regressions[0].to_string(),
+            "baseline-slower - mean 15 ms, 50.0% slower than the baseline of 10 ms"
+        );
+        assert!(baseline.compare(&report, 50.0).is_empty());
+
+        let path = std::env::temp_dir().join(format!("arbitime-baseline-{}.json", std::process::id()));
+        baseline.save(&path).unwrap();
+        assert_eq!(Baseline::load(&path).unwrap(), baseline);
+        assert_eq!(check(&path, 10.0).unwrap(), regressions);
+        fs::write(&path, "{").unwrap();
+        assert_eq!(Baseline::load(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(path).unwrap();
+    }
+}
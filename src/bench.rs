@@ -0,0 +1,391 @@
+//! Statistics for repeated measurements of the same code.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::HumanDuration;
+
+/// Summary statistics of repeated runs, as returned by [`bench_time!`](crate::bench_time)
+/// and [`time_n!`](crate::time_n).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchStats {
+    /// Number of measured iterations (excluding warmup)
+    pub iterations: usize,
+    /// Fastest iteration
+    pub min: Duration,
+    /// Slowest iteration
+    pub max: Duration,
+    /// Arithmetic mean of all iterations
+    pub mean: Duration,
+    /// Median of all iterations
+    pub median: Duration,
+    /// Sample standard deviation of all iterations
+    pub stddev: Duration,
+    /// The duration of every iteration, in the order they ran
+    ///
+    /// For [`time_n!`](crate::time_n), which runs fast code in batches, each
+    /// sample is the mean duration of the iterations of one batch.
+    pub samples: Vec<Duration>,
+}
+
+impl BenchStats {
+    /// Computes statistics from a list of measured durations.
+    pub fn from_samples(samples: Vec<Duration>) -> Self {
+        let iterations = samples.len();
+        if iterations == 0 {
+            return BenchStats {
+                iterations,
+                min: Duration::ZERO,
+                max: Duration::ZERO,
+                mean: Duration::ZERO,
+                median: Duration::ZERO,
+                stddev: Duration::ZERO,
+                samples,
+            };
+        }
+
+        let mut sorted = samples.clone();
+        sorted.sort_unstable();
+        let median = if iterations.is_multiple_of(2) {
+            (sorted[iterations / 2 - 1] + sorted[iterations / 2]) / 2
+        } else {
+            sorted[iterations / 2]
+        };
+
+        let mean_secs = sorted.iter().map(Duration::as_secs_f64).sum::<f64>() / iterations as f64;
+        let variance = if iterations > 1 {
+            sorted
+                .iter()
+                .map(|sample| (sample.as_secs_f64() - mean_secs).powi(2))
+                .sum::<f64>()
+                / (iterations - 1) as f64
+        } else {
+            0.0
+        };
+
+        BenchStats {
+            iterations,
+            min: sorted[0],
+            max: sorted[iterations - 1],
+            mean: Duration::from_secs_f64(mean_secs),
+            median,
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+            samples,
+        }
+    }
+}
+
+impl fmt::Display for BenchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} iterations - mean: {} ± {}, median: {}, min: {}, max: {}",
+            self.iterations,
+            HumanDuration(self.mean),
+            HumanDuration(self.stddev),
+            HumanDuration(self.median),
+            HumanDuration(self.min),
+            HumanDuration(self.max)
+        )
+    }
+}
+
+/// The outcome of comparing two implementations with
+/// [`compare_time!`](crate::compare_time).
+///
+/// Both are compared by their median duration. With at least two samples on
+/// each side, a Mann-Whitney U test over all samples tells whether the
+/// difference is more than noise, see [`p_value`](Comparison::p_value).
+/// Formatting a comparison with `{}` gives a one-line summary, e.g.
+/// `old: 12 ms, new: 4 ms - new is 3.00x faster (-8 ms, -66.7%, p < 0.001)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    /// The label of the first block, the baseline
+    pub baseline_label: String,
+    /// The statistics of the first block
+    pub baseline: BenchStats,
+    /// The label of the second block, compared against the baseline
+    pub candidate_label: String,
+    /// The statistics of the second block
+    pub candidate: BenchStats,
+}
+
+impl Comparison {
+    /// Returns the absolute difference between the two median durations.
+    pub fn difference(&self) -> Duration {
+        self.baseline.median.abs_diff(self.candidate.median)
+    }
+
+    /// Returns how many times faster the candidate is than the baseline:
+    /// above 1 if it is faster, below 1 if it is slower.
+    pub fn speedup(&self) -> f64 {
+        let candidate = self.candidate.median.as_secs_f64();
+        if candidate == 0.0 {
+            return if self.baseline.median.is_zero() { 1.0 } else { f64::INFINITY };
+        }
+        self.baseline.median.as_secs_f64() / candidate
+    }
+
+    /// Returns the change from the baseline to the candidate in percent:
+    /// negative if the candidate is faster.
+    pub fn change_percent(&self) -> f64 {
+        let baseline = self.baseline.median.as_secs_f64();
+        if baseline == 0.0 {
+            return 0.0;
+        }
+        (self.candidate.median.as_secs_f64() - baseline) / baseline * 100.0
+    }
+
+    /// Returns the two-sided p-value of a Mann-Whitney U test between the
+    /// samples of both blocks: the probability of seeing a difference at
+    /// least this large if both were equally fast.
+    ///
+    /// The test makes no assumption about the distribution of the durations,
+    /// which are usually skewed by outliers. Returns `None` with fewer than
+    /// two samples on either side.
+    pub fn p_value(&self) -> Option<f64> {
+        mann_whitney(&self.baseline.samples, &self.candidate.samples)
+    }
+
+    /// Returns whether the difference is statistically significant at the
+    /// given level, e.g. `0.05`.
+    pub fn is_significant(&self, alpha: f64) -> bool {
+        self.p_value().is_some_and(|p| p < alpha)
+    }
+}
+
+/// The two-sided p-value of a Mann-Whitney U test, using the normal
+/// approximation with tie and continuity corrections.
+fn mann_whitney(a: &[Duration], b: &[Duration]) -> Option<f64> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let mut all: Vec<(Duration, bool)> = a.iter().map(|&d| (d, true)).chain(b.iter().map(|&d| (d, false))).collect();
+    all.sort_unstable_by_key(|&(d, _)| d);
+
+    // Rank both samples together, giving tied durations their average rank
+    let (mut rank_sum, mut ties) = (0.0, 0.0);
+    let mut start = 0;
+    while start < all.len() {
+        let end = start + all[start..].iter().take_while(|&&(d, _)| d == all[start].0).count();
+        let rank = (start + end + 1) as f64 / 2.0;
+        rank_sum += rank * all[start..end].iter().filter(|&&(_, first)| first).count() as f64;
+        let tied = (end - start) as f64;
+        ties += tied.powi(3) - tied;
+        start = end;
+    }
+
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    let n = n1 + n2;
+    let u = rank_sum - n1 * (n1 + 1.0) / 2.0;
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - ties / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        // All samples are equal
+        return Some(1.0);
+    }
+    let z = ((u - mean).abs() - 0.5).max(0.0) / variance.sqrt();
+    Some(erfc(z / std::f64::consts::SQRT_2).min(1.0))
+}
+
+/// The complementary error function, accurate to about 1e-7.
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * x.abs());
+    let y = t
+        * (-x * x - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98 + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77)))))))))
+            .exp();
+    if x >= 0.0 { y } else { 2.0 - y }
+}
+
+/// Formats a p-value for messages, e.g. `p = 0.031` or `p < 0.001`.
+struct PValue(f64);
+
+impl fmt::Display for PValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 < 0.001 {
+            f.write_str("p < 0.001")
+        } else {
+            write!(f, "p = {:.3}", self.0)
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}, {}: {} - ",
+            self.baseline_label,
+            HumanDuration(self.baseline.median),
+            self.candidate_label,
+            HumanDuration(self.candidate.median)
+        )?;
+        let (candidate, baseline) = (self.candidate.median, self.baseline.median);
+        if candidate == baseline {
+            return write!(f, "{} is as fast as {}", self.candidate_label, self.baseline_label);
+        }
+        let (verdict, factor, sign) = if candidate < baseline {
+            ("faster", self.speedup(), '-')
+        } else {
+            ("slower", 1.0 / self.speedup(), '+')
+        };
+        write!(
+            f,
+            "{} is {:.2}x {} ({}{}, {:+.1}%",
+            self.candidate_label,
+            factor,
+            verdict,
+            sign,
+            HumanDuration(self.difference()),
+            self.change_percent()
+        )?;
+        match self.p_value() {
+            Some(p) => write!(f, ", {})", PValue(p)),
+            None => f.write_str(")"),
+        }
+    }
+}
+
+/// The shortest batch of iterations that [`time_n!`](crate::time_n) keeps as
+/// a sample. Shorter batches are dominated by the cost of reading the clock.
+const MIN_BATCH_TIME: Duration = Duration::from_micros(10);
+
+/// Drives the loop of [`time_n!`](crate::time_n): picks how many iterations
+/// to run per batch and collects one per-iteration sample for each batch.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Adaptive {
+    target: Duration,
+    /// A reading of the real clock, so that a mock clock cannot stall the run
+    start: u64,
+    batch: u64,
+    iterations: u64,
+    samples: Vec<Duration>,
+}
+
+impl Adaptive {
+    /// Starts a run that lasts about `target`.
+    pub fn new(target: Duration) -> Self {
+        Adaptive {
+            target,
+            start: crate::clock::real_now(),
+            batch: 1,
+            iterations: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Returns the number of iterations to run next, or `None` once the
+    /// target time is reached.
+    pub fn next_batch(&self) -> Option<u64> {
+        let done = if cfg!(feature = "disable") {
+            self.iterations > 0
+        } else {
+            !self.samples.is_empty() && self.elapsed() >= self.target
+        };
+        (!done).then_some(self.batch)
+    }
+
+    /// Records how long the last batch took.
+    pub fn record(&mut self, elapsed: Duration) {
+        if cfg!(feature = "disable") {
+            self.iterations += self.batch;
+            self.samples.push(Duration::ZERO);
+            return;
+        }
+        // Batches that are too short to measure only calibrate the batch size
+        if elapsed < MIN_BATCH_TIME && self.elapsed() < self.target {
+            self.batch = self.batch.saturating_mul(2);
+            return;
+        }
+        self.iterations += self.batch;
+        self.samples.push(elapsed.div_f64(self.batch as f64));
+    }
+
+    fn elapsed(&self) -> Duration {
+        Duration::from_nanos(crate::clock::real_now().saturating_sub(self.start))
+    }
+
+    /// Computes the statistics of all recorded batches.
+    pub fn finish(self) -> BenchStats {
+        let mut stats = BenchStats::from_samples(self.samples);
+        stats.iterations = self.iterations as usize;
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_statistics() {
+        let samples = [4, 1, 3, 2].map(Duration::from_millis).to_vec();
+        let stats = BenchStats::from_samples(samples);
+        assert_eq!(stats.iterations, 4);
+        assert_eq!(stats.min, Duration::from_millis(1));
+        assert_eq!(stats.max, Duration::from_millis(4));
+        assert_eq!(stats.median, Duration::from_micros(2500));
+        assert_eq!(stats.mean.as_micros(), 2500);
+        // Sample standard deviation of 1, 2, 3, 4 ms is ~1.291 ms
+        assert_eq!(stats.stddev.as_micros(), 1290);
+
+        let empty = BenchStats::from_samples(Vec::new());
+        assert_eq!(empty.iterations, 0);
+        assert_eq!(empty.mean, Duration::ZERO);
+    }
+
+    #[test]
+    fn compares_medians() {
+        let stats = |millis: &[u64]| BenchStats::from_samples(millis.iter().copied().map(Duration::from_millis).collect());
+        let mut comparison = Comparison {
+            baseline_label: "old".into(),
+            baseline: stats(&[12, 13, 11]),
+            candidate_label: "new".into(),
+            candidate: stats(&[4, 4, 9]),
+        };
+        assert_eq!(comparison.difference(), Duration::from_millis(8));
+        assert_eq!(comparison.speedup(), 3.0);
+        assert_eq!(comparison.to_string(), "old: 12 ms, new: 4 ms - new is 3.00x faster (-8 ms, -66.7%, p = 0.077)");
+
+        comparison.candidate = stats(&[18]);
+        assert_eq!(comparison.to_string(), "old: 12 ms, new: 18 ms - new is 1.50x slower (+6 ms, +50.0%)");
+
+        comparison.candidate = stats(&[12]);
+        assert_eq!(comparison.to_string(), "old: 12 ms, new: 12 ms - new is as fast as old");
+    }
+
+    #[test]
+    fn tests_significance() {
+        let stats = |millis: &[u64]| BenchStats::from_samples(millis.iter().copied().map(Duration::from_millis).collect());
+        let mut comparison = Comparison {
+            baseline_label: "old".into(),
+            baseline: stats(&[12, 13, 11]),
+            candidate_label: "new".into(),
+            candidate: stats(&[4, 4, 9]),
+        };
+        // U = 0 for 3 vs 3 samples with one tie: z = 4 / 2.258, p ~ 0.0765
+        let p = comparison.p_value().unwrap();
+        assert!((p - 0.0765).abs() < 0.0005, "{p}");
+        assert!(!comparison.is_significant(0.05));
+        assert!(comparison.is_significant(0.1));
+
+        comparison.baseline = stats(&(20..40).collect::<Vec<_>>());
+        comparison.candidate = stats(&(10..30).collect::<Vec<_>>());
+        assert!(comparison.is_significant(0.05));
+        assert!(comparison.to_string().ends_with("p < 0.001)"), "{comparison}");
+
+        comparison.candidate = stats(&(20..40).rev().collect::<Vec<_>>());
+        assert!(comparison.p_value().unwrap() > 0.99);
+
+        comparison.candidate = stats(&[25]);
+        assert_eq!(comparison.p_value(), None);
+        assert!(!comparison.is_significant(0.05));
+    }
+}
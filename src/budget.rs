@@ -0,0 +1,242 @@
+//! Time budgets split across the phases of a frame or request.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::{HumanDuration, Level};
+
+/// The time spent in one phase of a [`TimeBudget`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phase {
+    /// The label given to [`TimeBudget::spend`]
+    pub label: String,
+    /// The total time spent in the phase
+    pub duration: Duration,
+    /// Number of times the phase was entered
+    pub calls: u64,
+}
+
+impl Phase {
+    /// Returns the share of `total` taken by this phase, in percent.
+    pub fn percent_of(&self, total: Duration) -> f64 {
+        percent(self.duration, total)
+    }
+}
+
+/// A total allowance of time, such as 16 ms per frame, that the phases of
+/// some work draw from.
+///
+/// Each call to [`spend`](Self::spend) times a closure and charges it to a
+/// phase. The first time the cumulative spend goes over the budget, a warning
+/// with the breakdown so far is logged at [`Level::Warn`]. Formatting the
+/// budget with `{}` produces the per-phase breakdown, e.g.
+/// `"14 ms of 16 ms (87.5%): physics 4 ms (25.0%), render 10 ms (62.5%)"`.
+/// [`reset`](Self::reset) starts the next frame with the same allowance.
+///
+/// Warnings are printed to stderr, or logged through the `log` crate with the
+/// `log` feature, unless another function is set with
+/// [`emitter`](Self::emitter).
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::TimeBudget;
+/// use std::time::Duration;
+///
+/// let mut budget = TimeBudget::new(Duration::from_millis(16));
+/// for _ in 0..3 {
+///     budget.spend("physics", || (1..=1000).sum::<u64>());
+///     let pixels = budget.spend("render", || vec![0u8; 1024]);
+///     assert_eq!(pixels.len(), 1024);
+///
+///     println!("{}", budget);
+///     budget.reset();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TimeBudget {
+    total: Duration,
+    /// The phases in the order they were first entered
+    phases: Vec<Phase>,
+    spent: Duration,
+    /// Whether the warning was already logged since the last reset
+    warned: bool,
+    emit: fn(Level, &str),
+}
+
+impl TimeBudget {
+    /// Creates a budget of `total`, with nothing spent yet.
+    pub fn new(total: Duration) -> Self {
+        TimeBudget {
+            total,
+            phases: Vec::new(),
+            spent: Duration::ZERO,
+            warned: false,
+            emit: crate::sink::emit_message,
+        }
+    }
+
+    /// Sets the function used to log the warning, e.g. to route it through
+    /// `log` or a test's own buffer.
+    pub fn emitter(mut self, emit: fn(Level, &str)) -> Self {
+        self.emit = emit;
+        self
+    }
+
+    /// Runs `f` and charges the time it took to the phase `label`, returning
+    /// its result.
+    pub fn spend<T>(&mut self, label: &str, f: impl FnOnce() -> T) -> T {
+        if cfg!(feature = "disable") {
+            return f();
+        }
+        let start = crate::clock::now();
+        let result = f();
+        let duration = crate::calibration::correct(crate::clock::elapsed_since(start));
+        self.charge(label, duration);
+        result
+    }
+
+    /// Charges a duration measured elsewhere to the phase `label`.
+    pub fn charge(&mut self, label: &str, duration: Duration) {
+        match self.phases.iter_mut().find(|phase| phase.label == label) {
+            Some(phase) => {
+                phase.duration += duration;
+                phase.calls += 1;
+            }
+            None => self.phases.push(Phase {
+                label: label.to_string(),
+                duration,
+                calls: 1,
+            }),
+        }
+        self.spent += duration;
+        if self.is_over() && !self.warned {
+            self.warned = true;
+            if let Some(warning) = self.warning(label) {
+                (self.emit)(Level::Warn, &warning);
+            }
+        }
+    }
+
+    /// Returns the allowance of the budget.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Returns the time spent in all phases since the last reset.
+    pub fn spent(&self) -> Duration {
+        self.spent
+    }
+
+    /// Returns the time left, or zero once the budget is exceeded.
+    pub fn remaining(&self) -> Duration {
+        self.total.saturating_sub(self.spent)
+    }
+
+    /// Returns `true` if more than the allowance was spent.
+    pub fn is_over(&self) -> bool {
+        self.spent > self.total
+    }
+
+    /// Returns the phases, in the order they were first entered.
+    pub fn phases(&self) -> &[Phase] {
+        &self.phases
+    }
+
+    /// Returns the phase with the given label, if it was entered.
+    pub fn phase(&self, label: &str) -> Option<&Phase> {
+        self.phases.iter().find(|phase| phase.label == label)
+    }
+
+    /// Forgets the time spent, e.g. at the start of the next frame, keeping
+    /// the allowance.
+    pub fn reset(&mut self) {
+        self.phases.clear();
+        self.spent = Duration::ZERO;
+        self.warned = false;
+    }
+
+    /// Returns the warning for a budget exceeded in the phase `label`, or
+    /// `None` if timing output is disabled.
+    fn warning(&self, label: &str) -> Option<String> {
+        if !crate::is_enabled() || !crate::filter::enabled(Some(label)) {
+            return None;
+        }
+        Some(format!("Time budget exceeded in {} - {}", label, self))
+    }
+}
+
+impl fmt::Display for TimeBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} ({:.1}%)",
+            HumanDuration(self.spent),
+            HumanDuration(self.total),
+            percent(self.spent, self.total)
+        )?;
+        for (i, phase) in self.phases.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { ", " };
+            write!(
+                f,
+                "{}{} {} ({:.1}%)",
+                separator,
+                phase.label,
+                HumanDuration(phase.duration),
+                phase.percent_of(self.total)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the share of `total` taken by `duration`, in percent.
+fn percent(duration: Duration, total: Duration) -> f64 {
+    match total.as_secs_f64() {
+        0.0 => 0.0,
+        total => duration.as_secs_f64() / total * 100.0,
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+    use std::sync::Mutex;
+
+    static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    fn collect(_: Level, msg: &str) {
+        WARNINGS.lock().unwrap().push(msg.to_string());
+    }
+
+    #[test]
+    fn splits_the_budget_across_phases() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let mut budget = TimeBudget::new(Duration::from_millis(16)).emitter(collect);
+        for (label, millis) in [("budget-physics", 4), ("budget-render", 6), ("budget-render", 4)] {
+            budget.spend(label, || clock.advance(Duration::from_millis(millis)));
+        }
+        assert_eq!(budget.remaining(), Duration::from_millis(2));
+        assert_eq!(budget.phase("budget-render").map(|phase| phase.calls), Some(2));
+        assert_eq!(
+            budget.to_string(),
+            "14 ms of 16 ms (87.5%): budget-physics 4 ms (25.0%), budget-render 10 ms (62.5%)"
+        );
+        assert!(WARNINGS.lock().unwrap().is_empty());
+
+        budget.spend("budget-audio", || clock.advance(Duration::from_millis(4)));
+        budget.charge("budget-audio", Duration::from_millis(1));
+        assert!(budget.is_over());
+        assert_eq!(
+            *WARNINGS.lock().unwrap(),
+            ["Time budget exceeded in budget-audio - 18 ms of 16 ms (112.5%): budget-physics 4 ms (25.0%), budget-render 10 ms (62.5%), budget-audio 4 ms (25.0%)"]
+        );
+
+        budget.reset();
+        assert_eq!((budget.spent(), budget.phases().len()), (Duration::ZERO, 0));
+        assert_eq!(budget.to_string(), "0 ns of 16 ms (0.0%)");
+    }
+}
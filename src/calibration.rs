@@ -0,0 +1,123 @@
+//! Measurement overhead calibration.
+//!
+//! Reading the [`clock`](crate::clock) twice is not free: on a typical
+//! machine, timing an empty block reports somewhere between 15 and 50 ns. For
+//! blocks that only take a few nanoseconds, this overhead dominates the
+//! measurement.
+//! [`calibrate`] measures it on the current machine, and with
+//! [`set_overhead_correction`] enabled, the measured overhead is subtracted
+//! from every duration reported by the timing macros, [`ScopeTimer`](crate::ScopeTimer)
+//! and [`TimedIteratorExt`](crate::TimedIteratorExt), saturating at zero.
+//! Calibrating needs the `std` feature.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{calibration, time};
+//!
+//! let overhead = arbitime::calibrate();
+//! println!("timing overhead: {:?}", overhead); // "timing overhead: 21ns"
+//!
+//! calibration::set_overhead_correction(true);
+//! let (duration, _) = time!(std::hint::black_box(1 + 1));
+//! println!("{:?}", duration); // Close to zero
+//! ```
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+/// Number of empty blocks timed by [`calibrate`].
+#[cfg(feature = "std")]
+const SAMPLES: usize = 10_001;
+
+/// The calibrated overhead in nanoseconds, or `u64::MAX` if not calibrated yet.
+static OVERHEAD_NANOS: AtomicU64 = AtomicU64::new(u64::MAX);
+static CORRECTION: AtomicBool = AtomicBool::new(false);
+
+/// Measures the overhead of timing a block on the current machine, remembers
+/// it for [`set_overhead_correction`], and returns it.
+///
+/// The overhead is the median duration reported for an empty block, over
+/// thousands of runs. Calibrating takes well under a millisecond. With the
+/// `disable` feature, the overhead is zero.
+#[cfg(feature = "std")]
+pub fn calibrate() -> Duration {
+    let overhead = if cfg!(feature = "disable") {
+        Duration::ZERO
+    } else {
+        let mut samples: Vec<Duration> = (0..SAMPLES)
+            .map(|_| {
+                let start = crate::clock::now();
+                std::hint::black_box(());
+                crate::clock::elapsed_since(start)
+            })
+            .collect();
+        samples.sort_unstable();
+        samples[SAMPLES / 2]
+    };
+    let nanos = u64::try_from(overhead.as_nanos()).unwrap_or(u64::MAX - 1);
+    OVERHEAD_NANOS.store(nanos, Ordering::Relaxed);
+    overhead
+}
+
+/// Returns the overhead measured by the last call to [`calibrate`], if any.
+pub fn overhead() -> Option<Duration> {
+    match OVERHEAD_NANOS.load(Ordering::Relaxed) {
+        u64::MAX => None,
+        nanos => Some(Duration::from_nanos(nanos)),
+    }
+}
+
+/// Sets whether the calibrated overhead is subtracted from measured durations.
+///
+/// Disabled by default. Enabling it runs [`calibrate`] first if it has not
+/// been called yet.
+///
+/// ```rust
+/// use arbitime::calibration;
+/// use std::time::Duration;
+///
+/// calibration::set_overhead_correction(true);
+/// let overhead = calibration::overhead().unwrap();
+/// let measured = overhead + Duration::from_nanos(500);
+/// assert_eq!(calibration::correct(measured), Duration::from_nanos(500));
+/// assert_eq!(calibration::correct(Duration::ZERO), Duration::ZERO);
+/// ```
+#[cfg(feature = "std")]
+pub fn set_overhead_correction(enabled: bool) {
+    if enabled && overhead().is_none() {
+        calibrate();
+    }
+    CORRECTION.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether the calibrated overhead is subtracted from measured durations.
+pub fn overhead_correction() -> bool {
+    CORRECTION.load(Ordering::Relaxed)
+}
+
+/// Subtracts the calibrated overhead from a measured duration if overhead
+/// correction is enabled.
+#[inline]
+pub fn correct(duration: Duration) -> Duration {
+    if !overhead_correction() {
+        return duration;
+    }
+    duration.saturating_sub(overhead().unwrap_or_default())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrects_by_calibrated_overhead() {
+        let overhead = calibrate();
+        assert!(overhead < Duration::from_millis(1), "{:?}", overhead);
+        assert_eq!(super::overhead(), Some(overhead));
+
+        // Correction is off unless enabled
+        let measured = overhead + Duration::from_nanos(500);
+        assert_eq!(correct(measured), measured);
+    }
+}
@@ -0,0 +1,152 @@
+//! Per-call-site counters.
+//!
+//! Every expansion of [`format_time!`](crate::format_time),
+//! [`log_time!`](crate::log_time), [`json_time!`](crate::json_time), their
+//! async variants, [`time_scope!`](crate::time_scope) and
+//! [`timed`](crate::timed) has its own hidden `static` [`CallSite`], which
+//! counts the calls and total duration of that line of code with two atomic
+//! additions, without any lookup or lock. A call site is registered the first
+//! time it records a measurement, and [`call_sites`] returns all of them,
+//! e.g. to export them from a long-running service.
+//!
+//! Unlike the [`registry`](crate::registry), which aggregates by label, call
+//! sites tell apart measurements with the same label made from different
+//! places.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{callsite, log_time};
+//!
+//! for _ in 0..3 {
+//!     log_time!("parse" => (1..=100).sum::<u32>());
+//! }
+//!
+//! for site in callsite::call_sites() {
+//!     println!(
+//!         "{} ({}): {} calls, {:?} in total",
+//!         site.label().unwrap_or("-"),
+//!         site.location(),
+//!         site.count(),
+//!         site.total()
+//!     );
+//! }
+//! ```
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+static SITES: Mutex<Vec<&'static CallSite>> = Mutex::new(Vec::new());
+
+/// The counters of one timing macro in the source code.
+#[derive(Debug)]
+pub struct CallSite {
+    file: &'static str,
+    line: u32,
+    module_path: &'static str,
+    label: OnceLock<Option<String>>,
+    count: AtomicU64,
+    total_ns: AtomicU64,
+    registered: AtomicBool,
+}
+
+impl CallSite {
+    /// Creates the counters of a call site, for a `static` in a macro
+    /// expansion.
+    #[doc(hidden)]
+    pub const fn new(file: &'static str, line: u32, module_path: &'static str) -> Self {
+        CallSite {
+            file,
+            line,
+            module_path,
+            label: OnceLock::new(),
+            count: AtomicU64::new(0),
+            total_ns: AtomicU64::new(0),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the source file of the call site.
+    pub fn file(&self) -> &'static str {
+        self.file
+    }
+
+    /// Returns the line of the call site in its [`file`](Self::file).
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Returns the module path of the call site, such as `my_app::parser`.
+    pub fn module_path(&self) -> &'static str {
+        self.module_path
+    }
+
+    /// Returns the call site as `file:line`.
+    pub fn location(&self) -> String {
+        format!("{}:{}", self.file, self.line)
+    }
+
+    /// Returns the label of the first measurement of the call site, or `None`
+    /// if it is unlabelled. Labels built from format arguments may differ
+    /// between calls.
+    pub fn label(&self) -> Option<&str> {
+        self.label.get().and_then(Option::as_deref)
+    }
+
+    /// Returns the number of recorded calls.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total duration of all recorded calls.
+    pub fn total(&self) -> Duration {
+        Duration::from_nanos(self.total_ns.load(Ordering::Relaxed))
+    }
+
+    /// Returns the mean duration of all recorded calls.
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::ZERO,
+            count => self.total().div_f64(count as f64),
+        }
+    }
+
+    /// Records a measurement that stands for `weight` calls.
+    pub(crate) fn record(&'static self, label: Option<&str>, duration: Duration, weight: u64) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.count.fetch_add(weight, Ordering::Relaxed);
+        self.total_ns
+            .fetch_add(nanos.saturating_mul(weight), Ordering::Relaxed);
+        self.label.get_or_init(|| label.map(str::to_string));
+        if !self.registered.swap(true, Ordering::Relaxed) {
+            SITES.lock().unwrap_or_else(PoisonError::into_inner).push(self);
+        }
+    }
+}
+
+/// Returns every call site that recorded a measurement so far, in the order
+/// of their first measurement.
+pub fn call_sites() -> Vec<&'static CallSite> {
+    SITES.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_calls_per_site() {
+        static SITE: CallSite = CallSite::new("src/lib.rs", 7, "app");
+        assert_eq!(SITE.mean(), Duration::ZERO);
+        SITE.record(Some("first"), Duration::from_millis(3), 1);
+        SITE.record(Some("second"), Duration::from_millis(1), 2);
+
+        assert_eq!(SITE.count(), 3);
+        assert_eq!(SITE.total(), Duration::from_millis(5));
+        assert_eq!(SITE.label(), Some("first"));
+        assert_eq!(SITE.location(), "src/lib.rs:7");
+        let registered = call_sites().into_iter().filter(|site| std::ptr::eq(*site, &SITE)).count();
+        assert_eq!(registered, 1);
+    }
+}
@@ -0,0 +1,186 @@
+//! Intermediate splits inside a timed block.
+//!
+//! [`checkpoint!`](crate::checkpoint) marks the end of a phase inside the
+//! innermost enclosing [`log_time!`](crate::log_time),
+//! [`format_time!`](crate::format_time), [`json_time!`](crate::json_time) or
+//! [`ScopeTimer`](crate::ScopeTimer) on the same thread. When the block
+//! finishes, its record lists every phase with its duration and share of the
+//! total, the last one running from the last checkpoint to the end of the
+//! block:
+//!
+//! ```text
+//! load config - Execution time: 12 ms (after read: 3 ms, 25.0%; after parse: 8 ms, 66.7%; end: 1 ms, 8.3%)
+//! ```
+//!
+//! This breaks a block down into phases without nesting a timed block around
+//! each of them. Checkpoints outside of a timed block do nothing. Like
+//! [`nesting`](crate::nesting), checkpoints are not supported by the async
+//! macros, since a task can move between threads while it runs.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{checkpoint, format_time};
+//!
+//! let (msg, config) = format_time!("load config" => {
+//!     let text = "retries = 3".to_string();
+//!     checkpoint!("after read");
+//!     let retries: u32 = text.rsplit(' ').next().unwrap().parse().unwrap();
+//!     checkpoint!("after parse");
+//!     retries
+//! });
+//! assert_eq!(config, 3);
+//! # #[cfg(not(feature = "disable"))]
+//! assert!(msg.contains("after read: "));
+//! ```
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+thread_local! {
+    /// The open blocks on this thread, outermost first.
+    static STACK: RefCell<Vec<Block>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The start of an open block and the checkpoints reached in it so far, as
+/// readings of the [`clock`](crate::clock).
+#[derive(Debug)]
+struct Block {
+    start: u64,
+    checkpoints: Vec<(String, u64)>,
+}
+
+/// The label of the phase from the last checkpoint to the end of the block.
+const END: &str = "end";
+
+/// One phase of a timed block, ending at a [`checkpoint!`](crate::checkpoint)
+/// or at the end of the block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Split {
+    /// The label of the checkpoint ending the phase, or `"end"` for the last
+    /// phase
+    pub label: String,
+    /// The duration of the phase
+    pub duration: Duration,
+}
+
+impl Split {
+    /// Returns the share of `total` taken by this phase, in percent.
+    pub fn percent_of(&self, total: Duration) -> f64 {
+        match total.as_secs_f64() {
+            0.0 => 0.0,
+            total => self.duration.as_secs_f64() / total * 100.0,
+        }
+    }
+}
+
+/// Records a checkpoint with the given label in the innermost open block.
+#[doc(hidden)]
+pub fn mark(label: impl Into<String>) {
+    if cfg!(feature = "disable") {
+        return;
+    }
+    STACK.with_borrow_mut(|stack| {
+        if let Some(block) = stack.last_mut() {
+            block.checkpoints.push((label.into(), crate::clock::now()));
+        }
+    });
+}
+
+/// The checkpoints of an open timed block.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Splits {
+    depth: usize,
+}
+
+/// Opens a block that collects checkpoints, for a timed block that is about
+/// to start.
+#[doc(hidden)]
+pub fn enter() -> Splits {
+    let depth = STACK.with_borrow_mut(|stack| {
+        stack.push(Block {
+            start: crate::clock::now(),
+            checkpoints: Vec::new(),
+        });
+        stack.len() - 1
+    });
+    Splits { depth }
+}
+
+impl Splits {
+    /// Closes the block, which took `total`, and returns its phases, or
+    /// nothing if no checkpoint was reached.
+    pub fn finish(self, total: Duration) -> Vec<Split> {
+        let Some(Block { start, checkpoints }) = self.close() else {
+            return Vec::new();
+        };
+        if checkpoints.is_empty() {
+            return Vec::new();
+        }
+        let mut splits = Vec::with_capacity(checkpoints.len() + 1);
+        let mut previous = start;
+        for (label, at) in checkpoints {
+            let duration = Duration::from_nanos(at.saturating_sub(previous));
+            splits.push(Split { label, duration });
+            previous = at;
+        }
+        // The total may have been corrected for overhead, so the last phase
+        // takes whatever is left of it
+        let elapsed = Duration::from_nanos(previous.saturating_sub(start));
+        splits.push(Split {
+            label: END.to_string(),
+            duration: total.saturating_sub(elapsed),
+        });
+        splits
+    }
+
+    /// Removes this block (and any blocks left open inside it) from the stack.
+    fn close(&self) -> Option<Block> {
+        STACK.with_borrow_mut(|stack| {
+            if stack.len() <= self.depth {
+                return None;
+            }
+            stack.drain(self.depth..).next()
+        })
+    }
+}
+
+impl Drop for Splits {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+
+    #[test]
+    fn splits_blocks_at_checkpoints() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let outer = enter();
+        clock.advance(Duration::from_millis(3));
+        mark("read");
+        let inner = enter();
+        mark("inner");
+        assert_eq!(inner.finish(Duration::ZERO).len(), 2);
+        clock.advance(Duration::from_millis(8));
+        mark(String::from("parse"));
+        clock.advance(Duration::from_millis(1));
+
+        let splits = outer.finish(Duration::from_millis(12));
+        let phases: Vec<_> = splits.iter().map(|split| (split.label.as_str(), split.duration.as_millis())).collect();
+        assert_eq!(phases, [("read", 3), ("parse", 8), ("end", 1)]);
+        assert_eq!(splits[0].percent_of(Duration::from_millis(12)), 25.0);
+
+        assert!(enter().finish(Duration::from_millis(1)).is_empty());
+        // Without an open block, checkpoints are ignored
+        mark("nowhere");
+        assert!(STACK.with_borrow(Vec::is_empty));
+    }
+}
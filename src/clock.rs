@@ -0,0 +1,558 @@
+//! Pluggable time sources.
+//!
+//! All measurements read the time from a global [`Clock`]. By default this is
+//! [`MonotonicClock`], built on [`std::time::Instant`]. [`set_clock`] replaces
+//! it with any other source of nanoseconds: the TSC-based `TscClock` on
+//! x86-64, the cheaper but millisecond-resolution [`CoarseClock`], the
+//! timer of an embedded HAL, or a [`MockClock`](crate::test::MockClock) that
+//! tests advance by hand.
+//!
+//! The clock is used by the timing macros, [`ScopeTimer`](crate::ScopeTimer),
+//! [`Stopwatch`](crate::Stopwatch) and [`TimedIteratorExt`](crate::TimedIteratorExt).
+//! Timestamps of records are still taken from the system time.
+//!
+//! On `wasm32-unknown-unknown`, where `std::time::Instant` panics, enable
+//! the `wasm` feature and call `install_wasm_clock!` at startup to read the
+//! browser's `performance.now()`.
+//!
+//! Without the `std` feature, there is no default clock and no `set_clock`.
+//! Embedded users register a counter with [`set_tick_source`] instead, such
+//! as the DWT cycle counter of a Cortex-M core, and [`time!`](crate::time)
+//! converts its ticks to durations. Until a tick source is registered, all
+//! durations are zero.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::clock::{self, Clock};
+//! use arbitime::time;
+//! use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+//!
+//! /// A clock that only advances in whole milliseconds.
+//! struct MillisClock(Instant);
+//!
+//! impl Clock for MillisClock {
+//!     fn now(&self) -> u64 {
+//!         self.0.elapsed().as_millis() as u64 * 1_000_000
+//!     }
+//! }
+//!
+//! clock::set_clock(MillisClock(Instant::now()));
+//! let (duration, _) = time!(std::thread::sleep(Duration::from_millis(3)));
+//! assert_eq!(duration.subsec_nanos() % 1_000_000, 0);
+//! clock::reset_clock();
+//! ```
+
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+#[cfg(feature = "std")]
+use std::sync::{Arc, OnceLock, PoisonError, RwLock, Weak};
+#[cfg(feature = "std")]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Whether the standard library has no clock, as on `wasm32-unknown-unknown`,
+/// where `Instant::now` and `SystemTime::now` panic.
+#[cfg(feature = "std")]
+const NO_STD_CLOCK: bool = cfg!(all(target_arch = "wasm32", target_os = "unknown"));
+
+/// The function registered with [`set_tick_source`], or null
+static TICKS: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+/// The number of ticks of [`TICKS`] per second
+static TICKS_PER_SECOND: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(feature = "std")]
+static CLOCK: RwLock<Option<Arc<dyn Clock>>> = RwLock::new(None);
+/// Whether a custom clock is installed, so the default path takes no lock
+#[cfg(feature = "std")]
+static CUSTOM: AtomicBool = AtomicBool::new(false);
+/// Number of threads with a clock of their own
+#[cfg(feature = "std")]
+static THREAD_CLOCKS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "std")]
+thread_local! {
+    /// A clock overriding the global one on this thread, see [`set_thread_clock`]
+    static THREAD_CLOCK: RefCell<Option<Arc<dyn Clock>>> = const { RefCell::new(None) };
+}
+
+/// A source of monotonic time.
+///
+/// Closures returning a `u64` implement it too.
+pub trait Clock: Send + Sync {
+    /// Returns the current time in nanoseconds since an arbitrary, fixed
+    /// point. The value must never decrease.
+    fn now(&self) -> u64;
+
+    /// Returns the current wall-clock time as a duration since the Unix
+    /// epoch, if this clock knows it.
+    ///
+    /// Only used for the timestamps of records on targets where
+    /// `SystemTime::now` is unavailable, such as `wasm32-unknown-unknown`.
+    fn unix_time(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> u64 + Send + Sync,
+{
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// The default clock, reading [`Instant::now`].
+///
+/// Its readings are nanoseconds since the clock was first used. On
+/// `wasm32-unknown-unknown`, where `Instant` is unavailable, it always reads
+/// zero; install a clock with `install_wasm_clock!` or [`set_clock`] there.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MonotonicClock;
+
+#[cfg(feature = "std")]
+impl Clock for MonotonicClock {
+    fn now(&self) -> u64 {
+        if NO_STD_CLOCK {
+            return 0;
+        }
+        static BASE: OnceLock<Instant> = OnceLock::new();
+        let base = *BASE.get_or_init(Instant::now);
+        u64::try_from(base.elapsed().as_nanos()).unwrap_or(u64::MAX)
+    }
+}
+
+/// A clock with millisecond resolution that is cheaper to read than
+/// [`MonotonicClock`], for very hot call sites where overhead matters more
+/// than precision.
+///
+/// On Linux and Android, [`new`](Self::new) reads `CLOCK_MONOTONIC_COARSE`,
+/// which the kernel updates at every timer tick, typically every 1 to 4 ms,
+/// and which is read without a system call. Elsewhere, and with
+/// [`cached`](Self::cached), a background thread stores the time in an atomic
+/// at a fixed interval, and reading the clock is a single atomic load. The
+/// thread exits once the clock and all its clones are dropped.
+///
+/// Durations are multiples of the [`resolution`](Self::resolution), so
+/// code that runs for less than one tick usually measures zero.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::clock::{self, CoarseClock};
+///
+/// let coarse = CoarseClock::new();
+/// println!("Resolution: {:?}", coarse.resolution());
+/// clock::set_clock(coarse);
+/// let (duration, _) = arbitime::time!(std::thread::sleep(std::time::Duration::from_millis(20)));
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(duration.as_millis() >= 10);
+/// clock::reset_clock();
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct CoarseClock {
+    source: CoarseSource,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+enum CoarseSource {
+    /// `CLOCK_MONOTONIC_COARSE`
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Kernel,
+    /// A reading of [`MonotonicClock`] updated by a background thread
+    Cached { now: Arc<AtomicU64>, interval: Duration },
+}
+
+#[cfg(feature = "std")]
+impl CoarseClock {
+    /// Creates a coarse clock, reading `CLOCK_MONOTONIC_COARSE` where it is
+    /// available, and otherwise a timestamp cached every millisecond.
+    pub fn new() -> Self {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if coarse_sys::now().is_some() {
+            return CoarseClock {
+                source: CoarseSource::Kernel,
+            };
+        }
+        Self::cached(Duration::from_millis(1))
+    }
+
+    /// Creates a clock reading a timestamp that a background thread updates
+    /// every `interval`.
+    pub fn cached(interval: Duration) -> Self {
+        let now = Arc::new(AtomicU64::new(MonotonicClock.now()));
+        let shared: Weak<AtomicU64> = Arc::downgrade(&now);
+        let updater = move || {
+            while let Some(now) = shared.upgrade() {
+                now.store(MonotonicClock.now(), Ordering::Relaxed);
+                drop(now);
+                std::thread::sleep(interval);
+            }
+        };
+        // If the thread cannot be spawned, the clock stands still
+        let _ = std::thread::Builder::new().name("arbitime-coarse-clock".into()).spawn(updater);
+        CoarseClock {
+            source: CoarseSource::Cached { now, interval },
+        }
+    }
+
+    /// Returns how often the clock advances.
+    pub fn resolution(&self) -> Duration {
+        match &self.source {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            CoarseSource::Kernel => coarse_sys::resolution().unwrap_or(Duration::from_millis(4)),
+            CoarseSource::Cached { interval, .. } => *interval,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for CoarseClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for CoarseClock {
+    fn now(&self) -> u64 {
+        match &self.source {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            CoarseSource::Kernel => coarse_sys::now().unwrap_or(0),
+            CoarseSource::Cached { now, .. } => now.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+mod coarse_sys {
+    use std::os::raw::{c_int, c_long};
+    use std::time::Duration;
+
+    const CLOCK_MONOTONIC_COARSE: c_int = 6;
+
+    #[repr(C)]
+    struct Timespec {
+        tv_sec: c_long,
+        tv_nsec: c_long,
+    }
+
+    unsafe extern "C" {
+        fn clock_gettime(clock: c_int, time: *mut Timespec) -> c_int;
+        fn clock_getres(clock: c_int, resolution: *mut Timespec) -> c_int;
+    }
+
+    fn read(f: unsafe extern "C" fn(c_int, *mut Timespec) -> c_int) -> Option<Duration> {
+        let mut time = Timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `time` is a valid, writable `timespec`
+        if unsafe { f(CLOCK_MONOTONIC_COARSE, &mut time) } != 0 {
+            return None;
+        }
+        Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+    }
+
+    /// Reads `CLOCK_MONOTONIC_COARSE` in nanoseconds, or `None` if the kernel
+    /// does not provide it.
+    pub(super) fn now() -> Option<u64> {
+        read(clock_gettime).map(|time| u64::try_from(time.as_nanos()).unwrap_or(u64::MAX))
+    }
+
+    pub(super) fn resolution() -> Option<Duration> {
+        read(clock_getres)
+    }
+}
+
+/// A clock reading the time stamp counter of x86-64 processors with
+/// `rdtsc`, for timing operations of a few nanoseconds.
+///
+/// Reading [`Instant`] costs a `clock_gettime` call, about 20 ns, which
+/// dominates the measurement of nanosecond-scale code. Reading the time stamp
+/// counter takes a single instruction. [`calibrate`](Self::calibrate) measures
+/// its frequency against [`Instant`], and readings are converted to
+/// nanoseconds with a multiplication and a shift.
+///
+/// Only processors with an invariant TSC, which ticks at a constant rate in
+/// all power states and is synchronized across cores, are supported; that is
+/// every x86-64 processor of the last decade, though some hypervisors hide
+/// the flag. `rdtsc` is not serializing, so the processor may reorder it with
+/// a few neighbouring instructions.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::clock::{self, TscClock};
+///
+/// if let Some(tsc) = TscClock::calibrate() {
+///     println!("TSC at {:.2} GHz", tsc.ticks_per_second() as f64 / 1e9);
+///     clock::set_clock(tsc);
+/// }
+/// let (duration, _) = arbitime::time!(std::hint::black_box(2 + 2));
+/// clock::reset_clock();
+/// ```
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+#[derive(Debug, Clone, Copy)]
+pub struct TscClock {
+    /// The counter at calibration, so that readings start near zero
+    base: u64,
+    /// Nanoseconds per tick, as a fixed-point number with 32 fractional bits
+    scale: u64,
+    ticks_per_second: u64,
+}
+
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+impl TscClock {
+    /// Measures the frequency of the time stamp counter over 10 ms, or
+    /// returns `None` if the processor has no invariant TSC.
+    pub fn calibrate() -> Option<Self> {
+        Self::calibrate_for(Duration::from_millis(10))
+    }
+
+    /// Measures the frequency of the time stamp counter over `duration`, or
+    /// returns `None` if the processor has no invariant TSC.
+    ///
+    /// Longer calibrations give a more accurate frequency; the error is about
+    /// 100 ns divided by `duration`.
+    pub fn calibrate_for(duration: Duration) -> Option<Self> {
+        if !has_invariant_tsc() {
+            return None;
+        }
+        let (start, start_ticks) = (Instant::now(), rdtsc());
+        while start.elapsed() < duration {
+            std::hint::spin_loop();
+        }
+        let (elapsed, ticks) = (start.elapsed(), rdtsc().wrapping_sub(start_ticks));
+        let ticks_per_second = u64::try_from(u128::from(ticks) * 1_000_000_000 / elapsed.as_nanos().max(1)).ok()?;
+        if ticks_per_second == 0 {
+            return None;
+        }
+        Some(TscClock {
+            base: start_ticks,
+            scale: u64::try_from((1_000_000_000u128 << 32) / u128::from(ticks_per_second)).ok()?,
+            ticks_per_second,
+        })
+    }
+
+    /// Returns the measured frequency of the time stamp counter.
+    pub fn ticks_per_second(&self) -> u64 {
+        self.ticks_per_second
+    }
+}
+
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+impl Clock for TscClock {
+    fn now(&self) -> u64 {
+        let ticks = rdtsc().saturating_sub(self.base);
+        u64::try_from((u128::from(ticks) * u128::from(self.scale)) >> 32).unwrap_or(u64::MAX)
+    }
+}
+
+/// Reads the time stamp counter.
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+fn rdtsc() -> u64 {
+    // SAFETY: `rdtsc` is available on every x86-64 processor
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Returns whether the time stamp counter ticks at a constant rate in all
+/// power states, as reported by CPUID leaf `0x8000_0007`.
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+fn has_invariant_tsc() -> bool {
+    use core::arch::x86_64::__cpuid;
+    if __cpuid(0x8000_0000).eax < 0x8000_0007 {
+        return false;
+    }
+    __cpuid(0x8000_0007).edx & (1 << 8) != 0
+}
+
+/// Replaces the global clock that all measurements read.
+///
+/// Measurements that are running while the clock is replaced report
+/// meaningless durations.
+#[cfg(feature = "std")]
+pub fn set_clock(clock: impl Clock + 'static) {
+    *CLOCK.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(clock));
+    CUSTOM.store(true, Ordering::Release);
+}
+
+/// Registers a monotonic counter as the time source, for targets without the
+/// standard library.
+///
+/// `ticks` is called at the start and end of every measurement and must never
+/// decrease; a counter that wraps around, like the 32-bit DWT cycle counter,
+/// needs to be extended to 64 bits first. Its readings are converted to
+/// nanoseconds with `ticks_per_second`, e.g. the core clock frequency for a
+/// cycle counter. A clock installed with [`set_clock`] takes precedence.
+///
+/// # Panics
+///
+/// If `ticks_per_second` is zero.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::{clock, time};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::time::Duration;
+///
+/// // Stands in for a hardware counter running at 64 MHz
+/// static CYCLES: AtomicU64 = AtomicU64::new(0);
+///
+/// fn cycles() -> u64 {
+///     CYCLES.load(Ordering::Relaxed)
+/// }
+///
+/// clock::set_tick_source(cycles, 64_000_000);
+/// let (duration, _) = time!(CYCLES.fetch_add(64_000, Ordering::Relaxed));
+/// # #[cfg(not(feature = "disable"))]
+/// assert_eq!(duration, Duration::from_millis(1));
+/// clock::reset_clock();
+/// ```
+pub fn set_tick_source(ticks: fn() -> u64, ticks_per_second: u64) {
+    assert!(ticks_per_second > 0, "a tick source needs a non-zero frequency");
+    TICKS_PER_SECOND.store(ticks_per_second, Ordering::Release);
+    TICKS.store(ticks as *mut (), Ordering::Release);
+}
+
+/// Reads the tick source registered with [`set_tick_source`], in nanoseconds.
+fn read_ticks() -> Option<u64> {
+    let ticks = TICKS.load(Ordering::Acquire);
+    if ticks.is_null() {
+        return None;
+    }
+    // SAFETY: non-null values of `TICKS` are only ever stored by
+    // `set_tick_source`, from a `fn() -> u64`
+    let ticks = unsafe { core::mem::transmute::<*mut (), fn() -> u64>(ticks) };
+    let per_second = u128::from(TICKS_PER_SECOND.load(Ordering::Acquire));
+    let nanos = u128::from(ticks()) * 1_000_000_000 / per_second;
+    Some(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+/// Removes the clock installed with [`set_clock`] and the tick source
+/// registered with [`set_tick_source`], restoring the default
+/// [`MonotonicClock`].
+pub fn reset_clock() {
+    TICKS.store(core::ptr::null_mut(), Ordering::Release);
+    #[cfg(feature = "std")]
+    {
+        CUSTOM.store(false, Ordering::Release);
+        *CLOCK.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// Overrides the global clock on the current thread only, returning the
+/// previous override. Used by [`MockClock::install`](crate::test::MockClock::install).
+#[cfg(feature = "std")]
+pub(crate) fn set_thread_clock(clock: Option<Arc<dyn Clock>>) -> Option<Arc<dyn Clock>> {
+    let installed = clock.is_some();
+    let previous = THREAD_CLOCK.with(|local| local.replace(clock));
+    match (previous.is_some(), installed) {
+        (false, true) => {
+            THREAD_CLOCKS.fetch_add(1, Ordering::AcqRel);
+        }
+        (true, false) => {
+            THREAD_CLOCKS.fetch_sub(1, Ordering::AcqRel);
+        }
+        _ => {}
+    }
+    previous
+}
+
+/// Reads the clock of the current thread, in nanoseconds: the global clock
+/// unless a thread-local override is installed.
+#[cfg(feature = "std")]
+pub fn now() -> u64 {
+    if THREAD_CLOCKS.load(Ordering::Acquire) > 0 {
+        let local = THREAD_CLOCK.with(|local| local.borrow().clone());
+        if let Some(clock) = local {
+            return clock.now();
+        }
+    }
+    if CUSTOM.load(Ordering::Acquire) {
+        let clock = CLOCK.read().unwrap_or_else(PoisonError::into_inner).clone();
+        if let Some(clock) = clock {
+            return clock.now();
+        }
+    }
+    read_ticks().unwrap_or_else(|| MonotonicClock.now())
+}
+
+/// Reads the tick source registered with [`set_tick_source`], in
+/// nanoseconds, or zero if there is none.
+#[cfg(not(feature = "std"))]
+pub fn now() -> u64 {
+    read_ticks().unwrap_or(0)
+}
+
+/// Reads the real, monotonic time in nanoseconds, ignoring mock clocks
+/// installed on the thread, except where the standard library has no clock.
+#[cfg(feature = "std")]
+pub(crate) fn real_now() -> u64 {
+    if NO_STD_CLOCK {
+        return now();
+    }
+    MonotonicClock.now()
+}
+
+/// Returns the current wall-clock time, for the timestamps of records.
+#[cfg(feature = "std")]
+pub(crate) fn system_time() -> SystemTime {
+    if !NO_STD_CLOCK {
+        return SystemTime::now();
+    }
+    let clock = CLOCK.read().unwrap_or_else(PoisonError::into_inner).clone();
+    let since_epoch = clock.and_then(|clock| clock.unix_time()).unwrap_or_default();
+    UNIX_EPOCH + since_epoch
+}
+
+/// Returns the time elapsed since `start`, a reading of [`now`].
+pub fn elapsed_since(start: u64) -> Duration {
+    Duration::from_nanos(now().saturating_sub(start))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monotonic_clock_advances() {
+        let start = MonotonicClock.now();
+        std::thread::sleep(Duration::from_millis(2));
+        let elapsed = MonotonicClock.now() - start;
+        assert!(elapsed >= 2_000_000, "{}", elapsed);
+    }
+
+    #[test]
+    fn coarse_clocks_advance() {
+        for coarse in [CoarseClock::new(), CoarseClock::cached(Duration::from_millis(1))] {
+            let start = coarse.now();
+            std::thread::sleep(Duration::from_millis(30));
+            let elapsed = coarse.now() - start;
+            assert!(elapsed >= 10_000_000, "{}", elapsed);
+            assert!(coarse.resolution() <= Duration::from_millis(10), "{:?}", coarse.resolution());
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn tsc_clock_matches_instant() {
+        let Some(tsc) = TscClock::calibrate_for(Duration::from_millis(20)) else {
+            return;
+        };
+        assert!(tsc.ticks_per_second() > 1_000_000, "{}", tsc.ticks_per_second());
+        let (start, start_nanos) = (Instant::now(), tsc.now());
+        std::thread::sleep(Duration::from_millis(20));
+        let (expected, elapsed) = (start.elapsed().as_nanos() as i128, i128::from(tsc.now() - start_nanos));
+        // Within 5% of the time measured by `Instant`
+        assert!((elapsed - expected).abs() < expected / 20, "{} vs {}", elapsed, expected);
+    }
+}
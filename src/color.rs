@@ -0,0 +1,149 @@
+//! Colored durations in terminal output (`color` feature).
+//!
+//! Durations in the default text output are colored by how slow they are:
+//! green below the "fast" threshold, yellow up to the "slow" threshold and
+//! red above it. Thresholds default to [`DEFAULT_FAST`] and [`DEFAULT_SLOW`]
+//! and can be changed with [`set_thresholds`].
+//!
+//! By default ([`ColorMode::Auto`]), colors are only used when stderr is a
+//! terminal and the `NO_COLOR` environment variable is not set. JSON output
+//! and [`WriterSink`](crate::sink::WriterSink)s are never colored.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::color::{self, ColorMode};
+//! use arbitime::log_time;
+//! use std::time::Duration;
+//!
+//! color::set_thresholds(Duration::from_micros(100), Duration::from_millis(5));
+//! color::set_mode(ColorMode::Always);
+//!
+//! log_time!("sum" => (1..=1000).sum::<u32>()); // Prints the duration in green
+//! ```
+
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::HumanDuration;
+
+/// The default upper bound of durations shown in green.
+pub const DEFAULT_FAST: Duration = Duration::from_millis(10);
+
+/// The default lower bound of durations shown in red.
+pub const DEFAULT_SLOW: Duration = Duration::from_millis(100);
+
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+static MODE: AtomicU8 = AtomicU8::new(ColorMode::Auto as u8);
+static FAST_NANOS: AtomicU64 = AtomicU64::new(DEFAULT_FAST.as_nanos() as u64);
+static SLOW_NANOS: AtomicU64 = AtomicU64::new(DEFAULT_SLOW.as_nanos() as u64);
+
+/// When to color durations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ColorMode {
+    /// Color when stderr is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    /// Always color
+    Always,
+    /// Never color
+    Never,
+}
+
+/// Sets when durations are colored. [`ColorMode::Auto`] by default.
+pub fn set_mode(mode: ColorMode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Returns when durations are colored; see [`set_mode`].
+pub fn mode() -> ColorMode {
+    match MODE.load(Ordering::Relaxed) {
+        1 => ColorMode::Always,
+        2 => ColorMode::Never,
+        _ => ColorMode::Auto,
+    }
+}
+
+/// Returns `true` if durations in text output are currently colored.
+pub fn enabled() -> bool {
+    static TERMINAL: OnceLock<bool> = OnceLock::new();
+    match mode() {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => *TERMINAL.get_or_init(|| {
+            std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stderr().is_terminal()
+        }),
+    }
+}
+
+/// Sets the durations below which timings are green and above which they are
+/// red. Durations in between are yellow.
+///
+/// The thresholds are swapped if `fast` is greater than `slow`.
+pub fn set_thresholds(fast: Duration, slow: Duration) {
+    let nanos = |duration: Duration| u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+    FAST_NANOS.store(nanos(fast.min(slow)), Ordering::Relaxed);
+    SLOW_NANOS.store(nanos(fast.max(slow)), Ordering::Relaxed);
+}
+
+/// Returns the `(fast, slow)` thresholds; see [`set_thresholds`].
+pub fn thresholds() -> (Duration, Duration) {
+    (
+        Duration::from_nanos(FAST_NANOS.load(Ordering::Relaxed)),
+        Duration::from_nanos(SLOW_NANOS.load(Ordering::Relaxed)),
+    )
+}
+
+/// A [`HumanDuration`] wrapped in the ANSI color for its speed.
+///
+/// Always colored, regardless of [`mode`]. Format options such as precision
+/// are passed on to [`HumanDuration`].
+///
+/// ```rust
+/// use arbitime::color::ColoredDuration;
+/// use std::time::Duration;
+///
+/// let colored = ColoredDuration(Duration::from_secs(2)).to_string();
+/// assert_eq!(colored, "\x1b[31m2 s\x1b[0m");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ColoredDuration(pub Duration);
+
+impl fmt::Display for ColoredDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (fast, slow) = thresholds();
+        let color = if self.0 < fast {
+            GREEN
+        } else if self.0 < slow {
+            YELLOW
+        } else {
+            RED
+        };
+        f.write_str(color)?;
+        fmt::Display::fmt(&HumanDuration(self.0), f)?;
+        f.write_str(RESET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_by_threshold() {
+        let (fast, slow) = thresholds();
+        let paint = |duration| ColoredDuration(duration).to_string();
+        assert_eq!(paint(fast / 2), format!("{}{}{}", GREEN, HumanDuration(fast / 2), RESET));
+        assert!(paint(fast).starts_with(YELLOW));
+        assert!(paint(slow).starts_with(RED));
+        assert_eq!(format!("{:.1}", ColoredDuration(Duration::from_secs(2))), "\x1b[31m2.0 s\x1b[0m");
+    }
+}
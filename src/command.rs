@@ -0,0 +1,80 @@
+//! Timing child processes.
+
+use std::io;
+use std::process::{Command, ExitStatus, Output};
+
+use crate::cpu::{CpuTime, Timings};
+
+/// Extension trait that times the external programs run by a [`Command`].
+///
+/// The returned [`Timings`] hold the wall-clock time from spawning the child
+/// until it was waited for, read from the same [`clock`](crate::clock) as the
+/// timing macros, and on Unix the user and system time of the child, from
+/// `getrusage(RUSAGE_CHILDREN)`. Children reaped by other threads in the
+/// meantime are counted too. On other platforms, and with the `disable`
+/// feature, the CPU times are zero.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::TimedCommandExt;
+/// use std::process::Command;
+///
+/// # #[cfg(unix)]
+/// # {
+/// let (timings, output) = Command::new("echo").arg("hello").timed_output().unwrap();
+/// assert_eq!(output.stdout, b"hello\n");
+/// println!("echo: {}", timings); // "echo: wall: 1.2 ms, user: 0 ns, system: 812 µs"
+/// # }
+/// ```
+pub trait TimedCommandExt {
+    /// Runs the command like [`Command::output`], collecting its output, and
+    /// returns how long it took with the output.
+    fn timed_output(&mut self) -> io::Result<(Timings, Output)>;
+
+    /// Runs the command like [`Command::status`], and returns how long it
+    /// took with its exit status.
+    fn timed_status(&mut self) -> io::Result<(Timings, ExitStatus)>;
+}
+
+impl TimedCommandExt for Command {
+    fn timed_output(&mut self) -> io::Result<(Timings, Output)> {
+        measure(|| self.output())
+    }
+
+    fn timed_status(&mut self) -> io::Result<(Timings, ExitStatus)> {
+        measure(|| self.status())
+    }
+}
+
+/// Runs a child process to completion and measures it.
+fn measure<T>(run: impl FnOnce() -> io::Result<T>) -> io::Result<(Timings, T)> {
+    if cfg!(feature = "disable") {
+        return Ok((Timings::default(), run()?));
+    }
+    let children = CpuTime::children();
+    let start = crate::clock::now();
+    let result = run()?;
+    let wall = crate::clock::elapsed_since(start);
+    Ok((Timings::new(wall, CpuTime::children() - children), result))
+}
+
+#[cfg(all(test, unix, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn times_child_processes() {
+        let (timings, output) = Command::new("sh").args(["-c", "echo done"]).timed_output().unwrap();
+        assert_eq!(output.stdout, b"done\n");
+        assert!(timings.wall > Duration::ZERO);
+
+        let (timings, status) = Command::new("sleep").arg("0.05").timed_status().unwrap();
+        assert!(status.success());
+        assert!(timings.wall >= Duration::from_millis(50), "{}", timings);
+        assert!(timings.cpu() < timings.wall, "{}", timings);
+
+        assert!(Command::new("arbitime-missing-program").timed_status().is_err());
+    }
+}
@@ -0,0 +1,386 @@
+//! Process-wide configuration in one place.
+//!
+//! [`Config::builder`] collects the settings that are otherwise spread over
+//! [`set_sink`](crate::set_sink), [`set_template`](crate::set_template),
+//! [`filter::set_filter`](crate::filter::set_filter), the
+//! [`format`](crate::format) switches and others, and installs them at once,
+//! typically at the top of `main`. All macros read these global settings, so
+//! there is no need to repeat options at every call site. Settings that are
+//! not given to the builder keep their current value.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::sink::StdoutSink;
+//! use arbitime::{log_time, Config};
+//! use std::time::Duration;
+//!
+//! Config::builder()
+//!     .sink(StdoutSink)
+//!     .template("[{thread}] {label}: {duration:.1ms}")
+//!     .threshold(Duration::from_millis(1))
+//!     .show_location(true)
+//!     .install()
+//!     .unwrap();
+//!
+//! log_time!("quick" => (1..=100).sum::<u32>()); // Not logged, below 1 ms
+//! ```
+//!
+//! # Per-label overrides
+//!
+//! Labels matching a pattern can get their own threshold and level with
+//! [`label`], e.g. to quiet a noisy family of labels without touching its
+//! call sites. Patterns match whole labels, with `*` standing for any run of
+//! characters, as in [`filter`](crate::filter). When several patterns set
+//! the same setting for a label, the last registered one wins.
+//!
+//! ```rust
+//! use arbitime::{config, Level};
+//! use std::time::Duration;
+//!
+//! // Only log database timings over 100 ms, as warnings
+//! config::label("db.*").threshold(Duration::from_millis(100)).level(Level::Warn);
+//! ```
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+#[cfg(feature = "color")]
+use crate::color::{self, ColorMode};
+use crate::filter::{self, Filter};
+use crate::sink::{self, TimeSink};
+use crate::template::{self, Template, TemplateError};
+use crate::{Level, TimingRecord};
+
+static THRESHOLD_NANOS: AtomicU64 = AtomicU64::new(0);
+static OVERRIDES: RwLock<Vec<Override>> = RwLock::new(Vec::new());
+static HAS_OVERRIDES: AtomicBool = AtomicBool::new(false);
+
+/// The settings of the labels matching a pattern.
+#[derive(Debug, Clone)]
+struct Override {
+    pattern: String,
+    threshold: Option<Duration>,
+    level: Option<Level>,
+}
+
+/// Sets the minimum duration of the timings that are logged. Zero by default.
+///
+/// Shorter timings are still recorded in the [`registry`](crate::registry),
+/// but are not sent to the sink. Per-call thresholds, such as
+/// `log_time!(threshold: ...)`, apply on top of it.
+pub fn set_threshold(threshold: Duration) {
+    let nanos = u64::try_from(threshold.as_nanos()).unwrap_or(u64::MAX);
+    THRESHOLD_NANOS.store(nanos, Ordering::Relaxed);
+}
+
+/// Returns the minimum duration of logged timings; see [`set_threshold`].
+pub fn threshold() -> Duration {
+    Duration::from_nanos(THRESHOLD_NANOS.load(Ordering::Relaxed))
+}
+
+/// Returns the settings of the labels matching `pattern`, to override the
+/// global ones; see [Per-label overrides](self#per-label-overrides).
+///
+/// Each setting is applied as soon as it is set.
+pub fn label(pattern: &str) -> LabelConfig {
+    let mut overrides = OVERRIDES.write().unwrap_or_else(PoisonError::into_inner);
+    if !overrides.iter().any(|o| o.pattern == pattern) {
+        overrides.push(Override {
+            pattern: pattern.to_string(),
+            threshold: None,
+            level: None,
+        });
+        HAS_OVERRIDES.store(true, Ordering::Relaxed);
+    }
+    LabelConfig {
+        pattern: pattern.to_string(),
+    }
+}
+
+/// Removes all per-label overrides.
+pub fn clear_labels() {
+    OVERRIDES.write().unwrap_or_else(PoisonError::into_inner).clear();
+    HAS_OVERRIDES.store(false, Ordering::Relaxed);
+}
+
+/// The settings of the labels matching a pattern, created by [`label`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelConfig {
+    pattern: String,
+}
+
+impl LabelConfig {
+    /// Only logs matching timings that take at least `threshold`, instead of
+    /// the global [`threshold`].
+    pub fn threshold(self, threshold: Duration) -> Self {
+        self.update(|o| o.threshold = Some(threshold));
+        self
+    }
+
+    /// Logs matching timings at `level`, instead of the level given at their
+    /// call site.
+    pub fn level(self, level: Level) -> Self {
+        self.update(|o| o.level = Some(level));
+        self
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Override)) {
+        let mut overrides = OVERRIDES.write().unwrap_or_else(PoisonError::into_inner);
+        match overrides.iter_mut().find(|o| o.pattern == self.pattern) {
+            Some(entry) => f(entry),
+            // Re-register the pattern if the overrides were cleared in between
+            None => {
+                let mut entry = Override {
+                    pattern: self.pattern.clone(),
+                    threshold: None,
+                    level: None,
+                };
+                f(&mut entry);
+                overrides.push(entry);
+                HAS_OVERRIDES.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Applies the threshold and level of the label of `record`, returning
+/// `false` if the record is below its threshold.
+pub(crate) fn apply(record: &mut TimingRecord) -> bool {
+    let mut threshold = None;
+    if HAS_OVERRIDES.load(Ordering::Relaxed)
+        && let Some(label) = record.label.as_deref()
+    {
+        let overrides = OVERRIDES.read().unwrap_or_else(PoisonError::into_inner);
+        for entry in overrides.iter().filter(|o| filter::matches(&o.pattern, label)) {
+            threshold = entry.threshold.or(threshold);
+            if let Some(level) = entry.level {
+                record.level = level;
+            }
+        }
+    }
+    record.duration >= threshold.unwrap_or_else(self::threshold)
+}
+
+/// A set of global settings, installed with [`Config::install`].
+///
+/// Created with [`Config::builder`].
+#[derive(Clone, Default)]
+pub struct Config {
+    enabled: Option<bool>,
+    sink: Option<Arc<dyn TimeSink>>,
+    template: Option<Template>,
+    threshold: Option<Duration>,
+    filter: Option<Filter>,
+    #[cfg(feature = "color")]
+    color: Option<ColorMode>,
+    show_location: Option<bool>,
+    show_thread: Option<bool>,
+    show_timestamp: Option<bool>,
+    show_percentages: Option<bool>,
+}
+
+impl Config {
+    /// Starts a configuration that changes no settings.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Applies the settings of this configuration, leaving the others as
+    /// they are.
+    pub fn install(&self) {
+        if let Some(enabled) = self.enabled {
+            crate::set_enabled(enabled);
+        }
+        if let Some(sink) = &self.sink {
+            sink::install(Arc::clone(sink));
+        }
+        if let Some(template) = &self.template {
+            template::install(template.clone());
+        }
+        if let Some(threshold) = self.threshold {
+            set_threshold(threshold);
+        }
+        if let Some(filter) = &self.filter {
+            filter::set_filter(filter.clone());
+        }
+        #[cfg(feature = "color")]
+        if let Some(mode) = self.color {
+            color::set_mode(mode);
+        }
+        if let Some(show) = self.show_location {
+            crate::format::set_show_location(show);
+        }
+        if let Some(show) = self.show_thread {
+            crate::format::set_show_thread(show);
+        }
+        if let Some(show) = self.show_timestamp {
+            crate::format::set_show_timestamp(show);
+        }
+        if let Some(show) = self.show_percentages {
+            crate::nesting::set_show_percentages(show);
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Config");
+        debug
+            .field("enabled", &self.enabled)
+            .field("sink", &self.sink.as_ref().map(|_| "TimeSink"))
+            .field("template", &self.template)
+            .field("threshold", &self.threshold)
+            .field("filter", &self.filter);
+        #[cfg(feature = "color")]
+        debug.field("color", &self.color);
+        debug
+            .field("show_location", &self.show_location)
+            .field("show_thread", &self.show_thread)
+            .field("show_timestamp", &self.show_timestamp)
+            .field("show_percentages", &self.show_percentages)
+            .finish()
+    }
+}
+
+/// Builds a [`Config`].
+#[derive(Debug, Default)]
+#[must_use = "the configuration is only applied by `install`"]
+pub struct ConfigBuilder {
+    config: Config,
+    template: Option<String>,
+}
+
+impl ConfigBuilder {
+    /// Turns timing output on or off; see [`set_enabled`](crate::set_enabled).
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.config.enabled = Some(enabled);
+        self
+    }
+
+    /// Sends all timings to `sink`; see [`set_sink`](crate::set_sink).
+    pub fn sink(mut self, sink: impl TimeSink + 'static) -> Self {
+        self.config.sink = Some(Arc::new(sink));
+        self
+    }
+
+    /// Formats text messages with a template such as
+    /// `"{label}: {duration:.2ms}"`; see [`template`](crate::template).
+    ///
+    /// The template is parsed by [`build`](Self::build) or
+    /// [`install`](Self::install).
+    pub fn template(mut self, template: &str) -> Self {
+        self.template = Some(template.to_string());
+        self
+    }
+
+    /// Only logs timings that take at least `threshold`; see [`set_threshold`].
+    pub fn threshold(mut self, threshold: Duration) -> Self {
+        self.config.threshold = Some(threshold);
+        self
+    }
+
+    /// Only measures the labels enabled by `filter`; see [`filter`](crate::filter).
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.config.filter = Some(filter);
+        self
+    }
+
+    /// Sets when durations are colored; see [`color`](crate::color).
+    #[cfg(feature = "color")]
+    pub fn color(mut self, mode: ColorMode) -> Self {
+        self.config.color = Some(mode);
+        self
+    }
+
+    /// Prefixes messages with their call site; see
+    /// [`format::set_show_location`](crate::format::set_show_location).
+    pub fn show_location(mut self, show: bool) -> Self {
+        self.config.show_location = Some(show);
+        self
+    }
+
+    /// Prefixes messages with the thread name; see
+    /// [`format::set_show_thread`](crate::format::set_show_thread).
+    pub fn show_thread(mut self, show: bool) -> Self {
+        self.config.show_thread = Some(show);
+        self
+    }
+
+    /// Prefixes messages with the time they finished at; see
+    /// [`format::set_show_timestamp`](crate::format::set_show_timestamp).
+    pub fn show_timestamp(mut self, show: bool) -> Self {
+        self.config.show_timestamp = Some(show);
+        self
+    }
+
+    /// Shows nested timings as a share of their parent; see
+    /// [`nesting::set_show_percentages`](crate::nesting::set_show_percentages).
+    pub fn show_percentages(mut self, show: bool) -> Self {
+        self.config.show_percentages = Some(show);
+        self
+    }
+
+    /// Finishes the configuration without installing it.
+    ///
+    /// Returns an error if the template is invalid.
+    pub fn build(self) -> Result<Config, TemplateError> {
+        let mut config = self.config;
+        if let Some(template) = self.template {
+            config.template = Some(Template::parse(&template)?);
+        }
+        Ok(config)
+    }
+
+    /// Finishes the configuration and installs it.
+    ///
+    /// Returns an error, and changes no settings, if the template is invalid.
+    pub fn install(self) -> Result<(), TemplateError> {
+        self.build().map(|config| config.install())
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_configurations() {
+        let config = Config::builder()
+            .template("{label}: {duration}")
+            .threshold(Duration::from_millis(3))
+            .show_thread(false)
+            .build()
+            .unwrap();
+        assert_eq!(config.template, Some(Template::parse("{label}: {duration}").unwrap()));
+        assert_eq!((config.threshold, config.show_thread, config.enabled), (Some(Duration::from_millis(3)), Some(false), None));
+        assert!(format!("{:?}", config).starts_with("Config { enabled: None, sink: None, template: Some("));
+
+        let error = Config::builder().template("{nope}").install().unwrap_err();
+        assert!(error.to_string().starts_with("invalid template `{nope}`"));
+    }
+
+    #[test]
+    fn overrides_labels() {
+        let record = |label: &str, millis| {
+            TimingRecord::new(Some(label.into()), Duration::from_millis(millis), file!(), line!())
+        };
+        label("config-db.*").threshold(Duration::from_millis(100)).level(Level::Warn);
+        label("config-db.cache").level(Level::Debug);
+
+        let mut query = record("config-db.query", 150);
+        assert!(apply(&mut query));
+        assert_eq!(query.level, Level::Warn);
+        assert!(!apply(&mut record("config-db.query", 50)));
+
+        let mut cache = record("config-db.cache", 120);
+        assert!(apply(&mut cache));
+        assert_eq!(cache.level, Level::Debug);
+
+        let mut other = record("config-render", 1);
+        assert!(apply(&mut other));
+        assert_eq!(other.level, Level::Info);
+    }
+}
@@ -0,0 +1,95 @@
+//! Correlation IDs attached to timing records.
+//!
+//! [`with_context`] runs a closure with a correlation ID, such as a request
+//! or job ID. Every timing recorded inside it on the same thread carries the
+//! ID in its [`context`](crate::TimingRecord::context), which is printed
+//! before the message (`[req-123] parse - Execution time: 12 ms`), written as
+//! the `"context"` field of JSON lines and available to templates as
+//! `{context}`. Grepping the logs for the ID then reconstructs the latency
+//! breakdown of a single request.
+//!
+//! Contexts nest: the innermost one applies, and the outer one is restored
+//! when the inner closure returns.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{context, format_time, with_context};
+//!
+//! let message = with_context("req-123", || {
+//!     assert_eq!(context::current().as_deref(), Some("req-123"));
+//!     let (message, _) = format_time!("parse" => "42".parse::<u32>());
+//!     message
+//! });
+//! # #[cfg(not(feature = "disable"))]
+//! assert!(message.starts_with("[req-123] parse - Execution time: "));
+//! assert_eq!(context::current(), None);
+//! ```
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+thread_local! {
+    /// The correlation ID of the innermost context on this thread.
+    static CURRENT: RefCell<Option<Arc<str>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with the correlation ID `id` attached to every timing recorded
+/// on this thread until it returns, and returns its result.
+pub fn with_context<T>(id: impl Into<Arc<str>>, f: impl FnOnce() -> T) -> T {
+    let _guard = enter(id);
+    f()
+}
+
+/// Attaches the correlation ID `id` to every timing recorded on this thread
+/// until the returned guard is dropped, for code that cannot be wrapped in a
+/// closure.
+#[must_use = "the context ends when the guard is dropped"]
+pub fn enter(id: impl Into<Arc<str>>) -> ContextGuard {
+    let previous = CURRENT.with_borrow_mut(|current| current.replace(id.into()));
+    ContextGuard { previous }
+}
+
+/// Returns the correlation ID of the innermost context on this thread, if
+/// any.
+pub fn current() -> Option<Arc<str>> {
+    CURRENT.try_with(|current| current.borrow().clone()).ok().flatten()
+}
+
+/// Restores the previous context of the thread when dropped.
+///
+/// Returned by [`enter`].
+#[derive(Debug)]
+#[must_use = "the context ends when the guard is dropped"]
+pub struct ContextGuard {
+    previous: Option<Arc<str>>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        let _ = CURRENT.try_with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TimingRecord;
+    use std::time::Duration;
+
+    #[test]
+    fn nests_contexts() {
+        let record = || TimingRecord::new(Some("context-step".into()), Duration::from_millis(3), "", 0);
+        with_context("req-1", || {
+            assert_eq!(record().context.as_deref(), Some("req-1"));
+            {
+                let _job = enter(String::from("job-7"));
+                assert_eq!(record().to_string(), "[job-7] context-step - Execution time: 3 ms");
+            }
+            assert_eq!(current().as_deref(), Some("req-1"));
+        });
+        assert_eq!(record().context, None);
+        assert_eq!(record().to_string(), "context-step - Execution time: 3 ms");
+    }
+}
@@ -0,0 +1,417 @@
+//! CPU time measurement.
+//!
+//! Wall-clock time alone does not tell whether a slow block was busy computing
+//! or just waiting on I/O, locks or the scheduler. The functions here read the
+//! CPU time consumed by the whole process, as used by
+//! [`cpu_time!`](crate::cpu_time) and [`time_all!`](crate::time_all), and by
+//! the current thread, as used by [`thread_cpu_time!`](crate::thread_cpu_time).
+//!
+//! CPU time is read with `clock_gettime(CLOCK_PROCESS_CPUTIME_ID)`,
+//! `clock_gettime(CLOCK_THREAD_CPUTIME_ID)` and `getrusage` on Linux, Android,
+//! macOS, iOS and FreeBSD, and with `GetProcessTimes` and `GetThreadTimes` on
+//! Windows. On other platforms, and with the `disable` feature, all CPU times
+//! are zero.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::time_all;
+//!
+//! let (timings, sum) = time_all!((1..=1_000_000u64).map(|n| n % 7).sum::<u64>());
+//! println!("{}", timings); // "wall: 2.1 ms, user: 2.09 ms, system: 0 ns"
+//! assert!(sum > 0);
+//! ```
+
+use std::fmt;
+use std::ops::Sub;
+use std::time::Duration;
+
+use crate::HumanDuration;
+
+/// CPU time split into time spent in user code and in the kernel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CpuTime {
+    /// Time spent executing user code
+    pub user: Duration,
+    /// Time spent in the kernel on behalf of the process
+    pub system: Duration,
+}
+
+impl CpuTime {
+    /// Returns the CPU time consumed by the current process so far.
+    pub fn now() -> Self {
+        if cfg!(feature = "disable") {
+            return CpuTime::default();
+        }
+        sys::process_times()
+    }
+
+    /// Returns the CPU time consumed so far by all child processes of the
+    /// current process that terminated and were waited for.
+    ///
+    /// The difference between two readings around a child's `wait` is the CPU
+    /// time of that child, unless other threads reaped children in between.
+    /// Only available on Unix; zero on other platforms.
+    pub fn children() -> Self {
+        if cfg!(feature = "disable") {
+            return CpuTime::default();
+        }
+        sys::children_times()
+    }
+
+    /// Returns the sum of user and system time.
+    pub fn total(&self) -> Duration {
+        self.user + self.system
+    }
+}
+
+impl Sub for CpuTime {
+    type Output = CpuTime;
+
+    /// Returns the CPU time elapsed between two readings, saturating at zero.
+    fn sub(self, earlier: CpuTime) -> CpuTime {
+        CpuTime {
+            user: self.user.saturating_sub(earlier.user),
+            system: self.system.saturating_sub(earlier.system),
+        }
+    }
+}
+
+/// Returns the total CPU time consumed by the current process so far.
+///
+/// This is more precise than [`CpuTime::now`] where the platform offers a
+/// nanosecond process clock, but does not split user and system time.
+pub fn process_time() -> Duration {
+    if cfg!(feature = "disable") {
+        return Duration::ZERO;
+    }
+    sys::process_clock().unwrap_or_else(|| sys::process_times().total())
+}
+
+/// Returns the CPU time consumed by the current thread so far.
+///
+/// Unlike [`process_time`], this excludes the work of other threads, so the
+/// difference between a block's wall time and its thread CPU time is the time
+/// the thread spent blocked or descheduled.
+pub fn thread_time() -> Duration {
+    if cfg!(feature = "disable") {
+        return Duration::ZERO;
+    }
+    sys::thread_clock()
+}
+
+/// Wall-clock and CPU time of a block, returned by [`time_all!`](crate::time_all).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Timings {
+    /// Elapsed wall-clock time
+    pub wall: Duration,
+    /// CPU time spent in user code
+    pub user: Duration,
+    /// CPU time spent in the kernel
+    pub system: Duration,
+}
+
+impl Timings {
+    /// Combines a wall-clock duration with the CPU time used in the same period.
+    pub fn new(wall: Duration, cpu: CpuTime) -> Self {
+        Timings {
+            wall,
+            user: cpu.user,
+            system: cpu.system,
+        }
+    }
+
+    /// Returns the total CPU time, user plus system.
+    ///
+    /// This can exceed [`wall`](Self::wall) when other threads of the process
+    /// were busy at the same time.
+    pub fn cpu(&self) -> Duration {
+        self.user + self.system
+    }
+
+    /// Returns the effective parallelism of the block: its CPU time divided
+    /// by its wall time, or zero if no wall time was measured.
+    ///
+    /// A block running on four busy threads has a parallelism close to 4.
+    /// As the CPU time is that of the whole process, other threads busy at
+    /// the same time count too.
+    pub fn parallelism(&self) -> f64 {
+        if self.wall.is_zero() {
+            return 0.0;
+        }
+        self.cpu().as_secs_f64() / self.wall.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "wall: {}, user: {}, system: {}",
+            HumanDuration(self.wall),
+            HumanDuration(self.user),
+            HumanDuration(self.system)
+        )
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+))]
+mod sys {
+    use std::os::raw::{c_int, c_long};
+    use std::time::Duration;
+
+    use super::CpuTime;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const CLOCK_PROCESS_CPUTIME_ID: c_int = 2;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const CLOCK_THREAD_CPUTIME_ID: c_int = 3;
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    const CLOCK_PROCESS_CPUTIME_ID: c_int = 12;
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    const CLOCK_THREAD_CPUTIME_ID: c_int = 16;
+    #[cfg(target_os = "freebsd")]
+    const CLOCK_PROCESS_CPUTIME_ID: c_int = 15;
+    #[cfg(target_os = "freebsd")]
+    const CLOCK_THREAD_CPUTIME_ID: c_int = 14;
+
+    const RUSAGE_SELF: c_int = 0;
+    const RUSAGE_CHILDREN: c_int = -1;
+
+    #[repr(C)]
+    struct Timespec {
+        tv_sec: c_long,
+        tv_nsec: c_long,
+    }
+
+    #[repr(C)]
+    struct Timeval {
+        tv_sec: c_long,
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        tv_usec: i32,
+        #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+        tv_usec: c_long,
+    }
+
+    #[repr(C)]
+    struct Rusage {
+        ru_utime: Timeval,
+        ru_stime: Timeval,
+        /// The remaining counters, which are not used
+        rest: [c_long; 14],
+    }
+
+    unsafe extern "C" {
+        fn clock_gettime(clock: c_int, time: *mut Timespec) -> c_int;
+        fn getrusage(who: c_int, usage: *mut Rusage) -> c_int;
+    }
+
+    /// Reads a CPU-time clock, or `None` if it is unavailable.
+    fn clock(id: c_int) -> Option<Duration> {
+        let mut time = Timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `time` is a valid, writable `timespec`
+        if unsafe { clock_gettime(id, &mut time) } != 0 {
+            return None;
+        }
+        Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+    }
+
+    pub(super) fn process_clock() -> Option<Duration> {
+        clock(CLOCK_PROCESS_CPUTIME_ID)
+    }
+
+    pub(super) fn thread_clock() -> Duration {
+        clock(CLOCK_THREAD_CPUTIME_ID).unwrap_or_default()
+    }
+
+    fn duration(time: &Timeval) -> Duration {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    }
+
+    fn usage(who: c_int) -> CpuTime {
+        // SAFETY: `rusage` is plain old data, for which all zeroes is valid
+        let mut usage: Rusage = unsafe { std::mem::zeroed() };
+        // SAFETY: `usage` is a valid, writable `rusage`
+        if unsafe { getrusage(who, &mut usage) } != 0 {
+            return CpuTime::default();
+        }
+        CpuTime {
+            user: duration(&usage.ru_utime),
+            system: duration(&usage.ru_stime),
+        }
+    }
+
+    pub(super) fn process_times() -> CpuTime {
+        usage(RUSAGE_SELF)
+    }
+
+    pub(super) fn children_times() -> CpuTime {
+        usage(RUSAGE_CHILDREN)
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::c_void;
+    use std::time::Duration;
+
+    use super::CpuTime;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Filetime {
+        low: u32,
+        high: u32,
+    }
+
+    impl Filetime {
+        /// Converts a duration in 100-nanosecond units.
+        fn duration(&self) -> Duration {
+            let ticks = (u64::from(self.high) << 32) | u64::from(self.low);
+            Duration::from_nanos(ticks.saturating_mul(100))
+        }
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetCurrentProcess() -> *mut c_void;
+        fn GetCurrentThread() -> *mut c_void;
+        fn GetProcessTimes(
+            process: *mut c_void,
+            creation: *mut Filetime,
+            exit: *mut Filetime,
+            kernel: *mut Filetime,
+            user: *mut Filetime,
+        ) -> i32;
+        fn GetThreadTimes(
+            thread: *mut c_void,
+            creation: *mut Filetime,
+            exit: *mut Filetime,
+            kernel: *mut Filetime,
+            user: *mut Filetime,
+        ) -> i32;
+    }
+
+    pub(super) fn process_clock() -> Option<Duration> {
+        None
+    }
+
+    pub(super) fn thread_clock() -> Duration {
+        let (mut creation, mut exit) = (Filetime::default(), Filetime::default());
+        let (mut kernel, mut user) = (Filetime::default(), Filetime::default());
+        // SAFETY: all pointers are valid and writable, and the pseudo handle
+        // of the current thread needs no cleanup
+        let ok = unsafe {
+            GetThreadTimes(GetCurrentThread(), &mut creation, &mut exit, &mut kernel, &mut user)
+        };
+        if ok == 0 {
+            return Duration::ZERO;
+        }
+        user.duration() + kernel.duration()
+    }
+
+    pub(super) fn process_times() -> CpuTime {
+        let (mut creation, mut exit) = (Filetime::default(), Filetime::default());
+        let (mut kernel, mut user) = (Filetime::default(), Filetime::default());
+        // SAFETY: all pointers are valid and writable, and the pseudo handle
+        // of the current process needs no cleanup
+        let ok = unsafe {
+            GetProcessTimes(GetCurrentProcess(), &mut creation, &mut exit, &mut kernel, &mut user)
+        };
+        if ok == 0 {
+            return CpuTime::default();
+        }
+        CpuTime {
+            user: user.duration(),
+            system: kernel.duration(),
+        }
+    }
+
+    pub(super) fn children_times() -> CpuTime {
+        CpuTime::default()
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    windows
+)))]
+mod sys {
+    use std::time::Duration;
+
+    use super::CpuTime;
+
+    pub(super) fn process_clock() -> Option<Duration> {
+        None
+    }
+
+    pub(super) fn thread_clock() -> Duration {
+        Duration::ZERO
+    }
+
+    pub(super) fn process_times() -> CpuTime {
+        CpuTime::default()
+    }
+
+    pub(super) fn children_times() -> CpuTime {
+        CpuTime::default()
+    }
+}
+
+#[cfg(all(test, target_os = "linux", not(feature = "disable")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_work_uses_cpu_time() {
+        let start = CpuTime::now();
+        let clock = process_time();
+        let mut x = 0u64;
+        while process_time() - clock < Duration::from_millis(20) {
+            x = std::hint::black_box(x.wrapping_add(1));
+        }
+        let used = CpuTime::now() - start;
+        assert!(used.total() >= Duration::from_millis(10), "{:?}", used);
+
+        let (duration, _) = crate::cpu_time!(std::thread::sleep(Duration::from_millis(20)));
+        assert!(duration < Duration::from_millis(10), "{:?}", duration);
+    }
+
+    #[test]
+    fn divides_cpu_time_by_wall_time() {
+        let cpu = CpuTime {
+            user: Duration::from_secs(3),
+            system: Duration::from_millis(500),
+        };
+        assert_eq!(Timings::new(Duration::from_secs(1), cpu).parallelism(), 3.5);
+        assert_eq!(Timings::new(Duration::ZERO, cpu).parallelism(), 0.0);
+    }
+
+    #[test]
+    fn thread_time_excludes_other_threads() {
+        let start = thread_time();
+        std::thread::spawn(|| {
+            let clock = thread_time();
+            let mut x = 0u64;
+            while thread_time() - clock < Duration::from_millis(20) {
+                x = std::hint::black_box(x.wrapping_add(1));
+            }
+        })
+        .join()
+        .unwrap();
+        assert!(thread_time() - start < Duration::from_millis(10));
+    }
+}
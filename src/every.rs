@@ -0,0 +1,140 @@
+//! Aggregation windows for [`time_every!`](crate::time_every).
+
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::registry::LabelStats;
+use crate::{HumanDuration, TimingRecord};
+
+/// When [`time_every!`](crate::time_every) logs the window of a call site.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Every {
+    /// After this many calls
+    Calls(u64),
+    /// At the first call once this much time has passed since the window
+    /// started
+    Interval(Duration),
+}
+
+/// The measurements of one call site since its last message.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Window {
+    state: Mutex<Option<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    stats: LabelStats,
+    /// A reading of the [`clock`](crate::clock) when the window started
+    start: u64,
+}
+
+impl Window {
+    /// Creates an empty window, for a `static` at the call site.
+    pub const fn new() -> Self {
+        Window {
+            state: Mutex::new(None),
+        }
+    }
+
+    /// Adds a measurement, and returns the summary record of the window if
+    /// it is complete, starting a new one.
+    pub fn add(&self, record: &TimingRecord, every: Every) -> Option<TimingRecord> {
+        if cfg!(feature = "disable")
+            || !crate::is_enabled()
+            || !crate::filter::enabled(record.label.as_deref())
+        {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let window = match state.as_mut() {
+            Some(window) => {
+                window.stats.add(record.duration);
+                window
+            }
+            None => state.insert(State {
+                stats: LabelStats::new(record.duration),
+                start: crate::clock::now(),
+            }),
+        };
+        let complete = match every {
+            Every::Calls(calls) => window.stats.count >= calls,
+            Every::Interval(interval) => crate::clock::elapsed_since(window.start) >= interval,
+        };
+        if !complete {
+            return None;
+        }
+        let stats = state.take()?.stats;
+        let mut summary = TimingRecord::new(record.label.clone(), stats.total, record.file.clone(), record.line);
+        summary.module_path = record.module_path.clone();
+        summary.summary = Some(self::summary(record.label.as_deref(), &stats));
+        Some(summary)
+    }
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window::new()
+    }
+}
+
+/// Formats the statistics of a window, e.g.
+/// `parse - 100 calls, mean: 1.2 ms, min: 1 ms, max: 3 ms, total: 120 ms`.
+fn summary(label: Option<&str>, stats: &LabelStats) -> String {
+    let calls = match stats.count {
+        1 => "1 call".to_string(),
+        count => format!("{} calls", count),
+    };
+    let prefix = match label {
+        Some(label) => format!("{} - {}", label, calls),
+        None => calls,
+    };
+    format!(
+        "{}, mean: {}, min: {}, max: {}, total: {}",
+        prefix,
+        HumanDuration(stats.mean()),
+        HumanDuration(stats.min),
+        HumanDuration(stats.max),
+        HumanDuration(stats.total)
+    )
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+
+    fn record(millis: u64) -> TimingRecord {
+        TimingRecord::new(Some("every-test".into()), Duration::from_millis(millis), "", 0)
+    }
+
+    #[test]
+    fn summarizes_every_n_calls() {
+        let window = Window::new();
+        assert_eq!(window.add(&record(1), Every::Calls(3)), None);
+        assert_eq!(window.add(&record(3), Every::Calls(3)), None);
+        let summary = window.add(&record(2), Every::Calls(3)).unwrap();
+        assert_eq!(summary.to_string(), "every-test - 3 calls, mean: 2 ms, min: 1 ms, max: 3 ms, total: 6 ms");
+        assert_eq!(summary.duration, Duration::from_millis(6));
+        assert_eq!(window.add(&record(2), Every::Calls(3)), None);
+    }
+
+    #[test]
+    fn summarizes_every_interval() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+        let every = Every::Interval(Duration::from_secs(1));
+
+        let window = Window::new();
+        assert_eq!(window.add(&record(4), every), None);
+        clock.advance(Duration::from_millis(999));
+        assert_eq!(window.add(&record(4), every), None);
+        clock.advance(Duration::from_millis(1));
+        let mut unlabeled = record(1);
+        unlabeled.label = None;
+        let summary = window.add(&unlabeled, every).unwrap();
+        assert_eq!(summary.to_string(), "3 calls, mean: 3 ms, min: 1 ms, max: 4 ms, total: 9 ms");
+    }
+}
@@ -0,0 +1,263 @@
+//! Label filtering through the `ARBITIME` environment variable.
+//!
+//! Like `RUST_LOG`, the `ARBITIME` variable holds a comma-separated list of
+//! directives that control which labels are recorded and logged:
+//!
+//! - `pattern` enables labels matching the pattern
+//! - `pattern=off` disables them
+//! - `pattern=debug` (or any other [`Level`]) only logs them at that level or
+//!   more severe; `pattern=on` allows every level
+//! - `off`, `on` or a level without a pattern sets the default for labels that
+//!   no directive matches
+//!
+//! Patterns match whole labels, with `*` standing for any run of characters.
+//! When several directives match a label, the last one wins. Without a
+//! default, labels that no directive matches are enabled, unless the filter
+//! enables specific patterns, in which case only those are.
+//!
+//! ```text
+//! ARBITIME=db*,render=off     # only labels starting with "db"
+//! ARBITIME=render=off         # everything except "render"
+//! ARBITIME=warn,db*=on        # only warnings, except all "db..." timings
+//! ARBITIME=off                # nothing
+//! ```
+//!
+//! Unlabelled timings follow the default. Filtered-out timings still run
+//! their code, but are neither logged nor recorded in the
+//! [`registry`](crate::registry) or [`trace`](crate::trace). The variable is
+//! read once, on first use; [`set_filter`] replaces it programmatically.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::filter::{self, Filter};
+//! use arbitime::Level;
+//!
+//! let filter = Filter::parse("db*,render=off").unwrap();
+//! assert!(filter.enabled(Some("db query")));
+//! assert!(!filter.enabled(Some("render")));
+//! assert!(!filter.enabled(Some("physics")));
+//!
+//! filter::set_filter(Filter::parse("warn,db*=on").unwrap());
+//! assert!(filter::allows(Some("db query"), Level::Debug));
+//! assert!(!filter::allows(Some("physics"), Level::Info));
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Once, PoisonError, RwLock};
+
+use crate::Level;
+
+/// The environment variable the filter is read from.
+pub const ENV_VAR: &str = "ARBITIME";
+
+static FILTER: RwLock<Option<Arc<Filter>>> = RwLock::new(None);
+static FROM_ENV: Once = Once::new();
+
+/// A parsed set of filter directives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    default: Option<Option<Level>>,
+    directives: Vec<Directive>,
+}
+
+/// A single `pattern=value` directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Directive {
+    pattern: String,
+    /// The most verbose level allowed, or `None` if the pattern is off
+    level: Option<Level>,
+}
+
+impl Filter {
+    /// Parses a comma-separated list of directives, as described in the
+    /// [module documentation](self).
+    pub fn parse(spec: &str) -> Result<Self, FilterError> {
+        let mut filter = Filter {
+            default: None,
+            directives: Vec::new(),
+        };
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let invalid = || FilterError {
+                directive: directive.to_string(),
+            };
+            match directive.split_once('=') {
+                Some((pattern, value)) => {
+                    let pattern = pattern.trim();
+                    if pattern.is_empty() {
+                        return Err(invalid());
+                    }
+                    filter.directives.push(Directive {
+                        pattern: pattern.to_string(),
+                        level: parse_value(value.trim()).ok_or_else(invalid)?,
+                    });
+                }
+                None => match parse_value(directive) {
+                    Some(level) => filter.default = Some(level),
+                    None => filter.directives.push(Directive {
+                        pattern: directive.to_string(),
+                        level: Some(Level::Trace),
+                    }),
+                },
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Returns the most verbose level logged for `label`, or `None` if the
+    /// label is filtered out.
+    pub fn level(&self, label: Option<&str>) -> Option<Level> {
+        let matched = label.and_then(|label| {
+            self.directives
+                .iter()
+                .rev()
+                .find(|directive| matches(&directive.pattern, label))
+        });
+        match (matched, self.default) {
+            (Some(directive), _) => directive.level,
+            (None, Some(default)) => default,
+            (None, None) if self.directives.iter().any(|d| d.level.is_some()) => None,
+            (None, None) => Some(Level::Trace),
+        }
+    }
+
+    /// Returns `true` if `label` is measured at all.
+    pub fn enabled(&self, label: Option<&str>) -> bool {
+        self.level(label).is_some()
+    }
+
+    /// Returns `true` if `label` is logged at `level`.
+    pub fn allows(&self, label: Option<&str>, level: Level) -> bool {
+        self.level(label).is_some_and(|max| level <= max)
+    }
+}
+
+/// Parses `on`, `off` or a level name.
+fn parse_value(value: &str) -> Option<Option<Level>> {
+    match value.to_ascii_lowercase().as_str() {
+        "off" => Some(None),
+        "on" | "all" | "trace" => Some(Some(Level::Trace)),
+        "debug" => Some(Some(Level::Debug)),
+        "info" => Some(Some(Level::Info)),
+        "warn" => Some(Some(Level::Warn)),
+        "error" => Some(Some(Level::Error)),
+        _ => None,
+    }
+}
+
+/// Matches `text` against a pattern where `*` stands for any run of characters.
+pub(crate) fn matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No wildcard: the whole label must match
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// An invalid directive in a filter specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    directive: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid filter directive `{}`", self.directive)
+    }
+}
+
+impl Error for FilterError {}
+
+/// Replaces the global filter, overriding the `ARBITIME` variable.
+pub fn set_filter(filter: Filter) {
+    FROM_ENV.call_once(|| {});
+    *FILTER.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(filter));
+}
+
+/// Removes the global filter, so that every label is enabled.
+pub fn clear_filter() {
+    FROM_ENV.call_once(|| {});
+    *FILTER.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Returns the global filter, reading `ARBITIME` on first use.
+pub fn current() -> Option<Arc<Filter>> {
+    FROM_ENV.call_once(|| {
+        let Ok(spec) = std::env::var(ENV_VAR) else {
+            return;
+        };
+        match Filter::parse(&spec) {
+            Ok(filter) => {
+                *FILTER.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(filter));
+            }
+            Err(error) => eprintln!("arbitime: ignoring {}: {}", ENV_VAR, error),
+        }
+    });
+    FILTER.read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Returns `true` if the global filter lets `label` be measured.
+pub fn enabled(label: Option<&str>) -> bool {
+    current().is_none_or(|filter| filter.enabled(label))
+}
+
+/// Returns `true` if the global filter lets `label` be logged at `level`.
+pub fn allows(label: Option<&str>, level: Level) -> bool {
+    current().is_none_or(|filter| filter.allows(label, level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("db", "db"));
+        assert!(!matches("db", "db query"));
+        assert!(matches("db*", "db query"));
+        assert!(matches("*query", "db query"));
+        assert!(matches("d*q*y", "db query"));
+        assert!(!matches("d*q*z", "db query"));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    fn parses_directives() {
+        let filter = Filter::parse("db*, render=off").unwrap();
+        assert_eq!(filter.level(Some("db query")), Some(Level::Trace));
+        assert_eq!(filter.level(Some("render")), None);
+        assert_eq!(filter.level(Some("physics")), None);
+        assert_eq!(filter.level(None), None);
+
+        let filter = Filter::parse("render=off").unwrap();
+        assert!(filter.enabled(Some("physics")));
+        assert!(filter.enabled(None));
+        assert!(!filter.enabled(Some("render")));
+
+        let filter = Filter::parse("warn,db*=debug,db cache=off").unwrap();
+        assert!(filter.allows(Some("physics"), Level::Warn));
+        assert!(!filter.allows(Some("physics"), Level::Info));
+        assert!(filter.allows(Some("db query"), Level::Debug));
+        assert!(!filter.allows(Some("db query"), Level::Trace));
+        assert!(!filter.enabled(Some("db cache")));
+
+        assert!(Filter::parse("db=loud").is_err());
+        assert_eq!(
+            Filter::parse("=off").unwrap_err().to_string(),
+            "invalid filter directive `=off`"
+        );
+    }
+}
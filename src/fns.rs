@@ -0,0 +1,99 @@
+//! Timing functions, for code that does not fit a macro.
+//!
+//! [`time!`](crate::time) takes a block of code, which cannot be passed
+//! around. These functions measure closures instead, so that they can be
+//! used in higher-order code, such as a combinator that is handed a timer.
+//! They read the same [`clock`](crate::clock) and apply the same
+//! [overhead correction](crate::calibration) as the macros, work without the
+//! `std` feature, and return [`Duration::ZERO`] with the `disable` feature.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::fns::{time_closure, time_fn, try_time_fn};
+//!
+//! let (duration, sum) = time_fn(|| (1..=100).sum::<u32>());
+//! assert_eq!(sum, 5050);
+//!
+//! let (duration, parsed) = try_time_fn(|| "42".parse::<u32>());
+//! assert_eq!(parsed, Ok(42));
+//!
+//! // Any function taking a closure can be timed
+//! fn retry<T, E>(attempts: usize, mut f: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+//!     let mut result = f();
+//!     for _ in 1..attempts {
+//!         if result.is_ok() {
+//!             break;
+//!         }
+//!         result = f();
+//!     }
+//!     result
+//! }
+//! let (duration, result) = time_fn(|| retry(3, || "7".parse::<u8>()));
+//! assert_eq!(result, Ok(7));
+//!
+//! // Or each call of a closure
+//! let mut square = time_closure(|| 12 * 12);
+//! let (duration, value) = square();
+//! assert_eq!(value, 144);
+//! ```
+
+use core::time::Duration;
+
+/// Calls `f` and returns the time it took with its result, like
+/// [`time!`](crate::time).
+#[inline]
+pub fn time_fn<T>(f: impl FnOnce() -> T) -> (Duration, T) {
+    if cfg!(feature = "disable") {
+        return (Duration::ZERO, f());
+    }
+    let start = crate::clock::now();
+    let result = f();
+    (crate::calibration::correct(crate::clock::elapsed_since(start)), result)
+}
+
+/// Calls the fallible `f` and returns the time it took with its `Result`,
+/// whether it succeeded or not.
+///
+/// This is [`time_fn`] for closures returning a [`Result`], which spares
+/// callers from annotating the error type of closures using `?`.
+#[inline]
+pub fn try_time_fn<T, E>(f: impl FnOnce() -> Result<T, E>) -> (Duration, Result<T, E>) {
+    time_fn(f)
+}
+
+/// Wraps `f` in a closure that times each of its calls, returning the time
+/// each call took with its result.
+pub fn time_closure<T>(mut f: impl FnMut() -> T) -> impl FnMut() -> (Duration, T) {
+    move || time_fn(&mut f)
+}
+
+#[cfg(all(test, feature = "std", not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+
+    #[test]
+    fn times_closures() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+        let tick = || clock.advance(Duration::from_millis(5));
+
+        assert_eq!(time_fn(|| { tick(); 1 }), (Duration::from_millis(5), 1));
+        let (duration, result) = try_time_fn(|| {
+            tick();
+            "x".parse::<u32>()
+        });
+        assert_eq!(duration, Duration::from_millis(5));
+        assert!(result.is_err());
+
+        let mut calls = 0;
+        let mut counted = time_closure(|| {
+            tick();
+            calls += 1;
+            calls
+        });
+        assert_eq!(counted(), (Duration::from_millis(5), 1));
+        assert_eq!(counted(), (Duration::from_millis(5), 2));
+    }
+}
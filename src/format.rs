@@ -0,0 +1,395 @@
+//! Human-readable duration formatting.
+//!
+//! The timing macros format durations with [`HumanDuration`], which picks a
+//! unit that keeps the number readable: `1.83 s`, `250 ms`, `4 µs` or
+//! `2 min 3 s`.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::format::HumanDuration;
+//! use std::time::Duration;
+//!
+//! assert_eq!(HumanDuration(Duration::from_micros(1_834_645)).to_string(), "1.83 s");
+//! assert_eq!(HumanDuration(Duration::from_millis(250)).to_string(), "250 ms");
+//! assert_eq!(HumanDuration(Duration::from_secs(123)).to_string(), "2 min 3 s");
+//!
+//! // Precision can be set explicitly, either on the wrapper or in the format string
+//! let duration = Duration::from_nanos(1_234_567);
+//! assert_eq!(HumanDuration(duration).precision(4).to_string(), "1.2346 ms");
+//! assert_eq!(format!("{:.1}", HumanDuration(duration)), "1.2 ms");
+//! ```
+//!
+//! [`DurationExt`] adds these and a few unit conversions as methods on
+//! [`Duration`], for post-processing the durations returned by
+//! [`time!`](crate::time):
+//!
+//! ```rust
+//! use arbitime::{time, DurationExt};
+//!
+//! let items: Vec<u32> = (0..1000).collect();
+//! let (duration, sum) = time!(items.iter().sum::<u32>());
+//! println!("{} ({} per item, {:.3} ms)", duration.human(), duration.per(items.len() as u64).human(), duration.as_millis_f64());
+//! ```
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The number of decimal places used when no precision is given.
+pub const DEFAULT_PRECISION: usize = 2;
+
+static SHOW_LOCATION: AtomicBool = AtomicBool::new(false);
+static SHOW_THREAD: AtomicBool = AtomicBool::new(false);
+static SHOW_TIMESTAMP: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether timing messages start with the call site of the measurement,
+/// e.g. `[src/parser.rs:88] parsing - Execution time: 12 ms`.
+///
+/// Disabled by default. The call site is always available as
+/// [`TimingRecord::location`](crate::TimingRecord::location) and in JSON output.
+///
+/// ```rust
+/// use arbitime::{format, format_time};
+///
+/// format::set_show_location(true);
+/// let (message, _) = format_time!("parsing" => "42".parse::<u32>());
+/// let call_site = format!("[{}:{}]", file!(), line!() - 1);
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(message.starts_with(&format!("{} parsing - ", call_site)));
+/// ```
+pub fn set_show_location(show: bool) {
+    SHOW_LOCATION.store(show, Ordering::Relaxed);
+}
+
+/// Returns whether timing messages start with the call site of the measurement.
+pub fn show_location() -> bool {
+    SHOW_LOCATION.load(Ordering::Relaxed)
+}
+
+/// Sets whether timing messages start with the thread that took the
+/// measurement, e.g. `[worker-2] parsing - Execution time: 12 ms`, so that
+/// interleaved output from several threads can be told apart.
+///
+/// Unnamed threads are shown by their id, such as `ThreadId(3)`. Disabled by
+/// default. The thread is always available as
+/// [`TimingRecord::thread`](crate::TimingRecord::thread) and in JSON output.
+/// When the call site is [shown](set_show_location) too, the thread comes
+/// first.
+///
+/// ```rust
+/// use arbitime::{format, format_time};
+///
+/// format::set_show_thread(true);
+/// let handle = std::thread::Builder::new().name("worker-2".into()).spawn(|| {
+///     format_time!("parsing" => "42".parse::<u32>()).0
+/// });
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(handle.unwrap().join().unwrap().starts_with("[worker-2] parsing - "));
+/// ```
+pub fn set_show_thread(show: bool) {
+    SHOW_THREAD.store(show, Ordering::Relaxed);
+}
+
+/// Returns whether timing messages start with the thread that took the
+/// measurement.
+pub fn show_thread() -> bool {
+    SHOW_THREAD.load(Ordering::Relaxed)
+}
+
+/// Sets whether timing messages start with the wall-clock time at which the
+/// measurement finished, as an [RFC 3339](rfc3339) UTC timestamp, e.g.
+/// `2026-10-15T23:40:11.556957Z parsing - Execution time: 12 ms`, to
+/// correlate timings with other logs.
+///
+/// Disabled by default. The timestamp is always available as
+/// [`TimingRecord::timestamp`](crate::TimingRecord::timestamp), in JSON output
+/// and as the `{timestamp}` placeholder of [templates](crate::template). It
+/// comes before the [thread](set_show_thread) and the
+/// [call site](set_show_location).
+///
+/// ```rust
+/// use arbitime::{format, format_time};
+///
+/// format::set_show_timestamp(true);
+/// let (message, _) = format_time!("parsing" => "42".parse::<u32>());
+/// # #[cfg(not(feature = "disable"))]
+/// let (timestamp, rest) = message.split_once(' ').unwrap();
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(timestamp.ends_with('Z') && timestamp.len() == 27);
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(rest.starts_with("parsing - "));
+/// ```
+pub fn set_show_timestamp(show: bool) {
+    SHOW_TIMESTAMP.store(show, Ordering::Relaxed);
+}
+
+/// Returns whether timing messages start with the time at which the
+/// measurement finished.
+pub fn show_timestamp() -> bool {
+    SHOW_TIMESTAMP.load(Ordering::Relaxed)
+}
+
+/// A [`Duration`] wrapper whose [`Display`](fmt::Display) implementation is human-readable.
+///
+/// Durations below a minute are printed as a decimal number in the largest
+/// unit that keeps it at or above one (`ns`, `µs`, `ms` or `s`). Without an
+/// explicit precision, up to [`DEFAULT_PRECISION`] decimal places are shown and
+/// trailing zeros are removed. Longer durations are broken down into whole
+/// hours, minutes and seconds, e.g. `1 h 2 min 3 s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HumanDuration(pub Duration);
+
+impl HumanDuration {
+    /// Sets the number of decimal places to show. Trailing zeros are kept.
+    pub fn precision(self, precision: usize) -> Precise {
+        Precise {
+            duration: self.0,
+            precision,
+        }
+    }
+}
+
+impl From<Duration> for HumanDuration {
+    fn from(duration: Duration) -> Self {
+        HumanDuration(duration)
+    }
+}
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = format_duration(self.0, f.precision());
+        pad(f, &text)
+    }
+}
+
+/// Convenience methods for [`Duration`].
+///
+/// The standard library has unstable inherent methods also named
+/// `as_millis_f64` and `as_micros_f64`. Once they are stabilized, they take
+/// precedence over these, with the same results.
+pub trait DurationExt {
+    /// Returns the duration in milliseconds, with a fractional part.
+    fn as_millis_f64(&self) -> f64;
+
+    /// Returns the duration in microseconds, with a fractional part.
+    fn as_micros_f64(&self) -> f64;
+
+    /// Wraps the duration in a [`HumanDuration`], for display.
+    fn human(&self) -> HumanDuration;
+
+    /// Divides the duration by a number of items, e.g. to get the time per
+    /// iteration of a loop. Returns zero if `count` is zero.
+    fn per(&self, count: u64) -> Duration;
+}
+
+impl DurationExt for Duration {
+    fn as_millis_f64(&self) -> f64 {
+        self.as_secs_f64() * 1e3
+    }
+
+    fn as_micros_f64(&self) -> f64 {
+        self.as_secs_f64() * 1e6
+    }
+
+    fn human(&self) -> HumanDuration {
+        HumanDuration(*self)
+    }
+
+    fn per(&self, count: u64) -> Duration {
+        let Some(nanos) = self.as_nanos().checked_div(u128::from(count)) else {
+            return Duration::ZERO;
+        };
+        // At most the duration itself, so the seconds fit in a `u64`
+        Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)
+    }
+}
+
+/// A [`HumanDuration`] with a fixed number of decimal places.
+///
+/// Created by [`HumanDuration::precision`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Precise {
+    duration: Duration,
+    precision: usize,
+}
+
+impl fmt::Display for Precise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = format_duration(self.duration, Some(self.precision));
+        pad(f, &text)
+    }
+}
+
+fn format_duration(duration: Duration, precision: Option<usize>) -> String {
+    let secs = duration.as_secs();
+    if secs >= 60 {
+        let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+        let parts = [(hours, "h"), (minutes, "min"), (seconds, "s")];
+        let first = parts.iter().position(|&(value, _)| value > 0).unwrap_or(2);
+        return parts[first..]
+            .iter()
+            .filter(|&&(value, _)| value > 0)
+            .map(|(value, unit)| format!("{} {}", value, unit))
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+
+    let nanos = duration.as_nanos() as f64;
+    let (value, unit) = if nanos >= 1e9 {
+        (nanos / 1e9, "s")
+    } else if nanos >= 1e6 {
+        (nanos / 1e6, "ms")
+    } else if nanos >= 1e3 {
+        (nanos / 1e3, "µs")
+    } else {
+        (nanos, "ns")
+    };
+
+    format_value(value, unit, precision)
+}
+
+/// Formats a number followed by a unit, with up to [`DEFAULT_PRECISION`]
+/// decimal places and no trailing zeros unless `precision` is given.
+pub(crate) fn format_value(value: f64, unit: &str, precision: Option<usize>) -> String {
+    match precision {
+        Some(precision) => format!("{:.*} {}", precision, value, unit),
+        None => {
+            let number = format!("{:.*}", DEFAULT_PRECISION, value);
+            let number = number.trim_end_matches('0').trim_end_matches('.');
+            format!("{} {}", number, unit)
+        }
+    }
+}
+
+/// Formats a [`SystemTime`] as an RFC 3339 timestamp in UTC with microsecond
+/// precision, e.g. `2023-11-14T22:13:20.123456Z`.
+///
+/// Times before the Unix epoch are clamped to it.
+///
+/// ```rust
+/// use arbitime::format::rfc3339;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+/// assert_eq!(rfc3339(time), "2023-11-14T22:13:20.000000Z");
+/// ```
+pub fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days(secs / 86_400);
+    let seconds_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_micros()
+    )
+}
+
+/// Converts days since the Unix epoch into a `(year, month, day)` date.
+///
+/// Based on Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a `(year, month, day)` date into days since the Unix epoch, the
+/// inverse of [`civil_from_days`], or `None` for dates before the epoch.
+pub(crate) fn days_from_civil(year: u64, month: u64, day: u64) -> Option<u64> {
+    let year = year.checked_sub(u64::from(month <= 2))?;
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * mp + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146_097 + day_of_era).checked_sub(719_468)
+}
+
+/// Writes `text` honoring the width and alignment of the formatter, but not its
+/// precision, which has already been used for the number of decimal places.
+fn pad(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    let Some(width) = f.width() else {
+        return f.write_str(text);
+    };
+    match f.align() {
+        Some(fmt::Alignment::Right) => write!(f, "{:>width$}", text),
+        Some(fmt::Alignment::Center) => write!(f, "{:^width$}", text),
+        _ => write!(f, "{:<width$}", text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn human(duration: Duration) -> String {
+        HumanDuration(duration).to_string()
+    }
+
+    #[test]
+    fn picks_readable_units() {
+        assert_eq!(human(Duration::ZERO), "0 ns");
+        assert_eq!(human(Duration::from_nanos(999)), "999 ns");
+        assert_eq!(human(Duration::from_nanos(4_000)), "4 µs");
+        assert_eq!(human(Duration::from_nanos(42_123)), "42.12 µs");
+        assert_eq!(human(Duration::from_micros(1_500)), "1.5 ms");
+        assert_eq!(human(Duration::from_millis(59_999)), "60 s");
+        assert_eq!(human(Duration::from_secs(60)), "1 min");
+        assert_eq!(human(Duration::from_secs(3_723)), "1 h 2 min 3 s");
+        assert_eq!(human(Duration::from_secs(7_200)), "2 h");
+    }
+
+    #[test]
+    fn extends_durations() {
+        let duration = Duration::from_micros(1_500);
+        assert_eq!(DurationExt::as_millis_f64(&duration), 1.5);
+        assert_eq!(DurationExt::as_micros_f64(&duration), 1500.0);
+        assert_eq!(duration.human().to_string(), "1.5 ms");
+        assert_eq!(duration.per(1_000), Duration::from_nanos(1_500));
+        assert_eq!(Duration::from_secs(u64::MAX).per(1), Duration::from_secs(u64::MAX));
+        assert_eq!(duration.per(0), Duration::ZERO);
+    }
+
+    #[test]
+    fn formats_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000000Z");
+        // Leap day
+        let time = UNIX_EPOCH + Duration::from_secs(951_782_400) + Duration::from_micros(42);
+        assert_eq!(rfc3339(time), "2000-02-29T00:00:00.000042Z");
+        let time = UNIX_EPOCH + Duration::from_secs(4_102_444_799);
+        assert_eq!(rfc3339(time), "2099-12-31T23:59:59.000000Z");
+    }
+
+    #[test]
+    fn converts_days_both_ways() {
+        for days in [0, 59, 11_016, 19_675, 47_481] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), Some(days));
+        }
+        assert_eq!(days_from_civil(1969, 12, 31), None);
+    }
+
+    #[test]
+    fn honors_precision_and_width() {
+        let duration = Duration::from_micros(1_500);
+        assert_eq!(HumanDuration(duration).precision(3).to_string(), "1.500 ms");
+        assert_eq!(format!("{:.0}", HumanDuration(duration)), "2 ms");
+        assert_eq!(format!("[{:>8}]", HumanDuration(duration)), "[  1.5 ms]");
+        assert_eq!(format!("[{:<8.1}]", HumanDuration(duration)), "[1.5 ms  ]");
+    }
+}
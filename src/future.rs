@@ -0,0 +1,507 @@
+//! Future adapters that time how long a future takes to complete, and how
+//! it spends that time.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use crate::{registry, HumanDuration, TimingRecord};
+
+/// Extension trait that adds timing to every [`Future`].
+///
+/// Like [`time_async!`](crate::time_async), the time measured is the time
+/// from the first poll of the future until it completes, including the time
+/// it spends waiting between polls. Unlike the macros, the adapters can be
+/// chained with other combinators, passed around and stored before they are
+/// awaited.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::TimedFutureExt;
+///
+/// async fn fetch(id: u32) -> u32 {
+///     id * 2
+/// }
+///
+/// async fn run() {
+///     let (duration, value) = fetch(21).timed().await;
+///     assert_eq!(value, 42);
+///     println!("fetch took {:?}", duration);
+///
+///     // Log and record the duration like `log_time!`
+///     let value = fetch(1).timed_with("fetch").await;
+///     // Prints: "fetch - Execution time: ..."
+///     assert_eq!(value, 2);
+/// }
+/// ```
+pub trait TimedFutureExt: Future + Sized {
+    /// Resolves to a `(Duration, Output)` pair.
+    fn timed(self) -> TimedFuture<Self> {
+        TimedFuture {
+            future: self,
+            start: None,
+        }
+    }
+
+    /// Logs the duration with the given label in the same format as
+    /// [`log_time!`](crate::log_time), records it in the
+    /// [`registry`](crate::registry), and resolves to the output unchanged.
+    ///
+    /// Like a [`ScopeTimer`](crate::ScopeTimer), it prints to stderr, or
+    /// logs through the `log` crate with the `log` feature, unless a
+    /// [`sink`](crate::sink) is installed.
+    #[track_caller]
+    fn timed_with(self, label: impl Into<Cow<'static, str>>) -> LoggedFuture<Self> {
+        LoggedFuture {
+            inner: self.timed(),
+            label: Some(label.into()),
+            location: Location::caller(),
+        }
+    }
+
+    /// Resolves to a `(PollStats, Output)` pair, breaking the time the
+    /// future took down by polls.
+    ///
+    /// The time until the first poll is counted from this call.
+    fn instrumented(self) -> InstrumentedFuture<Self> {
+        InstrumentedFuture {
+            future: self,
+            created: if cfg!(feature = "disable") { 0 } else { crate::clock::now() },
+            start: None,
+            stats: PollStats::default(),
+        }
+    }
+}
+
+impl<F: Future> TimedFutureExt for F {}
+
+/// How a future spent its time, measured by
+/// [`TimedFutureExt::instrumented`].
+///
+/// A future whose `busy` time is close to its `elapsed` time is slow because
+/// of the work it does; one that is mostly idle is waiting on I/O, timers or
+/// other tasks, and one with a long `to_first_poll` waited for the executor.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::TimedFutureExt;
+///
+/// async fn run() {
+///     let (stats, value) = async { 42 }.instrumented().await;
+///     assert_eq!(stats.polls, 1);
+///     println!("{}", stats); // "1 polls, busy: 120 ns, idle: 0 ns, first poll after 2.1 µs"
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PollStats {
+    /// The number of times the future was polled
+    pub polls: u64,
+    /// The time spent inside `poll`
+    pub busy: Duration,
+    /// The time from the first poll until the future completed
+    pub elapsed: Duration,
+    /// The time from the creation of the adapter until the first poll
+    pub to_first_poll: Duration,
+}
+
+impl PollStats {
+    /// Returns the time between the first poll and completion that was not
+    /// spent inside `poll`, i.e. waiting to be woken and scheduled.
+    pub fn idle(&self) -> Duration {
+        self.elapsed.saturating_sub(self.busy)
+    }
+
+    /// Returns the mean time of a poll.
+    pub fn mean_poll(&self) -> Duration {
+        match u32::try_from(self.polls) {
+            Ok(0) => Duration::ZERO,
+            Ok(polls) => self.busy / polls,
+            Err(_) => Duration::from_secs_f64(self.busy.as_secs_f64() / self.polls as f64),
+        }
+    }
+}
+
+impl fmt::Display for PollStats {
+    /// Formats the statistics as e.g.
+    /// `3 polls, busy: 1.2 ms, idle: 40 ms, first poll after 12 µs`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} polls, busy: {}, idle: {}, first poll after {}",
+            self.polls,
+            HumanDuration(self.busy),
+            HumanDuration(self.idle()),
+            HumanDuration(self.to_first_poll)
+        )
+    }
+}
+
+/// A future that resolves to its output and the time it took.
+///
+/// Created by [`TimedFutureExt::timed`].
+#[derive(Debug, Clone)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct TimedFuture<F> {
+    future: F,
+    /// A reading of the [`clock`](crate::clock) at the first poll
+    start: Option<u64>,
+}
+
+impl<F: Future> Future for TimedFuture<F> {
+    type Output = (Duration, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is pinned along with `self` and never moved out;
+        // `start` is not structurally pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        if cfg!(feature = "disable") {
+            let future = unsafe { Pin::new_unchecked(&mut this.future) };
+            return future.poll(cx).map(|output| (Duration::ZERO, output));
+        }
+        let start = *this.start.get_or_insert_with(crate::clock::now);
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        future
+            .poll(cx)
+            .map(|output| (crate::calibration::correct(crate::clock::elapsed_since(start)), output))
+    }
+}
+
+/// A future that resolves to its output and [`PollStats`].
+///
+/// Created by [`TimedFutureExt::instrumented`].
+#[derive(Debug, Clone)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct InstrumentedFuture<F> {
+    future: F,
+    /// Readings of the [`clock`](crate::clock) at creation and at the first
+    /// poll
+    created: u64,
+    start: Option<u64>,
+    stats: PollStats,
+}
+
+impl<F: Future> Future for InstrumentedFuture<F> {
+    type Output = (PollStats, F::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is pinned along with `self` and never moved out;
+        // the other fields are not structurally pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        if cfg!(feature = "disable") {
+            return future.poll(cx).map(|output| (PollStats::default(), output));
+        }
+        let poll_start = crate::clock::now();
+        let start = *this.start.get_or_insert_with(|| {
+            this.stats.to_first_poll = crate::clock::elapsed_since(this.created);
+            poll_start
+        });
+        let poll = future.poll(cx);
+        this.stats.polls += 1;
+        this.stats.busy += crate::clock::elapsed_since(poll_start);
+        let Poll::Ready(output) = poll else {
+            return Poll::Pending;
+        };
+        this.stats.elapsed = crate::clock::elapsed_since(start);
+        Poll::Ready((this.stats, output))
+    }
+}
+
+/// A future that logs the time it took when it completes.
+///
+/// Created by [`TimedFutureExt::timed_with`].
+#[derive(Debug, Clone)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct LoggedFuture<F> {
+    inner: TimedFuture<F>,
+    label: Option<Cow<'static, str>>,
+    location: &'static Location<'static>,
+}
+
+impl<F: Future> Future for LoggedFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        // SAFETY: `inner` is pinned along with `self` and never moved out;
+        // the other fields are not structurally pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let inner = unsafe { Pin::new_unchecked(&mut this.inner) };
+        let Poll::Ready((duration, output)) = inner.poll(cx) else {
+            return Poll::Pending;
+        };
+        if let Some(label) = this.label.take() {
+            log(label, duration, this.location);
+        }
+        Poll::Ready(output)
+    }
+}
+
+/// Records and logs the duration of a labelled future.
+fn log(label: Cow<'static, str>, duration: Duration, location: &'static Location<'static>) {
+    if cfg!(feature = "disable") || !crate::is_enabled() || !crate::filter::enabled(Some(&label)) {
+        return;
+    }
+    registry::record(&label, duration);
+    let record = TimingRecord::new(Some(label.into_owned()), duration, location.file(), location.line());
+    crate::trace::record(&record);
+    crate::slow::check(&record);
+    crate::sink::dispatch(vec![record], crate::sink::emit_message);
+}
+
+/// The error of a future that did not complete within its time limit.
+///
+/// Returned by [`Timeout`] and [`time_or_timeout!`](crate::time_or_timeout).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Elapsed {
+    /// The time limit of the future
+    pub limit: Duration,
+    /// The time from the first poll until the future was given up, at least
+    /// the limit unless the timer fired early
+    pub after: Duration,
+}
+
+impl fmt::Display for Elapsed {
+    /// Formats the error as e.g. `timed out after 5 s`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out after {}", HumanDuration(self.after))
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+/// A future that races another future against a timer, and resolves to the
+/// output and the time it took, or to [`Elapsed`] if the timer fires first.
+///
+/// The timer is any future that completes once the time limit has passed,
+/// such as `tokio::time::sleep(limit)`, so that `Timeout` works with every
+/// async runtime. Usually created by [`time_or_timeout!`](crate::time_or_timeout),
+/// which picks the timer of the runtime enabled by the crate features.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::Timeout;
+/// use std::future::pending;
+/// use std::time::Duration;
+///
+/// async fn run() {
+///     let limit = Duration::from_secs(1);
+///     // A timer that fires right away
+///     let result = Timeout::new(pending::<u32>(), async {}, limit).await;
+///     assert_eq!(result.unwrap_err().limit, limit);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Timeout<F, S> {
+    future: F,
+    sleep: S,
+    limit: Duration,
+    /// A reading of the [`clock`](crate::clock) at the first poll
+    start: Option<u64>,
+}
+
+impl<F, S> Timeout<F, S> {
+    /// Races `future` against `sleep`, a timer that completes after `limit`.
+    pub fn new(future: F, sleep: S, limit: Duration) -> Self {
+        Timeout {
+            future,
+            sleep,
+            limit,
+            start: None,
+        }
+    }
+}
+
+impl<F: Future, S: Future> Future for Timeout<F, S> {
+    type Output = Result<(Duration, F::Output), Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` and `sleep` are pinned along with `self` and never
+        // moved out; the other fields are not structurally pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let start = *this.start.get_or_insert_with(|| if cfg!(feature = "disable") { 0 } else { crate::clock::now() });
+        let elapsed = || {
+            if cfg!(feature = "disable") {
+                return Duration::ZERO;
+            }
+            crate::calibration::correct(crate::clock::elapsed_since(start))
+        };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok((elapsed(), output)));
+        }
+        let sleep = unsafe { Pin::new_unchecked(&mut this.sleep) };
+        if sleep.poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let after = if cfg!(feature = "disable") { this.limit } else { elapsed() };
+        Poll::Ready(Err(Elapsed { limit: this.limit, after }))
+    }
+}
+
+/// A timer for [`Timeout`] without an async runtime: a thread that sleeps
+/// until the time limit and then wakes the task.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct ThreadSleep {
+    duration: Duration,
+    state: Option<Arc<Mutex<SleepState>>>,
+}
+
+#[derive(Debug, Default)]
+struct SleepState {
+    done: bool,
+    waker: Option<Waker>,
+}
+
+impl ThreadSleep {
+    /// Creates a timer that completes `duration` after its first poll.
+    pub fn new(duration: Duration) -> Self {
+        ThreadSleep { duration, state: None }
+    }
+}
+
+impl Future for ThreadSleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let duration = self.duration;
+        let state = self.state.get_or_insert_with(|| {
+            let state = Arc::new(Mutex::new(SleepState::default()));
+            let timer = Arc::clone(&state);
+            std::thread::spawn(move || {
+                std::thread::sleep(duration);
+                let mut state = timer.lock().unwrap_or_else(PoisonError::into_inner);
+                state.done = true;
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+            state
+        });
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.done {
+            return Poll::Ready(());
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+    use std::task::Wake;
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// A future that is pending for `polls` polls, advancing the clock by
+    /// 1 ms each time.
+    struct Ticks<'a> {
+        clock: &'a MockClock,
+        polls: u32,
+    }
+
+    impl Future for Ticks<'_> {
+        type Output = u32;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            self.clock.advance(Duration::from_millis(1));
+            if self.polls == 0 {
+                return Poll::Ready(7);
+            }
+            self.polls -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn times_futures() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let future = Ticks { clock: &clock, polls: 2 }.timed();
+        clock.advance(Duration::from_secs(1)); // Not polled yet
+        assert_eq!(block_on(future), (Duration::from_millis(3), 7));
+
+        assert_eq!(block_on(Ticks { clock: &clock, polls: 4 }.timed_with("future-ticks")), 7);
+        let stats = registry::stats("future-ticks").unwrap();
+        assert_eq!((stats.count, stats.total), (1, Duration::from_millis(5)));
+    }
+
+    #[test]
+    fn breaks_futures_down_by_polls() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let future = Ticks { clock: &clock, polls: 2 }.instrumented();
+        clock.advance(Duration::from_millis(4));
+        let (stats, value) = block_on(future);
+        assert_eq!(value, 7);
+        let expected = PollStats {
+            polls: 3,
+            busy: Duration::from_millis(3),
+            elapsed: Duration::from_millis(3),
+            to_first_poll: Duration::from_millis(4),
+        };
+        assert_eq!(stats, expected);
+        assert_eq!((stats.idle(), stats.mean_poll()), (Duration::ZERO, Duration::from_millis(1)));
+        assert_eq!(stats.to_string(), "3 polls, busy: 3 ms, idle: 0 ns, first poll after 4 ms");
+
+        // Waiting to be polled again is idle time
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(Ticks { clock: &clock, polls: 1 }.instrumented());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        clock.advance(Duration::from_millis(10));
+        let Poll::Ready((stats, _)) = future.as_mut().poll(&mut cx) else {
+            panic!("future did not complete");
+        };
+        assert_eq!((stats.polls, stats.busy, stats.idle()), (2, Duration::from_millis(2), Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn times_out_futures() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+        let limit = Duration::from_millis(4);
+
+        let sleep = Ticks { clock: &clock, polls: 9 };
+        let result = block_on(Timeout::new(Ticks { clock: &clock, polls: 1 }, sleep, limit));
+        assert_eq!(result, Ok((Duration::from_millis(3), 7)));
+
+        let sleep = Ticks { clock: &clock, polls: 1 };
+        let result = block_on(Timeout::new(std::future::pending::<()>(), sleep, limit));
+        let elapsed = Elapsed {
+            limit,
+            after: Duration::from_millis(2),
+        };
+        assert_eq!(result, Err(elapsed));
+        assert_eq!(elapsed.to_string(), "timed out after 2 ms");
+    }
+}
@@ -0,0 +1,260 @@
+//! HDR-style histograms for percentile statistics.
+//!
+//! A [`Histogram`] records durations into log-linear buckets: every power of
+//! two is split into enough linear sub-buckets to keep the relative error of
+//! any reported value below the configured number of significant decimal
+//! digits. Memory grows with the number of distinct buckets used, not with
+//! the number of recorded values.
+//!
+//! With the `histogram` feature enabled, the [`registry`](crate::registry)
+//! keeps a histogram for every label and reports p50/p90/p99/p99.9.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::histogram::Histogram;
+//! use std::time::Duration;
+//!
+//! let mut histogram = Histogram::new(3);
+//! for micros in 1..=1000 {
+//!     histogram.record(Duration::from_micros(micros));
+//! }
+//!
+//! let p99 = histogram.percentile(99.0);
+//! assert!(p99 >= Duration::from_micros(989) && p99 <= Duration::from_micros(991));
+//! println!("{}", histogram.percentiles()); // "p50: 500 µs, p90: 900 µs, p99: 990 µs, p99.9: 999 µs"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::HumanDuration;
+
+/// The default number of significant decimal digits.
+pub const DEFAULT_SIGNIFICANT_DIGITS: u8 = 3;
+
+/// A histogram of durations with bounded relative error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Histogram {
+    significant_digits: u8,
+    /// Number of bits needed to index the linear sub-buckets of a power of two
+    sub_bucket_bits: u32,
+    /// Recorded counts by bucket index
+    buckets: BTreeMap<u32, u64>,
+    count: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    /// Creates an empty histogram that reports values with the given number of
+    /// significant decimal digits, clamped to `1..=5`.
+    pub fn new(significant_digits: u8) -> Self {
+        let significant_digits = significant_digits.clamp(1, 5);
+        let sub_buckets = 10u64.pow(u32::from(significant_digits));
+        Histogram {
+            significant_digits,
+            sub_bucket_bits: sub_buckets.next_power_of_two().trailing_zeros() + 1,
+            buckets: BTreeMap::new(),
+            count: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Returns the number of significant decimal digits of this histogram.
+    pub fn significant_digits(&self) -> u8 {
+        self.significant_digits
+    }
+
+    /// Records a duration.
+    pub fn record(&mut self, duration: Duration) {
+        self.record_n(duration, 1);
+    }
+
+    /// Records a duration `count` times, e.g. for a sample that stands for
+    /// `count` measurements.
+    pub fn record_n(&mut self, duration: Duration, count: u64) {
+        if count == 0 {
+            return;
+        }
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        *self.buckets.entry(self.index(nanos)).or_default() += count;
+        self.count += count;
+        self.min = self.min.min(nanos);
+        self.max = self.max.max(nanos);
+    }
+
+    /// Adds all values recorded in `other` to this histogram.
+    ///
+    /// Both histograms must have the same number of significant digits.
+    pub fn merge(&mut self, other: &Histogram) {
+        assert_eq!(
+            self.significant_digits, other.significant_digits,
+            "cannot merge histograms with different precisions"
+        );
+        for (&index, &count) in &other.buckets {
+            *self.buckets.entry(index).or_default() += count;
+        }
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Returns the number of recorded values.
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns `true` if no values were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the smallest recorded value, or zero if empty.
+    pub fn min(&self) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.min)
+    }
+
+    /// Returns the largest recorded value.
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+
+    /// Returns the value below which `percentile` percent of the recorded
+    /// values fall, e.g. `percentile(99.0)` for p99.
+    ///
+    /// The result is accurate to the histogram's significant digits. Returns
+    /// zero if the histogram is empty.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+        let fraction = (percentile / 100.0).clamp(0.0, 1.0);
+        let rank = ((fraction * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (&index, &count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                let value = self.highest_equivalent(index).clamp(self.min, self.max);
+                return Duration::from_nanos(value);
+            }
+        }
+        self.max()
+    }
+
+    /// Returns the commonly reported percentiles.
+    pub fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            p50: self.percentile(50.0),
+            p90: self.percentile(90.0),
+            p95: self.percentile(95.0),
+            p99: self.percentile(99.0),
+            p999: self.percentile(99.9),
+        }
+    }
+
+    fn index(&self, value: u64) -> u32 {
+        let bits = self.sub_bucket_bits;
+        let half = 1u64 << (bits - 1);
+        if value < 1u64 << bits {
+            return value as u32;
+        }
+        let shift = 64 - value.leading_zeros() - bits;
+        let top = value >> shift;
+        ((1u64 << bits) + u64::from(shift - 1) * half + (top - half)) as u32
+    }
+
+    /// Returns the largest value that falls into the bucket at `index`.
+    fn highest_equivalent(&self, index: u32) -> u64 {
+        let bits = self.sub_bucket_bits;
+        let half = 1u64 << (bits - 1);
+        let index = u64::from(index);
+        if index < 1u64 << bits {
+            return index;
+        }
+        let offset = index - (1u64 << bits);
+        let shift = offset / half + 1;
+        let top = offset % half + half;
+        let high = ((u128::from(top) + 1) << shift) - 1;
+        u64::try_from(high).unwrap_or(u64::MAX)
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new(DEFAULT_SIGNIFICANT_DIGITS)
+    }
+}
+
+/// The p50, p90, p95, p99 and p99.9 of a [`Histogram`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Percentiles {
+    /// The median
+    pub p50: Duration,
+    /// The 90th percentile
+    pub p90: Duration,
+    /// The 95th percentile
+    pub p95: Duration,
+    /// The 99th percentile
+    pub p99: Duration,
+    /// The 99.9th percentile
+    pub p999: Duration,
+}
+
+impl fmt::Display for Percentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "p50: {}, p90: {}, p99: {}, p99.9: {}",
+            HumanDuration(self.p50),
+            HumanDuration(self.p90),
+            HumanDuration(self.p99),
+            HumanDuration(self.p999)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stays_within_precision() {
+        for digits in 1..=4 {
+            let histogram = Histogram::new(digits);
+            let tolerance = 10f64.powi(-i32::from(digits));
+            for value in [1u64, 999, 1_000, 123_456, 98_765_432, 1 << 40, u64::MAX / 3] {
+                let index = histogram.index(value);
+                let high = histogram.highest_equivalent(index);
+                assert!(high >= value);
+                assert!((high - value) as f64 <= value as f64 * tolerance, "{} -> {}", value, high);
+                assert_eq!(histogram.index(high), index);
+            }
+        }
+    }
+
+    #[test]
+    fn reports_percentiles() {
+        let mut histogram = Histogram::new(2);
+        assert_eq!(histogram.percentile(50.0), Duration::ZERO);
+        for millis in 1..=100 {
+            histogram.record(Duration::from_millis(millis));
+        }
+        let mut other = Histogram::new(2);
+        other.record(Duration::from_secs(10));
+        histogram.merge(&other);
+
+        assert_eq!(histogram.len(), 101);
+        assert_eq!(histogram.min(), Duration::from_millis(1));
+        assert_eq!(histogram.percentile(100.0), Duration::from_secs(10));
+        let p50 = histogram.percentiles().p50.as_secs_f64();
+        assert!((0.0505..0.0515).contains(&p50), "{}", p50);
+    }
+}
@@ -0,0 +1,155 @@
+//! Standalone HTML pages of reports.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::registry::Report;
+use crate::HumanDuration;
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; }
+th, td { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; text-align: right; white-space: nowrap; }
+th { cursor: pointer; user-select: none; background: #f4f4f4; }
+th:first-child, td:first-child { text-align: left; }
+td.bar { width: 20em; text-align: left; }
+td.bar div { background: #4a90d9; height: 0.9em; border-radius: 2px; }
+";
+
+/// Sorts the table by the clicked column, on the `data-value` of its cells.
+const SCRIPT: &str = "
+document.querySelectorAll('th').forEach((th, column) => th.addEventListener('click', () => {
+  const body = th.closest('table').tBodies[0];
+  const descending = th.dataset.order !== 'desc';
+  th.dataset.order = descending ? 'desc' : 'asc';
+  const value = row => row.cells[column].dataset.value;
+  const rows = Array.from(body.rows).sort((a, b) => {
+    const [x, y] = [value(a), value(b)];
+    const order = isNaN(x) ? x.localeCompare(y) : x - y;
+    return descending ? -order : order;
+  });
+  rows.forEach(row => body.appendChild(row));
+}));
+";
+
+impl Report {
+    /// Formats the report as a standalone HTML page, with a table that is
+    /// sorted by clicking its headers and a bar chart of each label's total
+    /// time.
+    ///
+    /// The page has no external dependencies, so it can be shared as a
+    /// single file. Requires the `html` feature.
+    pub fn to_html(&self) -> String {
+        let mut columns = vec!["Label", "Count", "Total", "Self", "Mean", "Min", "Max"];
+        #[cfg(feature = "histogram")]
+        columns.extend(["p50", "p90", "p99", "p99.9"]);
+        columns.push(if self.runtime().is_some() { "% Runtime" } else { "% of Total" });
+        let whole = match self.runtime() {
+            Some(runtime) => runtime,
+            None => self.entries().iter().map(|(_, stats)| stats.total).sum(),
+        };
+        let longest = self.entries().iter().map(|(_, stats)| stats.total).max().unwrap_or_default();
+
+        let mut page = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        page.push_str("<title>arbitime report</title>\n<style>");
+        page.push_str(STYLE);
+        page.push_str("</style>\n</head>\n<body>\n<h1>arbitime report</h1>\n");
+        if let Some(runtime) = self.runtime() {
+            let _ = writeln!(page, "<p>Runtime: {}</p>", HumanDuration(runtime));
+        }
+        page.push_str("<table>\n<thead>\n<tr>");
+        for column in &columns {
+            let _ = write!(page, "<th>{}</th>", column);
+        }
+        page.push_str("<th></th></tr>\n</thead>\n<tbody>\n");
+        for (label, stats) in self.entries() {
+            page.push_str("<tr>");
+            let _ = write!(page, "<td data-value=\"{0}\">{0}</td>", escape(label));
+            let count = if stats.estimated { format!("~{}", stats.count) } else { stats.count.to_string() };
+            let _ = write!(page, "<td data-value=\"{}\">{}</td>", stats.count, count);
+            for duration in [stats.total, stats.self_total, stats.mean(), stats.min, stats.max] {
+                duration_cell(&mut page, duration);
+            }
+            #[cfg(feature = "histogram")]
+            match self.percentiles(label) {
+                Some(percentiles) => {
+                    for duration in [percentiles.p50, percentiles.p90, percentiles.p99, percentiles.p999] {
+                        duration_cell(&mut page, duration);
+                    }
+                }
+                None => page.push_str(&"<td data-value=\"0\">-</td>".repeat(4)),
+            }
+            let share = fraction(stats.total, whole) * 100.0;
+            let _ = write!(page, "<td data-value=\"{0}\">{0:.1}%</td>", share);
+            let width = fraction(stats.total, longest) * 100.0;
+            let _ = write!(page, "<td class=\"bar\" data-value=\"{0}\"><div style=\"width: {0:.1}%\"></div></td>", width);
+            page.push_str("</tr>\n");
+        }
+        page.push_str("</tbody>\n</table>\n<script>");
+        page.push_str(SCRIPT);
+        page.push_str("</script>\n</body>\n</html>\n");
+        page
+    }
+
+    /// Writes the page of [`to_html`](Self::to_html) to `path`.
+    pub fn save_html(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_html())
+    }
+}
+
+fn duration_cell(page: &mut String, duration: Duration) {
+    let _ = write!(page, "<td data-value=\"{}\">{}</td>", duration.as_nanos(), HumanDuration(duration));
+}
+
+/// Returns `part / whole`, or zero if `whole` is zero.
+fn fraction(part: Duration, whole: Duration) -> f64 {
+    match whole.as_nanos() {
+        0 => 0.0,
+        nanos => part.as_nanos() as f64 / nanos as f64,
+    }
+}
+
+/// Escapes text for HTML element content and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use crate::registry;
+    use std::time::Duration;
+
+    #[test]
+    fn writes_standalone_pages() {
+        registry::record("html-<parse>", Duration::from_millis(30));
+        registry::record("html-load", Duration::from_millis(10));
+        let report = registry::snapshot().with_runtime(Duration::from_millis(100));
+
+        let page = report.to_html();
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<p>Runtime: 100 ms</p>"));
+        assert!(page.contains("<td data-value=\"html-&lt;parse&gt;\">html-&lt;parse&gt;</td><td data-value=\"1\">1</td>"));
+        assert!(page.contains("<td data-value=\"30000000\">30 ms</td>"));
+        assert!(page.contains("<td data-value=\"30\">30.0%</td>"));
+        assert!(!page.contains("<parse>"));
+
+        let path = std::env::temp_dir().join(format!("arbitime-report-{}.html", std::process::id()));
+        report.save_html(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), page);
+        std::fs::remove_file(path).unwrap();
+    }
+}
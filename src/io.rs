@@ -0,0 +1,296 @@
+//! Timing I/O inside a block.
+//!
+//! A block timed as one lump mixes the time spent computing with the time
+//! spent waiting on files, sockets or pipes. [`TimedReader`] and
+//! [`TimedWriter`] wrap any [`Read`] or [`Write`] and accumulate the time
+//! spent inside its `read`, `write` and `flush` calls, with the number of
+//! calls and bytes transferred, as [`IoStats`]. The rest of the block's time
+//! is compute time.
+//!
+//! Wrappers created with a label report their totals when they are dropped
+//! (or [unwrapped](TimedReader::into_inner)): the I/O time is recorded in the
+//! [`registry`](crate::registry) under the label and logged like
+//! [`log_time!`](crate::log_time) output with the transfer rate, e.g.
+//! `"config read - Execution time: 2.1 ms (1.2 GiB/s)"`. Like a
+//! [`ScopeTimer`](crate::ScopeTimer), they print to stderr, or log through
+//! the `log` crate with the `log` feature, unless a [`sink`](crate::sink) is
+//! installed.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::io::{TimedReader, TimedWriter};
+//! use arbitime::time;
+//! use std::io::{Read, Write};
+//!
+//! let mut reader = TimedReader::new(&b"1 2 3 4 5"[..]);
+//! let mut writer = TimedWriter::labeled("results written", Vec::new());
+//! let (total, ()) = time!({
+//!     let mut text = String::new();
+//!     reader.read_to_string(&mut text).unwrap();
+//!     let sum: u32 = text.split(' ').map(|n| n.parse::<u32>().unwrap()).sum();
+//!     writeln!(writer, "{}", sum).unwrap();
+//! });
+//!
+//! let io = reader.stats().time + writer.stats().time;
+//! println!("I/O: {:?}, compute: {:?}", io, total.saturating_sub(io));
+//! # #[cfg(not(feature = "disable"))]
+//! assert_eq!(reader.stats().bytes, 9);
+//! assert_eq!(writer.into_inner(), b"15\n"); // Prints: "results written - Execution time: ..."
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::panic::Location;
+use std::time::Duration;
+
+use crate::{registry, HumanDuration, Throughput, TimingRecord};
+
+/// The I/O done through a [`TimedReader`] or [`TimedWriter`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct IoStats {
+    /// The number of `read`, `write` or `flush` calls
+    pub calls: u64,
+    /// The number of bytes transferred
+    pub bytes: u64,
+    /// The time spent inside the calls
+    pub time: Duration,
+}
+
+impl IoStats {
+    /// Returns the transfer rate during the calls in bytes per second, or
+    /// zero if no time was measured.
+    pub fn bytes_per_sec(&self) -> f64 {
+        Throughput::Bytes(self.bytes).per_second(self.time)
+    }
+
+    /// Times an I/O call transferring the number of bytes it returns.
+    fn add<T>(&mut self, call: impl FnOnce() -> io::Result<T>, bytes: impl Fn(&T) -> usize) -> io::Result<T> {
+        if cfg!(feature = "disable") {
+            return call();
+        }
+        let start = crate::clock::now();
+        let result = call();
+        self.time += crate::clock::elapsed_since(start);
+        self.calls += 1;
+        if let Ok(value) = &result {
+            self.bytes += bytes(value) as u64;
+        }
+        result
+    }
+}
+
+impl fmt::Display for IoStats {
+    /// Formats the statistics as e.g.
+    /// `1048576 bytes in 16 calls, 2.1 ms (476.19 MiB/s)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes in {} calls, {} ({})",
+            self.bytes,
+            self.calls,
+            HumanDuration(self.time),
+            Throughput::Bytes(self.bytes).rate(self.time)
+        )
+    }
+}
+
+/// The statistics of a wrapper, reported on drop if it has a label.
+#[derive(Debug)]
+struct Totals {
+    stats: IoStats,
+    label: Option<Cow<'static, str>>,
+    location: &'static Location<'static>,
+}
+
+impl Drop for Totals {
+    fn drop(&mut self) {
+        let Some(label) = self.label.take() else {
+            return;
+        };
+        if cfg!(feature = "disable") || !crate::is_enabled() || !crate::filter::enabled(Some(&label)) {
+            return;
+        }
+        registry::record(&label, self.stats.time);
+        let mut record = TimingRecord::new(
+            Some(label.into_owned()),
+            self.stats.time,
+            self.location.file(),
+            self.location.line(),
+        );
+        record.throughput = Some(Throughput::Bytes(self.stats.bytes));
+        crate::sink::dispatch(vec![record], crate::sink::emit_message);
+    }
+}
+
+/// A [`Read`] adapter that measures the time spent reading.
+///
+/// See the [module documentation](self) for an example.
+#[derive(Debug)]
+pub struct TimedReader<R> {
+    inner: R,
+    totals: Totals,
+}
+
+impl<R> TimedReader<R> {
+    /// Wraps a reader, without reporting its statistics.
+    #[track_caller]
+    pub fn new(inner: R) -> Self {
+        TimedReader {
+            inner,
+            totals: Totals {
+                stats: IoStats::default(),
+                label: None,
+                location: Location::caller(),
+            },
+        }
+    }
+
+    /// Wraps a reader, and reports its statistics with the given label when
+    /// the wrapper is dropped or unwrapped.
+    #[track_caller]
+    pub fn labeled(label: impl Into<Cow<'static, str>>, inner: R) -> Self {
+        let mut reader = TimedReader::new(inner);
+        reader.totals.label = Some(label.into());
+        reader
+    }
+
+    /// Returns the statistics of the reads so far.
+    pub fn stats(&self) -> IoStats {
+        self.totals.stats
+    }
+
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped reader. Reads made through
+    /// it are not measured.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Reports the statistics if the wrapper has a label, and returns the
+    /// wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for TimedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        self.totals.stats.add(|| inner.read(buf), |&read| read)
+    }
+}
+
+/// A [`Write`] adapter that measures the time spent writing and flushing.
+///
+/// See the [module documentation](self) for an example.
+#[derive(Debug)]
+pub struct TimedWriter<W> {
+    inner: W,
+    totals: Totals,
+}
+
+impl<W> TimedWriter<W> {
+    /// Wraps a writer, without reporting its statistics.
+    #[track_caller]
+    pub fn new(inner: W) -> Self {
+        TimedWriter {
+            inner,
+            totals: Totals {
+                stats: IoStats::default(),
+                label: None,
+                location: Location::caller(),
+            },
+        }
+    }
+
+    /// Wraps a writer, and reports its statistics with the given label when
+    /// the wrapper is dropped or unwrapped.
+    #[track_caller]
+    pub fn labeled(label: impl Into<Cow<'static, str>>, inner: W) -> Self {
+        let mut writer = TimedWriter::new(inner);
+        writer.totals.label = Some(label.into());
+        writer
+    }
+
+    /// Returns the statistics of the writes and flushes so far.
+    pub fn stats(&self) -> IoStats {
+        self.totals.stats
+    }
+
+    /// Returns a reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped writer. Writes made through
+    /// it are not measured.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Reports the statistics if the wrapper has a label, and returns the
+    /// wrapped writer without flushing it.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for TimedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        self.totals.stats.add(|| inner.write(buf), |&written| written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let inner = &mut self.inner;
+        self.totals.stats.add(|| inner.flush(), |_| 0)
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+
+    /// A reader whose reads take 2 ms each on the mock clock.
+    struct Slow<'a>(&'a MockClock, &'a [u8]);
+
+    impl Read for Slow<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.advance(Duration::from_millis(2));
+            let len = buf.len().min(self.1.len()).min(4);
+            buf[..len].copy_from_slice(&self.1[..len]);
+            self.1 = &self.1[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn measures_reads_and_writes() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let mut reader = TimedReader::new(Slow(&clock, b"0123456789"));
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "0123456789");
+        // Three reads of data and one at the end
+        let stats = reader.stats();
+        assert_eq!((stats.calls, stats.bytes, stats.time), (4, 10, Duration::from_millis(8)));
+        assert_eq!(stats.bytes_per_sec(), 1250.0);
+        assert_eq!(stats.to_string(), "10 bytes in 4 calls, 8 ms (1.22 KiB/s)");
+
+        let mut writer = TimedWriter::labeled("io-written", Vec::new());
+        writer.write_all(b"hello").unwrap();
+        writer.flush().unwrap();
+        assert_eq!((writer.stats().calls, writer.stats().bytes), (2, 5));
+        assert_eq!(writer.into_inner(), b"hello");
+        assert_eq!(registry::stats("io-written").unwrap().count, 1);
+    }
+}
@@ -0,0 +1,156 @@
+//! Iterator adapters that time each item.
+
+use std::iter::FusedIterator;
+use std::time::Duration;
+
+/// Extension trait that adds per-item timing to every [`Iterator`].
+///
+/// The time measured for an item is the time the underlying iterator took to
+/// produce it, i.e. the duration of its `next()` call. For a pipeline such as
+/// `lines.map(parse).filter(valid)`, that is the processing time of the item
+/// through every stage before the adapter.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::TimedIteratorExt;
+///
+/// let inputs = ["1", "22", "333"];
+/// for (duration, value) in inputs.iter().map(|s| s.parse::<u32>().unwrap()).timed() {
+///     println!("parsed {} in {:?}", value, duration);
+/// }
+///
+/// // Report without changing the items
+/// let total: u32 = inputs
+///     .iter()
+///     .map(|s| s.parse::<u32>().unwrap())
+///     .timed_with(|duration, value| println!("parsed {} in {:?}", value, duration))
+///     .sum();
+/// assert_eq!(total, 356);
+/// ```
+pub trait TimedIteratorExt: Iterator + Sized {
+    /// Yields a `(Duration, Item)` pair for every item.
+    fn timed(self) -> Timed<Self> {
+        Timed { iter: self }
+    }
+
+    /// Calls `f` with the duration and a reference to every item, and yields
+    /// the items unchanged.
+    fn timed_with<F>(self, f: F) -> TimedWith<Self, F>
+    where
+        F: FnMut(Duration, &Self::Item),
+    {
+        TimedWith { iter: self, f }
+    }
+}
+
+impl<I: Iterator> TimedIteratorExt for I {}
+
+/// Times a call to `next`, without reading the clock when timing is disabled.
+fn time_next<I: Iterator>(iter: &mut I) -> (Duration, Option<I::Item>) {
+    if cfg!(feature = "disable") {
+        return (Duration::ZERO, iter.next());
+    }
+    let start = crate::clock::now();
+    let item = iter.next();
+    (crate::calibration::correct(crate::clock::elapsed_since(start)), item)
+}
+
+/// An iterator that yields each item with the time it took to produce.
+///
+/// Created by [`TimedIteratorExt::timed`].
+#[derive(Debug, Clone)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct Timed<I> {
+    iter: I,
+}
+
+impl<I: Iterator> Iterator for Timed<I> {
+    type Item = (Duration, I::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (duration, item) = time_next(&mut self.iter);
+        item.map(|item| (duration, item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<I: ExactSizeIterator> ExactSizeIterator for Timed<I> {}
+
+impl<I: FusedIterator> FusedIterator for Timed<I> {}
+
+/// An iterator that reports the time it took to produce each item.
+///
+/// Created by [`TimedIteratorExt::timed_with`].
+#[derive(Clone)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct TimedWith<I, F> {
+    iter: I,
+    f: F,
+}
+
+impl<I: std::fmt::Debug, F> std::fmt::Debug for TimedWith<I, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimedWith").field("iter", &self.iter).finish_non_exhaustive()
+    }
+}
+
+impl<I, F> Iterator for TimedWith<I, F>
+where
+    I: Iterator,
+    F: FnMut(Duration, &I::Item),
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (duration, item) = time_next(&mut self.iter);
+        if let Some(item) = &item {
+            (self.f)(duration, item);
+        }
+        item
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<I, F> ExactSizeIterator for TimedWith<I, F>
+where
+    I: ExactSizeIterator,
+    F: FnMut(Duration, &I::Item),
+{
+}
+
+impl<I, F> FusedIterator for TimedWith<I, F>
+where
+    I: FusedIterator,
+    F: FnMut(Duration, &I::Item),
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_every_item() {
+        let slow = [2u64, 0, 1]
+            .into_iter()
+            .inspect(|millis| std::thread::sleep(Duration::from_millis(*millis)));
+        let timed: Vec<_> = slow.timed().collect();
+        assert_eq!(timed.len(), 3);
+        if !cfg!(feature = "disable") {
+            assert!(timed[0].0 >= Duration::from_millis(2));
+        }
+        assert_eq!(timed.iter().map(|(_, item)| *item).collect::<Vec<_>>(), [2, 0, 1]);
+
+        let mut seen = Vec::new();
+        let items: Vec<_> = (1..=3).timed_with(|_, item| seen.push(*item)).collect();
+        assert_eq!(items, seen);
+        assert_eq!((0..3).timed().len(), 3);
+    }
+}
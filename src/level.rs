@@ -0,0 +1,43 @@
+//! Severity levels for timing output.
+
+use core::fmt;
+
+/// The level at which a timing message is logged.
+///
+/// With the `log` feature enabled, these map one-to-one onto the levels of the
+/// [`log`](https://docs.rs/log) crate. Without it, messages are always printed
+/// to stderr and the level is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Level {
+    /// Designates very serious errors
+    Error,
+    /// Designates hazardous situations
+    Warn,
+    /// Designates useful information (the default)
+    #[default]
+    Info,
+    /// Designates lower priority information
+    Debug,
+    /// Designates very low priority, often extremely verbose, information
+    Trace,
+}
+
+impl Level {
+    /// Returns the upper-case name of this level, e.g. `"INFO"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
@@ -4,9 +4,23 @@
 //! 
 //! ## Features
 //! 
-//! - [`time!`] - Time code execution and return both duration and result
-//! - [`format_time!`] - Time code execution and format duration as a string
-//! - [`log_time!`] - Time code execution with automatic logging to stderr
+//! | Area | Entry points |
+//! |------|--------------|
+//! | Measuring | [`time!`], [`time_ex!`], [`time_ms!`], [`time_async!`], [`time_scope!`], [`fns`], [`Stopwatch`], [`begin`] / [`end`] |
+//! | Logging | [`log_time!`], [`debug_time!`], [`format_time!`], [`json_time!`], [`template`], [`format`](mod@format), [`sink`], [`Config`] |
+//! | Phases and loops | [`checkpoint`](mod@checkpoint), [`time_iterations!`], [`time_every!`], [`TimedIteratorExt`], [`RateCounter`], [`TimeBudget`] |
+//! | Budgets and failures | [`assert_time!`], [`warn_if_over!`], [`time_result!`], [`retry_timed!`], [`time_catch!`], [`slow`] |
+//! | Async | [`TimedFutureExt`], [`PollStats`], [`time_or_timeout!`], `task` (`tokio` feature), `stream` (`stream` feature) |
+//! | Resources | [`cpu`], [`alloc`], [`io`], [`sync`], [`TimedCommandExt`], `perf_time!` (`perf` feature) |
+//! | Benchmarks | [`bench_time!`], [`compare_time!`], [`time_n!`], [`calibration`], [`black_box_input!`] |
+//! | Aggregation | [`registry`], [`histogram`], [`window`], [`reservoir`], [`baseline`], [`callsite`], [`trace`], [`nesting`] |
+//! | Clocks | [`clock`], [`test::MockClock`], `no_std` through [`clock::set_tick_source`] |
+//! | Control | [`set_enabled`], [`filter`], [`config`], [`context`] |
+//! | Attributes | [`timed`], [`timed_test`] (`macros` feature, on by default) |
+//! | Parsing | [`parse`] |
+//! 
+//! Integrations with `log`, `tracing`, `metrics`, `defmt`, `tower` and other
+//! crates are opt-in Cargo features, listed in the README.
 //! 
 //! ## Examples
 //! 
@@ -51,6 +65,187 @@
 //! };
 //! ```
 
+#![cfg_attr(
+    feature = "macros",
+    doc = r#"
+### Timing whole functions with [`timed`]
+
+```rust
+use arbitime::timed;
+
+#[timed(threshold = "1ms")]
+fn compute() -> u32 {
+    (1..=1000).sum::<u32>()
+}
+
+compute(); // Prints "compute - Execution time: ..." if it took 1ms or more
+```
+"#
+)]
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+pub mod alloc;
+#[cfg(feature = "std")]
+pub mod baseline;
+#[cfg(feature = "std")]
+mod bench;
+#[cfg(feature = "std")]
+mod budget;
+pub mod calibration;
+#[cfg(feature = "std")]
+pub mod callsite;
+#[cfg(feature = "std")]
+pub mod checkpoint;
+pub mod clock;
+#[cfg(feature = "std")]
+mod command;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod context;
+#[cfg(feature = "color")]
+pub mod color;
+#[cfg(feature = "std")]
+pub mod cpu;
+#[cfg(feature = "std")]
+mod every;
+#[cfg(feature = "std")]
+pub mod filter;
+pub mod fns;
+#[cfg(feature = "std")]
+pub mod format;
+#[cfg(feature = "std")]
+mod future;
+#[cfg(feature = "std")]
+pub mod histogram;
+#[cfg(feature = "html")]
+mod html;
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(feature = "std")]
+mod iter;
+mod level;
+#[cfg(feature = "std")]
+pub mod nesting;
+#[cfg(feature = "std")]
+pub mod parse;
+#[cfg(feature = "perf")]
+pub mod perf;
+#[cfg(feature = "std")]
+mod rate;
+#[cfg(feature = "std")]
+mod record;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod reservoir;
+#[cfg(feature = "std")]
+mod retry;
+#[cfg(feature = "std")]
+mod scope;
+#[cfg(all(feature = "signal", unix))]
+pub mod signal;
+#[cfg(feature = "std")]
+mod series;
+#[cfg(feature = "std")]
+pub mod sink;
+#[cfg(feature = "std")]
+pub mod slow;
+#[cfg(feature = "std")]
+mod span;
+#[cfg(feature = "std")]
+mod stopwatch;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "std")]
+mod switch;
+#[cfg(feature = "std")]
+pub mod sync;
+#[cfg(feature = "std")]
+pub mod template;
+#[cfg(feature = "tokio")]
+pub mod task;
+#[cfg(feature = "std")]
+pub mod test;
+#[cfg(feature = "std")]
+mod timing;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "watchdog")]
+pub mod watchdog;
+#[cfg(feature = "std")]
+pub mod window;
+
+#[cfg(feature = "macros")]
+pub use arbitime_macros::{timed, timed_test};
+
+#[cfg(feature = "log")]
+#[doc(hidden)]
+pub use log as __log;
+
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use tracing as __tracing;
+
+#[cfg(feature = "tokio")]
+#[doc(hidden)]
+pub use tokio as __tokio;
+
+// Lets the attribute macros' `::arbitime` paths resolve in the crate's own tests
+#[cfg(all(test, feature = "macros"))]
+extern crate self as arbitime;
+#[cfg(feature = "std")]
+pub use bench::{BenchStats, Comparison};
+#[cfg(feature = "std")]
+pub use budget::{Phase, TimeBudget};
+#[cfg(feature = "std")]
+pub use calibration::calibrate;
+#[cfg(feature = "std")]
+pub use checkpoint::Split;
+#[cfg(feature = "std")]
+pub use command::TimedCommandExt;
+#[cfg(feature = "std")]
+pub use config::Config;
+#[cfg(feature = "std")]
+pub use context::with_context;
+#[cfg(feature = "std")]
+pub use format::{DurationExt, HumanDuration};
+#[cfg(feature = "std")]
+pub use future::{Elapsed, InstrumentedFuture, LoggedFuture, PollStats, TimedFuture, TimedFutureExt, Timeout};
+#[cfg(feature = "std")]
+pub use iter::{Timed, TimedIteratorExt, TimedWith};
+pub use level::Level;
+#[cfg(feature = "std")]
+pub use rate::{Frame, RateCounter};
+#[cfg(feature = "std")]
+pub use record::{FieldValue, Outcome, Throughput, TimingRecord};
+#[cfg(feature = "std")]
+pub use registry::{report, report_on_exit, spawn_reporter};
+#[cfg(feature = "std")]
+pub use retry::{Attempt, Backoff};
+#[cfg(feature = "std")]
+pub use scope::ScopeTimer;
+#[cfg(feature = "std")]
+pub use series::{Iteration, Series};
+#[cfg(feature = "std")]
+pub use sink::{set_sink, TimeSink};
+#[cfg(feature = "std")]
+pub use slow::on_slow;
+#[cfg(feature = "std")]
+pub use span::{begin, end, SpanId};
+#[cfg(feature = "std")]
+pub use stopwatch::Stopwatch;
+#[cfg(feature = "std")]
+pub use stream::{StreamStats, StreamTimer};
+#[cfg(feature = "std")]
+pub use switch::{is_enabled, set_enabled};
+#[cfg(feature = "std")]
+pub use template::set_template;
+#[cfg(feature = "std")]
+pub use timing::Timing;
+
 /// Times the execution of a code block and returns both the duration and result.
 /// 
 /// This macro measures the time it takes to execute the given code and returns
@@ -73,6 +268,15 @@
 /// println!("Computation took: {:?}", duration);
 /// ```
 /// 
+/// With the `disable` feature enabled, the body is run without calling
+/// [`Instant::now`](std::time::Instant::now) and the duration is always
+/// [`Duration::ZERO`](std::time::Duration::ZERO).
+/// 
+/// With the `tracing` feature enabled, the body runs inside a
+/// [`tracing`](https://docs.rs/tracing) span named `time`, and the measured
+/// duration is recorded in its `elapsed_ns` field. Labelled macros such as
+/// [`log_time!`] also set a `label` field on the span.
+/// 
 /// # Returns
 /// 
 /// A tuple `(Duration, T)` where:
@@ -80,12 +284,158 @@
 /// - `T` is the result of the executed code
 #[macro_export]
 macro_rules! time {
-    ($($body:tt)*) => {{
-        let __start = std::time::Instant::now();
-        let __result = { $($body)* };
-        let __duration = __start.elapsed();
+    ($($body:tt)*) => {
+        $crate::__time!($crate::__span!(), $($body)*)
+    };
+}
+/// Times a code block inside the given span and records the duration on it.
+#[cfg(not(feature = "disable"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __time {
+    ($span:expr, expr: $body:expr) => {{
+        let __span = $span;
+        let (__duration, __result) = {
+            let __entered = __span.enter();
+            let __start = $crate::clock::now();
+            let __result = $body;
+            ($crate::calibration::correct($crate::clock::elapsed_since(__start)), __result)
+        };
+        __span.record("elapsed_ns", __duration.as_nanos() as u64);
         (__duration, __result)
     }};
+    ($span:expr, $($body:tt)*) => {
+        $crate::__time!($span, expr: { $($body)* })
+    };
+}
+/// Runs a code block without timing it when the `disable` feature is enabled.
+#[cfg(feature = "disable")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __time {
+    ($span:expr, expr: $body:expr) => {
+        (::core::time::Duration::ZERO, $body)
+    };
+    ($span:expr, $($body:tt)*) => {
+        (::core::time::Duration::ZERO, { $($body)* })
+    };
+}
+/// Times a code block like [`time!`] and returns the elapsed time in
+/// milliseconds as an `f64`, with the result.
+/// 
+/// [`time_us!`] and [`time_ns!`] return microseconds and nanoseconds instead,
+/// for callers that feed the number straight into metrics or arithmetic.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_ms;
+/// 
+/// let (millis, result) = time_ms!((1..=1000).sum::<u32>());
+/// assert!(millis >= 0.0);
+/// assert_eq!(result, 500500);
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(f64, T)` of the elapsed milliseconds and the result of the code.
+#[macro_export]
+macro_rules! time_ms {
+    ($($body:tt)*) => {{
+        let (duration, result) = $crate::time!($($body)*);
+        (duration.as_secs_f64() * 1e3, result)
+    }};
+}
+/// Times a code block like [`time!`] and returns the elapsed time in
+/// microseconds as an `f64`, with the result.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_us;
+/// 
+/// let (micros, _) = time_us!(std::thread::sleep(std::time::Duration::from_millis(1)));
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(micros >= 1000.0);
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(f64, T)` of the elapsed microseconds and the result of the code.
+#[macro_export]
+macro_rules! time_us {
+    ($($body:tt)*) => {{
+        let (duration, result) = $crate::time!($($body)*);
+        (duration.as_secs_f64() * 1e6, result)
+    }};
+}
+/// Times a code block like [`time!`] and returns the elapsed time in whole
+/// nanoseconds as a `u128`, with the result.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_ns;
+/// 
+/// let (nanos, result) = time_ns!(2 + 2);
+/// let nanos: u128 = nanos;
+/// assert_eq!(result, 4);
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(u128, T)` of the elapsed nanoseconds and the result of the code.
+#[macro_export]
+macro_rules! time_ns {
+    ($($body:tt)*) => {{
+        let (duration, result) = $crate::time!($($body)*);
+        (duration.as_nanos(), result)
+    }};
+}
+/// Times a code block like [`time!`] and returns a [`Timing`], which keeps
+/// the start and end of the measurement, its label and call site with the
+/// result.
+/// 
+/// The code can be given a label with `message => code`, or a format string
+/// and its arguments with `"format", args => code`. Like [`time!`], nothing
+/// is logged or recorded: the measurement is only returned, for code that
+/// stores or forwards it.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_ex;
+/// 
+/// let timing = time_ex!("load {}", "users" => vec!["ada", "grace"]);
+/// assert_eq!(timing.label(), Some("load users"));
+/// assert!(timing.start() <= timing.end());
+/// println!("{} at line {}", timing, timing.call_site().line());
+/// 
+/// let (duration, users) = timing.into_tuple();
+/// assert_eq!(users.len(), 2);
+/// ```
+/// 
+/// # Returns
+/// 
+/// A [`Timing<T>`](Timing) of the measurement and the result of the code.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! time_ex {
+    ($fmt:literal, $($arg:expr),+ => $body:expr $(,)?) => {
+        $crate::time_ex!(::std::format!($fmt, $($arg),+) => $body)
+    };
+    ($msg:expr => $body:expr $(,)?) => {{
+        static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+        let __label = $msg;
+        let (__duration, __result) = $crate::__time!($crate::__span!(&__label), expr: $body);
+        let __label = ::std::string::ToString::to_string(&__label);
+        $crate::__private::timing(__duration, ::std::option::Option::Some(__label), &SITE, __result)
+    }};
+    ($($body:tt)*) => {{
+        static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+        let (__duration, __result) = $crate::time!($($body)*);
+        $crate::__private::timing(__duration, ::std::option::Option::None, &SITE, __result)
+    }};
 }
 /// Times the execution of code blocks and formats the duration as a string.
 /// 
@@ -112,11 +462,15 @@ macro_rules! time {
 /// 
 /// ## Multiple operations
 /// 
+/// Several `message => code` pairs can be timed in one call. Each pair is
+/// measured separately, and the result is a tuple with one `(String, T)` per
+/// pair, in order:
+/// 
 /// ```rust
 /// use arbitime::format_time;
 /// 
-/// let (msg1, result1) = format_time!("Fast calculation" => 2 + 2);
-/// let (msg2, result2) = format_time!(
+/// let ((msg1, result1), (msg2, result2)) = format_time!(
+///     "Fast calculation" => 2 + 2,
 ///     "Slow calculation" => {
 ///         let mut result = 0;
 ///         for i in 1..=100 {
@@ -125,9 +479,31 @@ macro_rules! time {
 ///         result
 ///     }
 /// );
-/// // Each call returns a formatted message and the result
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(msg1.starts_with("Fast calculation - Execution time: "));
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(msg2.starts_with("Slow calculation - Execution time: "));
+/// assert_eq!((result1, result2), (4, 5050));
+/// ```
+/// 
+/// ## Message with format arguments
+/// 
+/// The message can be given as a format string and its arguments, like
+/// [`format!`], instead of building a `String` first:
+/// 
+/// ```rust
+/// use arbitime::format_time;
+/// 
+/// let user_id = 42;
+/// let (msg, result) = format_time!("query user {}", user_id => user_id * 2);
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(msg.starts_with("query user 42 - Execution time: "));
+/// assert_eq!(result, 84);
 /// ```
 /// 
+/// The other macros taking a message accept this form too, for a single
+/// message.
+/// 
 /// ## Simple timing without custom message
 /// 
 /// ```rust
@@ -139,51 +515,118 @@ macro_rules! time {
 /// // msg contains: "Execution time: ..."
 /// ```
 /// 
+/// ## Custom message format
+/// 
+/// A `template:` option formats the message with a [`template::Template`]
+/// instead of the default format or the one set with [`set_template`]:
+/// 
+/// ```rust
+/// use arbitime::format_time;
+/// 
+/// let (msg, result) = format_time!(template: "{label} took {duration:.1ms}", "Sum" => 2 + 2);
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(msg.starts_with("Sum took 0."));
+/// assert_eq!(result, 4);
+/// ```
+/// 
 /// # Returns
 /// 
 /// A tuple `(String, T)` where:
 /// - `String` is the formatted timing message
 /// - `T` is the result of the executed code
+/// 
+/// With multiple pairs, a tuple of such tuples, one per pair.
+#[cfg(feature = "std")]
 #[macro_export]
 macro_rules! format_time {
-    ($($msg:expr => { $($body:tt)* }),+ $(,)?) => {
-        {
-            $(
-                {
-                    let (duration, result) = $crate::time!({ $($body)* });
-                    (format!("{} - Execution time: {:?}", $msg, duration), result)
-                }
-            );+
-        }
-    };
-    // Multiple message-body pairs without braces
+    ($msg:expr => $body:expr $(,)?) => {{
+        let (record, result) = $crate::__measure!($msg => $body);
+        ($crate::__private::message(&record), result)
+    }};
+    // Multiple message-body pairs yield a tuple with one `(String, T)` per pair
     ($($msg:expr => $body:expr),+ $(,)?) => {
-        {
-            $(
-                {
-                    let (duration, result) = $crate::time!($body);
-                    (format!("{} - Execution time: {:?}", $msg, duration), result)
-                }
-            );+
-        }
+        ($($crate::format_time!($msg => $body)),+)
+    };
+    (template: $template:literal, $($expr:tt)*) => {{
+        let (mut record, result) = $crate::__measure!($($expr)*);
+        record.template = ::std::option::Option::Some($crate::__template!($template));
+        ($crate::__private::message(&record), result)
+    }};
+    ($($expr:tt)*) => {{
+        let (record, result) = $crate::__measure!($($expr)*);
+        ($crate::__private::message(&record), result)
+    }};
+}
+/// Parses a template literal once and returns it as a `&'static Template`.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __template {
+    ($template:literal) => {{
+        static TEMPLATE: ::std::sync::OnceLock<$crate::template::Template> = ::std::sync::OnceLock::new();
+        TEMPLATE.get_or_init(|| {
+            $crate::template::Template::parse($template).unwrap_or_else(|error| panic!("{}", error))
+        })
+    }};
+}
+/// Times code like [`format_time!`], returning a `(TimingRecord, T)`.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __measure {
+    // A measurement that stands for `$sample` executions in the registry
+    // and is reported by the watchdog once it runs for `$watch`, if given
+    (@sample $sample:expr; @watch $watch:expr; $fmt:literal, $($arg:expr),+ => $body:expr $(,)?) => {
+        $crate::__measure!(@sample $sample; @watch $watch; ::std::format!($fmt, $($arg),+) => $body)
     };
-    // Single message-body pair with braces
-    // This arm is redundant because the first macro arm already matches ($msg:expr => { $($body:tt)* })
-    // and handles the case. You can safely remove this arm.
-    // Single message-body pair without braces
-    ($msg:expr => $body:expr) => {
+    (@sample $sample:expr; @watch $watch:expr; $msg:expr => $body:expr $(,)?) => {
         {
-            let (duration, result) = $crate::time!($body);
-            (format!("{} - Execution time: {:?}", $msg, duration), result)
+            static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+            let label = $msg;
+            let _watch = $crate::__private::watch($watch, ::std::option::Option::Some(&label), &SITE, |level, msg| $crate::__emit!(level, msg));
+            let nested = $crate::registry::enter().sampled($sample);
+            let splits = $crate::checkpoint::enter();
+            let (duration, result) = $crate::__time!($crate::__span!(&label), expr: $body);
+            let mut record = $crate::__private::labeled_record(label, duration, ::std::option::Option::Some(nested), &SITE);
+            record.splits = splits.finish(duration);
+            (record, result)
         }
     };
     // Just body without message
-    ($($body:tt)*) => {
+    (@sample $sample:expr; @watch $watch:expr; $($body:tt)*) => {
         {
+            static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+            let _watch = $crate::__private::watch($watch, ::std::option::Option::None, &SITE, |level, msg| $crate::__emit!(level, msg));
+            let splits = $crate::checkpoint::enter();
             let (duration, result) = $crate::time!($($body)*);
-            (format!("Execution time: {:?}", duration), result)
+            let mut record = $crate::__private::unlabeled_record(duration, &SITE);
+            record.splits = splits.finish(duration);
+            (record, result)
         }
     };
+    (@sample $sample:expr; $($expr:tt)*) => {
+        $crate::__measure!(@sample $sample; @watch ::std::option::Option::None; $($expr)*)
+    };
+    ($($expr:tt)*) => {
+        $crate::__measure!(@sample 1; $($expr)*)
+    };
+}
+/// Returns the code of the arguments of [`format_time!`], without timing it.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __body {
+    ($fmt:literal, $($arg:expr),+ => $body:expr $(,)?) => {{
+        let __result = $body;
+        __result
+    }};
+    ($msg:expr => $body:expr $(,)?) => {{
+        let __result = $body;
+        __result
+    }};
+    ($($body:tt)*) => {
+        { $($body)* }
+    };
 }
 /// Times the execution of code and automatically logs the duration to stderr.
 /// 
@@ -217,48 +660,2950 @@ macro_rules! format_time {
 /// // Prints: "Execution time: ..."
 /// ```
 /// 
+/// ## Choosing a log level
+/// 
+/// ```rust
+/// use arbitime::log_time;
+/// 
+/// let result = log_time!(level: Debug, "Cache lookup" => 2 + 2);
+/// assert_eq!(result, 4);
+/// ```
+/// 
+/// ## Only logging slow operations
+/// 
+/// ```rust
+/// use arbitime::log_time;
+/// use std::time::Duration;
+/// 
+/// // Nothing is printed unless the block takes at least 50ms
+/// let rows = log_time!(threshold: Duration::from_millis(50), "db query" => {
+///     vec![1, 2, 3]
+/// });
+/// assert_eq!(rows.len(), 3);
+/// 
+/// // Both options can be combined
+/// log_time!(level: Warn, threshold: Duration::from_millis(50), "render" => ());
+/// ```
+/// 
+/// With `backtrace: true`, a message that is logged because it reached the
+/// threshold is followed by a [`Backtrace`](std::backtrace::Backtrace) of the
+/// call site, captured regardless of `RUST_BACKTRACE`, to see the call path
+/// that led to the slow invocation. It is also available to sinks as
+/// [`TimingRecord::backtrace`]. Capturing is slow, so only use it with a
+/// threshold that few calls exceed.
+/// 
+/// ```rust
+/// use arbitime::log_time;
+/// use std::time::Duration;
+/// 
+/// log_time!(threshold: Duration::from_millis(100), backtrace: true, "render" => ());
+/// ```
+/// 
+/// ## Structured fields
+/// 
+/// After a single message, `fields: { key = value, ... }` attaches key-value
+/// pairs to the measurement, to slice latency data by more than its label.
+/// The values are evaluated before the code runs and converted with
+/// [`FieldValue::from`]. They are appended to the text message as
+/// `key=value`, written as a `"fields"` object in JSON output, and available
+/// to sinks as [`TimingRecord::fields`].
+/// 
+/// ```rust
+/// use arbitime::log_time;
+/// 
+/// let rows = 3;
+/// let result = log_time!("query", fields: { rows = rows, table = "users" } => {
+///     vec![0; rows]
+/// });
+/// // Prints: "query - Execution time: 1.2 µs rows=3 table=users"
+/// assert_eq!(result.len(), 3);
+/// ```
+/// 
+/// ## Custom message format
+/// 
+/// A `template:` option formats the message with a [`template::Template`]
+/// instead of the default format. It can be combined with the other options,
+/// in any order:
+/// 
+/// ```rust
+/// use arbitime::log_time;
+/// 
+/// log_time!(template: "{label}: {duration:.2ms} ({location})", level: Debug, "parse" => 42);
+/// // Prints: "parse: 0.01 ms (src/main.rs:3)"
+/// ```
+/// 
+/// ## Sampling hot call sites
+/// 
+/// With `sample: N`, only one in `N` executions of the call site is timed
+/// and logged; the others just run the code, at the cost of incrementing an
+/// atomic counter. In the [`registry`], each sampled measurement counts as
+/// `N` calls of the measured duration, so the report extrapolates the call
+/// count and total time, and marks them as estimated.
+/// 
+/// ```rust
+/// use arbitime::{log_time, registry};
+/// 
+/// for i in 0..1000u64 {
+///     log_time!(sample: 100, "hash" => i.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+/// }
+/// // Only 10 lines are printed, but 1000 calls are reported
+/// # #[cfg(not(feature = "disable"))]
+/// let stats = registry::stats("hash").unwrap();
+/// # #[cfg(not(feature = "disable"))]
+/// assert_eq!(stats.count, 1000);
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(stats.estimated);
+/// ```
+/// 
+/// ## Warning about hangs
+/// 
+/// With the `watchdog` feature, a `watchdog:` threshold makes a background
+/// thread log a warning, such as `"sync - still running after 5 s on thread
+/// main (src/main.rs:4)"`, as soon as the block has been running for that
+/// long, rather than only once it returns. See `watchdog` for details. Without
+/// the feature, the option does nothing.
+/// 
+/// ```rust
+/// use arbitime::log_time;
+/// use std::time::Duration;
+/// 
+/// log_time!(watchdog: Duration::from_secs(5), "sync" => {
+///     std::thread::sleep(Duration::from_millis(1));
+/// });
+/// ```
+/// 
 /// # Output
 /// 
-/// All timing information is printed to stderr using `eprintln!`.
+/// By default, all timing information is printed to stderr using `eprintln!`.
+/// When `log_time!` blocks are nested, the inner timings are printed indented
+/// below the outer one once it finishes; see [`nesting`].
+/// 
+/// With the `log` feature enabled, messages are emitted through the
+/// [`log`](https://docs.rs/log) crate instead, at [`Level::Info`] unless a
+/// `level:` is given.
+/// 
+/// A `target:` option logs the message under that target, which must be a
+/// string literal, instead of the module path of the call site, so that it
+/// can be filtered like any other log line, e.g. with `RUST_LOG=perf=trace`:
+/// 
+/// ```rust
+/// use arbitime::log_time;
+/// 
+/// log_time!(target: "perf", level: Trace, "physics step" => ());
+/// ```
+/// 
+/// Installing a [`TimeSink`] with [`set_sink`] replaces both of these. With
+/// the `defmt` feature, `log_time!` logs through [`defmt`](https://docs.rs/defmt)
+/// instead, without sinks, nesting or templates.
 /// 
 /// # Returns
 /// 
-/// The result of the executed code (type `T`).
+/// The result of the executed code (type `T`). With multiple `message => code`
+/// pairs, each pair is logged separately and a tuple of all results is returned.
+#[cfg(all(feature = "std", not(feature = "defmt")))]
 #[macro_export]
 macro_rules! log_time {
-    ($($expr:tt)*) => {{
-        let (msg, result) = $crate::format_time!($($expr)*);
-        eprintln!("{}", msg);
-        result
-    }}
+    // Options are collected into `[level, threshold, template, sample, watchdog, backtrace, target]` in any order
+    (@options [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] level: $new:ident, $($rest:tt)*) => {
+        $crate::log_time!(@options [$new, $threshold, $template, $sample, $watchdog, $backtrace, $target] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] threshold: $new:expr, $($rest:tt)*) => {
+        $crate::log_time!(@options [$level, $new, $template, $sample, $watchdog, $backtrace, $target] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] template: $new:literal, $($rest:tt)*) => {
+        $crate::log_time!(@options [$level, $threshold, ::std::option::Option::Some($crate::__template!($new)), $sample, $watchdog, $backtrace, $target] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] sample: $new:expr, $($rest:tt)*) => {
+        $crate::log_time!(@options [$level, $threshold, $template, $new, $watchdog, $backtrace, $target] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] watchdog: $new:expr, $($rest:tt)*) => {
+        $crate::log_time!(@options [$level, $threshold, $template, $sample, ::std::option::Option::Some($new), $backtrace, $target] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] backtrace: $new:expr, $($rest:tt)*) => {
+        $crate::log_time!(@options [$level, $threshold, $template, $sample, $watchdog, $new, $target] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] target: $new:literal, $($rest:tt)*) => {
+        $crate::log_time!(@options [$level, $threshold, $template, $sample, $watchdog, $backtrace, $new] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] $msg:expr, fields: { $($key:ident = $value:expr),* $(,)? } => $body:expr $(,)?) => {
+        $crate::log_time!(@one [$level, $threshold, $template, $sample, $watchdog, $backtrace, $target] [$($key = $value),*] $msg => $body)
+    };
+    (@options [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] $($msg:expr => $body:expr),+ $(,)?) => {
+        ($($crate::log_time!(@one [$level, $threshold, $template, $sample, $watchdog, $backtrace, $target] [] $msg => $body)),+)
+    };
+    (@options [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] $($expr:tt)*) => {
+        $crate::log_time!(@one [$level, $threshold, $template, $sample, $watchdog, $backtrace, $target] [] $($expr)*)
+    };
+    (@one [$level:ident, $threshold:expr, $template:expr, $sample:expr, $watchdog:expr, $backtrace:expr, $target:expr] [$($key:ident = $value:expr),*] $($expr:tt)*) => {{
+        let sample: u64 = $sample;
+        static CALLS: ::std::sync::atomic::AtomicU64 = ::std::sync::atomic::AtomicU64::new(0);
+        if sample > 1 && CALLS.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed) % sample != 0 {
+            $crate::__body!($($expr)*)
+        } else {
+            let threshold: ::std::time::Duration = $threshold;
+            // Fields are evaluated before the code runs, like the count of `throughput_time!`
+            let fields = ::std::vec![$((::std::borrow::Cow::Borrowed(::core::stringify!($key)), $crate::FieldValue::from($value))),*];
+            let frame = $crate::nesting::enter();
+            let (mut record, result) = $crate::__measure!(@sample sample; @watch $watchdog; $($expr)*);
+            record.level = $crate::Level::$level;
+            record.template = $template;
+            record.fields = fields;
+            let logged = record.duration >= threshold;
+            if logged && $backtrace {
+                $crate::__private::capture_backtrace(&mut record);
+            }
+            let records = frame.finish(logged.then_some(record));
+            $crate::__dispatch!(target: $target, records);
+            result
+        }
+    }};
+    ($($expr:tt)*) => {
+        $crate::__log_time!($($expr)*)
+    };
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Starts [`log_time!`] with the default options.
+#[cfg(all(feature = "std", not(feature = "defmt"), not(feature = "disable")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_time {
+    ($($expr:tt)*) => {
+        $crate::log_time!(@options [Info, ::std::time::Duration::ZERO, ::std::option::Option::None, 1, ::std::option::Option::None, false, ::core::module_path!()] $($expr)*)
+    };
+}
 
-    #[test]
-    fn it_works() {
-        let (duration, result) = time! {
-            let sum: u32 = (1..=1000).sum();
-            sum
-        };
-        let logged_result = log_time!(
-            "Summing numbers" => {
-                let sum: u32 = (1..=1000).sum();
-                sum
-            }
-        );
-        
-        log_time!(
-            "First operation" => 5*5,
-            "Second operation" => {
-                10 + 15
-            }
-        );
-        
-        assert_eq!(result, 500500);
-        assert_eq!(logged_result, 500500);
-        assert!(duration >= std::time::Duration::new(0, 0));
+/// Runs the code of [`log_time!`] without timing or logging it when the
+/// `disable` feature is enabled, like a release build of [`debug_time!`].
+#[cfg(all(feature = "std", not(feature = "defmt"), feature = "disable"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_time {
+    ($($expr:tt)*) => {
+        $crate::debug_time!(@release $($expr)*)
+    };
+}
+/// Times the execution of code and logs the duration through `defmt`.
+/// 
+/// Replaces the standard [`log_time!`] when the `defmt` feature is enabled,
+/// with or without `std`. It accepts the same options and forms, and logs
+/// e.g. `"Database query - Execution time: 125 us"` with `defmt::info!`, or
+/// the macro of the given level. String literal labels are interned, so only
+/// their index is sent to the host; other labels must implement
+/// `defmt::Format`. Format string labels are formatted when the message is
+/// logged, after the code has run, so their arguments must still be
+/// available then.
+/// 
+/// Only `level:` and `threshold:` take effect. `template:`, `sample:`,
+/// `watchdog:`, `backtrace:` and `target:` are accepted but have no effect,
+/// and the values of `fields:` are evaluated but not logged. Messages do not go
+/// through sinks, nesting or the registry. The crate invoking the macro must
+/// depend on `defmt` itself.
+#[cfg(feature = "defmt")]
+#[macro_export]
+macro_rules! log_time {
+    (@options [$level:ident, $threshold:expr] level: $new:ident, $($rest:tt)*) => {
+        $crate::log_time!(@options [$new, $threshold] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr] threshold: $new:expr, $($rest:tt)*) => {
+        $crate::log_time!(@options [$level, $new] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr] template: $template:literal, $($rest:tt)*) => {
+        $crate::log_time!(@options [$level, $threshold] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr] sample: $sample:expr, $($rest:tt)*) => {{
+        let _ = $sample;
+        $crate::log_time!(@options [$level, $threshold] $($rest)*)
+    }};
+    (@options [$level:ident, $threshold:expr] watchdog: $watchdog:expr, $($rest:tt)*) => {{
+        let _ = $watchdog;
+        $crate::log_time!(@options [$level, $threshold] $($rest)*)
+    }};
+    (@options [$level:ident, $threshold:expr] backtrace: $backtrace:expr, $($rest:tt)*) => {{
+        let _ = $backtrace;
+        $crate::log_time!(@options [$level, $threshold] $($rest)*)
+    }};
+    (@options [$level:ident, $threshold:expr] target: $target:literal, $($rest:tt)*) => {
+        $crate::log_time!(@options [$level, $threshold] $($rest)*)
+    };
+    (@options [$level:ident, $threshold:expr] $msg:literal, fields: { $($key:ident = $value:expr),* $(,)? } => $body:expr $(,)?) => {{
+        $(let _ = $value;)*
+        $crate::log_time!(@one [$level, $threshold] $msg => $body)
+    }};
+    (@options [$level:ident, $threshold:expr] $msg:expr, fields: { $($key:ident = $value:expr),* $(,)? } => $body:expr $(,)?) => {{
+        $(let _ = $value;)*
+        $crate::log_time!(@one [$level, $threshold] $msg => $body)
+    }};
+    (@options [$level:ident, $threshold:expr] $fmt:literal, $($arg:expr),+ => $body:expr $(,)?) => {
+        $crate::log_time!(@emit [$level, $threshold] "{} - Execution time: {=u64} us", [::defmt::Display2Format(&::core::format_args!($fmt, $($arg),+))] $body)
+    };
+    (@options [$level:ident, $threshold:expr] $($msg:tt => $body:expr),+ $(,)?) => {
+        ($($crate::log_time!(@one [$level, $threshold] $msg => $body)),+)
+    };
+    (@options [$level:ident, $threshold:expr] $($expr:tt)*) => {
+        $crate::log_time!(@one [$level, $threshold] $($expr)*)
+    };
+    (@one [$level:ident, $threshold:expr] $msg:literal => $body:expr) => {{
+        let label = ::defmt::intern!($msg);
+        $crate::log_time!(@emit [$level, $threshold] "{=istr} - Execution time: {=u64} us", [label] $body)
+    }};
+    (@one [$level:ident, $threshold:expr] $msg:expr => $body:expr) => {{
+        let label = $msg;
+        $crate::log_time!(@emit [$level, $threshold] "{} - Execution time: {=u64} us", [label] $body)
+    }};
+    (@one [$level:ident, $threshold:expr] $($body:tt)*) => {
+        $crate::log_time!(@emit [$level, $threshold] "Execution time: {=u64} us", [] { $($body)* })
+    };
+    (@emit [$level:ident, $threshold:expr] $format:literal, [$($label:expr)?] $body:expr) => {{
+        let threshold: ::core::time::Duration = $threshold;
+        let (duration, result) = $crate::__time!($crate::__span!(), expr: $body);
+        if duration >= threshold {
+            $crate::__defmt!($level, $format, $($label,)? duration.as_micros() as u64);
+        }
+        result
+    }};
+    ($($expr:tt)*) => {
+        $crate::log_time!(@options [Info, ::core::time::Duration::ZERO] $($expr)*)
+    };
+}
+
+/// Times the execution of code and logs it like [`log_time!`], but only in
+/// debug builds.
+/// 
+/// When `debug_assertions` are off, the macro expands to the code alone: it is
+/// not timed, its label is not evaluated and nothing is logged or recorded.
+/// The check uses the `debug_assertions` of the crate invoking the macro, so
+/// timing left in library code costs nothing in its users' release builds.
+/// It accepts the same options and `message => code` pairs as [`log_time!`].
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::debug_time;
+/// 
+/// let rows = debug_time!(level: Debug, "Parse rows" => {
+///     "1,2,3".split(',').count()
+/// });
+/// // Prints in debug builds only: "Parse rows - Execution time: 2.1 µs"
+/// assert_eq!(rows, 3);
+/// ```
+/// 
+/// # Returns
+/// 
+/// The result of the executed code (type `T`), or a tuple of results for
+/// several `message => code` pairs.
+#[macro_export]
+macro_rules! debug_time {
+    // Options only matter when timing, so release builds skip them
+    (@release level: $level:ident, $($rest:tt)*) => {
+        $crate::debug_time!(@release $($rest)*)
+    };
+    (@release threshold: $threshold:expr, $($rest:tt)*) => {
+        $crate::debug_time!(@release $($rest)*)
+    };
+    (@release template: $template:literal, $($rest:tt)*) => {
+        $crate::debug_time!(@release $($rest)*)
+    };
+    (@release sample: $sample:expr, $($rest:tt)*) => {
+        $crate::debug_time!(@release $($rest)*)
+    };
+    (@release watchdog: $watchdog:expr, $($rest:tt)*) => {
+        $crate::debug_time!(@release $($rest)*)
+    };
+    (@release backtrace: $backtrace:expr, $($rest:tt)*) => {
+        $crate::debug_time!(@release $($rest)*)
+    };
+    (@release target: $target:literal, $($rest:tt)*) => {
+        $crate::debug_time!(@release $($rest)*)
+    };
+    // Labels are borrowed in dead code, so variables used only in them are not unused
+    (@release $msg:expr, fields: { $($key:ident = $value:expr),* $(,)? } => $body:expr $(,)?) => {{
+        if false {
+            let _ = (&$msg, $(&$value),*);
+        }
+        $body
+    }};
+    (@release $fmt:literal, $($arg:expr),+ => $body:expr $(,)?) => {{
+        if false {
+            let _ = ($(&$arg),+);
+        }
+        $body
+    }};
+    (@release $($msg:expr => $body:expr),+ $(,)?) => {
+        ($({
+            if false {
+                let _ = &$msg;
+            }
+            $body
+        }),+)
+    };
+    (@release $($body:tt)*) => {
+        { $($body)* }
+    };
+    ($($expr:tt)*) => {{
+        #[cfg(debug_assertions)]
+        let __result = $crate::log_time!($($expr)*);
+        #[cfg(not(debug_assertions))]
+        let __result = $crate::debug_time!(@release $($expr)*);
+        __result
+    }};
+}
+
+/// Times the execution of code and logs it along with the throughput: the
+/// number of items or bytes it processed per second.
+/// 
+/// The first argument is `items = count` or `bytes = count`, where `count` is
+/// any integer, evaluated before the code runs. The rest is the same as for
+/// [`log_time!`] with a single message. Items are reported with decimal
+/// prefixes, e.g. `1.2 M items/s`, and bytes with binary ones, e.g.
+/// `345 MiB/s`. The amount is also available to sinks as
+/// [`TimingRecord::throughput`], and in JSON output.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::throughput_time;
+/// 
+/// let lines = vec!["1", "2", "3"];
+/// let parsed: Vec<u32> = throughput_time!(items = lines.len(), "parse" => {
+///     lines.iter().map(|line| line.parse().unwrap()).collect()
+/// });
+/// // Prints: "parse - Execution time: 1.5 µs (2 M items/s)"
+/// assert_eq!(parsed, [1, 2, 3]);
+/// 
+/// let data = vec![0u8; 1 << 20];
+/// let sum = throughput_time!(bytes = data.len(), "checksum" => data.iter().map(|&b| b as u64).sum::<u64>());
+/// // Prints: "checksum - Execution time: 350 µs (2.79 GiB/s)"
+/// ```
+/// 
+/// # Returns
+/// 
+/// The result of the executed code (type `T`).
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! throughput_time {
+    (items = $count:expr, $($expr:tt)*) => {
+        $crate::throughput_time!(@log $crate::Throughput::Items(($count) as u64), $($expr)*)
+    };
+    (bytes = $count:expr, $($expr:tt)*) => {
+        $crate::throughput_time!(@log $crate::Throughput::Bytes(($count) as u64), $($expr)*)
+    };
+    (@log $throughput:expr, $($expr:tt)*) => {{
+        let throughput = $throughput;
+        let frame = $crate::nesting::enter();
+        let (mut record, result) = $crate::__measure!($($expr)*);
+        record.throughput = ::std::option::Option::Some(throughput);
+        $crate::__dispatch!(frame.finish(::std::option::Option::Some(record)));
+        result
+    }};
+}
+/// Times the execution of code and logs the measurement as a line of JSON.
+/// 
+/// Accepts the same forms as [`format_time!`]. Instead of a text message, a
+/// [`TimingRecord`] is emitted as a single-line JSON object, ready to be
+/// shipped into a log pipeline. Like [`log_time!`], the output goes to stderr
+/// unless the `log` feature is enabled.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::json_time;
+/// 
+/// let result = json_time!("parse" => {
+///     "42".parse::<u32>().unwrap()
+/// });
+/// assert_eq!(result, 42);
+/// // Prints: {"label":"parse","duration_ns":1234,"timestamp":"2026-01-01T12:00:00.000000Z","thread":"main","file":"src/main.rs","line":3,"module_path":"my_app"}
+/// ```
+/// 
+/// # Returns
+/// 
+/// The result of the executed code (type `T`), or a tuple of all results when
+/// several pairs are given.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! json_time {
+    ($msg:expr => $body:expr, $($rest_msg:expr => $rest_body:expr),+ $(,)?) => {
+        ($crate::json_time!($msg => $body), $($crate::json_time!($rest_msg => $rest_body)),+)
+    };
+    ($($expr:tt)*) => {{
+        let (record, result) = $crate::__measure!($($expr)*);
+        $crate::sink::dispatch_json(record, |level, msg| $crate::__emit!(level, msg));
+        result
+    }};
+}
+
+/// Times fallible code and logs whether it succeeded or failed.
+/// 
+/// Accepts the same forms as [`format_time!`] with a single pair, but the
+/// code must evaluate to a [`Result`]. The message tells the two paths apart:
+/// 
+/// - `Ok`: `"label - succeeded in 12 ms"`, logged at [`Level::Info`]
+/// - `Err`: `"label - failed in 3 ms (error: ...)"`, logged at [`Level::Warn`],
+///   using the error's [`Display`](std::fmt::Display) implementation
+/// 
+/// The levels can be changed with `ok:` and `err:` options before the code.
+/// The `Result` itself is passed through unchanged, so the macro composes
+/// with `?`. The output goes wherever [`log_time!`] output goes.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_result;
+/// 
+/// fn parse(input: &str) -> Result<u32, std::num::ParseIntError> {
+///     let value = time_result!("parse" => input.trim().parse::<u32>())?;
+///     Ok(value * 2)
+/// }
+/// 
+/// assert_eq!(parse("21"), Ok(42)); // Prints: "parse - succeeded in ..."
+/// assert!(parse("oops").is_err()); // Prints: "parse - failed in ... (error: invalid digit found in string)"
+/// 
+/// // Successes at Debug, failures at Error
+/// let result = time_result!(ok: Debug, err: Error, "open" => std::fs::File::open("missing.txt"));
+/// assert!(result.is_err());
+/// ```
+/// 
+/// # Returns
+/// 
+/// The `Result` produced by the executed code.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! time_result {
+    (ok: $ok:ident, err: $err:ident, $($expr:tt)*) => {{
+        let frame = $crate::nesting::enter();
+        let (mut record, result) = $crate::__measure!($($expr)*);
+        $crate::__private::outcome(&mut record, &result, $crate::Level::$ok, $crate::Level::$err);
+        $crate::__dispatch!(frame.finish(::std::option::Option::Some(record)));
+        result
+    }};
+    (err: $err:ident, ok: $ok:ident, $($expr:tt)*) => {
+        $crate::time_result!(ok: $ok, err: $err, $($expr)*)
+    };
+    (ok: $ok:ident, $($expr:tt)*) => {
+        $crate::time_result!(ok: $ok, err: Warn, $($expr)*)
+    };
+    (err: $err:ident, $($expr:tt)*) => {
+        $crate::time_result!(ok: Info, err: $err, $($expr)*)
+    };
+    ($($expr:tt)*) => {
+        $crate::time_result!(ok: Info, err: Warn, $($expr)*)
+    };
+}
+
+/// Retries fallible code and returns the final result along with the
+/// duration and outcome of every attempt.
+/// 
+/// The code must evaluate to a [`Result`] and is run up to `attempts` times,
+/// until it returns `Ok`. Between attempts, the thread sleeps for the
+/// `backoff`, which is either a fixed [`Duration`](std::time::Duration) or a
+/// closure taking the number of the failed attempt and returning the delay
+/// (see [`Backoff`]). Without `backoff:`, attempts follow each other
+/// immediately.
+/// 
+/// With a `"label" =>` or `"format {}", args =>` before the code, every attempt is recorded in the
+/// [`registry`] under the label, and a summary is logged like [`log_time!`]:
+/// `"fetch - succeeded on attempt 2 of 3 after 1.25 s"` at [`Level::Info`],
+/// or `"fetch - failed 3 attempts after 2.3 s (error: ...)"` at
+/// [`Level::Warn`]. The total includes the backoff.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::retry_timed;
+/// use std::time::Duration;
+/// 
+/// let mut calls = 0;
+/// let (result, attempts) = retry_timed!(attempts: 3, backoff: Duration::from_millis(10), {
+///     calls += 1;
+///     if calls < 2 { Err("busy") } else { Ok(calls) }
+/// });
+/// assert_eq!(result, Ok(2));
+/// assert_eq!(attempts.len(), 2);
+/// for attempt in &attempts {
+///     println!("{}", attempt); // "attempt 1: failed in 120 ns (error: busy)", ...
+/// }
+/// 
+/// // Exponential backoff, with a summary logged under a label
+/// let backoff = |attempt: u32| Duration::from_millis(1) * 2u32.pow(attempt - 1);
+/// let (result, _) = retry_timed!(attempts: 4, backoff: backoff, "parse" => "42".parse::<u32>());
+/// // Prints: "parse - succeeded on attempt 1 of 4 after ..."
+/// assert_eq!(result, Ok(42));
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(Result<T, E>, Vec<Attempt>)` of the result of the last attempt
+/// and the [`Attempt`]s in order.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! retry_timed {
+    (attempts: $attempts:expr, backoff: $backoff:expr, $fmt:literal, $($arg:expr),+ => $body:expr) => {
+        $crate::retry_timed!(attempts: $attempts, backoff: $backoff, ::std::format!($fmt, $($arg),+) => $body)
+    };
+    (attempts: $attempts:expr, backoff: $backoff:expr, $label:expr => $body:expr) => {{
+        let __label = $label;
+        let __attempts: u32 = $attempts;
+        let (__result, __log) = $crate::__private::retry(__attempts, $backoff, || $body);
+        static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+        if let ::std::option::Option::Some(record) = $crate::__private::retry_summary(&__label, __attempts, &__log, &SITE) {
+            $crate::__dispatch!(::std::vec![record]);
+        }
+        (__result, __log)
+    }};
+    (attempts: $attempts:expr, backoff: $backoff:expr, $body:expr) => {
+        $crate::__private::retry($attempts, $backoff, || $body)
+    };
+    (attempts: $attempts:expr, $($rest:tt)*) => {
+        $crate::retry_timed!(attempts: $attempts, backoff: ::std::time::Duration::ZERO, $($rest)*)
+    };
+}
+
+/// Times a future from its first poll until it completes.
+/// 
+/// Unlike [`time!`], which would only measure how long it takes to *construct*
+/// a future, this macro awaits the given future and measures the time it takes
+/// to drive it to completion. It must be used inside an `async` context.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_async;
+/// 
+/// async fn fetch() -> u32 {
+///     42
+/// }
+/// 
+/// async fn run() {
+///     let (duration, result) = time_async!(fetch());
+///     assert_eq!(result, 42);
+///     println!("Fetching took: {:?}", duration);
+/// 
+///     // Async blocks work too
+///     let (duration, result) = time_async!(async {
+///         fetch().await + 1
+///     });
+///     assert_eq!(result, 43);
+/// }
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(Duration, T)` where:
+/// - `Duration` is the time elapsed until the future completed
+/// - `T` is the output of the future
+#[macro_export]
+macro_rules! time_async {
+    ($fut:expr) => {
+        $crate::__time_async!($crate::__span!(), $fut)
+    };
+}
+/// Awaits a future with a time limit, and returns how long it took.
+/// 
+/// The future is raced against a timer. If it completes within `limit`, the
+/// result is `Ok((Duration, T))` with the time from the first poll. Otherwise
+/// it is dropped and the result is `Err(`[`Elapsed`]`)`, which holds the time
+/// at which it was given up. It must be used inside an `async` context.
+/// 
+/// The timer comes from the async runtime enabled by the crate features:
+/// `tokio::time::sleep` with the `tokio` feature, or
+/// `async_std::task::sleep` with the `async-std` feature, in which case the
+/// crate invoking the macro must depend on `async-std` itself. Without either
+/// feature, the timer is a thread that sleeps until the limit, which works
+/// with any runtime. Other timers can be used with [`Timeout`] directly.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_or_timeout;
+/// use std::time::Duration;
+/// 
+/// async fn fetch() -> u32 {
+///     42
+/// }
+/// 
+/// async fn run() {
+///     match time_or_timeout!(Duration::from_secs(5), fetch()) {
+///         Ok((duration, value)) => println!("fetched {} in {:?}", value, duration),
+///         Err(elapsed) => eprintln!("fetch {}", elapsed), // "fetch timed out after 5 s"
+///     }
+/// }
+/// ```
+/// 
+/// # Returns
+/// 
+/// `Result<(Duration, T), Elapsed>`, where `T` is the output of the future.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! time_or_timeout {
+    ($limit:expr, $fut:expr $(,)?) => {{
+        let __limit: ::std::time::Duration = $limit;
+        $crate::Timeout::new($fut, $crate::__sleep!(__limit), __limit).await
+    }};
+}
+/// Creates a future that completes after the given duration, with the timer
+/// of the enabled async runtime.
+#[cfg(feature = "tokio")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __sleep {
+    ($duration:expr) => {
+        $crate::__tokio::time::sleep($duration)
+    };
+}
+#[cfg(all(feature = "async-std", not(feature = "tokio")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __sleep {
+    ($duration:expr) => {
+        ::async_std::task::sleep($duration)
+    };
+}
+#[cfg(all(feature = "std", not(any(feature = "tokio", feature = "async-std"))))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __sleep {
+    ($duration:expr) => {
+        $crate::__private::ThreadSleep::new($duration)
+    };
+}
+/// Times a future instrumented with the given span and records the duration on it.
+#[cfg(not(feature = "disable"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __time_async {
+    ($span:expr, $fut:expr) => {{
+        let __span = $span;
+        let __start = $crate::clock::now();
+        let __result = $crate::__instrument!(__span, $fut).await;
+        let __duration = $crate::calibration::correct($crate::clock::elapsed_since(__start));
+        __span.record("elapsed_ns", __duration.as_nanos() as u64);
+        (__duration, __result)
+    }};
+}
+/// Awaits a future without timing it when the `disable` feature is enabled.
+#[cfg(feature = "disable")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __time_async {
+    ($span:expr, $fut:expr) => {
+        (::core::time::Duration::ZERO, $fut.await)
+    };
+}
+/// Times a future and formats the duration as a string.
+/// 
+/// This is the `async` counterpart of [`format_time!`], built on [`time_async!`].
+/// It must be used inside an `async` context.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::format_time_async;
+/// 
+/// async fn run() {
+///     let (msg, result) = format_time_async!("Loading config" => async { 7 });
+///     // msg contains: "Loading config - Execution time: ..."
+///     assert_eq!(result, 7);
+/// 
+///     let (msg, result) = format_time_async!(async { 7 });
+///     // msg contains: "Execution time: ..."
+/// }
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(String, T)` where:
+/// - `String` is the formatted timing message
+/// - `T` is the output of the future
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! format_time_async {
+    ($($expr:tt)*) => {{
+        let (record, result) = $crate::__measure_async!($($expr)*);
+        ($crate::__private::message(&record), result)
+    }};
+}
+/// Times a future like [`format_time_async!`], returning a `(TimingRecord, T)`.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __measure_async {
+    // A label built from format arguments
+    ($fmt:literal, $($arg:expr),+ => $fut:expr) => {
+        $crate::__measure_async!(::std::format!($fmt, $($arg),+) => $fut)
+    };
+    ($msg:expr => $fut:expr) => {
+        {
+            static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+            let label = $msg;
+            let (duration, result) = $crate::__time_async!($crate::__span!(&label), $fut);
+            ($crate::__private::labeled_record(label, duration, ::std::option::Option::None, &SITE), result)
+        }
+    };
+    ($fut:expr) => {
+        {
+            static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+            let (duration, result) = $crate::time_async!($fut);
+            ($crate::__private::unlabeled_record(duration, &SITE), result)
+        }
+    };
+}
+/// Times a future and automatically logs the duration to stderr.
+/// 
+/// This is the `async` counterpart of [`log_time!`], built on [`format_time_async!`].
+/// It must be used inside an `async` context.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::log_time_async;
+/// 
+/// async fn run() {
+///     let result = log_time_async!("Handling request" => async { 42 });
+///     // Prints: "Handling request - Execution time: ..."
+///     assert_eq!(result, 42);
+/// }
+/// ```
+/// 
+/// Like [`log_time!`], a `level:` and a `threshold:` can be given before the future.
+/// 
+/// Inside a `task::scope` (`tokio` feature), the timings taken while the
+/// future runs are printed as a tree below it, like those nested inside
+/// [`log_time!`], even if the task moves between threads at each `.await`.
+/// 
+/// # Returns
+/// 
+/// The output of the future (type `T`).
+#[cfg(all(feature = "std", not(feature = "defmt")))]
+#[macro_export]
+macro_rules! log_time_async {
+    (level: $level:ident, threshold: $threshold:expr, $($expr:tt)*) => {{
+        let threshold: ::std::time::Duration = $threshold;
+        let frame = $crate::nesting::enter_task();
+        let (mut record, result) = $crate::__measure_async!($($expr)*);
+        record.level = $crate::Level::$level;
+        let logged = record.duration >= threshold;
+        match frame {
+            ::std::option::Option::Some(frame) => $crate::__dispatch!(frame.finish(logged.then_some(record))),
+            ::std::option::Option::None if logged => $crate::__dispatch!(::std::vec![record]),
+            ::std::option::Option::None => {}
+        }
+        result
+    }};
+    (threshold: $threshold:expr, level: $level:ident, $($expr:tt)*) => {
+        $crate::log_time_async!(level: $level, threshold: $threshold, $($expr)*)
+    };
+    (level: $level:ident, $($expr:tt)*) => {
+        $crate::log_time_async!(level: $level, threshold: ::std::time::Duration::ZERO, $($expr)*)
+    };
+    (threshold: $threshold:expr, $($expr:tt)*) => {
+        $crate::log_time_async!(level: Info, threshold: $threshold, $($expr)*)
+    };
+    ($($expr:tt)*) => {
+        $crate::log_time_async!(level: Info, threshold: ::std::time::Duration::ZERO, $($expr)*)
+    };
+}
+/// Times a future and logs the duration through `defmt`.
+/// 
+/// Replaces the standard [`log_time_async!`] when the `defmt` feature is
+/// enabled; see the `defmt` variant of [`log_time!`].
+#[cfg(feature = "defmt")]
+#[macro_export]
+macro_rules! log_time_async {
+    (level: $level:ident, threshold: $threshold:expr, $msg:literal => $fut:expr) => {{
+        let label = ::defmt::intern!($msg);
+        $crate::log_time_async!(@emit [$level, $threshold] "{=istr} - Execution time: {=u64} us", [label] $fut)
+    }};
+    (level: $level:ident, threshold: $threshold:expr, $msg:expr => $fut:expr) => {{
+        let label = $msg;
+        $crate::log_time_async!(@emit [$level, $threshold] "{} - Execution time: {=u64} us", [label] $fut)
+    }};
+    (level: $level:ident, threshold: $threshold:expr, $fut:expr) => {
+        $crate::log_time_async!(@emit [$level, $threshold] "Execution time: {=u64} us", [] $fut)
+    };
+    (@emit [$level:ident, $threshold:expr] $format:literal, [$($label:ident)?] $fut:expr) => {{
+        let threshold: ::core::time::Duration = $threshold;
+        let (duration, result) = $crate::time_async!($fut);
+        if duration >= threshold {
+            $crate::__defmt!($level, $format, $($label,)? duration.as_micros() as u64);
+        }
+        result
+    }};
+    (threshold: $threshold:expr, level: $level:ident, $($expr:tt)*) => {
+        $crate::log_time_async!(level: $level, threshold: $threshold, $($expr)*)
+    };
+    (level: $level:ident, $($expr:tt)*) => {
+        $crate::log_time_async!(level: $level, threshold: ::core::time::Duration::ZERO, $($expr)*)
+    };
+    (threshold: $threshold:expr, $($expr:tt)*) => {
+        $crate::log_time_async!(level: Info, threshold: $threshold, $($expr)*)
+    };
+    ($($expr:tt)*) => {
+        $crate::log_time_async!(level: Info, threshold: ::core::time::Duration::ZERO, $($expr)*)
+    };
+}
+
+/// Times the rest of the current scope and logs the duration to stderr.
+/// 
+/// This creates a [`ScopeTimer`] guard bound to a hidden local variable, so
+/// the duration is logged when the enclosing scope ends, no matter how it is
+/// left: falling off the end, `return`, `?` or a panic.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_scope;
+/// 
+/// fn load(path: &str) -> Option<usize> {
+///     time_scope!("Loading file");
+///     if path.is_empty() {
+///         return None; // Still logged
+///     }
+///     Some(path.len())
+/// } // Prints: "Loading file - Execution time: ..."
+/// 
+/// assert_eq!(load("data.txt"), Some(8));
+/// assert_eq!(load(""), None);
+/// 
+/// {
+///     time_scope!();
+///     // Prints: "Execution time: ..." at the end of this block
+/// }
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! time_scope {
+    () => {
+        let __arbitime_scope = $crate::ScopeTimer::unlabeled()
+            .__callsite({
+                static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+                &SITE
+            });
+    };
+    ($label:expr) => {
+        let __arbitime_scope = $crate::ScopeTimer::new($label)
+            .__callsite({
+                static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+                &SITE
+            });
+    };
+}
+
+/// Times code like [`log_time!`], and still logs the measurement if the code
+/// panics.
+/// 
+/// A panic inside [`log_time!`] unwinds past the measurement, so nothing is
+/// logged. `time_catch!` measures with a [`ScopeTimer`] guard instead: when
+/// the code panics, the time until the panic is logged as
+/// `"label - panicked after 12 ms"` and recorded in the [`registry`] while
+/// unwinding, and the panic then continues. The code is never interrupted,
+/// and its result is returned when it finishes normally.
+/// 
+/// Accepts the same `message => code` and bare-code forms as [`format_time!`]
+/// with a single pair.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_catch;
+/// 
+/// let sum = time_catch!("sum" => (1..=100).sum::<u32>());
+/// assert_eq!(sum, 5050); // Prints: "sum - Execution time: ..."
+/// 
+/// let result = std::panic::catch_unwind(|| {
+///     time_catch!("parse" => "oops".parse::<u32>().unwrap())
+/// });
+/// assert!(result.is_err()); // Prints: "parse - panicked after ..."
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! time_catch {
+    ($fmt:literal, $($arg:expr),+ => $body:expr $(,)?) => {
+        $crate::time_catch!(::std::format!($fmt, $($arg),+) => $body)
+    };
+    ($msg:expr => $body:expr $(,)?) => {{
+        $crate::time_scope!($msg);
+        $body
+    }};
+    ($($body:tt)*) => {{
+        $crate::time_scope!();
+        $($body)*
+    }};
+}
+
+/// Times code on every execution, but only logs a summary every so often.
+/// 
+/// Logging every iteration of a hot loop floods the output and slows the loop
+/// down. `time_every!` measures every execution and records it in the
+/// [`registry`] like [`format_time!`], but collects the durations of each call
+/// site into a window and only logs the window's statistics, either after a
+/// number of `calls:` or, with `interval:`, at the first execution once the
+/// interval has passed since the window started. The message looks like
+/// `parse - 100 calls, mean: 1.2 ms, min: 1 ms, max: 3 ms, total: 120 ms`.
+/// It goes through the [`sink`] as a record with this
+/// [`summary`](TimingRecord::summary) and the total of the window as its
+/// duration.
+/// 
+/// Accepts the same `message => code` and bare-code forms as [`format_time!`]
+/// after the window option, and returns the result of the code.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_every;
+/// use std::time::Duration;
+/// 
+/// for i in 0..1000u64 {
+///     // Logs 10 summaries of 100 calls each
+///     time_every!(calls: 100, "square" => i * i);
+/// 
+///     // Logs at most once per second
+///     let cube = time_every!(interval: Duration::from_secs(1), "cube" => i * i * i);
+///     assert_eq!(cube, i.pow(3));
+/// }
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! time_every {
+    (calls: $calls:expr, $($expr:tt)*) => {
+        $crate::time_every!(@window $crate::__private::Every::Calls($calls), $($expr)*)
+    };
+    (interval: $interval:expr, $($expr:tt)*) => {
+        $crate::time_every!(@window $crate::__private::Every::Interval($interval), $($expr)*)
+    };
+    (@window $every:expr, $($expr:tt)*) => {{
+        static WINDOW: $crate::__private::Window = $crate::__private::Window::new();
+        let (record, result) = $crate::__measure!($($expr)*);
+        if let ::std::option::Option::Some(summary) = WINDOW.add(&record, $every) {
+            $crate::__dispatch!(::std::vec![summary]);
+        }
+        result
+    }};
+}
+
+/// Times every iteration of a loop, and logs a summary when it finishes.
+/// 
+/// Wraps a `for` or `while` loop, and records the duration of each iteration
+/// in a [`Series`], which keeps the count, mean, standard deviation, minimum,
+/// maximum and total, and, with the `samples: N` option, a reservoir of up to
+/// `N` raw durations picked uniformly at random. When the loop finishes, a
+/// single message is logged, e.g.
+/// `rows - 1000 iterations, mean: 1.2 ms, std dev: 300 µs, min: 1 ms, max: 3 ms, total: 1.2 s`.
+/// The iterations of a labelled loop are also recorded in the [`registry`]
+/// under its label. The summary goes through the [`sink`] as a record with
+/// this [`summary`](TimingRecord::summary) and the total of the iterations as
+/// its duration.
+/// 
+/// An iteration ends however its body is left, including with `continue` and
+/// `break`. The label can be built from format arguments. If the loop is
+/// left with `return` or `?`, nothing is logged.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_iterations;
+/// 
+/// let lines = ["1", "2", "x", "4"];
+/// let mut sum = 0;
+/// let series = time_iterations!(samples: 100, "parse" => for line in lines {
+///     let Ok(value) = line.parse::<u32>() else { continue };
+///     sum += value;
+/// });
+/// // Prints: "parse - 4 iterations, mean: 120 ns, std dev: 40 ns, min: 80 ns, max: 200 ns, total: 480 ns"
+/// assert_eq!(sum, 7);
+/// # #[cfg(not(feature = "disable"))]
+/// assert_eq!(series.stats().count, 4);
+/// # #[cfg(not(feature = "disable"))]
+/// assert_eq!(series.samples().len(), 4);
+/// 
+/// let mut n = 10;
+/// time_iterations!(while n > 0 {
+///     n -= 1;
+/// });
+/// ```
+/// 
+/// # Returns
+/// 
+/// The [`Series`] of the loop's iterations.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! time_iterations {
+    (@samples $samples:expr; $fmt:literal, $($arg:expr),+ => $($loop:tt)+) => {
+        $crate::time_iterations!(@samples $samples; ::std::format!($fmt, $($arg),+) => $($loop)+)
+    };
+    (@samples $samples:expr; $msg:expr => $($loop:tt)+) => {
+        $crate::time_iterations!(@series [::std::option::Option::Some(::std::string::ToString::to_string(&$msg)), $samples] $($loop)+)
+    };
+    (@samples $samples:expr; $($loop:tt)+) => {
+        $crate::time_iterations!(@series [::std::option::Option::None, $samples] $($loop)+)
+    };
+    (@series [$label:expr, $samples:expr] $($loop:tt)+) => {{
+        let mut __series = $crate::Series::new($label).with_samples($samples);
+        $crate::time_iterations!(@loop __series [] $($loop)+);
+        if let ::std::option::Option::Some(summary) = __series.summary() {
+            static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+            let label = __series.label().map(::std::string::ToString::to_string);
+            let record = $crate::__private::summary_record(label, __series.stats().total, summary, &SITE);
+            $crate::__dispatch!(::std::vec![record]);
+        }
+        __series
+    }};
+    // Moves tokens before the body of the loop into the brackets, one at a time
+    (@loop $series:ident [$($head:tt)*] $body:block) => {
+        $($head)* {
+            let _iteration = $series.iteration();
+            $body
+        }
+    };
+    (@loop $series:ident [$($head:tt)*] $next:tt $($rest:tt)+) => {
+        $crate::time_iterations!(@loop $series [$($head)* $next] $($rest)+)
+    };
+    (samples: $samples:expr, $($rest:tt)+) => {
+        $crate::time_iterations!(@samples $samples; $($rest)+)
+    };
+    ($($rest:tt)+) => {
+        $crate::time_iterations!(@samples 0; $($rest)+)
+    };
+}
+
+/// Marks the end of a phase inside the enclosing timed block.
+/// 
+/// The message of the innermost [`log_time!`], [`format_time!`],
+/// [`json_time!`] or [`time_scope!`] on the current thread then lists the
+/// duration of every phase and its share of the total, the last phase running
+/// from the last checkpoint to the end of the block. The label can be built
+/// from format arguments. Outside of a timed block, this does nothing. See
+/// [`checkpoint`](mod@checkpoint) for details.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::{checkpoint, log_time};
+/// 
+/// let count = log_time!("load config" => {
+///     let text = "retries = 3\ntimeout = 30".to_string();
+///     checkpoint!("after read");
+///     let lines: Vec<&str> = text.lines().collect();
+///     for (i, line) in lines.iter().enumerate() {
+///         checkpoint!("line {}", i + 1);
+///         assert!(line.contains('='));
+///     }
+///     lines.len()
+/// });
+/// // Prints: "load config - Execution time: 12 µs (after read: 9 µs, 75.0%; line 1: 1 µs, 8.3%; line 2: 1 µs, 8.3%; end: 1 µs, 8.3%)"
+/// assert_eq!(count, 2);
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! checkpoint {
+    ($fmt:literal, $($arg:expr),+ $(,)?) => {
+        $crate::checkpoint::mark(::std::format!($fmt, $($arg),+))
+    };
+    ($label:expr $(,)?) => {
+        $crate::checkpoint::mark($label)
+    };
+}
+
+/// Times a code block and counts the allocations it makes.
+/// 
+/// Returns [`alloc::AllocTimings`] with the elapsed time and the number of
+/// allocations and bytes allocated by the current thread while the block ran.
+/// The counts are only collected when [`alloc::CountingAlloc`] is installed as
+/// the `#[global_allocator]`; otherwise they are zero.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::alloc::CountingAlloc;
+/// use arbitime::time_alloc;
+/// 
+/// #[global_allocator]
+/// static ALLOC: CountingAlloc = CountingAlloc::new();
+/// 
+/// let (timings, buffer) = time_alloc!(vec![0u8; 4096]);
+/// # #[cfg(not(feature = "disable"))]
+/// assert_eq!(timings.allocations, 1);
+/// # #[cfg(not(feature = "disable"))]
+/// assert_eq!(timings.bytes, 4096);
+/// println!("{}", timings); // "1.2 µs, 1 allocations, 4096 bytes"
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(alloc::AllocTimings, T)` of the measurements and the result of the code.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! time_alloc {
+    ($($body:tt)*) => {{
+        let __allocs = $crate::alloc::AllocStats::now();
+        let (__duration, __result) = $crate::time!($($body)*);
+        let __allocs = $crate::alloc::AllocStats::now() - __allocs;
+        ($crate::alloc::AllocTimings::new(__duration, __allocs), __result)
+    }};
+}
+
+/// Times a code block and reads the hardware performance counters of the
+/// current thread around it.
+/// 
+/// Returns [`perf::PerfTimings`] with the elapsed time and the CPU cycles,
+/// instructions, cache misses and branch mispredictions of the block, read
+/// with `perf_event_open`, to explain why a block got slower when its wall
+/// time alone can't. Counters the system does not provide are `None`; see
+/// [`perf`] for when that happens. Requires the `perf` feature.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::perf_time;
+/// 
+/// let data: Vec<u64> = (0..100_000).collect();
+/// let (counters, sum) = perf_time!({
+///     data.iter().sum::<u64>()
+/// });
+/// println!("{}", counters); // "41 µs, 120 k cycles, 410 k instructions (3.42 IPC), 3 cache misses, 18 branch misses"
+/// assert_eq!(sum, 4_999_950_000);
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(perf::PerfTimings, T)` of the measurements and the result of the code.
+#[cfg(feature = "perf")]
+#[macro_export]
+macro_rules! perf_time {
+    ($($body:tt)*) => {{
+        let __counters = $crate::perf::Counters::now();
+        let (__duration, __result) = $crate::time!($($body)*);
+        let __counters = $crate::perf::Counters::now() - __counters;
+        ($crate::perf::PerfTimings::new(__duration, __counters), __result)
+    }};
+}
+
+/// Asserts that a code block finishes within a time budget.
+/// 
+/// The block is executed once and timed like [`time!`]. If it took longer
+/// than the budget, the macro panics with a message containing the actual
+/// duration, which makes it suitable for simple latency budgets inside
+/// `#[test]` functions. Otherwise the result of the block is returned.
+/// 
+/// Like [`assert!`], a custom message with format arguments can be appended.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::assert_time;
+/// use std::time::Duration;
+/// 
+/// let sum = assert_time!(Duration::from_secs(1), {
+///     (1..=1000).sum::<u32>()
+/// });
+/// assert_eq!(sum, 500500);
+/// 
+/// let input = "42";
+/// assert_time!(Duration::from_millis(100), input.parse::<u32>(), "parsing {:?} is too slow", input);
+/// ```
+/// 
+/// ```rust,should_panic
+/// use arbitime::assert_time;
+/// use std::time::Duration;
+/// 
+/// // Panics with: "block took 10.1 ms, exceeding its budget of 1 ms"
+/// assert_time!(Duration::from_millis(1), std::thread::sleep(Duration::from_millis(10)));
+/// # if cfg!(feature = "disable") {
+/// #     panic!("nothing is measured with the `disable` feature");
+/// # }
+/// ```
+/// 
+/// With the `disable` feature enabled, no time is measured and the assertion
+/// always passes.
+/// 
+/// # Returns
+/// 
+/// The result of the executed code (type `T`).
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! assert_time {
+    ($budget:expr, $body:expr $(,)?) => {{
+        let budget: ::std::time::Duration = $budget;
+        let (duration, result) = $crate::time!($body);
+        if duration > budget {
+            ::std::panic!(
+                "block took {}, exceeding its budget of {}",
+                $crate::HumanDuration(duration),
+                $crate::HumanDuration(budget)
+            );
+        }
+        result
+    }};
+    ($budget:expr, $body:expr, $($arg:tt)+) => {{
+        let budget: ::std::time::Duration = $budget;
+        let (duration, result) = $crate::time!($body);
+        if duration > budget {
+            ::std::panic!(
+                "block took {}, exceeding its budget of {}: {}",
+                $crate::HumanDuration(duration),
+                $crate::HumanDuration(budget),
+                ::std::format_args!($($arg)+)
+            );
+        }
+        result
+    }};
+}
+
+/// Logs a warning when code exceeds a time budget, without interrupting it.
+/// 
+/// A soft counterpart of [`assert_time!`] for production code paths: the code
+/// is timed like [`log_time!`] and always runs to completion, but nothing is
+/// logged unless it took longer than the budget. Then the message is logged at
+/// [`Level::Warn`] with the budget appended, e.g.
+/// `"render - Execution time: 150 ms, exceeding its budget of 100 ms"`. Like
+/// other records, the warning goes through the [`sink`], with its
+/// [`budget`](TimingRecord::budget) set.
+/// 
+/// With `backtrace: true` before the budget, the warning is followed by a
+/// [`Backtrace`](std::backtrace::Backtrace) of the call site, captured
+/// regardless of `RUST_BACKTRACE`, to tell apart the callers of a shared slow
+/// path. Capturing is slow, but only happens for blocks that are over budget.
+/// 
+/// Accepts the same `message => code` and bare-code forms as [`format_time!`]
+/// with a single pair, and returns the result of the code.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::warn_if_over;
+/// use std::time::Duration;
+/// 
+/// let sum = warn_if_over!(Duration::from_millis(100), "sum" => (1..=100).sum::<u32>());
+/// assert_eq!(sum, 5050); // Prints nothing
+/// 
+/// warn_if_over!(Duration::from_millis(1), "sleep" => std::thread::sleep(Duration::from_millis(5)));
+/// // Prints: "sleep - Execution time: 5.07 ms, exceeding its budget of 1 ms"
+/// 
+/// warn_if_over!(backtrace: true, Duration::from_millis(1), std::thread::sleep(Duration::from_millis(5)));
+/// // Prints: "Execution time: 5.06 ms, exceeding its budget of 1 ms", then the backtrace
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! warn_if_over {
+    (backtrace: $backtrace:expr, $budget:expr, $($expr:tt)*) => {{
+        let budget: ::std::time::Duration = $budget;
+        let (mut record, result) = $crate::__measure!($($expr)*);
+        if $crate::__private::over_budget(&mut record, budget, $backtrace) {
+            record.level = $crate::Level::Warn;
+            $crate::__dispatch!(::std::vec![record]);
+        }
+        result
+    }};
+    ($budget:expr, $($expr:tt)*) => {
+        $crate::warn_if_over!(backtrace: false, $budget, $($expr)*)
+    };
+}
+
+/// Times the CPU time a code block consumes, returning it with the result.
+/// 
+/// Unlike [`time!`], which measures wall-clock time, this measures the CPU
+/// time (user plus system) consumed by the whole process while the block
+/// runs. Time spent sleeping or blocked is not counted, so comparing both
+/// shows whether a slow block was compute-bound or waiting. Time used by other
+/// threads of the process during the block is counted too. See [`cpu`] for
+/// platform support.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::cpu_time;
+/// use std::time::Duration;
+/// 
+/// let (cpu, ()) = cpu_time!(std::thread::sleep(Duration::from_millis(20)));
+/// println!("Sleeping used {:?} of CPU time", cpu); // Close to zero
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(Duration, T)` of the CPU time and the result of the code.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! cpu_time {
+    ($($body:tt)*) => {
+        $crate::__cpu!(process_time, $($body)*)
+    };
+}
+
+/// Times the CPU time the current thread spends running a code block.
+/// 
+/// Like [`cpu_time!`], but only counts the time the *current thread* spent
+/// executing, not other threads of the process. Comparing it to the wall time
+/// of the block shows how long the thread was blocked: waiting on I/O, locks,
+/// channels or other threads. See [`cpu`] for platform support.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::{thread_cpu_time, time};
+/// 
+/// let (wall, (cpu, sum)) = time!(thread_cpu_time!({
+///     let worker = std::thread::spawn(|| (1..=100_000u64).sum::<u64>());
+///     worker.join().unwrap() // Blocked while the worker runs
+/// }));
+/// println!("blocked for {:?}", wall.saturating_sub(cpu));
+/// assert_eq!(sum, 5_000_050_000);
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(Duration, T)` of the thread's CPU time and the result of the code.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! thread_cpu_time {
+    ($($body:tt)*) => {
+        $crate::__cpu!(thread_time, $($body)*)
+    };
+}
+
+/// Times a parallel section and logs its wall time, CPU time and effective
+/// parallelism.
+/// 
+/// Made for sections that fan out over a thread pool, such as `rayon`
+/// parallel iterators or [`std::thread::scope`], but independent of any
+/// library. The section is measured like [`time_all!`], and the message
+/// reads e.g. `"resize - wall: 120 ms, user: 890 ms, system: 12 ms, parallelism: 7.52x"`:
+/// the CPU time is what all threads of the process consumed during the
+/// section, so dividing it by the wall time shows how many cores were kept
+/// busy on average. A labelled section's wall time is recorded in the
+/// [`registry`] like [`log_time!`] output. The label can be built from
+/// format arguments. The message goes through the [`sink`] as a record with
+/// this [`summary`](TimingRecord::summary) and the wall time as its duration.
+/// 
+/// To see how the work was spread across the pool, time the work items
+/// inside the section with a label, e.g. with [`time_scope!`], and use
+/// [`registry::Report::by_thread`].
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::par_time;
+/// 
+/// let inputs: Vec<u64> = (0..8).collect();
+/// let sums = par_time!("sum chunks" => std::thread::scope(|scope| {
+///     let handles: Vec<_> = inputs
+///         .chunks(2)
+///         .map(|chunk| scope.spawn(move || chunk.iter().map(|n| (0..n * 1000).sum::<u64>()).sum::<u64>()))
+///         .collect();
+///     handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+/// }));
+/// // Prints: "sum chunks - wall: ..., user: ..., system: ..., parallelism: ...x"
+/// assert_eq!(sums.len(), 4);
+/// ```
+/// 
+/// # Returns
+/// 
+/// The result of the code (type `T`). Use [`time_all!`] and
+/// [`cpu::Timings::parallelism`] to get the measurements instead.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! par_time {
+    ($fmt:literal, $($arg:expr),+ => $($body:tt)*) => {
+        $crate::par_time!(::std::format!($fmt, $($arg),+) => $($body)*)
+    };
+    ($label:expr => $($body:tt)*) => {{
+        let __label = $label;
+        let (__timings, __result) = $crate::time_all!($($body)*);
+        static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+        if let ::std::option::Option::Some(record) = $crate::__private::parallel(::std::option::Option::Some(&__label), &__timings, &SITE) {
+            $crate::__dispatch!(::std::vec![record]);
+        }
+        __result
+    }};
+    ($($body:tt)*) => {{
+        let (__timings, __result) = $crate::time_all!($($body)*);
+        static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+        if let ::std::option::Option::Some(record) = $crate::__private::parallel(::std::option::Option::None, &__timings, &SITE) {
+            $crate::__dispatch!(::std::vec![record]);
+        }
+        __result
+    }};
+}
+
+/// Times a code block in wall-clock time and user and system CPU time.
+/// 
+/// Combines [`time!`] and [`cpu_time!`] into a single measurement, returning
+/// [`cpu::Timings`] with the `wall`, `user` and `system` durations. A block
+/// whose CPU time is much lower than its wall time spent most of it waiting.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_all;
+/// 
+/// let (timings, sum) = time_all!((1..=100_000u64).sum::<u64>());
+/// println!("{}", timings); // "wall: 1.2 ms, user: 1.19 ms, system: 0 ns"
+/// println!("waiting: {:?}", timings.wall.saturating_sub(timings.cpu()));
+/// assert_eq!(sum, 5_000_050_000);
+/// ```
+/// 
+/// # Returns
+/// 
+/// A tuple `(cpu::Timings, T)` of the timings and the result of the code.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! time_all {
+    ($($body:tt)*) => {
+        $crate::__cpu!(all, $($body)*)
+    };
+}
+
+/// Reads a CPU clock of the [`cpu`] module around a code block.
+#[cfg(all(feature = "std", not(feature = "disable")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cpu {
+    (all, $($body:tt)*) => {{
+        let __cpu = $crate::cpu::CpuTime::now();
+        let (__wall, __result) = $crate::time!($($body)*);
+        let __cpu = $crate::cpu::CpuTime::now() - __cpu;
+        ($crate::cpu::Timings::new(__wall, __cpu), __result)
+    }};
+    ($clock:ident, $($body:tt)*) => {{
+        let __start = $crate::cpu::$clock();
+        let __result = { $($body)* };
+        ($crate::cpu::$clock().saturating_sub(__start), __result)
+    }};
+}
+
+/// Runs a code block without reading any clock when the `disable` feature is
+/// enabled.
+#[cfg(all(feature = "std", feature = "disable"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cpu {
+    (all, $($body:tt)*) => {
+        (<$crate::cpu::Timings as ::core::default::Default>::default(), { $($body)* })
+    };
+    ($clock:ident, $($body:tt)*) => {
+        (::core::time::Duration::ZERO, { $($body)* })
+    };
+}
+
+/// Runs a code block repeatedly and returns statistics about its duration.
+/// 
+/// The body is executed `iterations` times and each run is timed individually.
+/// An optional number of `warmup` runs can be executed first; they are not
+/// included in the statistics. The results of the body are passed through
+/// [`std::hint::black_box`] and discarded, so the compiler cannot optimize
+/// the measured work away; use [`black_box_input!`] to do the same for its
+/// inputs.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::bench_time;
+/// 
+/// let stats = bench_time!(1000, {
+///     (1..=100).sum::<u32>()
+/// });
+/// assert_eq!(stats.iterations, 1000);
+/// println!("{}", stats); // "1000 iterations - mean: ... ± ..., median: ..., min: ..., max: ..."
+/// 
+/// // Warm up caches before measuring
+/// let stats = bench_time!(100, warmup: 10, (1..=20).product::<u64>());
+/// assert!(stats.min <= stats.median && stats.median <= stats.max);
+/// ```
+/// 
+/// # Returns
+/// 
+/// A [`BenchStats`] with the min, max, mean, median and standard deviation
+/// of all iterations, along with the raw samples.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! bench_time {
+    ($iterations:expr, warmup: $warmup:expr, $body:expr) => {{
+        let __iterations: usize = $iterations;
+        for _ in 0..$warmup {
+            ::std::hint::black_box($body);
+        }
+        let mut __samples = ::std::vec::Vec::with_capacity(__iterations);
+        for _ in 0..__iterations {
+            let (__duration, _) = $crate::time!(::std::hint::black_box($body));
+            __samples.push(__duration);
+        }
+        $crate::BenchStats::from_samples(__samples)
+    }};
+    ($iterations:expr, $body:expr) => {
+        $crate::bench_time!($iterations, warmup: 0, $body)
+    };
+}
+
+/// Times two implementations of the same thing against each other and logs
+/// how much faster or slower the second one is.
+/// 
+/// Each block is run once, or `iterations:` times after one warmup run, and
+/// the two are compared by their median duration. With at least two
+/// iterations, a Mann-Whitney U test over all samples adds a p-value, so that
+/// noise can be told apart from a real difference. The comparison is logged
+/// like [`log_time!`] output, e.g.
+/// `old: 12 ms, new: 4 ms - new is 3.00x faster (-8 ms, -66.7%, p < 0.001)`,
+/// as an unlabelled record with this [`summary`](TimingRecord::summary) and
+/// the median of the second block as its duration, and returned as a
+/// [`Comparison`]. The results of the blocks are passed through
+/// [`std::hint::black_box`] and discarded.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::compare_time;
+/// 
+/// let data: Vec<u64> = (0..10_000).collect();
+/// let comparison = compare_time!(
+///     iterations: 50,
+///     "loop" => {
+///         let mut sum = 0;
+///         for x in &data {
+///             sum += x;
+///         }
+///         sum
+///     },
+///     "iter" => data.iter().sum::<u64>(),
+/// );
+/// println!("speedup: {:.2}x", comparison.speedup());
+/// if comparison.is_significant(0.05) {
+///     println!("the difference is not just noise");
+/// }
+/// ```
+/// 
+/// # Returns
+/// 
+/// A [`Comparison`] with the [`BenchStats`] of both blocks.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! compare_time {
+    (iterations: $iterations:expr, $baseline_label:expr => $baseline:expr, $candidate_label:expr => $candidate:expr $(,)?) => {{
+        let __iterations: usize = $iterations;
+        let __warmup = if __iterations > 1 { 1 } else { 0 };
+        let __baseline = $crate::bench_time!(__iterations, warmup: __warmup, $baseline);
+        let __candidate = $crate::bench_time!(__iterations, warmup: __warmup, $candidate);
+        let __comparison = $crate::Comparison {
+            baseline_label: ::std::string::ToString::to_string(&$baseline_label),
+            baseline: __baseline,
+            candidate_label: ::std::string::ToString::to_string(&$candidate_label),
+            candidate: __candidate,
+        };
+        static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+        let __summary = ::std::string::ToString::to_string(&__comparison);
+        let __record = $crate::__private::summary_record(::std::option::Option::None, __comparison.candidate.median, __summary, &SITE);
+        $crate::__dispatch!(::std::vec![__record]);
+        __comparison
+    }};
+    ($baseline_label:expr => $baseline:expr, $candidate_label:expr => $candidate:expr $(,)?) => {
+        $crate::compare_time!(iterations: 1, $baseline_label => $baseline, $candidate_label => $candidate)
+    };
+}
+/// Runs a code block repeatedly until a target time is spent, and returns
+/// statistics about its duration.
+/// 
+/// Unlike [`bench_time!`], no iteration count has to be picked: the body is
+/// run in batches whose size doubles until a batch takes long enough to be
+/// measured reliably, and batches are then repeated until the total time
+/// reaches the target. The calibration batches act as warmup and are not
+/// included in the statistics. Without a target, the body runs for one
+/// second. Like with [`bench_time!`], the results of the body are passed
+/// through [`std::hint::black_box`] and discarded.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::time_n;
+/// use std::time::Duration;
+/// 
+/// let stats = time_n!(Duration::from_millis(50), (1..=100).sum::<u32>());
+/// println!("{}", stats); // "2386432 iterations - mean: 18.4 ns ± ..., median: ..., min: ..., max: ..."
+/// assert!(stats.iterations >= stats.samples.len());
+/// ```
+/// 
+/// # Returns
+/// 
+/// A [`BenchStats`] with the total number of iterations and one sample per
+/// batch, holding the mean duration of an iteration in that batch.
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! time_n {
+    ($target:expr, $body:expr) => {{
+        let mut __bench = $crate::__private::Adaptive::new($target);
+        while let ::std::option::Option::Some(__batch) = __bench.next_batch() {
+            let (__elapsed, _) = $crate::time!(for _ in 0..__batch {
+                ::std::hint::black_box($body);
+            });
+            __bench.record(__elapsed);
+        }
+        __bench.finish()
+    }};
+    ($body:expr) => {
+        $crate::time_n!(::std::time::Duration::from_secs(1), $body)
+    };
+}
+
+/// Hides a value from the optimizer, so that benchmarked code cannot be
+/// constant-folded on its inputs.
+/// 
+/// This is [`std::hint::black_box`]: the compiler has to assume the value
+/// could be anything, so work depending on it is really done on every
+/// iteration. [`bench_time!`] and [`time_n!`] already apply it to the result
+/// of the body.
+/// 
+/// # Examples
+/// 
+/// ```rust
+/// use arbitime::{bench_time, black_box_input};
+/// 
+/// // Without black_box_input!, the sum could be computed at compile time
+/// let stats = bench_time!(100, (1..=black_box_input!(1000u64)).sum::<u64>());
+/// assert_eq!(stats.iterations, 100);
+/// ```
+#[macro_export]
+macro_rules! black_box_input {
+    ($value:expr) => {
+        ::core::hint::black_box($value)
+    };
+}
+
+/// Installs a global [`clock`] reading the browser's `performance.now()`, for
+/// `wasm32-unknown-unknown` where [`std::time::Instant`] is unavailable.
+/// 
+/// Falls back to `Date.now()` when there is no `window`, e.g. in web workers.
+/// Record timestamps are taken from `Date.now()`. Requires the `wasm`
+/// feature, and the crate invoking the macro must depend on
+/// [`web-sys`](https://docs.rs/web-sys), with its `Window` and `Performance`
+/// features, and on [`js-sys`](https://docs.rs/js-sys).
+/// 
+/// # Examples
+/// 
+/// ```rust,ignore
+/// use wasm_bindgen::prelude::*;
+/// 
+/// #[wasm_bindgen(start)]
+/// fn start() {
+///     arbitime::install_wasm_clock!();
+///     arbitime::log_time!("startup" => (1..=1000).sum::<u32>());
+/// }
+/// ```
+#[cfg(feature = "wasm")]
+#[macro_export]
+macro_rules! install_wasm_clock {
+    () => {{
+        struct PerformanceClock;
+
+        impl $crate::clock::Clock for PerformanceClock {
+            fn now(&self) -> u64 {
+                let millis = ::web_sys::window()
+                    .and_then(|window| window.performance())
+                    .map(|performance| performance.now())
+                    .unwrap_or_else(::js_sys::Date::now);
+                (millis * 1e6) as u64
+            }
+
+            fn unix_time(&self) -> ::std::option::Option<::std::time::Duration> {
+                ::std::option::Option::Some(::std::time::Duration::from_secs_f64(::js_sys::Date::now() / 1e3))
+            }
+        }
+
+        $crate::clock::set_clock(PerformanceClock);
+    }};
+}
+
+/// Records every labelled timing in a [`metrics`](https://docs.rs/metrics)
+/// histogram named after its label.
+/// 
+/// Installs a [`registry::set_observer`] that calls
+/// `metrics::histogram!(label).record(duration)`, so timings reach whatever
+/// exporter the application has installed (statsd, Prometheus, OTLP, ...),
+/// in seconds. Text output continues as before; install a
+/// [`NullSink`](sink::NullSink) to only emit metrics. Requires the `metrics`
+/// feature, and the crate invoking the macro must depend on `metrics` itself.
+/// 
+/// # Examples
+/// 
+/// ```rust,ignore
+/// metrics_exporter_prometheus::PrometheusBuilder::new().install()?;
+/// arbitime::install_metrics!();
+/// 
+/// arbitime::log_time!("db query" => run_query()); // Recorded in the "db query" histogram
+/// ```
+#[cfg(feature = "metrics")]
+#[macro_export]
+macro_rules! install_metrics {
+    () => {{
+        fn record(label: &str, duration: ::std::time::Duration) {
+            ::metrics::histogram!(::std::string::ToString::to_string(label)).record(duration);
+        }
+
+        $crate::registry::set_observer(record);
+    }};
+}
+
+/// Times each item of a [`futures::Stream`](https://docs.rs/futures).
+/// 
+/// With a stream alone, evaluates to a stream of `(Duration, Item)` pairs,
+/// the duration being the latency of the item: the time from the first poll
+/// requesting it until the stream produced it. With a label, evaluates to a
+/// stream of the items unchanged, records each latency in the
+/// [`registry`](registry) under the label, and logs a summary of the
+/// latencies and of the time the consumer spent processing the items when
+/// the stream ends, e.g.
+/// `"events - 10 items, latency: mean 1.2 ms, max 3 ms, processing: mean 400 µs, max 1 ms"`,
+/// through the [`sink`] as a record with this
+/// [`summary`](TimingRecord::summary) and the total latency as its duration.
+/// The label can be built from format arguments.
+/// 
+/// Requires the `stream` feature, and the crate invoking the macro must
+/// depend on `futures` itself. Streams of other libraries can be timed with a
+/// [`StreamTimer`] directly.
+/// 
+/// # Examples
+/// 
+/// ```rust,ignore
+/// use futures::StreamExt;
+/// 
+/// let mut events = arbitime::timed_stream!(subscribe());
+/// while let Some((latency, event)) = events.next().await {
+///     println!("{:?} after {:?}", event, latency);
+/// }
+/// 
+/// let total: u64 = arbitime::timed_stream!("rows" => query(sql))
+///     .map(|row| row.size)
+///     .sum()
+///     .await; // Logs "rows - 250 items, latency: mean ..." at the end
+/// ```
+#[cfg(feature = "stream")]
+#[macro_export]
+macro_rules! timed_stream {
+    ($fmt:literal, $($arg:expr),+ => $stream:expr) => {
+        $crate::timed_stream!(::std::format!($fmt, $($arg),+) => $stream)
+    };
+    ($label:expr => $stream:expr) => {{
+        let mut __stream = ::std::boxed::Box::pin($stream);
+        let mut __timer = $crate::StreamTimer::labeled($label);
+        ::futures::stream::poll_fn(move |cx| {
+            let __poll = __timer.poll_next(__stream.as_mut(), cx, ::futures::Stream::poll_next);
+            if let ::std::option::Option::Some(summary) = __timer.summary() {
+                static SITE: $crate::callsite::CallSite = $crate::callsite::CallSite::new(file!(), line!(), module_path!());
+                let label = __timer.label().map(::std::string::ToString::to_string);
+                let record = $crate::__private::summary_record(label, __timer.stats().latency, summary, &SITE);
+                $crate::__dispatch!(::std::vec![record]);
+            }
+            __poll.map(|item| item.map(|(_, item)| item))
+        })
+    }};
+    ($stream:expr) => {{
+        let mut __stream = ::std::boxed::Box::pin($stream);
+        let mut __timer = $crate::StreamTimer::new();
+        ::futures::stream::poll_fn(move |cx| __timer.poll_next(__stream.as_mut(), cx, ::futures::Stream::poll_next))
+    }};
+}
+
+/// Defines a [`tower`](https://docs.rs/tower) middleware that times every
+/// request of the services it wraps.
+/// 
+/// Expands to two types in the invoking module, with the given visibility:
+/// `ArbitimeLayer`, a `tower::Layer` created with
+/// `ArbitimeLayer::new(label)`, and the `ArbitimeService` it wraps services
+/// in. The latency of each request, from the first poll of the response
+/// future until the response (or error) is ready, is recorded in the
+/// [`registry`](registry) under the label and logged like
+/// [`log_time!`] output, e.g. `"http - Execution time: 1.2 ms"`, so that
+/// request timings appear next to block-level timings. The types are
+/// defined in the caller's crate because `tower`'s traits can only be
+/// implemented where `tower` is a dependency.
+/// 
+/// Requires the `tower` feature, and the crate invoking the macro must
+/// depend on `tower` itself.
+/// 
+/// # Examples
+/// 
+/// ```rust,ignore
+/// arbitime::tower_layer!(pub);
+/// 
+/// let app = axum::Router::new()
+///     .route("/", axum::routing::get(index))
+///     .layer(ArbitimeLayer::new("http")); // Logs "http - Execution time: ..." per request
+/// ```
+#[cfg(feature = "tower")]
+#[macro_export]
+macro_rules! tower_layer {
+    () => {
+        $crate::tower_layer!(pub(self));
+    };
+    ($vis:vis) => {
+        /// A `tower::Layer` that times the requests of the services it wraps.
+        #[derive(Debug, Clone)]
+        $vis struct ArbitimeLayer {
+            label: ::std::borrow::Cow<'static, str>,
+        }
+
+        impl ArbitimeLayer {
+            /// Creates a layer recording request latencies under `label`.
+            $vis fn new(label: impl ::std::convert::Into<::std::borrow::Cow<'static, str>>) -> Self {
+                ArbitimeLayer { label: label.into() }
+            }
+        }
+
+        impl<S> ::tower::Layer<S> for ArbitimeLayer {
+            type Service = ArbitimeService<S>;
+
+            fn layer(&self, inner: S) -> ArbitimeService<S> {
+                ArbitimeService {
+                    inner,
+                    label: ::std::clone::Clone::clone(&self.label),
+                }
+            }
+        }
+
+        /// A `tower::Service` that times the requests of the service it wraps.
+        #[derive(Debug, Clone)]
+        $vis struct ArbitimeService<S> {
+            inner: S,
+            label: ::std::borrow::Cow<'static, str>,
+        }
+
+        impl<S, R> ::tower::Service<R> for ArbitimeService<S>
+        where
+            S: ::tower::Service<R>,
+        {
+            type Response = S::Response;
+            type Error = S::Error;
+            type Future = $crate::LoggedFuture<S::Future>;
+
+            fn poll_ready(
+                &mut self,
+                cx: &mut ::std::task::Context<'_>,
+            ) -> ::std::task::Poll<::std::result::Result<(), S::Error>> {
+                self.inner.poll_ready(cx)
+            }
+
+            fn call(&mut self, request: R) -> Self::Future {
+                let label = ::std::clone::Clone::clone(&self.label);
+                $crate::TimedFutureExt::timed_with(self.inner.call(request), label)
+            }
+        }
+    };
+}
+
+/// Sends finished records to the global sink, falling back to [`__emit!`].
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __dispatch {
+    ($records:expr) => {
+        $crate::sink::dispatch($records, |level, msg| $crate::__emit!(level, msg))
+    };
+    (target: $target:expr, $records:expr) => {
+        $crate::sink::dispatch($records, |level, msg| $crate::__emit!(target: $target, level, msg))
+    };
+}
+
+/// Emits a timing message at the given [`Level`] through the `log` crate.
+#[cfg(all(feature = "log", not(feature = "disable")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __emit {
+    ($level:expr, $msg:expr) => {
+        $crate::__emit!(target: ::core::module_path!(), $level, $msg)
+    };
+    (target: $target:expr, $level:expr, $msg:expr) => {{
+        let __level = match $level {
+            $crate::Level::Error => $crate::__log::Level::Error,
+            $crate::Level::Warn => $crate::__log::Level::Warn,
+            $crate::Level::Info => $crate::__log::Level::Info,
+            $crate::Level::Debug => $crate::__log::Level::Debug,
+            $crate::Level::Trace => $crate::__log::Level::Trace,
+        };
+        $crate::__log::log!(target: $target, __level, "{}", $msg);
+    }};
+}
+/// Emits a timing message to stderr, ignoring the level.
+#[cfg(all(feature = "std", not(feature = "log"), not(feature = "disable")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __emit {
+    ($level:expr, $msg:expr) => {{
+        let _: $crate::Level = $level;
+        eprintln!("{}", $msg);
+    }};
+    (target: $target:expr, $level:expr, $msg:expr) => {
+        $crate::__emit!($level, $msg)
+    };
+}
+/// Discards timing messages when the `disable` feature is enabled.
+#[cfg(all(feature = "std", feature = "disable"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __emit {
+    ($level:expr, $msg:expr) => {{
+        let _: $crate::Level = $level;
+        let _ = $msg;
+    }};
+    (target: $target:expr, $level:expr, $msg:expr) => {
+        $crate::__emit!($level, $msg)
+    };
+}
+
+/// Logs a timing message with the `defmt` macro of the given [`Level`].
+#[cfg(all(feature = "defmt", not(feature = "disable")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __defmt {
+    (Error, $($arg:tt)*) => { ::defmt::error!($($arg)*) };
+    (Warn, $($arg:tt)*) => { ::defmt::warn!($($arg)*) };
+    (Info, $($arg:tt)*) => { ::defmt::info!($($arg)*) };
+    (Debug, $($arg:tt)*) => { ::defmt::debug!($($arg)*) };
+    (Trace, $($arg:tt)*) => { ::defmt::trace!($($arg)*) };
+}
+/// Discards `defmt` timing messages when the `disable` feature is enabled.
+#[cfg(all(feature = "defmt", feature = "disable"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __defmt {
+    ($level:ident, $format:literal $(, $arg:expr)*) => {{
+        let _ = $crate::Level::$level;
+        $(let _ = $arg;)*
+    }};
+}
+
+/// Creates a `tracing` span for a timed block.
+#[cfg(all(feature = "tracing", not(feature = "disable")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __span {
+    () => {
+        $crate::__tracing::info_span!("time", elapsed_ns = $crate::__tracing::field::Empty)
+    };
+    ($label:expr) => {
+        $crate::__tracing::info_span!("time", label = %$label, elapsed_ns = $crate::__tracing::field::Empty)
+    };
+}
+/// Stands in for a `tracing` span when the feature is disabled.
+#[cfg(any(not(feature = "tracing"), feature = "disable"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __span {
+    ($($label:expr)?) => {
+        $crate::__private::NoSpan
+    };
+}
+/// Instruments a future with a `tracing` span.
+#[cfg(all(feature = "tracing", not(feature = "disable")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __instrument {
+    ($span:expr, $fut:expr) => {
+        $crate::__tracing::Instrument::instrument($fut, ::core::clone::Clone::clone(&$span))
+    };
+}
+/// Returns the future unchanged when the `tracing` feature is disabled.
+#[cfg(any(not(feature = "tracing"), feature = "disable"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __instrument {
+    ($span:expr, $fut:expr) => {
+        $fut
+    };
+}
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "std")]
+    use std::fmt::Display;
+    #[cfg(feature = "std")]
+    use std::time::Duration;
+
+    #[cfg(feature = "std")]
+    use crate::callsite::CallSite;
+    #[cfg(feature = "std")]
+    use crate::{Level, Outcome, TimingRecord};
+
+    #[cfg(feature = "std")]
+    pub use crate::bench::Adaptive;
+    #[cfg(feature = "std")]
+    pub use crate::every::{Every, Window};
+    #[cfg(feature = "std")]
+    pub use crate::future::ThreadSleep;
+    #[cfg(feature = "std")]
+    pub use crate::retry::{retry, summary as retry_summary};
+
+    /// A no-op replacement for `tracing::Span` used when the `tracing` feature is disabled.
+    #[derive(Clone)]
+    pub struct NoSpan;
+
+    impl NoSpan {
+        #[inline(always)]
+        pub fn enter(&self) -> NoSpan {
+            NoSpan
+        }
+
+        #[inline(always)]
+        pub fn record<V>(&self, _field: &str, _value: V) -> &Self {
+            self
+        }
+    }
+
+    /// Creates the record of a labelled measurement and adds it to the
+    /// registry, with its self time if it was opened as `nested`, and to the
+    /// counters of its call site.
+    #[cfg(all(feature = "std", not(feature = "disable")))]
+    pub fn labeled_record(
+        label: impl Display,
+        duration: Duration,
+        nested: Option<crate::registry::Nested>,
+        site: &'static CallSite,
+    ) -> TimingRecord {
+        let label = label.to_string();
+        let enabled = crate::is_enabled() && crate::filter::enabled(Some(&label));
+        let weight = nested.as_ref().map_or(1, crate::registry::Nested::weight);
+        let self_duration = match nested {
+            Some(nested) => nested.finish(duration, enabled),
+            None => duration,
+        };
+        if enabled {
+            crate::registry::record_sampled(&label, duration, self_duration, weight);
+            site.record(Some(&label), duration, weight);
+        }
+        let mut record = TimingRecord::new(Some(label), duration, site.file(), site.line());
+        record.module_path = site.module_path().into();
+        if enabled {
+            crate::trace::record(&record);
+            crate::slow::check(&record);
+        }
+        record
+    }
+
+    /// Creates the record of an unlabelled measurement and adds it to the
+    /// counters of its call site.
+    #[cfg(all(feature = "std", not(feature = "disable")))]
+    pub fn unlabeled_record(duration: Duration, site: &'static CallSite) -> TimingRecord {
+        let mut record = TimingRecord::new(None, duration, site.file(), site.line());
+        record.module_path = site.module_path().into();
+        if crate::is_enabled() && crate::filter::enabled(None) {
+            site.record(None, duration, 1);
+            crate::trace::record(&record);
+            crate::slow::check(&record);
+        }
+        record
+    }
+
+    /// Creates the record of a summary of measurements taken at a call site,
+    /// such as their total `duration`.
+    #[cfg(feature = "std")]
+    pub fn summary_record(label: Option<String>, duration: Duration, summary: String, site: &'static CallSite) -> TimingRecord {
+        let mut record = TimingRecord::new(label, duration, site.file(), site.line());
+        record.module_path = site.module_path().into();
+        record.summary = Some(summary);
+        record
+    }
+
+    /// Returns the [`Timing`](crate::Timing) of a measurement of
+    /// [`time_ex!`](crate::time_ex) that just ended.
+    #[cfg(feature = "std")]
+    pub fn timing<T>(duration: Duration, label: Option<String>, site: &'static CallSite, result: T) -> crate::Timing<T> {
+        crate::Timing::new(duration, label, site, result)
+    }
+
+    /// Starts watching a measurement that is about to run, if it has a
+    /// watchdog `threshold`.
+    #[cfg(all(feature = "watchdog", not(feature = "disable")))]
+    pub fn watch(
+        threshold: Option<Duration>,
+        label: Option<&dyn Display>,
+        site: &'static CallSite,
+        emit: fn(Level, &str),
+    ) -> Option<crate::watchdog::Watch> {
+        let threshold = threshold?;
+        let label = label.map(|label| label.to_string());
+        if !crate::is_enabled() || !crate::filter::enabled(label.as_deref()) {
+            return None;
+        }
+        Some(crate::watchdog::enter(label, threshold, site.file(), site.line(), emit))
+    }
+
+    #[cfg(all(feature = "std", any(not(feature = "watchdog"), feature = "disable")))]
+    #[inline(always)]
+    pub fn watch(
+        _threshold: Option<Duration>,
+        _label: Option<&dyn Display>,
+        _site: &'static CallSite,
+        _emit: fn(Level, &str),
+    ) {
+    }
+
+    /// Sets the outcome and level of a record from the result of the timed code.
+    #[cfg(feature = "std")]
+    pub fn outcome<T, E: Display>(
+        record: &mut TimingRecord,
+        result: &Result<T, E>,
+        ok: Level,
+        err: Level,
+    ) {
+        if cfg!(feature = "disable") {
+            return;
+        }
+        match result {
+            Ok(_) => {
+                record.level = ok;
+                record.outcome = Some(Outcome::Ok);
+            }
+            Err(error) => {
+                record.level = err;
+                record.outcome = Some(Outcome::Err(error.to_string()));
+            }
+        }
+    }
+
+    /// Attaches a backtrace of the caller to a record.
+    #[cfg(feature = "std")]
+    pub fn capture_backtrace(record: &mut TimingRecord) {
+        if cfg!(feature = "disable") {
+            return;
+        }
+        record.backtrace = Some(std::backtrace::Backtrace::force_capture().to_string());
+    }
+
+    /// Formats the text message of a record.
+    #[cfg(all(feature = "std", not(feature = "disable")))]
+    pub fn message(record: &TimingRecord) -> String {
+        record.message()
+    }
+
+    /// Returns whether a measurement took longer than `budget`, and if so
+    /// sets the budget of its record and, if `backtrace` is set, captures a
+    /// backtrace of the caller.
+    #[cfg(all(feature = "std", not(feature = "disable")))]
+    pub fn over_budget(record: &mut TimingRecord, budget: Duration, backtrace: bool) -> bool {
+        if record.duration <= budget
+            || !crate::is_enabled()
+            || !crate::filter::enabled(record.label.as_deref())
+        {
+            return false;
+        }
+        record.budget = Some(budget);
+        if backtrace {
+            capture_backtrace(record);
+        }
+        true
+    }
+
+    /// Returns the record of a [`par_time!`] section, recording its wall
+    /// time in the registry if it has a label.
+    #[cfg(all(feature = "std", not(feature = "disable")))]
+    pub fn parallel(label: Option<&dyn Display>, timings: &crate::cpu::Timings, site: &'static CallSite) -> Option<TimingRecord> {
+        let label = label.map(ToString::to_string);
+        if !crate::is_enabled() || !crate::filter::enabled(label.as_deref()) {
+            return None;
+        }
+        let parallelism = format!("{}, parallelism: {:.2}x", timings, timings.parallelism());
+        let summary = match &label {
+            Some(label) => {
+                crate::registry::record(label, timings.wall);
+                format!("{} - {}", label, parallelism)
+            }
+            None => parallelism,
+        };
+        Some(summary_record(label, timings.wall, summary, site))
+    }
+
+    #[cfg(all(feature = "std", feature = "disable"))]
+    #[inline(always)]
+    pub fn labeled_record(
+        _label: impl Display,
+        duration: Duration,
+        _nested: Option<crate::registry::Nested>,
+        site: &'static CallSite,
+    ) -> TimingRecord {
+        unlabeled_record(duration, site)
+    }
+
+    #[cfg(all(feature = "std", feature = "disable"))]
+    #[inline(always)]
+    pub fn unlabeled_record(duration: Duration, site: &'static CallSite) -> TimingRecord {
+        TimingRecord {
+            label: None,
+            duration,
+            timestamp: std::time::UNIX_EPOCH,
+            thread: String::new(),
+            file: site.file().into(),
+            line: site.line(),
+            module_path: site.module_path().into(),
+            level: crate::Level::Info,
+            depth: 0,
+            parent_duration: None,
+            panicked: false,
+            outcome: None,
+            template: None,
+            throughput: None,
+            splits: Vec::new(),
+            backtrace: None,
+            budget: None,
+            context: None,
+            fields: Vec::new(),
+            summary: None,
+        }
+    }
+
+    #[cfg(all(feature = "std", feature = "disable"))]
+    #[inline(always)]
+    pub fn message(_record: &TimingRecord) -> String {
+        String::new()
+    }
+
+    #[cfg(all(feature = "std", feature = "disable"))]
+    #[inline(always)]
+    pub fn parallel(_label: Option<&dyn Display>, _timings: &crate::cpu::Timings, _site: &'static CallSite) -> Option<TimingRecord> {
+        None
+    }
+
+    #[cfg(all(feature = "std", feature = "disable"))]
+    #[inline(always)]
+    pub fn over_budget(_record: &mut TimingRecord, _budget: Duration, _backtrace: bool) -> bool {
+        false
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    /// Held by tests that install a global sink, so that one test resetting
+    /// the sink does not remove the sink of another while it runs
+    #[cfg(not(any(feature = "disable", feature = "defmt")))]
+    static SINK_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[test]
+    fn it_works() {
+        let (duration, result) = time! {
+            let sum: u32 = (1..=1000).sum();
+            sum
+        };
+        let logged_result = log_time!(
+            "Summing numbers" => {
+                let sum: u32 = (1..=1000).sum();
+                sum
+            }
+        );
+        
+        let pair = log_time!(
+            "First operation" => 5*5,
+            "Second operation" => {
+                10 + 15
+            }
+        );
+        assert_eq!(pair, (25, 25));
+        
+        assert_eq!(result, 500500);
+        assert_eq!(logged_result, 500500);
+        assert!(duration >= std::time::Duration::new(0, 0));
+    }
+
+    /// Drives a future to completion on the current thread.
+    #[cfg(not(feature = "disable"))]
+    fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct ThreadWaker(std::thread::Thread);
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let mut fut = std::pin::pin!(fut);
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn async_timing() {
+        block_on(async {
+            let (duration, result) = time_async!(async {
+                std::thread::sleep(std::time::Duration::from_millis(5));
+                21 * 2
+            });
+            assert_eq!(result, 42);
+            assert!(duration >= std::time::Duration::from_millis(5));
+
+            let (msg, result) = format_time_async!("Async operation" => async { 1 + 1 });
+            assert!(msg.starts_with("Async operation - Execution time: "));
+            assert_eq!(result, 2);
+
+            let result = log_time_async!(async { "done" });
+            assert_eq!(result, "done");
+        });
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "disable", feature = "tokio", feature = "async-std")))]
+    fn times_out_on_a_thread_timer() {
+        use std::time::Duration;
+
+        block_on(async {
+            let (duration, value) = time_or_timeout!(Duration::from_secs(60), async { 42 }).unwrap();
+            assert_eq!(value, 42);
+            assert!(duration < Duration::from_secs(60));
+
+            let limit = Duration::from_millis(5);
+            let elapsed = time_or_timeout!(limit, std::future::pending::<()>()).unwrap_err();
+            assert_eq!(elapsed.limit, limit);
+            assert!(elapsed.after >= limit);
+        });
+    }
+
+    #[test]
+    #[cfg(all(feature = "tokio", not(feature = "disable")))]
+    fn times_out_on_a_tokio_timer() {
+        use std::time::Duration;
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        runtime.block_on(async {
+            let (_, value) = time_or_timeout!(Duration::from_secs(60), async { 42 }).unwrap();
+            assert_eq!(value, 42);
+
+            let limit = Duration::from_millis(5);
+            let elapsed = time_or_timeout!(limit, std::future::pending::<()>()).unwrap_err();
+            assert!(elapsed.after >= limit);
+        });
+    }
+
+    #[test]
+    fn retry_timed_returns_every_attempt() {
+        use crate::Outcome;
+        use std::time::Duration;
+
+        let mut calls = 0;
+        let (result, attempts) = retry_timed!(attempts: 3, {
+            calls += 1;
+            if calls < 3 { Err(format!("call {}", calls)) } else { Ok(calls) }
+        });
+        assert_eq!(result, Ok(3));
+        let outcomes: Vec<Outcome> = attempts.into_iter().map(|attempt| attempt.outcome).collect();
+        assert_eq!(outcomes, [Outcome::Err("call 1".into()), Outcome::Err("call 2".into()), Outcome::Ok]);
+
+        let (result, attempts) = retry_timed!(attempts: 2, backoff: Duration::from_millis(1), "retry-{}", "macro" => {
+            "x".parse::<u32>()
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts[0].backoff, Duration::from_millis(1));
+        if !cfg!(feature = "disable") {
+            assert_eq!(crate::registry::stats("retry-macro").unwrap().count, 2);
+        }
+    }
+
+    #[test]
+    fn scope_timer_covers_early_returns() {
+        fn check(value: Option<u32>) -> Option<u32> {
+            time_scope!("check");
+            let value = value?;
+            Some(value + 1)
+        }
+        assert_eq!(check(Some(1)), Some(2));
+        assert_eq!(check(None), None);
+
+        let timer = crate::ScopeTimer::new("manual").threshold(std::time::Duration::from_secs(60));
+        assert_eq!(timer.label(), Some("manual"));
+        assert!(timer.elapsed() < std::time::Duration::from_secs(60));
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn time_iterations_times_each_iteration() {
+        let mut visited = Vec::new();
+        let series = time_iterations!(samples: 2, "iterations-{}", "for" => for (i, value) in [10, 20, 30, 40].iter().enumerate() {
+            if i == 1 {
+                continue;
+            }
+            if *value > 30 {
+                break;
+            }
+            visited.push(*value);
+        });
+        assert_eq!(visited, [10, 30]);
+        assert_eq!(series.label(), Some("iterations-for"));
+        assert_eq!(series.stats().count, 4);
+        assert_eq!(series.samples().len(), 2);
+        assert_eq!(crate::registry::stats("iterations-for").unwrap().count, 4);
+
+        let mut stack = vec![1, 2, 3];
+        let series = time_iterations!(while let Some(_) = stack.pop() {});
+        assert_eq!((series.label(), series.stats().count), (None, 3));
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn time_catch_records_panics() {
+        assert_eq!(time_catch!("catch-ok {}", 1 => 2 + 2), 4);
+        let result = std::panic::catch_unwind(|| {
+            time_catch!("catch-panic" => {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                panic!("boom")
+            })
+        });
+        assert!(result.is_err());
+
+        assert_eq!(crate::registry::stats("catch-ok 1").unwrap().count, 1);
+        let stats = crate::registry::stats("catch-panic").unwrap();
+        assert_eq!(stats.count, 1);
+        assert!(stats.total >= std::time::Duration::from_millis(1));
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn log_time_options() {
+        use std::time::Duration;
+
+        let a = log_time!(threshold: Duration::from_secs(60), "Below threshold" => 1 + 1);
+        let b = log_time!(level: Debug, threshold: Duration::ZERO, "Leveled" => { 2 + 2 });
+        let c = log_time!(threshold: Duration::from_secs(60), level: Warn, 3 + 3);
+        let rows = 2;
+        let d = log_time!(threshold: Duration::from_secs(60), "Fielded", fields: { rows = rows, table = "users" } => rows * 4);
+        let e = log_time!(target: "perf", level: Trace, threshold: Duration::from_secs(60), "Targeted" => 5 + 5);
+        assert_eq!((a, b, c, d, e), (2, 4, 6, 8, 10));
+        // Suppressed output is still recorded in the registry, except by the `defmt` variant
+        assert_eq!(crate::registry::stats("Below threshold").is_some(), cfg!(not(feature = "defmt")));
+    }
+
+    #[test]
+    #[cfg(feature = "defmt")]
+    fn defmt_accepts_every_option() {
+        use std::time::Duration;
+
+        let id = 3;
+        let a = log_time!(template: "{label}", sample: 2, backtrace: true, "defmt-{}", id => 1 + 1);
+        let b = log_time!(watchdog: Duration::from_secs(5), target: "perf", "defmt-fields", fields: { id = id } => 2 + 2);
+        let c = log_time!(level: Warn, "defmt-first" => 3, "defmt-second" => 4);
+        assert_eq!((a, b, c), (2, 4, (3, 4)));
+    }
+
+    #[test]
+    fn debug_time_passes_through_in_release() {
+        let name = "rows";
+        let a = debug_time!(level: Debug, threshold: std::time::Duration::ZERO, "debug-{}", name => 1 + 1);
+        let pair = debug_time!("debug-first" => 2 + 2, "debug-second" => 3 + 3);
+        let b = debug_time!(5 * 5);
+        assert_eq!((a, pair, b), (2, (4, 6), 25));
+        // The expansion of release builds
+        let c = debug_time!(@release sample: 2, "debug-{}", name => 7);
+        let pair = debug_time!(@release level: Warn, "debug-release" => 8, "debug-release" => 9);
+        assert_eq!((c, pair, debug_time!(@release 10)), (7, (8, 9), 10));
+        let d = debug_time!(@release target: "perf", "debug-fields", fields: { name = name } => 11);
+        assert_eq!(d, 11);
+        assert_eq!(crate::registry::stats("debug-release"), None);
+        if cfg!(all(debug_assertions, not(feature = "disable"), not(feature = "defmt"))) {
+            assert_eq!(crate::registry::stats("debug-rows").unwrap().count, 1);
+            assert_eq!(crate::registry::stats("debug-first").unwrap().count, 1);
+        } else {
+            assert_eq!(crate::registry::stats("debug-rows"), None);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn assert_time_reports_actual_duration() {
+        use std::time::Duration;
+
+        assert_eq!(assert_time!(Duration::from_secs(60), 2 + 2), 4);
+        let panic = std::panic::catch_unwind(|| {
+            assert_time!(Duration::ZERO, std::thread::sleep(Duration::from_millis(2)), "step {}", 3)
+        })
+        .unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("block took "), "{}", message);
+        assert!(message.ends_with(" ms, exceeding its budget of 0 ns: step 3"), "{}", message);
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "disable", feature = "defmt")))]
+    fn sink_receives_nested_records() {
+        use std::sync::{Arc, Mutex, PoisonError};
+
+        let _lock = SINK_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&seen);
+        crate::set_sink(move |record: &crate::TimingRecord| {
+            if record.label.as_deref().is_some_and(|label| label.starts_with("sink-")) {
+                collected.lock().unwrap().push((record.label.clone().unwrap(), record.depth, record.level));
+            }
+        });
+        log_time!(level: Warn, "sink-outer" => {
+            log_time!("sink-inner" => 1 + 1);
+        });
+        crate::sink::reset_sink();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            *seen,
+            [
+                ("sink-outer".to_string(), 0, crate::Level::Warn),
+                ("sink-inner".to_string(), 1, crate::Level::Info),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "disable", feature = "defmt")))]
+    fn sends_summaries_through_the_sink() {
+        use std::sync::{Arc, Mutex, PoisonError};
+
+        let _lock = SINK_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&seen);
+        crate::set_sink(move |record: &crate::TimingRecord| {
+            if let Some(summary) = record.summary.as_deref().filter(|summary| summary.starts_with("summary-")) {
+                collected.lock().unwrap().push(summary.to_string());
+            }
+        });
+        for i in 0..2u64 {
+            time_every!(calls: 2, "summary-every" => i * i);
+        }
+        time_iterations!("summary-iterations" => for _ in 0..3 {});
+        par_time!("summary-parallel" => (1..=100).sum::<u32>());
+        let _ = retry_timed!(attempts: 2, "summary-retry" => "7".parse::<u32>());
+        compare_time!("summary-old" => 1 + 1, "new" => 2);
+        crate::sink::reset_sink();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 5);
+        assert!(seen[0].starts_with("summary-every - 2 calls, mean: "), "{}", seen[0]);
+        assert!(seen[1].starts_with("summary-iterations - 3 iterations, mean: "), "{}", seen[1]);
+        assert!(seen[2].starts_with("summary-parallel - wall: "), "{}", seen[2]);
+        assert!(seen[3].starts_with("summary-retry - succeeded on attempt 1 of 2 after "), "{}", seen[3]);
+        assert!(seen[4].starts_with("summary-old: "), "{}", seen[4]);
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "disable", feature = "defmt")))]
+    fn warns_over_budget() {
+        use std::sync::{Arc, Mutex, PoisonError};
+        use std::time::Duration;
+
+        let _lock = SINK_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&seen);
+        crate::set_sink(move |record: &crate::TimingRecord| {
+            if record.label.as_deref().is_some_and(|label| label.starts_with("budget-")) {
+                collected.lock().unwrap().push((record.label.clone().unwrap(), record.level, record.budget));
+            }
+        });
+        assert_eq!(warn_if_over!(Duration::from_secs(60), "budget-ok" => 2 + 2), 4);
+        assert_eq!(warn_if_over!(backtrace: true, Duration::ZERO, "budget-{}", 1 => 3), 3);
+        crate::sink::reset_sink();
+        assert_eq!(*seen.lock().unwrap(), [("budget-1".to_string(), crate::Level::Warn, Some(Duration::ZERO))]);
+
+        let mut record = crate::TimingRecord::new(Some("budget-slow".into()), Duration::from_millis(150), "", 0);
+        assert!(!crate::__private::over_budget(&mut record, Duration::from_millis(150), true));
+        assert!(crate::__private::over_budget(&mut record, Duration::from_millis(100), false));
+        assert_eq!(record.to_string(), "budget-slow - Execution time: 150 ms, exceeding its budget of 100 ms");
+        assert!(crate::__private::over_budget(&mut record, Duration::from_millis(100), true));
+        assert!(record.to_string().starts_with("budget-slow - Execution time: 150 ms, exceeding its budget of 100 ms\n"));
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "disable", feature = "defmt")))]
+    fn sends_reports_through_the_sink() {
+        use std::sync::{Arc, Mutex, PoisonError};
+        use std::time::Duration;
+
+        struct Reports(Arc<Mutex<Vec<Option<u64>>>>);
+
+        impl crate::TimeSink for Reports {
+            fn record(&self, _record: &crate::TimingRecord) {}
+
+            fn report(&self, report: &crate::registry::Report) {
+                self.0.lock().unwrap().push(report.get("report-sink").map(|stats| stats.count));
+            }
+        }
+
+        let _lock = SINK_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        crate::set_sink(Reports(Arc::clone(&seen)));
+        let guard = crate::report_on_exit();
+        crate::registry::record("report-sink", Duration::from_millis(1));
+        crate::report();
+        drop(guard);
+        crate::sink::reset_sink();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert!(seen.iter().all(|count| count.is_some_and(|count| count >= 1)), "{:?}", seen);
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn logs_parallelism() {
+        use std::time::Duration;
+
+        let sums = par_time!("par-{}", "chunks" => std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..2u64).map(|n| scope.spawn(move || (0..n * 100).sum::<u64>())).collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).sum::<u64>()
+        }));
+        assert_eq!(sums, 4950);
+        assert_eq!(crate::registry::stats("par-chunks").unwrap().count, 1);
+
+        let timings = crate::cpu::Timings {
+            wall: Duration::from_millis(100),
+            user: Duration::from_millis(700),
+            system: Duration::from_millis(50),
+        };
+        static SITE: crate::callsite::CallSite = crate::callsite::CallSite::new(file!(), line!(), module_path!());
+        let record = crate::__private::parallel(Some(&"par-resize"), &timings, &SITE).unwrap();
+        assert_eq!(record.to_string(), "par-resize - wall: 100 ms, user: 700 ms, system: 50 ms, parallelism: 7.50x");
+        assert_eq!(record.duration, Duration::from_millis(100));
+        assert_eq!(par_time!(1 + 1), 2);
+    }
+
+    #[cfg(all(feature = "macros", not(feature = "disable")))]
+    #[crate::timed_test(name = "timed-test-ok", budget = "1min")]
+    fn timed_test_passes_within_budget() {
+        assert_eq!(crate::registry::stats("timed-test-ok"), None);
+    }
+
+    #[cfg(all(feature = "macros", not(feature = "disable")))]
+    #[crate::timed_test(name = "timed-test-slow", budget = "1ns")]
+    #[should_panic(expected = "timed-test-slow - Execution time: ")]
+    fn timed_test_fails_over_budget() {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    #[cfg(all(feature = "macros", not(feature = "disable")))]
+    #[test]
+    fn timed_instruments_impl_blocks() {
+        use std::fmt;
+
+        struct TimedImplCache<T>(Vec<T>);
+
+        #[crate::timed]
+        impl<T: Copy> TimedImplCache<T>
+        where
+            T: fmt::Debug,
+        {
+            const EMPTY: usize = 0;
+
+            fn get(&self, index: usize) -> Option<T> {
+                self.0.get(index).copied()
+            }
+
+            #[crate::timed(name = "timed-impl-insert")]
+            fn insert(&mut self, value: T) {
+                self.0.push(value);
+            }
+
+            #[timed(skip)]
+            fn len(&self) -> usize {
+                self.0.len()
+            }
+
+            const fn capacity_hint() -> usize {
+                Self::EMPTY + 4
+            }
+        }
+
+        #[crate::timed(name = "timed-impl-display")]
+        impl<T> fmt::Display for TimedImplCache<T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{} items", self.0.len())
+            }
+        }
+
+        let mut cache = TimedImplCache(Vec::new());
+        cache.insert(7);
+        assert_eq!(cache.get(0), Some(7));
+        assert_eq!((cache.len(), TimedImplCache::<u8>::capacity_hint()), (1, 4));
+        assert_eq!(cache.to_string(), "1 items");
+
+        let labels = ["TimedImplCache::get", "timed-impl-insert", "timed-impl-display::fmt"];
+        for label in labels {
+            assert_eq!(crate::registry::stats(label).unwrap().count, 1, "{}", label);
+        }
+        assert_eq!(crate::registry::stats("TimedImplCache::len"), None);
+    }
+
+    #[test]
+    fn time_n_batches_fast_code() {
+        let target = std::time::Duration::from_millis(20);
+        let start = std::time::Instant::now();
+        let stats = time_n!(target, std::hint::black_box(2) + 2);
+        assert!(!stats.samples.is_empty());
+        if !cfg!(feature = "disable") {
+            assert!(start.elapsed() >= target);
+            assert!(stats.iterations > stats.samples.len());
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn returns_numbers_in_units() {
+        let clock = crate::test::MockClock::new();
+        let _guard = clock.install();
+        let advance = || clock.advance(std::time::Duration::from_micros(1500));
+
+        assert_eq!(time_ms!(advance()), (1.5, ()));
+        assert_eq!(time_us!(advance()), (1500.0, ()));
+        assert_eq!(time_ns!(advance()), (1_500_000, ()));
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn counts_calls_per_site() {
+        for _ in 0..3 {
+            format_time!("callsite-test" => ());
+        }
+        format_time!("callsite-test" => ());
+        let sites: Vec<_> = crate::callsite::call_sites()
+            .into_iter()
+            .filter(|site| site.label() == Some("callsite-test"))
+            .collect();
+        assert_eq!(sites.len(), 2);
+        assert_eq!(sites[0].count(), 3);
+        assert_eq!(sites[1].count(), 1);
+        assert_eq!(sites[0].line() + 2, sites[1].line());
+        assert_eq!(sites[0].module_path(), module_path!());
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn records_self_time() {
+        let clock = crate::test::MockClock::new();
+        let _guard = clock.install();
+        let advance = |millis| clock.advance(std::time::Duration::from_millis(millis));
+
+        format_time!("self-outer" => {
+            advance(1);
+            let _scope = crate::ScopeTimer::new("self-scope");
+            format_time!("self-inner" => advance(2));
+            format_time!(advance(4));
+            format_time!("self-inner" => advance(3));
+        });
+        let self_millis = |label| {
+            let stats = crate::registry::stats(label).unwrap();
+            (stats.total.as_millis(), stats.self_total.as_millis())
+        };
+        assert_eq!(self_millis("self-inner"), (5, 5));
+        assert_eq!(self_millis("self-scope"), (9, 4));
+        assert_eq!(self_millis("self-outer"), (10, 1));
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn splits_blocks_at_checkpoints() {
+        let clock = crate::test::MockClock::new();
+        let _guard = clock.install();
+        let advance = |millis| clock.advance(std::time::Duration::from_millis(millis));
+
+        let (msg, ()) = format_time!("checkpoints" => {
+            advance(1);
+            checkpoint!("step {}", 1);
+            let (inner, ()) = format_time!("inner" => {
+                advance(2);
+                checkpoint!("inner step");
+            });
+            assert_eq!(inner, "inner - Execution time: 2 ms (inner step: 2 ms, 100.0%; end: 0 ns, 0.0%)");
+            checkpoint!("step 2");
+            advance(1);
+        });
+        assert_eq!(msg, "checkpoints - Execution time: 4 ms (step 1: 1 ms, 25.0%; step 2: 2 ms, 50.0%; end: 1 ms, 25.0%)");
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn compares_two_blocks() {
+        let clock = crate::test::MockClock::new();
+        let _guard = clock.install();
+        let advance = |millis| clock.advance(std::time::Duration::from_millis(millis));
+
+        let comparison = compare_time!("cmp-old" => advance(6), "cmp-new" => advance(2));
+        assert_eq!(comparison.baseline.iterations, 1);
+        assert_eq!(comparison.speedup(), 3.0);
+        assert_eq!(comparison.to_string(), "cmp-old: 6 ms, cmp-new: 2 ms - cmp-new is 3.00x faster (-4 ms, -66.7%)");
+
+        let comparison = compare_time!(iterations: 5, "cmp-old" => advance(1), "cmp-new" => advance(2));
+        assert_eq!(comparison.candidate.iterations, 5);
+        assert_eq!(comparison.change_percent(), 100.0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "disable"))]
+    fn accepts_format_args_labels() {
+        let (id, table) = (7, "users");
+        let (msg, result) = format_time!("fmt-query {} from {}", id, table => id + 1);
+        assert!(msg.starts_with("fmt-query 7 from users - Execution time: "), "{}", msg);
+        assert_eq!(result, 8);
+
+        let (msg, _) = format_time!(template: "<{label}>", "fmt-{}", id => ());
+        assert_eq!(msg, "<fmt-7>");
+        assert_eq!(log_time!(level: Debug, "fmt-log {}", id => 3), 3);
+        #[cfg(not(feature = "defmt"))]
+        assert_eq!(crate::registry::stats("fmt-log 7").unwrap().count, 1);
+
+        let (msg, result) = block_on(async { format_time_async!("fmt-async {}", id => async { 4 }) });
+        assert!(msg.starts_with("fmt-async 7 - "), "{}", msg);
+        assert_eq!(result, 4);
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "disable", feature = "defmt")))]
+    fn applies_per_call_templates() {
+        use std::sync::{Arc, Mutex, PoisonError};
+
+        let (msg, _) = format_time!(template: "{label}|{level}", "tpl-format" => 1 + 1);
+        assert_eq!(msg, "tpl-format|INFO");
+
+        let _lock = SINK_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&seen);
+        crate::set_sink(move |record: &crate::TimingRecord| {
+            if record.label.as_deref().is_some_and(|label| label.starts_with("tpl-")) {
+                collected.lock().unwrap().push(record.message());
+            }
+        });
+        log_time!(threshold: std::time::Duration::ZERO, template: "{label}|{level}", level: Warn, "tpl-log" => 1 + 1);
+        crate::sink::reset_sink();
+        assert_eq!(*seen.lock().unwrap(), ["tpl-log|WARN"]);
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "disable", feature = "defmt")))]
+    fn records_call_site() {
+        use std::sync::{Arc, Mutex, PoisonError};
+
+        let _lock = SINK_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&seen);
+        crate::set_sink(move |record: &crate::TimingRecord| {
+            if record.label.as_deref().is_some_and(|label| label.starts_with("site-")) {
+                collected.lock().unwrap().push((record.line, record.module_path.to_string()));
+            }
+        });
+        let line = line!() + 2;
+        {
+            time_scope!("site-scope");
+            log_time!("site-block" => 1 + 1);
+        }
+        crate::sink::reset_sink();
+
+        let module = module_path!().to_string();
+        let seen = seen.lock().unwrap();
+        assert_eq!(*seen, [(line, module.clone()), (line + 1, module)]);
+    }
+
+    #[test]
+    #[cfg(feature = "disable")]
+    fn disabled_timing_is_a_pass_through() {
+        let (duration, result) = time!(std::thread::sleep(std::time::Duration::from_millis(1)));
+        assert_eq!(duration, std::time::Duration::ZERO);
+        assert_eq!(result, ());
+
+        let (msg, result) = format_time!("Disabled" => 1 + 1);
+        assert!(msg.is_empty());
+        assert_eq!(result, 2);
+        assert_eq!(log_time!("Disabled" => 3), 3);
+        assert!(crate::registry::stats("Disabled").is_none());
     }
 }
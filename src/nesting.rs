@@ -0,0 +1,327 @@
+//! Hierarchical output for nested timings.
+//!
+//! When [`log_time!`](crate::log_time) blocks or [`ScopeTimer`](crate::ScopeTimer)s
+//! are nested on the same thread, the inner timings are held back until the
+//! outermost one finishes, and then printed as an indented tree below their
+//! parent:
+//!
+//! ```text
+//! frame - Execution time: 16.2 ms
+//!   physics - Execution time: 4.1 ms
+//!   render - Execution time: 11.8 ms
+//!     upload - Execution time: 2.3 ms
+//! ```
+//!
+//! Timings that are not nested are printed immediately, as before. The async
+//! macros do not take part in nesting, since a task can move between threads
+//! while it runs, unless the task runs inside a `task::scope` (`tokio`
+//! feature), which keeps a stack of open timings per task.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{log_time, nesting};
+//!
+//! // Also show each child as a percentage of its parent
+//! nesting::set_show_percentages(true);
+//!
+//! log_time!("frame" => {
+//!     log_time!("physics" => (1..=1000).sum::<u32>());
+//!     log_time!("render" => (1..=30).product::<u128>());
+//! });
+//! // Prints:
+//! // frame - Execution time: ...
+//! //   physics - Execution time: ... (12.5% of parent)
+//! //   render - Execution time: ... (80.1% of parent)
+//! ```
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::TimingRecord;
+
+static SHOW_PERCENTAGES: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The frames open on this thread, or in the task it is polling.
+    static STACK: RefCell<Stack> = const { RefCell::new(Stack::new()) };
+}
+
+/// The open frames of a thread or of a task.
+#[derive(Debug)]
+pub(crate) struct Stack {
+    /// The open frames, outermost first
+    open: Vec<Open>,
+    /// Where the timings of a task go, or `None` for the stack of a thread
+    task: Option<Task>,
+}
+
+/// An open frame.
+#[derive(Debug, Default)]
+struct Open {
+    /// The children collected so far
+    children: Vec<Node>,
+    /// The mailboxes of the tasks created inside the frame
+    spawned: Vec<Mailbox>,
+}
+
+/// Where a task hands its finished timings to the frame it was created in,
+/// which closes it (sets it to `None`) when it finishes.
+type Mailbox = Arc<Mutex<Option<Vec<Node>>>>;
+
+/// The link from the stack of a task to the frame it was created in.
+#[derive(Debug)]
+struct Task {
+    /// The mailbox of that frame, if the task was created inside one
+    parent: Option<Mailbox>,
+    /// The depth of the task's timings when they are output on their own,
+    /// because that frame finished first
+    depth: usize,
+}
+
+impl Stack {
+    const fn new() -> Self {
+        Stack {
+            open: Vec::new(),
+            task: None,
+        }
+    }
+
+    /// Creates the stack of a task inside the innermost open frame of the
+    /// current thread or task.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn task() -> Self {
+        STACK.with_borrow_mut(|stack| {
+            let base = stack.task.as_ref().map_or(0, |task| task.depth);
+            let parent = match stack.open.last_mut() {
+                Some(open) => {
+                    let mailbox = Arc::new(Mutex::new(Some(Vec::new())));
+                    open.spawned.push(Arc::clone(&mailbox));
+                    Some(mailbox)
+                }
+                None => stack.task.as_ref().and_then(|task| task.parent.clone()),
+            };
+            Stack {
+                open: Vec::new(),
+                task: Some(Task {
+                    parent,
+                    depth: base + stack.open.len(),
+                }),
+            }
+        })
+    }
+
+    /// Exchanges this stack with the one of the current thread.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn swap(&mut self) {
+        STACK.with_borrow_mut(|stack| std::mem::swap(stack, self));
+    }
+}
+
+impl Open {
+    /// Returns the children of the frame, including those handed over by its
+    /// tasks so far, and closes the mailboxes of the tasks.
+    fn collect(self) -> Vec<Node> {
+        let mut children = self.children;
+        for mailbox in self.spawned {
+            let handed = mailbox.lock().unwrap_or_else(PoisonError::into_inner).take();
+            children.extend(handed.unwrap_or_default());
+        }
+        children
+    }
+}
+
+/// Sets whether nested timings show their share of the parent's duration.
+///
+/// Disabled by default.
+pub fn set_show_percentages(show: bool) {
+    SHOW_PERCENTAGES.store(show, Ordering::Relaxed);
+}
+
+/// Returns whether nested timings show their share of the parent's duration.
+pub fn show_percentages() -> bool {
+    SHOW_PERCENTAGES.load(Ordering::Relaxed)
+}
+
+/// Returns how many timed blocks are currently open on this thread.
+pub fn depth() -> usize {
+    STACK.with_borrow(|stack| stack.open.len())
+}
+
+/// A finished timing and the timings nested inside it.
+#[derive(Debug)]
+struct Node {
+    record: TimingRecord,
+    children: Vec<Node>,
+}
+
+/// An open timed block on the current thread's nesting stack.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Frame {
+    depth: usize,
+    finished: bool,
+}
+
+/// Opens a frame for a timed block that is about to start.
+#[doc(hidden)]
+pub fn enter() -> Frame {
+    let depth = STACK.with_borrow_mut(|stack| {
+        stack.open.push(Open::default());
+        stack.open.len() - 1
+    });
+    Frame {
+        depth,
+        finished: false,
+    }
+}
+
+/// Opens a frame for a timed future that is about to start, if it runs
+/// inside a `task::scope`, whose stack it can stay on across `.await`s.
+#[doc(hidden)]
+pub fn enter_task() -> Option<Frame> {
+    STACK.with_borrow(|stack| stack.task.is_some()).then(enter)
+}
+
+impl Frame {
+    /// Closes the frame with its record, or `None` if the block is not to be
+    /// logged (e.g. because it was below a threshold).
+    ///
+    /// Returns the records to output now, parents before their children with
+    /// [`depth`](TimingRecord::depth) and [`parent_duration`](TimingRecord::parent_duration)
+    /// filled in. The result is empty if the records were handed to the
+    /// enclosing frame and will be output when that one finishes.
+    pub fn finish(mut self, record: Option<TimingRecord>) -> Vec<TimingRecord> {
+        self.finished = true;
+        let children = self.close();
+        let nodes = match record {
+            Some(record) => vec![Node { record, children }],
+            None => children,
+        };
+        if nodes.is_empty() {
+            return Vec::new();
+        }
+        if self.depth > 0 {
+            let orphaned = STACK.with_borrow_mut(|stack| match stack.open.get_mut(self.depth - 1) {
+                Some(parent) => {
+                    parent.children.extend(nodes);
+                    None
+                }
+                None => Some(nodes),
+            });
+            return orphaned.map(|nodes| flatten(nodes, 0)).unwrap_or_default();
+        }
+        // The outermost timings of a task go to the frame it was created in,
+        // if that one is still open
+        let (parent, depth) = STACK.with_borrow(|stack| match &stack.task {
+            Some(task) => (task.parent.clone(), task.depth),
+            None => (None, 0),
+        });
+        if let Some(parent) = parent
+            && let Some(siblings) = parent.lock().unwrap_or_else(PoisonError::into_inner).as_mut()
+        {
+            siblings.extend(nodes);
+            return Vec::new();
+        }
+        flatten(nodes, depth)
+    }
+
+    /// Removes this frame (and any frames left open inside it) from the stack,
+    /// returning all the children they collected.
+    fn close(&self) -> Vec<Node> {
+        STACK.with_borrow_mut(|stack| {
+            if stack.open.len() <= self.depth {
+                return Vec::new();
+            }
+            stack.open.split_off(self.depth).into_iter().flat_map(Open::collect).collect()
+        })
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        if !self.finished {
+            // The block never finished (e.g. it panicked): pass its children up
+            let children = self.close();
+            if self.depth > 0 {
+                STACK.with_borrow_mut(|stack| {
+                    if let Some(parent) = stack.open.get_mut(self.depth - 1) {
+                        parent.children.extend(children);
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn flatten(nodes: Vec<Node>, depth: usize) -> Vec<TimingRecord> {
+    let mut records = Vec::new();
+    for node in nodes {
+        flatten_node(node, depth, None, &mut records);
+    }
+    records
+}
+
+fn flatten_node(
+    node: Node,
+    depth: usize,
+    parent: Option<Duration>,
+    records: &mut Vec<TimingRecord>,
+) {
+    let Node { mut record, children } = node;
+    record.depth = depth;
+    record.parent_duration = parent;
+    let duration = record.duration;
+    records.push(record);
+    for child in children {
+        flatten_node(child, depth + 1, Some(duration), records);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(label: &str, millis: u64) -> Option<TimingRecord> {
+        let duration = Duration::from_millis(millis);
+        Some(TimingRecord::new(Some(label.into()), duration, file!(), line!()))
+    }
+
+    fn lines(records: &[TimingRecord]) -> Vec<String> {
+        records
+            .iter()
+            .map(|record| format!("{:indent$}{}", "", record.label.as_deref().unwrap(), indent = record.depth * 2))
+            .collect()
+    }
+
+    #[test]
+    fn nests_children_under_parents() {
+        let outer = enter();
+        let inner = enter();
+        assert_eq!(depth(), 2);
+        let innermost = enter();
+        assert!(innermost.finish(record("innermost", 1)).is_empty());
+        assert!(inner.finish(record("inner", 5)).is_empty());
+        let suppressed = enter();
+        assert!(suppressed.finish(None).is_empty());
+
+        let output = outer.finish(record("outer", 10));
+        assert_eq!(lines(&output), ["outer", "  inner", "    innermost"]);
+        assert_eq!(output[2].parent_duration, Some(Duration::from_millis(5)));
+        assert_eq!(depth(), 0);
+    }
+
+    #[test]
+    fn unfinished_frames_pass_children_up() {
+        let outer = enter();
+        let broken = enter();
+        let child = enter();
+        assert!(child.finish(record("child", 1)).is_empty());
+        drop(broken);
+
+        let output = outer.finish(record("outer", 2));
+        assert_eq!(lines(&output), ["outer", "  child"]);
+    }
+}
@@ -0,0 +1,471 @@
+//! Parsing arbitime's own text output.
+//!
+//! Tools that consume arbitime logs, such as test harnesses and log
+//! processors, can turn the text back into data: [`parse_duration`] reads a
+//! duration as printed by [`HumanDuration`](crate::HumanDuration), and
+//! [`parse_line`] reads a whole timing message back into a [`TimingRecord`].
+//!
+//! Durations have the precision of the text they are parsed from, so
+//! `1.83 ms` becomes exactly 1 830 000 ns. Throughputs and shares of the
+//! parent are converted back from their rounded rates and percentages, so
+//! they are approximate.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::parse::{parse_duration, parse_line};
+//! use arbitime::Outcome;
+//! use std::time::Duration;
+//!
+//! assert_eq!(parse_duration("1.83ms").unwrap(), Duration::from_micros(1830));
+//! assert_eq!(parse_duration("2 min 3 s").unwrap(), Duration::from_secs(123));
+//!
+//! let record = parse_line("  [req-123] fetch - failed in 250 ms (error: timed out) attempt=3").unwrap();
+//! assert_eq!(record.label.as_deref(), Some("fetch"));
+//! assert_eq!(record.duration, Duration::from_millis(250));
+//! assert_eq!(record.outcome, Some(Outcome::Err("timed out".into())));
+//! assert_eq!(record.context.as_deref(), Some("req-123"));
+//! assert_eq!((record.depth, record.fields[0].1.to_string()), (1, "3".to_string()));
+//! ```
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::checkpoint::Split;
+use crate::record::INDENT;
+use crate::{FieldValue, Outcome, Throughput, TimingRecord};
+
+/// Text that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    input: String,
+    reason: String,
+}
+
+impl ParseError {
+    fn new(input: &str, reason: impl Into<String>) -> Self {
+        ParseError {
+            input: input.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot parse `{}`: {}", self.input, self.reason)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parses a duration as printed by [`HumanDuration`](crate::HumanDuration),
+/// such as `1.83 s`, `250 ms`, `4 µs` or `1 h 2 min 3 s`.
+///
+/// The space between a number and its unit is optional, and `us` is accepted
+/// for microseconds.
+pub fn parse_duration(text: &str) -> Result<Duration, ParseError> {
+    match duration_prefix(text.trim()) {
+        Some((duration, "")) => Ok(duration),
+        Some((_, rest)) => Err(ParseError::new(text, format!("unexpected `{}`", rest.trim()))),
+        None => Err(ParseError::new(text, "expected a number followed by ns, µs, us, ms, s, min or h")),
+    }
+}
+
+/// Parses a line of text output back into a [`TimingRecord`].
+///
+/// The line must use the default message format, not a
+/// [template](crate::template), and the same
+/// [`show_timestamp`](crate::format::show_timestamp),
+/// [`show_thread`](crate::format::show_thread) and
+/// [`show_location`](crate::format::show_location) settings as this process.
+/// Everything the text contains is recovered: the label, duration, outcome,
+/// correlation ID, indentation, throughput, splits, fields, share of the
+/// parent, and the backtrace on the following lines. Properties the line does
+/// not show are left at neutral values: the timestamp is the Unix epoch, the
+/// thread and file are empty, the line is 0 and the level is
+/// [`Info`](crate::Level::Info).
+pub fn parse_line(line: &str) -> Result<TimingRecord, ParseError> {
+    let invalid = |reason: &str| ParseError::new(line, reason);
+    let (first, backtrace) = match line.split_once('\n') {
+        Some((first, backtrace)) => (first, Some(backtrace.to_string())),
+        None => (line, None),
+    };
+    let mut record = TimingRecord::new(None, Duration::ZERO, "", 0);
+    record.timestamp = UNIX_EPOCH;
+    record.thread = String::new();
+    record.context = None;
+    record.backtrace = backtrace;
+
+    let mut rest = first.trim_end().trim_start_matches(' ');
+    record.depth = (first.len() - first.trim_start_matches(' ').len()) / INDENT;
+    if crate::format::show_timestamp() {
+        let (timestamp, after) = rest.split_once(' ').ok_or_else(|| invalid("missing timestamp"))?;
+        record.timestamp = parse_timestamp(timestamp).ok_or_else(|| invalid("invalid timestamp"))?;
+        rest = after;
+    }
+    if crate::format::show_thread() {
+        let (thread, after) = bracketed(rest).ok_or_else(|| invalid("missing thread"))?;
+        record.thread = thread.to_string();
+        rest = after;
+    }
+    if crate::format::show_location() {
+        let (location, after) = bracketed(rest).ok_or_else(|| invalid("missing call site"))?;
+        let (file, number) = location.rsplit_once(':').ok_or_else(|| invalid("invalid call site"))?;
+        record.file = Cow::Owned(file.to_string());
+        record.line = number.parse().map_err(|_| invalid("invalid call site"))?;
+        rest = after;
+    }
+    if let Some((context, after)) = bracketed(rest) {
+        record.context = Some(context.into());
+        rest = after;
+    }
+
+    let (label, kind, after) = message(rest).ok_or_else(|| invalid("not a timing message"))?;
+    record.label = label.map(str::to_string);
+    let (duration, mut rest) = duration_prefix(after).ok_or_else(|| invalid("invalid duration"))?;
+    record.duration = duration;
+    match kind {
+        Kind::Finished => {}
+        Kind::Panicked => record.panicked = true,
+        Kind::Succeeded => record.outcome = Some(Outcome::Ok),
+        Kind::Failed => {
+            let (error, after) = rest
+                .strip_prefix(' ')
+                .and_then(parenthesized)
+                .and_then(|(error, after)| Some((error.strip_prefix("error: ")?, after)))
+                .ok_or_else(|| invalid("missing error"))?;
+            record.outcome = Some(Outcome::Err(error.to_string()));
+            rest = after;
+        }
+    }
+
+    if let Some(before) = rest.strip_suffix("% of parent)")
+        && let Some((before, share)) = before.rsplit_once(" (")
+    {
+        let share: f64 = share.parse().map_err(|_| invalid("invalid share of parent"))?;
+        let parent = (duration.as_nanos() as f64 * 100.0 / share).round() as u64;
+        record.parent_duration = (share > 0.0).then(|| Duration::from_nanos(parent));
+        rest = before;
+    }
+    while let Some((group, after)) = rest.strip_prefix(' ').and_then(parenthesized) {
+        if group.ends_with("/s") {
+            record.throughput = Some(throughput(group, duration).ok_or_else(|| invalid("invalid throughput"))?);
+        } else {
+            record.splits = splits(group).ok_or_else(|| invalid("invalid splits"))?;
+        }
+        rest = after;
+    }
+    if !rest.is_empty() {
+        let fields = rest.strip_prefix(' ').ok_or_else(|| invalid("unexpected text after the duration"))?;
+        record.fields = parse_fields(fields).ok_or_else(|| invalid("invalid fields"))?;
+    }
+    Ok(record)
+}
+
+/// How a timing message describes the end of the timed code.
+enum Kind {
+    Finished,
+    Panicked,
+    Succeeded,
+    Failed,
+}
+
+/// Splits a message into its label, its kind, and the text from the duration
+/// on.
+fn message(text: &str) -> Option<(Option<&str>, Kind, &str)> {
+    let labelled = [
+        (" - Execution time: ", Kind::Finished),
+        (" - panicked after ", Kind::Panicked),
+        (" - succeeded in ", Kind::Succeeded),
+        (" - failed in ", Kind::Failed),
+    ];
+    let first = labelled
+        .into_iter()
+        .filter_map(|(marker, kind)| Some((text.find(marker)?, marker, kind)))
+        .min_by_key(|&(index, _, _)| index);
+    if let Some((index, marker, kind)) = first {
+        return Some((Some(&text[..index]), kind, &text[index + marker.len()..]));
+    }
+    let unlabelled = [
+        ("Execution time: ", Kind::Finished),
+        ("Panicked after ", Kind::Panicked),
+        ("Succeeded in ", Kind::Succeeded),
+        ("Failed in ", Kind::Failed),
+    ];
+    unlabelled
+        .into_iter()
+        .find_map(|(marker, kind)| Some((None, kind, text.strip_prefix(marker)?)))
+}
+
+/// Parses the duration at the start of `text`, and returns it with the text
+/// after it.
+fn duration_prefix(text: &str) -> Option<(Duration, &str)> {
+    let (mut total, mut rest) = component(text)?;
+    // Durations of a minute or more have several components, e.g. `1 h 2 min`
+    while let Some((duration, after)) = rest.strip_prefix(' ').and_then(component) {
+        total = total.checked_add(duration)?;
+        rest = after;
+    }
+    Some((total, rest))
+}
+
+/// Parses a number followed by a unit, e.g. `1.83 ms`.
+fn component(text: &str) -> Option<(Duration, &str)> {
+    let digits = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let value: f64 = text[..digits].parse().ok()?;
+    let after = text[digits..].strip_prefix(' ').unwrap_or(&text[digits..]);
+    let length = after.find(|c: char| !c.is_alphabetic()).unwrap_or(after.len());
+    let nanos_per_unit = match &after[..length] {
+        "ns" => 1.0,
+        "us" | "µs" => 1e3,
+        "ms" => 1e6,
+        "s" => 1e9,
+        "min" => 60e9,
+        "h" => 3600e9,
+        _ => return None,
+    };
+    let nanos = (value * nanos_per_unit).round();
+    if nanos > u64::MAX as f64 {
+        return None;
+    }
+    Some((Duration::from_nanos(nanos as u64), &after[length..]))
+}
+
+/// Parses an RFC 3339 UTC timestamp as written by
+/// [`rfc3339`](crate::format::rfc3339), e.g. `2023-11-14T22:13:20.123456Z`.
+fn parse_timestamp(text: &str) -> Option<SystemTime> {
+    let (date, time) = text.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<u64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (hours, minutes, seconds) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    let nanos = match fraction {
+        "" => 0,
+        fraction if fraction.len() <= 9 => format!("{:0<9}", fraction).parse().ok()?,
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let days = crate::format::days_from_civil(year, month, day)?;
+    let secs = days * 86_400 + hours * 3600 + minutes * 60 + seconds;
+    UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+}
+
+/// Splits `[inside] rest` into `inside` and `rest`.
+fn bracketed(text: &str) -> Option<(&str, &str)> {
+    text.strip_prefix('[')?.split_once("] ")
+}
+
+/// Splits `(inside) rest` into `inside` and ` rest`, matching nested
+/// parentheses.
+fn parenthesized(text: &str) -> Option<(&str, &str)> {
+    let inner = text.strip_prefix('(')?;
+    let mut depth = 0;
+    for (index, c) in inner.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some((&inner[..index], &inner[index + 1..])),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Converts a rate such as `1.2 M items/s` or `345 MiB/s` back into the
+/// amount of work done in `duration`.
+fn throughput(rate: &str, duration: Duration) -> Option<Throughput> {
+    let (value, unit) = rate.split_once(' ')?;
+    let value: f64 = value.parse().ok()?;
+    let (scale, bytes) = match unit {
+        "items/s" => (1.0, false),
+        "k items/s" => (1e3, false),
+        "M items/s" => (1e6, false),
+        "G items/s" => (1e9, false),
+        "T items/s" => (1e12, false),
+        "B/s" => (1.0, true),
+        "KiB/s" => (1024.0, true),
+        "MiB/s" => (1024f64.powi(2), true),
+        "GiB/s" => (1024f64.powi(3), true),
+        "TiB/s" => (1024f64.powi(4), true),
+        _ => return None,
+    };
+    let count = (value * scale * duration.as_secs_f64()).round() as u64;
+    Some(if bytes { Throughput::Bytes(count) } else { Throughput::Items(count) })
+}
+
+/// Parses phases such as `read: 3 ms, 25.0%; end: 9 ms, 75.0%`.
+fn splits(text: &str) -> Option<Vec<Split>> {
+    text.split("; ")
+        .map(|split| {
+            let (split, _percent) = split.rsplit_once(", ")?;
+            let (label, duration) = split.rsplit_once(": ")?;
+            let duration = parse_duration(duration).ok()?;
+            Some(Split {
+                label: label.to_string(),
+                duration,
+            })
+        })
+        .collect()
+}
+
+/// Parses fields such as `rows=42 table="user accounts"`.
+fn parse_fields(mut text: &str) -> Option<Vec<(Cow<'static, str>, FieldValue)>> {
+    let mut fields = Vec::new();
+    while !text.is_empty() {
+        let (key, after) = text.split_once('=')?;
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, after) = unquote(quoted)?;
+                (FieldValue::Str(value), after)
+            }
+            None => {
+                let (value, after) = after.split_once(' ').unwrap_or((after, ""));
+                (field_value(value), after)
+            }
+        };
+        fields.push((Cow::Owned(key.to_string()), value));
+        text = after.strip_prefix(' ').unwrap_or(after);
+    }
+    Some(fields)
+}
+
+/// Infers the type of an unquoted field value.
+fn field_value(value: &str) -> FieldValue {
+    if let Ok(value) = value.parse() {
+        FieldValue::Bool(value)
+    } else if let Ok(value) = value.parse() {
+        FieldValue::UInt(value)
+    } else if let Ok(value) = value.parse() {
+        FieldValue::Int(value)
+    } else if let Ok(value) = value.parse() {
+        FieldValue::Float(value)
+    } else {
+        FieldValue::Str(value.to_string())
+    }
+}
+
+/// Reads a string quoted with `{:?}`, starting after the opening quote, and
+/// returns it with the text after the closing quote.
+fn unquote(text: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[index + 1..])),
+            '\\' => {
+                let escaped = match chars.next()?.1 {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    '0' => '\0',
+                    'u' => {
+                        let (start, _) = chars.next().filter(|&(_, c)| c == '{')?;
+                        let end = text[start..].find('}')? + start;
+                        let code = u32::from_str_radix(&text[start + 1..end], 16).ok()?;
+                        while chars.next()?.0 < end {}
+                        char::from_u32(code)?
+                    }
+                    c => c,
+                };
+                value.push(escaped);
+            }
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(label: &str, millis: u64) -> TimingRecord {
+        let mut record = TimingRecord::new(Some(label.into()), Duration::from_millis(millis), "", 0);
+        record.timestamp = UNIX_EPOCH;
+        record.thread = String::new();
+        record.context = None;
+        record
+    }
+
+    #[test]
+    fn parses_durations() {
+        let cases = [
+            ("0 ns", Duration::ZERO),
+            ("999 ns", Duration::from_nanos(999)),
+            ("42.12 µs", Duration::from_nanos(42_120)),
+            ("42.12us", Duration::from_nanos(42_120)),
+            ("1.83ms", Duration::from_micros(1_830)),
+            (" 60 s ", Duration::from_secs(60)),
+            ("1 h 2 min 3 s", Duration::from_secs(3_723)),
+            ("2 h", Duration::from_secs(7_200)),
+        ];
+        for (text, duration) in cases {
+            assert_eq!(parse_duration(text), Ok(duration), "{}", text);
+        }
+        for duration in [Duration::from_nanos(12_340), Duration::from_millis(250), Duration::from_secs(86_461)] {
+            assert_eq!(parse_duration(&crate::HumanDuration(duration).to_string()), Ok(duration));
+        }
+        assert_eq!(
+            parse_duration("12 parsecs").unwrap_err().to_string(),
+            "cannot parse `12 parsecs`: expected a number followed by ns, µs, us, ms, s, min or h"
+        );
+        assert!(parse_duration("3 ms later").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn round_trips_records() {
+        let mut failed = record("fetch (retry)", 250);
+        failed.outcome = Some(Outcome::Err("timed out (after 3 tries)".into()));
+        failed.context = Some("req-1".into());
+        failed.depth = 2;
+        failed.fields = vec![
+            ("attempt".into(), FieldValue::UInt(3)),
+            ("delta".into(), FieldValue::Int(-1)),
+            ("ratio".into(), FieldValue::Float(0.5)),
+            ("cached".into(), FieldValue::Bool(false)),
+            ("table".into(), FieldValue::Str("user \"accounts\"".into())),
+            ("note".into(), FieldValue::Str(String::new())),
+        ];
+        let mut split = record("read", 12);
+        split.splits = vec![
+            Split { label: "after read".into(), duration: Duration::from_millis(3) },
+            Split { label: "end".into(), duration: Duration::from_millis(9) },
+        ];
+        split.throughput = Some(Throughput::Bytes(12 * 1024 * 1024));
+        split.backtrace = Some("   0: main\n   1: start".into());
+        let mut panicked = record("", 1);
+        panicked.label = None;
+        panicked.panicked = true;
+        let mut succeeded = record("Failed in tests", 7);
+        succeeded.outcome = Some(Outcome::Ok);
+
+        for record in [failed, split, panicked, succeeded, record("a - b", 60_000)] {
+            assert_eq!(parse_line(&record.to_text()), Ok(record.clone()), "{}", record);
+        }
+    }
+
+    #[test]
+    fn parses_approximate_values() {
+        let record = parse_line("  parse - Execution time: 25 ms (1.2 k items/s) (50.0% of parent)").unwrap();
+        assert_eq!(record.depth, 1);
+        assert_eq!(record.parent_duration, Some(Duration::from_millis(50)));
+        assert_eq!(record.throughput, Some(Throughput::Items(30)));
+        assert_eq!(
+            parse_timestamp("2023-11-14T22:13:20.123456Z"),
+            Some(UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_000))
+        );
+        assert_eq!(parse_timestamp("2023-13-14T22:13:20Z"), None);
+
+        let error = parse_line("parse took 3 ms").unwrap_err();
+        assert_eq!(error.to_string(), "cannot parse `parse took 3 ms`: not a timing message");
+        assert!(parse_line("parse - Execution time: 3 ms rows").is_err());
+        assert!(parse_line("parse - failed in 3 ms").is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Hardware performance counters.
+//!
+//! Wall time alone can't explain why a block got slower: more work, a worse
+//! cache hit rate and more branch mispredictions all look the same. With the
+//! `perf` feature, [`perf_time!`](crate::perf_time) also reports the CPU
+//! cycles, instructions, cache misses and branch mispredictions of the block,
+//! read from the processor's performance monitoring unit with
+//! `perf_event_open`.
+//!
+//! The counters count the user-space work of the current thread only, on
+//! whichever core it runs. They are opened on the first measurement of each
+//! thread and kept open until the thread exits. A counter the system does not
+//! provide is `None`: on platforms other than Linux on x86-64 or AArch64,
+//! in virtual machines without a virtual PMU, when
+//! `/proc/sys/kernel/perf_event_paranoid` is above 2, and with the `disable`
+//! feature. When more counters are in use than the processor has, the kernel
+//! time-slices them, and the counts cover only part of the block.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::perf_time;
+//!
+//! let (counters, sum) = perf_time!((1..=1_000_000u64).map(|n| n % 7).sum::<u64>());
+//! // "2.1 ms, 6.2 M cycles, 14 M instructions (2.26 IPC), 12 cache misses, 1.02 k branch misses"
+//! println!("{}", counters);
+//! if let Some(ipc) = counters.ipc() {
+//!     assert!(ipc > 0.0);
+//! }
+//! assert!(sum > 0);
+//! ```
+
+use std::fmt;
+use std::ops::Sub;
+use std::time::Duration;
+
+use crate::HumanDuration;
+
+/// Readings of the hardware counters of the current thread.
+///
+/// Each counter is `None` if it is unavailable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Counters {
+    /// CPU cycles
+    pub cycles: Option<u64>,
+    /// Retired instructions
+    pub instructions: Option<u64>,
+    /// Cache misses, usually of the last-level cache
+    pub cache_misses: Option<u64>,
+    /// Mispredicted branches
+    pub branch_misses: Option<u64>,
+}
+
+impl Counters {
+    /// Reads the counters of the current thread.
+    pub fn now() -> Self {
+        if cfg!(feature = "disable") {
+            return Counters::default();
+        }
+        sys::counters()
+    }
+}
+
+impl Sub for Counters {
+    type Output = Counters;
+
+    /// Returns the counts between two readings.
+    fn sub(self, earlier: Counters) -> Counters {
+        let delta = |now: Option<u64>, earlier: Option<u64>| Some(now?.saturating_sub(earlier?));
+        Counters {
+            cycles: delta(self.cycles, earlier.cycles),
+            instructions: delta(self.instructions, earlier.instructions),
+            cache_misses: delta(self.cache_misses, earlier.cache_misses),
+            branch_misses: delta(self.branch_misses, earlier.branch_misses),
+        }
+    }
+}
+
+/// Elapsed time and hardware counters of a block, returned by
+/// [`perf_time!`](crate::perf_time).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PerfTimings {
+    /// Elapsed wall-clock time
+    pub duration: Duration,
+    /// CPU cycles spent by the current thread
+    pub cycles: Option<u64>,
+    /// Instructions retired by the current thread
+    pub instructions: Option<u64>,
+    /// Cache misses of the current thread
+    pub cache_misses: Option<u64>,
+    /// Branches mispredicted by the current thread
+    pub branch_misses: Option<u64>,
+}
+
+impl PerfTimings {
+    /// Combines a duration with the counts of the same period.
+    pub fn new(duration: Duration, counters: Counters) -> Self {
+        PerfTimings {
+            duration,
+            cycles: counters.cycles,
+            instructions: counters.instructions,
+            cache_misses: counters.cache_misses,
+            branch_misses: counters.branch_misses,
+        }
+    }
+
+    /// Returns the instructions retired per cycle, or `None` if either
+    /// counter is unavailable or no cycles were counted.
+    ///
+    /// Modern cores retire up to 4 or more instructions per cycle; an IPC
+    /// well below 1 usually means the code waits on memory.
+    pub fn ipc(&self) -> Option<f64> {
+        let cycles = self.cycles.filter(|&cycles| cycles > 0)?;
+        Some(self.instructions? as f64 / cycles as f64)
+    }
+}
+
+/// Formats the duration followed by the available counters, e.g.
+/// `2.1 ms, 6.2 M cycles, 14 M instructions (2.26 IPC), 12 cache misses, 1.02 k branch misses`.
+impl fmt::Display for PerfTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", HumanDuration(self.duration))?;
+        let count = |count: u64, unit: &str| {
+            let prefixes = [("", 1.0), ("k ", 1e3), ("M ", 1e6), ("G ", 1e9), ("T ", 1e12)];
+            let (prefix, scale) = prefixes
+                .iter()
+                .rev()
+                .find(|(_, scale)| count as f64 >= *scale)
+                .unwrap_or(&prefixes[0]);
+            crate::format::format_value(count as f64 / scale, &format!("{}{}", prefix, unit), None)
+        };
+        if let Some(cycles) = self.cycles {
+            write!(f, ", {}", count(cycles, "cycles"))?;
+        }
+        if let Some(instructions) = self.instructions {
+            write!(f, ", {}", count(instructions, "instructions"))?;
+            if let Some(ipc) = self.ipc() {
+                write!(f, " ({:.2} IPC)", ipc)?;
+            }
+        }
+        if let Some(cache_misses) = self.cache_misses {
+            write!(f, ", {}", count(cache_misses, "cache misses"))?;
+        }
+        if let Some(branch_misses) = self.branch_misses {
+            write!(f, ", {}", count(branch_misses, "branch misses"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod sys {
+    use std::os::raw::{c_int, c_long, c_ulong, c_void};
+
+    use super::Counters;
+
+    #[cfg(target_arch = "x86_64")]
+    const SYS_PERF_EVENT_OPEN: c_long = 298;
+    #[cfg(target_arch = "aarch64")]
+    const SYS_PERF_EVENT_OPEN: c_long = 241;
+
+    const PERF_TYPE_HARDWARE: u32 = 0;
+    const PERF_COUNT_HW_CPU_CYCLES: u64 = 0;
+    const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+    const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+    const PERF_COUNT_HW_BRANCH_MISSES: u64 = 5;
+
+    /// The `exclude_kernel` and `exclude_hv` bits of the flags, so that
+    /// unprivileged processes may open the counters
+    const EXCLUDE_KERNEL_AND_HV: u64 = 1 << 5 | 1 << 6;
+
+    /// `struct perf_event_attr`, up to the flags
+    #[repr(C)]
+    struct PerfEventAttr {
+        kind: u32,
+        size: u32,
+        config: u64,
+        sample_period: u64,
+        sample_type: u64,
+        read_format: u64,
+        flags: u64,
+        /// The remaining fields, which are left zero
+        rest: [u64; 10],
+    }
+
+    unsafe extern "C" {
+        fn syscall(number: c_long, ...) -> c_long;
+        fn read(fd: c_int, buf: *mut c_void, count: usize) -> isize;
+        fn close(fd: c_int) -> c_int;
+    }
+
+    /// The counters of a thread, in the order of the fields of [`Counters`]
+    struct Fds([Option<c_int>; 4]);
+
+    impl Fds {
+        fn open() -> Self {
+            let events = [
+                PERF_COUNT_HW_CPU_CYCLES,
+                PERF_COUNT_HW_INSTRUCTIONS,
+                PERF_COUNT_HW_CACHE_MISSES,
+                PERF_COUNT_HW_BRANCH_MISSES,
+            ];
+            Fds(events.map(open))
+        }
+    }
+
+    impl Drop for Fds {
+        fn drop(&mut self) {
+            for fd in self.0.iter().flatten() {
+                // SAFETY: `fd` was opened by `open` and is closed only here
+                unsafe { close(*fd) };
+            }
+        }
+    }
+
+    thread_local! {
+        static FDS: Fds = Fds::open();
+    }
+
+    /// Opens a counter of a hardware event for the current thread on any
+    /// core, or returns `None` if the event is unavailable.
+    fn open(event: u64) -> Option<c_int> {
+        let attr = PerfEventAttr {
+            kind: PERF_TYPE_HARDWARE,
+            size: size_of::<PerfEventAttr>() as u32,
+            config: event,
+            sample_period: 0,
+            sample_type: 0,
+            read_format: 0,
+            flags: EXCLUDE_KERNEL_AND_HV,
+            rest: [0; 10],
+        };
+        let (pid, cpu, group_fd, flags): (c_int, c_int, c_int, c_ulong) = (0, -1, -1, 0);
+        // SAFETY: `attr` is a valid `perf_event_attr` that outlives the call
+        let fd = unsafe { syscall(SYS_PERF_EVENT_OPEN, &attr as *const PerfEventAttr, pid, cpu, group_fd, flags) };
+        c_int::try_from(fd).ok().filter(|&fd| fd >= 0)
+    }
+
+    fn value(fd: Option<c_int>) -> Option<u64> {
+        let mut value = 0u64;
+        // SAFETY: `value` is a valid, writable `u64`
+        let size = unsafe { read(fd?, (&mut value as *mut u64).cast(), size_of::<u64>()) };
+        (size == size_of::<u64>() as isize).then_some(value)
+    }
+
+    pub(super) fn counters() -> Counters {
+        FDS.try_with(|fds| {
+            let [cycles, instructions, cache_misses, branch_misses] = fds.0.map(value);
+            Counters {
+                cycles,
+                instructions,
+                cache_misses,
+                branch_misses,
+            }
+        })
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+mod sys {
+    use super::Counters;
+
+    pub(super) fn counters() -> Counters {
+        Counters::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_hardware_events() {
+        let (timings, sum) = crate::perf_time!((1..=100_000u64).map(std::hint::black_box).sum::<u64>());
+        assert_eq!(sum, 5_000_050_000);
+        if let Some(instructions) = timings.instructions {
+            assert!(instructions >= 100_000, "{}", instructions);
+        }
+        if cfg!(feature = "disable") {
+            assert_eq!(timings.cycles, None);
+        }
+
+        let timings = PerfTimings::new(
+            Duration::from_micros(2_100),
+            Counters {
+                cycles: Some(6_200_000),
+                instructions: Some(14_000_000),
+                cache_misses: None,
+                branch_misses: Some(1_024),
+            },
+        );
+        assert_eq!(
+            timings.to_string(),
+            "2.1 ms, 6.2 M cycles, 14 M instructions (2.26 IPC), 1.02 k branch misses"
+        );
+        assert_eq!(PerfTimings::new(Duration::ZERO, Counters::default()).to_string(), "0 ns");
+    }
+}
@@ -0,0 +1,202 @@
+//! Frame rate counter for continuously looping programs.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+use crate::HumanDuration;
+
+/// The default number of frames [`RateCounter`] keeps statistics over.
+const DEFAULT_WINDOW: usize = 120;
+
+/// A frame counted by [`RateCounter::tick`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// The number of the frame, starting at 1
+    pub number: u64,
+    /// The time since the previous tick
+    pub duration: Duration,
+}
+
+/// A frames-per-second counter for game, render and other loops that run
+/// continuously.
+///
+/// Call [`tick`](Self::tick) once per frame. The counter keeps the frame
+/// times of the last frames (120 by default), and reports the instantaneous
+/// and averaged frame rate, frame time percentiles and the worst frame among
+/// them. Formatting it with `{}` produces a one-line summary, e.g.
+/// `"59.8 fps (avg 60.1), frame time p50: 16.6 ms, p99: 18.2 ms, worst: 33.4 ms (frame 1234)"`.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::RateCounter;
+///
+/// let mut fps = RateCounter::new();
+/// for frame in 1..=300 {
+///     // update(); render();
+///     fps.tick();
+///     if frame % 60 == 0 {
+///         println!("{}", fps);
+///     }
+/// }
+/// assert_eq!(fps.frames(), 299);
+/// assert!(fps.average_fps() > 0.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateCounter {
+    /// A reading of the [`clock`](crate::clock) at the last tick
+    last_tick: Option<u64>,
+    /// The most recent frames, oldest first
+    recent: VecDeque<Frame>,
+    window: usize,
+    frames: u64,
+}
+
+impl RateCounter {
+    /// Creates a counter that keeps statistics over the last 120 frames.
+    pub fn new() -> Self {
+        Self::with_window(DEFAULT_WINDOW)
+    }
+
+    /// Creates a counter that keeps statistics over the last `frames` frames,
+    /// at least one.
+    pub fn with_window(frames: usize) -> Self {
+        let window = frames.max(1);
+        RateCounter {
+            last_tick: None,
+            recent: VecDeque::with_capacity(window),
+            window,
+            frames: 0,
+        }
+    }
+
+    /// Marks the end of a frame, and returns it, or `None` for the first
+    /// tick, which only starts the first frame.
+    pub fn tick(&mut self) -> Option<Frame> {
+        let now = crate::clock::now();
+        let last = self.last_tick.replace(now)?;
+        self.frames += 1;
+        let frame = Frame {
+            number: self.frames,
+            duration: Duration::from_nanos(now.saturating_sub(last)),
+        };
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(frame);
+        Some(frame)
+    }
+
+    /// Returns the number of frames counted since the counter was created.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the last frame, if any.
+    pub fn last_frame(&self) -> Option<Frame> {
+        self.recent.back().copied()
+    }
+
+    /// Returns the frame rate of the last frame alone, or zero if there is
+    /// none.
+    pub fn fps(&self) -> f64 {
+        self.last_frame().map_or(0.0, |frame| per_second(frame.duration, 1))
+    }
+
+    /// Returns the frame rate over the recent frames, or zero if there are
+    /// none.
+    pub fn average_fps(&self) -> f64 {
+        let total: Duration = self.recent.iter().map(|frame| frame.duration).sum();
+        per_second(total, self.recent.len())
+    }
+
+    /// Returns the frame time below which `percentile` percent of the recent
+    /// frames fall, e.g. `percentile(99.0)` for p99, or zero if there are no
+    /// frames.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.recent.is_empty() {
+            return Duration::ZERO;
+        }
+        let mut durations: Vec<Duration> = self.recent.iter().map(|frame| frame.duration).collect();
+        durations.sort_unstable();
+        let fraction = (percentile / 100.0).clamp(0.0, 1.0);
+        let rank = ((fraction * durations.len() as f64).ceil() as usize).max(1);
+        durations[rank - 1]
+    }
+
+    /// Returns the longest of the recent frames, the latest one if several
+    /// took as long.
+    pub fn worst(&self) -> Option<Frame> {
+        self.recent.iter().copied().max_by_key(|frame| frame.duration)
+    }
+
+    /// Forgets all frames, so the next tick starts a new first frame.
+    pub fn reset(&mut self) {
+        *self = Self::with_window(self.window);
+    }
+}
+
+impl Default for RateCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for RateCounter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(worst) = self.worst() else {
+            return write!(f, "no frames");
+        };
+        write!(
+            f,
+            "{:.1} fps (avg {:.1}), frame time p50: {}, p99: {}, worst: {} (frame {})",
+            self.fps(),
+            self.average_fps(),
+            HumanDuration(self.percentile(50.0)),
+            HumanDuration(self.percentile(99.0)),
+            HumanDuration(worst.duration),
+            worst.number
+        )
+    }
+}
+
+/// Returns how many `frames` per second took `duration` in total.
+fn per_second(duration: Duration, frames: usize) -> f64 {
+    if duration.is_zero() {
+        return 0.0;
+    }
+    frames as f64 / duration.as_secs_f64()
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+
+    #[test]
+    fn counts_frames() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let mut counter = RateCounter::with_window(4);
+        assert_eq!(counter.tick(), None);
+        assert_eq!(counter.to_string(), "no frames");
+        for millis in [10, 40, 20, 20, 20] {
+            clock.advance(Duration::from_millis(millis));
+            counter.tick();
+        }
+        assert_eq!(counter.frames(), 5);
+        assert_eq!(counter.fps(), 50.0);
+        assert_eq!(counter.average_fps(), 4.0 / 0.1);
+        assert_eq!(counter.percentile(50.0), Duration::from_millis(20));
+        assert_eq!(counter.worst(), Some(Frame { number: 2, duration: Duration::from_millis(40) }));
+        assert_eq!(
+            counter.to_string(),
+            "50.0 fps (avg 40.0), frame time p50: 20 ms, p99: 40 ms, worst: 40 ms (frame 2)"
+        );
+
+        counter.reset();
+        assert_eq!((counter.frames(), counter.last_frame()), (0, None));
+    }
+}
@@ -0,0 +1,738 @@
+//! Structured timing records.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::checkpoint::Split;
+use crate::template::Template;
+use crate::{HumanDuration, Level};
+
+/// Number of spaces each nesting level is indented by in text output.
+pub(crate) const INDENT: usize = 2;
+
+/// A single finished measurement, with where and when it was taken.
+///
+/// Records are produced by the labelled macros and by [`ScopeTimer`](crate::ScopeTimer).
+/// Formatting a record with `{}` produces the default text message, and
+/// [`to_json`](Self::to_json) produces a single-line JSON object. With the
+/// `serde` feature, records implement `serde::Serialize` and
+/// `serde::Deserialize` for any other format; the [`template`](Self::template)
+/// is left out.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::TimingRecord;
+/// use std::time::Duration;
+///
+/// let record = TimingRecord::new(Some("parse".into()), Duration::from_millis(12), file!(), line!());
+/// assert_eq!(record.to_string(), "parse - Execution time: 12 ms");
+/// assert!(record.to_json().starts_with(r#"{"label":"parse","duration_ns":12000000,"#));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct TimingRecord {
+    /// The label of the measurement, if any
+    pub label: Option<String>,
+    /// The measured duration
+    pub duration: Duration,
+    /// When the measurement finished
+    pub timestamp: SystemTime,
+    /// The name of the thread that took the measurement, or its id if unnamed
+    pub thread: String,
+    /// The source file of the measurement
+    pub file: Cow<'static, str>,
+    /// The line in [`file`](Self::file) of the measurement
+    pub line: u32,
+    /// The module path of the measurement, such as `my_app::parser`, or empty
+    /// if unknown
+    pub module_path: Cow<'static, str>,
+    /// The level the measurement is logged at
+    pub level: Level,
+    /// How deeply the measurement was nested inside other logged timings
+    pub depth: usize,
+    /// The duration of the enclosing logged timing, if nested
+    pub parent_duration: Option<Duration>,
+    /// Whether the timed code panicked
+    pub panicked: bool,
+    /// Whether the timed code succeeded, for measurements of fallible code
+    /// such as [`time_result!`](crate::time_result)
+    pub outcome: Option<Outcome>,
+    /// The template of the text message, overriding the global one set with
+    /// [`set_template`](crate::set_template)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub template: Option<&'static Template>,
+    /// How much work the timed code did, for
+    /// [`throughput_time!`](crate::throughput_time)
+    pub throughput: Option<Throughput>,
+    /// The phases of the timed code, ending at each
+    /// [`checkpoint!`](crate::checkpoint) it reached, or empty
+    pub splits: Vec<Split>,
+    /// A backtrace of the caller, captured when the measurement exceeded its
+    /// threshold with the `backtrace:` option of [`log_time!`](crate::log_time)
+    /// or [`ScopeTimer::backtrace`](crate::ScopeTimer::backtrace)
+    pub backtrace: Option<String>,
+    /// The budget the measurement exceeded, for
+    /// [`warn_if_over!`](crate::warn_if_over)
+    pub budget: Option<Duration>,
+    /// The correlation ID of the [context](crate::context) the measurement
+    /// was taken in, if any
+    pub context: Option<Arc<str>>,
+    /// Key-value pairs describing the timed code, such as the number of rows
+    /// a query returned, given with the `fields:` option of
+    /// [`log_time!`](crate::log_time)
+    pub fields: Vec<(Cow<'static, str>, FieldValue)>,
+    /// The text of a summary of one or more measurements, such as the window
+    /// of [`time_every!`](crate::time_every), which replaces the default
+    /// message
+    pub summary: Option<String>,
+}
+
+/// The outcome of timed fallible code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Outcome {
+    /// The code returned `Ok`
+    Ok,
+    /// The code returned `Err`, with the error's message
+    Err(String),
+}
+
+/// The value of a field of a [`TimingRecord`].
+///
+/// Numbers, booleans and strings convert into field values with
+/// [`From`]; other values can be converted with `.to_string()`. Floats are
+/// equal if they have the same bits, so that records can be compared.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FieldValue {
+    /// A boolean
+    Bool(bool),
+    /// A signed integer
+    Int(i64),
+    /// An unsigned integer
+    UInt(u64),
+    /// A floating-point number
+    Float(f64),
+    /// A string
+    Str(String),
+}
+
+impl PartialEq for FieldValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (FieldValue::Bool(a), FieldValue::Bool(b)) => a == b,
+            (FieldValue::Int(a), FieldValue::Int(b)) => a == b,
+            (FieldValue::UInt(a), FieldValue::UInt(b)) => a == b,
+            (FieldValue::Float(a), FieldValue::Float(b)) => a.to_bits() == b.to_bits(),
+            (FieldValue::Str(a), FieldValue::Str(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for FieldValue {}
+
+impl FieldValue {
+    /// Serializes the value as JSON: non-finite floats as `null`, and strings
+    /// quoted.
+    fn to_json(&self) -> String {
+        match self {
+            FieldValue::Float(value) if !value.is_finite() => "null".to_string(),
+            FieldValue::Str(value) => escape_json(value),
+            value => value.to_string(),
+        }
+    }
+}
+
+/// Formats the value as in text messages: strings are quoted if they are
+/// empty or contain spaces, quotes or `=`.
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Bool(value) => write!(f, "{}", value),
+            FieldValue::Int(value) => write!(f, "{}", value),
+            FieldValue::UInt(value) => write!(f, "{}", value),
+            FieldValue::Float(value) => write!(f, "{}", value),
+            FieldValue::Str(value) if value.is_empty() || value.contains([' ', '"', '=']) => {
+                write!(f, "{:?}", value)
+            }
+            FieldValue::Str(value) => f.write_str(value),
+        }
+    }
+}
+
+macro_rules! field_value_from {
+    ($variant:ident($target:ty): $($source:ty),+) => {
+        $(
+            impl From<$source> for FieldValue {
+                fn from(value: $source) -> Self {
+                    FieldValue::$variant(value as $target)
+                }
+            }
+        )+
+    };
+}
+
+field_value_from!(Int(i64): i8, i16, i32, i64, isize);
+field_value_from!(UInt(u64): u8, u16, u32, u64, usize);
+field_value_from!(Float(f64): f32, f64);
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        FieldValue::Bool(value)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::Str(value.to_string())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        FieldValue::Str(value)
+    }
+}
+
+impl From<&String> for FieldValue {
+    fn from(value: &String) -> Self {
+        FieldValue::Str(value.clone())
+    }
+}
+
+/// The amount of work done by timed code, to report it per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Throughput {
+    /// A number of items, reported as e.g. `1.2 M items/s`
+    Items(u64),
+    /// A number of bytes, reported as e.g. `345 MiB/s`
+    Bytes(u64),
+}
+
+impl Throughput {
+    /// Returns the amount of work done per second over `duration`, or zero if
+    /// the duration is zero.
+    pub fn per_second(&self, duration: Duration) -> f64 {
+        let count = match self {
+            Throughput::Items(count) | Throughput::Bytes(count) => *count,
+        };
+        match duration.as_secs_f64() {
+            0.0 => 0.0,
+            secs => count as f64 / secs,
+        }
+    }
+
+    /// Formats the rate over `duration` with a unit prefix, e.g.
+    /// `1.2 M items/s` or `345 MiB/s`.
+    pub fn rate(&self, duration: Duration) -> String {
+        let rate = self.per_second(duration);
+        let (prefixes, base, unit): (&[&str], f64, &str) = match self {
+            Throughput::Items(_) => (&["", "k", "M", "G", "T"], 1e3, "items/s"),
+            Throughput::Bytes(_) => (&["", "Ki", "Mi", "Gi", "Ti"], 1024.0, "B/s"),
+        };
+        let mut value = rate;
+        let mut prefix = 0;
+        while value >= base && prefix < prefixes.len() - 1 {
+            value /= base;
+            prefix += 1;
+        }
+        let unit = match (self, prefixes[prefix]) {
+            (Throughput::Items(_), "") => unit.to_string(),
+            (Throughput::Items(_), prefix) => format!("{} {}", prefix, unit),
+            (Throughput::Bytes(_), prefix) => format!("{}{}", prefix, unit),
+        };
+        crate::format::format_value(value, &unit, None)
+    }
+}
+
+impl TimingRecord {
+    /// Creates a record that finished now, on the current thread.
+    pub fn new(
+        label: Option<String>,
+        duration: Duration,
+        file: impl Into<Cow<'static, str>>,
+        line: u32,
+    ) -> Self {
+        TimingRecord {
+            label,
+            duration,
+            timestamp: crate::clock::system_time(),
+            thread: thread_name().to_string(),
+            file: file.into(),
+            line,
+            module_path: Cow::Borrowed(""),
+            level: Level::Info,
+            depth: 0,
+            parent_duration: None,
+            panicked: false,
+            outcome: None,
+            template: None,
+            throughput: None,
+            splits: Vec::new(),
+            backtrace: None,
+            budget: None,
+            context: crate::context::current(),
+            fields: Vec::new(),
+            summary: None,
+        }
+    }
+
+    /// Formats the record as a text message: arbitime's default, e.g.
+    /// `"parse - Execution time: 12 ms"`, or its [template](crate::template).
+    pub fn message(&self) -> String {
+        self.to_string()
+    }
+
+    /// Returns the call site of the measurement as `file:line`.
+    pub fn location(&self) -> String {
+        format!("{}:{}", self.file, self.line)
+    }
+
+    /// Formats the record as a line of text output: the message, indented by
+    /// its nesting [`depth`](Self::depth) and, if
+    /// [`nesting::show_percentages`](crate::nesting::show_percentages) is on,
+    /// followed by its share of the parent's duration.
+    pub fn to_text(&self) -> String {
+        self.text(crate::nesting::show_percentages(), HumanDuration(self.duration))
+    }
+
+    /// Formats the record as a line of text output for stderr, with the
+    /// duration colored if the `color` feature is enabled and `color::enabled`
+    /// returns `true`.
+    pub(crate) fn to_terminal_text(&self) -> String {
+        #[cfg(feature = "color")]
+        if crate::color::enabled() {
+            let duration = crate::color::ColoredDuration(self.duration);
+            return self.text(crate::nesting::show_percentages(), duration);
+        }
+        self.to_text()
+    }
+
+    fn text(&self, percentages: bool, duration: impl fmt::Display) -> String {
+        let mut line = " ".repeat(self.depth * INDENT);
+        let _ = self.write_message(&mut line, duration);
+        let parent = self.parent_duration.filter(|parent| !parent.is_zero());
+        if let Some(parent) = parent.filter(|_| percentages) {
+            let share = self.duration.as_secs_f64() / parent.as_secs_f64() * 100.0;
+            line.push_str(&format!(" ({:.1}% of parent)", share));
+        }
+        line
+    }
+
+    /// Writes the text message with the given rendering of the duration and
+    /// the budget it exceeded, followed by the backtrace on the next lines, if
+    /// any.
+    fn write_message(&self, out: &mut impl fmt::Write, duration: impl fmt::Display) -> fmt::Result {
+        self.write_line(out, duration)?;
+        if let Some(budget) = self.budget {
+            write!(out, ", exceeding its budget of {}", HumanDuration(budget))?;
+        }
+        if let Some(backtrace) = &self.backtrace {
+            write!(out, "\n{}", backtrace.trim_end())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the first line of the text message.
+    fn write_line(&self, out: &mut impl fmt::Write, duration: impl fmt::Display) -> fmt::Result {
+        if let Some(summary) = &self.summary {
+            return out.write_str(summary);
+        }
+        if let Some(template) = self.template {
+            return template.write(out, self, duration);
+        }
+        if let Some(template) = crate::template::current() {
+            return template.write(out, self, duration);
+        }
+        if crate::format::show_timestamp() {
+            write!(out, "{} ", crate::format::rfc3339(self.timestamp))?;
+        }
+        if crate::format::show_thread() {
+            write!(out, "[{}] ", self.thread)?;
+        }
+        if crate::format::show_location() {
+            write!(out, "[{}] ", self.location())?;
+        }
+        if let Some(context) = &self.context {
+            write!(out, "[{}] ", context)?;
+        }
+        match (&self.label, &self.outcome) {
+            (Some(label), _) if self.panicked => write!(out, "{} - panicked after {}", label, duration)?,
+            (None, _) if self.panicked => write!(out, "Panicked after {}", duration)?,
+            (Some(label), None) => write!(out, "{} - Execution time: {}", label, duration)?,
+            (None, None) => write!(out, "Execution time: {}", duration)?,
+            (Some(label), Some(Outcome::Ok)) => write!(out, "{} - succeeded in {}", label, duration)?,
+            (None, Some(Outcome::Ok)) => write!(out, "Succeeded in {}", duration)?,
+            (Some(label), Some(Outcome::Err(error))) => {
+                write!(out, "{} - failed in {} (error: {})", label, duration, error)?
+            }
+            (None, Some(Outcome::Err(error))) => {
+                write!(out, "Failed in {} (error: {})", duration, error)?
+            }
+        }
+        if let Some(throughput) = self.throughput {
+            write!(out, " ({})", throughput.rate(self.duration))?;
+        }
+        if !self.splits.is_empty() {
+            out.write_str(" (")?;
+            self.write_splits(out)?;
+            out.write_char(')')?;
+        }
+        if !self.fields.is_empty() {
+            out.write_char(' ')?;
+            self.write_fields(out)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the fields of the record, e.g. `rows=42 table=users`.
+    pub(crate) fn write_fields(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        for (i, (key, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                out.write_char(' ')?;
+            }
+            write!(out, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the phases of the record, e.g.
+    /// `after read: 3 ms, 25.0%; after parse: 8 ms, 66.7%; end: 1 ms, 8.3%`.
+    pub(crate) fn write_splits(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        for (i, split) in self.splits.iter().enumerate() {
+            if i > 0 {
+                out.write_str("; ")?;
+            }
+            write!(
+                out,
+                "{}: {}, {:.1}%",
+                split.label,
+                HumanDuration(split.duration),
+                split.percent_of(self.duration)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Serializes the record as a single-line JSON object.
+    ///
+    /// The duration is written in nanoseconds as `duration_ns`, and the
+    /// timestamp as an RFC 3339 string in UTC, followed by the call site as
+    /// `file`, `line` and `module_path`. Records with an
+    /// [`outcome`](Self::outcome) also have an `"ok"` field, and an `"error"`
+    /// field when they failed. Records with a [`throughput`](Self::throughput)
+    /// have `"items"` and `"items_per_sec"`, or `"bytes"` and `"bytes_per_sec"`.
+    /// Records with [`splits`](Self::splits) have a `"splits"` array of
+    /// objects with a `label` and a `duration_ns`, records of code that
+    /// [`panicked`](Self::panicked) have `"panicked":true`, records over a
+    /// [`budget`](Self::budget) have it in nanoseconds as `"budget_ns"`, and
+    /// records with a [`backtrace`](Self::backtrace) have it as a
+    /// `"backtrace"` string.
+    /// Records taken in a [`context`](Self::context) have its correlation ID
+    /// as a `"context"` string after the call site, and records with
+    /// [`fields`](Self::fields) have them as a `"fields"` object after that.
+    /// A [`summary`](Self::summary) comes last, as a `"summary"` string.
+    pub fn to_json(&self) -> String {
+        let label = match &self.label {
+            Some(label) => escape_json(label),
+            None => "null".to_string(),
+        };
+        let context = match &self.context {
+            None => String::new(),
+            Some(context) => format!(r#","context":{}"#, escape_json(context)),
+        };
+        let fields = match self.fields.as_slice() {
+            [] => String::new(),
+            fields => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(key, value)| format!("{}:{}", escape_json(key), value.to_json()))
+                    .collect();
+                format!(r#","fields":{{{}}}"#, fields.join(","))
+            }
+        };
+        let outcome = match &self.outcome {
+            None => String::new(),
+            Some(Outcome::Ok) => r#","ok":true"#.to_string(),
+            Some(Outcome::Err(error)) => format!(r#","ok":false,"error":{}"#, escape_json(error)),
+        };
+        let throughput = match self.throughput {
+            None => String::new(),
+            Some(throughput) => {
+                let (name, count) = match throughput {
+                    Throughput::Items(count) => ("items", count),
+                    Throughput::Bytes(count) => ("bytes", count),
+                };
+                let rate = throughput.per_second(self.duration);
+                format!(r#","{}":{},"{}_per_sec":{}"#, name, count, name, rate)
+            }
+        };
+        let splits = match self.splits.as_slice() {
+            [] => String::new(),
+            splits => {
+                let splits: Vec<_> = splits
+                    .iter()
+                    .map(|split| {
+                        format!(r#"{{"label":{},"duration_ns":{}}}"#, escape_json(&split.label), split.duration.as_nanos())
+                    })
+                    .collect();
+                format!(r#","splits":[{}]"#, splits.join(","))
+            }
+        };
+        let panicked = if self.panicked { r#","panicked":true"# } else { "" };
+        let budget = match self.budget {
+            None => String::new(),
+            Some(budget) => format!(r#","budget_ns":{}"#, budget.as_nanos()),
+        };
+        let summary = match &self.summary {
+            None => String::new(),
+            Some(summary) => format!(r#","summary":{}"#, escape_json(summary)),
+        };
+        let backtrace = match &self.backtrace {
+            None => String::new(),
+            Some(backtrace) => format!(r#","backtrace":{}"#, escape_json(backtrace)),
+        };
+        format!(
+            r#"{{"label":{},"duration_ns":{},"timestamp":{},"thread":{},"file":{},"line":{},"module_path":{}{}{}{}{}{}{}{}{}{}}}"#,
+            label,
+            self.duration.as_nanos(),
+            escape_json(&crate::format::rfc3339(self.timestamp)),
+            escape_json(&self.thread),
+            escape_json(&self.file),
+            self.line,
+            escape_json(&self.module_path),
+            context,
+            fields,
+            outcome,
+            throughput,
+            splits,
+            panicked,
+            budget,
+            backtrace,
+            summary
+        )
+    }
+
+    /// Returns the timestamp as a [`Duration`] since the Unix epoch.
+    pub fn unix_time(&self) -> Duration {
+        self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for TimingRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_message(f, HumanDuration(self.duration))
+    }
+}
+
+thread_local! {
+    static THREAD_NAME: Rc<str> = current_thread_name();
+}
+
+fn current_thread_name() -> Rc<str> {
+    let current = std::thread::current();
+    match current.name() {
+        Some(name) => name.into(),
+        None => format!("{:?}", current.id()).into(),
+    }
+}
+
+/// Returns the name of the current thread, or its id if unnamed.
+pub(crate) fn thread_name() -> Rc<str> {
+    THREAD_NAME
+        .try_with(Rc::clone)
+        .unwrap_or_else(|_| current_thread_name())
+}
+
+/// Quotes and escapes a string for use in JSON.
+pub(crate) fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_to_json() {
+        let mut record = TimingRecord::new(
+            Some("say \"hi\"\n".into()),
+            Duration::from_micros(1500),
+            "src/main.rs",
+            7,
+        );
+        record.timestamp = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        record.thread = "main".into();
+        record.module_path = "app::greet".into();
+        assert_eq!(
+            record.to_json(),
+            r#"{"label":"say \"hi\"\n","duration_ns":1500000,"timestamp":"2023-11-14T22:13:20.123000Z","thread":"main","file":"src/main.rs","line":7,"module_path":"app::greet"}"#
+        );
+        assert_eq!(record.location(), "src/main.rs:7");
+
+        record.context = Some("req-123".into());
+        assert!(record.to_json().ends_with(r#","module_path":"app::greet","context":"req-123"}"#));
+
+        record.label = None;
+        record.context = None;
+        assert!(record.to_json().starts_with(r#"{"label":null,"#));
+        assert_eq!(record.to_string(), "Execution time: 1.5 ms");
+    }
+
+    #[test]
+    fn names_threads() {
+        let named = std::thread::Builder::new().name("record-worker".into()).spawn(|| {
+            TimingRecord::new(None, Duration::ZERO, "", 0).thread
+        });
+        assert_eq!(named.unwrap().join().unwrap(), "record-worker");
+
+        let unnamed = std::thread::spawn(|| (thread_name().to_string(), std::thread::current().id()));
+        let (name, id) = unnamed.join().unwrap();
+        assert_eq!(name, format!("{:?}", id));
+    }
+
+    #[test]
+    fn reports_throughput() {
+        let mut record = TimingRecord::new(Some("parse".into()), Duration::from_millis(500), "", 0);
+        record.throughput = Some(Throughput::Items(600_000));
+        assert_eq!(record.to_string(), "parse - Execution time: 500 ms (1.2 M items/s)");
+        assert!(record.to_json().ends_with(r#","items":600000,"items_per_sec":1200000}"#));
+
+        record.throughput = Some(Throughput::Bytes(345 * 1024 * 1024));
+        assert_eq!(record.to_string(), "parse - Execution time: 500 ms (690 MiB/s)");
+        assert_eq!(Throughput::Items(999).rate(Duration::from_secs(1)), "999 items/s");
+        assert_eq!(Throughput::Bytes(1536).rate(Duration::from_secs(1)), "1.5 KiB/s");
+        assert_eq!(Throughput::Items(5).rate(Duration::ZERO), "0 items/s");
+    }
+
+    #[test]
+    fn lists_splits() {
+        let mut record = TimingRecord::new(Some("load".into()), Duration::from_millis(12), "", 0);
+        record.splits = [("after read", 3), ("after parse", 8), ("end", 1)]
+            .map(|(label, millis)| Split { label: label.into(), duration: Duration::from_millis(millis) })
+            .to_vec();
+        assert_eq!(
+            record.to_string(),
+            "load - Execution time: 12 ms (after read: 3 ms, 25.0%; after parse: 8 ms, 66.7%; end: 1 ms, 8.3%)"
+        );
+        assert!(record.to_json().ends_with(
+            r#","splits":[{"label":"after read","duration_ns":3000000},{"label":"after parse","duration_ns":8000000},{"label":"end","duration_ns":1000000}]}"#
+        ));
+    }
+
+    #[test]
+    fn appends_fields() {
+        let mut record = TimingRecord::new(Some("query".into()), Duration::from_millis(3), "", 0);
+        record.fields = vec![
+            ("rows".into(), FieldValue::from(42usize)),
+            ("table".into(), "user accounts".into()),
+            ("ratio".into(), f64::NAN.into()),
+            ("cached".into(), false.into()),
+        ];
+        assert_eq!(
+            record.to_string(),
+            r#"query - Execution time: 3 ms rows=42 table="user accounts" ratio=NaN cached=false"#
+        );
+        assert!(record.to_json().ends_with(
+            r#","fields":{"rows":42,"table":"user accounts","ratio":null,"cached":false}}"#
+        ));
+        assert_eq!(FieldValue::from(-1i32), FieldValue::Int(-1));
+    }
+
+    #[test]
+    fn appends_backtrace() {
+        let mut record = TimingRecord::new(Some("slow".into()), Duration::from_millis(120), "", 0);
+        record.backtrace = Some("   0: app::main\n             at ./src/main.rs:4:5\n".into());
+        assert_eq!(
+            record.to_string(),
+            "slow - Execution time: 120 ms\n   0: app::main\n             at ./src/main.rs:4:5"
+        );
+        assert!(record.to_json().ends_with(r#","backtrace":"   0: app::main\n             at ./src/main.rs:4:5\n"}"#));
+    }
+
+    #[test]
+    fn describes_outcome() {
+        let mut record = TimingRecord::new(Some("save".into()), Duration::from_millis(4), "", 0);
+        record.outcome = Some(Outcome::Ok);
+        assert_eq!(record.to_string(), "save - succeeded in 4 ms");
+        assert!(record.to_json().ends_with(r#","ok":true}"#));
+
+        record.outcome = Some(Outcome::Err("disk full".into()));
+        assert_eq!(record.to_string(), "save - failed in 4 ms (error: disk full)");
+        assert!(record.to_json().ends_with(r#","ok":false,"error":"disk full"}"#));
+
+        record.label = None;
+        assert_eq!(record.to_string(), "Failed in 4 ms (error: disk full)");
+    }
+
+    #[test]
+    fn formats_exceeded_budgets() {
+        let mut record = TimingRecord::new(Some("frame".into()), Duration::from_millis(20), "", 0);
+        record.budget = Some(Duration::from_millis(16));
+        assert_eq!(record.to_string(), "frame - Execution time: 20 ms, exceeding its budget of 16 ms");
+        assert!(record.to_json().ends_with(r#","budget_ns":16000000}"#));
+    }
+
+    #[test]
+    fn replaces_the_message_with_summaries() {
+        let mut record = TimingRecord::new(Some("tick".into()), Duration::from_millis(30), "", 0);
+        record.summary = Some("tick - 3 calls, mean: 10 ms".into());
+        assert_eq!(record.to_string(), "tick - 3 calls, mean: 10 ms");
+        assert!(record.to_json().ends_with(r#","summary":"tick - 3 calls, mean: 10 ms"}"#));
+    }
+
+    #[test]
+    fn indents_text_by_depth() {
+        let mut record = TimingRecord::new(Some("child".into()), Duration::from_millis(50), "", 0);
+        record.depth = 2;
+        record.panicked = true;
+        assert_eq!(record.to_text(), "    child - panicked after 50 ms");
+        assert!(record.to_json().ends_with(r#","panicked":true}"#));
+        record.label = None;
+        assert_eq!(record.to_string(), "Panicked after 50 ms");
+        record.label = Some("child".into());
+
+        record.panicked = false;
+        record.parent_duration = Some(Duration::from_millis(200));
+        assert_eq!(record.text(true, HumanDuration(record.duration)), "    child - Execution time: 50 ms (25.0% of parent)");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn round_trips_through_serde() {
+        let mut record = TimingRecord::new(Some("query".into()), Duration::from_micros(1_500), "src/db.rs", 12);
+        record.outcome = Some(Outcome::Err("timeout".into()));
+        record.throughput = Some(Throughput::Items(40));
+        record.splits = vec![Split {
+            label: "connect".into(),
+            duration: Duration::from_micros(300),
+        }];
+        record.context = Some("req-7".into());
+        record.fields = vec![("rows".into(), FieldValue::UInt(40)), ("ratio".into(), FieldValue::Float(0.5))];
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.starts_with(r#"{"label":"query","duration":{"secs":0,"nanos":1500000},"#), "{}", json);
+        assert_eq!(serde_json::from_str::<TimingRecord>(&json).unwrap(), record);
+    }
+}
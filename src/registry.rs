@@ -0,0 +1,1838 @@
+//! Global registry that aggregates timings by label.
+//!
+//! Every labelled measurement made through [`format_time!`](crate::format_time),
+//! [`log_time!`](crate::log_time), their async variants, [`ScopeTimer`](crate::ScopeTimer)
+//! and [`timed`](crate::timed) is recorded here, so repeated measurements of the
+//! same label accumulate a call count, total, minimum, maximum and mean.
+//!
+//! When labelled measurements nest on the same thread, the total of the outer
+//! label includes the time of the inner ones. The registry also keeps each
+//! label's self time, which excludes the time of labelled measurements nested
+//! directly inside it, to show where the time is actually spent. Measurements
+//! made by the async macros count as self time of the enclosing label, since
+//! a task can move between threads while it runs.
+//!
+//! Recording is cheap from many threads at once: the registry is split into
+//! 16 shards, each with its own lock, and threads are assigned to them
+//! round-robin when they first record. Up to 16 threads record without
+//! contending; beyond that, threads sharing a shard only wait for each other
+//! when they record at the same moment. The shards are only merged when the
+//! statistics are read.
+//!
+//! Measurements of call sites that are only timed once every N executions,
+//! such as `log_time!(sample: N, ...)`, count as N calls of the measured
+//! duration. Their statistics are marked as [`estimated`](LabelStats::estimated),
+//! shown with a `~` before the call count in the report.
+//!
+//! With the `histogram` feature enabled, every label also keeps a
+//! [`Histogram`](crate::histogram::Histogram), so the registry can report
+//! percentiles, which describe skewed latency distributions better than the
+//! mean. The precision of new histograms is set with [`set_histogram_precision`].
+//!
+//! [`set_window`] makes every label keep its recent measurements, and
+//! [`Label::window`] returns the statistics of the last N seconds of a label.
+//!
+//! [`set_reservoir_size`] makes every label also keep a bounded
+//! [`Reservoir`] of raw samples, from which exact percentiles can be computed.
+//!
+//! Reports of several processes or runs are combined with [`Report::merge`]
+//! and compared with [`Report::diff`]. With the `serde` feature, reports
+//! implement `serde::Serialize` and `serde::Deserialize` to send them between
+//! processes.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{log_time, registry};
+//!
+//! for _ in 0..3 {
+//!     log_time!("parse" => (1..=100).sum::<u32>());
+//! }
+//!
+//! # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+//! let stats = registry::stats("parse").unwrap();
+//! # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+//! assert_eq!(stats.count, 3);
+//! # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+//! assert!(stats.min <= stats.mean() && stats.mean() <= stats.max);
+//!
+//! // Print a summary table of all labels to stderr
+//! arbitime::report();
+//! ```
+//!
+//! To print the table when the program ends, keep the guard returned by
+//! [`report_on_exit`] alive until the end of `main`:
+//!
+//! ```rust
+//! let _report = arbitime::report_on_exit();
+//!
+//! arbitime::log_time!("load" => (1..=100).sum::<u32>());
+//! arbitime::log_time!("save" => (1..=20).product::<u64>());
+//! // Prints the table, with each label's share of the runtime, when `_report` is dropped
+//! ```
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "histogram")]
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::HumanDuration;
+use crate::record::escape_json;
+use crate::reservoir::Reservoir;
+use crate::window::{self, Recent, WindowStats};
+#[cfg(feature = "histogram")]
+use crate::histogram::{self, Histogram, Percentiles};
+
+/// The default smoothing factor of [`LabelStats::ewma`], see [`set_ewma_alpha`].
+pub const DEFAULT_EWMA_ALPHA: f64 = 0.1;
+
+/// The number of independently locked parts of the registry, and so the
+/// number of threads that can record without contending for a lock.
+const SHARDS: usize = 16;
+
+/// The entries recorded by the threads assigned to one shard.
+type Shard = Mutex<BTreeMap<String, Entry>>;
+
+static REGISTRY: [Shard; SHARDS] = [const { Mutex::new(BTreeMap::new()) }; SHARDS];
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+/// The names of the threads that recorded measurements, each with the key
+/// its statistics are kept under in [`Entry::threads`]. Unnamed threads share
+/// the key of [`UNNAMED_THREAD`], so that programs spawning short-lived
+/// threads do not add a key for each of them.
+static THREAD_KEYS: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+/// The moving average of each label, shared by its entries in all shards
+static EWMAS: Mutex<BTreeMap<String, Arc<Ewma>>> = Mutex::new(BTreeMap::new());
+static OBSERVER: RwLock<Option<Observer>> = RwLock::new(None);
+
+/// The name the statistics of all unnamed threads are kept under.
+pub const UNNAMED_THREAD: &str = "<unnamed>";
+
+/// A function called with every recorded measurement, see [`set_observer`].
+pub type Observer = fn(&str, Duration);
+/// The number of raw samples kept per label, see [`set_reservoir_size`]
+static RESERVOIR_SIZE: AtomicUsize = AtomicUsize::new(0);
+/// The smoothing factor of [`LabelStats::ewma`], as the bits of an `f64`
+static EWMA_ALPHA: AtomicU64 = AtomicU64::new(DEFAULT_EWMA_ALPHA.to_bits());
+/// How long recent measurements are kept, in nanoseconds, zero if they are not
+static WINDOW_RETENTION: AtomicU64 = AtomicU64::new(0);
+/// The number of recent measurements kept per label and shard
+static WINDOW_CAPACITY: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "histogram")]
+static HISTOGRAM_PRECISION: AtomicU8 = AtomicU8::new(histogram::DEFAULT_SIGNIFICANT_DIGITS);
+
+thread_local! {
+    /// The time spent in labelled measurements nested directly inside each
+    /// open labelled measurement on this thread, outermost first.
+    static CHILD_TIME: RefCell<Vec<Duration>> = const { RefCell::new(Vec::new()) };
+    /// The shard this thread records into, assigned round-robin.
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % SHARDS;
+    /// The key of this thread's name in [`THREAD_KEYS`], looked up once.
+    static THREAD_KEY: usize = thread_key(std::thread::current().name().unwrap_or(UNNAMED_THREAD));
+}
+
+/// Returns the key of a thread name, assigning the next one to a new name.
+fn thread_key(name: &str) -> usize {
+    let mut keys = THREAD_KEYS.lock().unwrap_or_else(PoisonError::into_inner);
+    let next = keys.len();
+    *keys.entry(name.to_string()).or_insert(next)
+}
+
+/// Returns the names of the threads that recorded measurements, indexed by
+/// their keys.
+fn thread_names() -> Vec<String> {
+    let keys = THREAD_KEYS.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut names = vec![String::new(); keys.len()];
+    for (name, &key) in keys.iter() {
+        names[key] = name.clone();
+    }
+    names
+}
+
+fn lock(shard: &'static Shard) -> MutexGuard<'static, BTreeMap<String, Entry>> {
+    shard.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Locks the shard of the current thread.
+fn own_shard() -> MutexGuard<'static, BTreeMap<String, Entry>> {
+    lock(&REGISTRY[SHARD.try_with(|shard| *shard).unwrap_or_default()])
+}
+
+/// Locks all shards, always in the same order.
+fn all_shards() -> Vec<MutexGuard<'static, BTreeMap<String, Entry>>> {
+    REGISTRY.iter().map(lock).collect()
+}
+
+/// Merges the entries of all shards by label.
+fn merged(shards: &[MutexGuard<'static, BTreeMap<String, Entry>>]) -> BTreeMap<String, Entry> {
+    let mut merged: BTreeMap<String, Entry> = BTreeMap::new();
+    for shard in shards {
+        for (label, entry) in shard.iter() {
+            match merged.get_mut(label) {
+                Some(merged) => merged.merge(entry),
+                None => {
+                    merged.insert(label.clone(), entry.snapshot());
+                }
+            }
+        }
+    }
+    merged
+}
+
+/// Merges everything recorded for `label` in all shards.
+fn entry(label: &str) -> Option<Entry> {
+    let mut merged: Option<Entry> = None;
+    for shard in &REGISTRY {
+        if let Some(entry) = lock(shard).get(label) {
+            match merged.as_mut() {
+                Some(merged) => merged.merge(entry),
+                None => merged = Some(entry.snapshot()),
+            }
+        }
+    }
+    merged
+}
+
+/// The moving average of a label, updated by the threads of every shard in
+/// the order they record it.
+#[derive(Debug)]
+struct Ewma {
+    /// The average in nanoseconds, or `u64::MAX` before the first measurement
+    nanos: AtomicU64,
+}
+
+impl Ewma {
+    fn new() -> Self {
+        Ewma {
+            nanos: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    /// Returns the moving average of `label`, creating it on first use.
+    fn of(label: &str) -> Arc<Ewma> {
+        let mut ewmas = EWMAS.lock().unwrap_or_else(PoisonError::into_inner);
+        match ewmas.get(label) {
+            Some(ewma) => Arc::clone(ewma),
+            None => Arc::clone(ewmas.entry(label.to_string()).or_insert_with(|| Arc::new(Ewma::new()))),
+        }
+    }
+
+    fn add(&self, duration: Duration, weight: u64) {
+        let _ = self.nanos.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |nanos| {
+            let ewma = match nanos {
+                u64::MAX => duration,
+                nanos => smooth(Duration::from_nanos(nanos), duration, weight),
+            };
+            Some(u64::try_from(ewma.as_nanos()).unwrap_or(u64::MAX - 1))
+        });
+    }
+
+    fn get(&self) -> Option<Duration> {
+        match self.nanos.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+}
+
+/// Moves a moving average towards `duration`, once for each of the `weight`
+/// measurements it stands for.
+fn smooth(ewma: Duration, duration: Duration, weight: u64) -> Duration {
+    let kept = (1.0 - ewma_alpha()).powf(weight as f64);
+    let ewma = ewma.as_nanos() as f64 * kept + duration.as_nanos() as f64 * (1.0 - kept);
+    Duration::from_nanos(ewma.round() as u64)
+}
+
+/// Everything recorded for a label.
+#[derive(Debug, Clone)]
+struct Entry {
+    stats: LabelStats,
+    /// The statistics of each thread that recorded the label, by the key of
+    /// its name, which is only resolved when the statistics are read
+    threads: BTreeMap<usize, LabelStats>,
+    #[cfg(feature = "histogram")]
+    histogram: Histogram,
+    reservoir: Reservoir,
+    /// Recent measurements, for [`window`]; not merged between shards
+    recent: Recent,
+    /// The moving average of the label in all shards, which replaces that
+    /// of [`stats`](Self::stats) when the entry is read
+    ewma: Arc<Ewma>,
+}
+
+impl Entry {
+    /// Creates an entry for `label`, sharing its moving average with the
+    /// other shards.
+    fn of(label: &str) -> Self {
+        Entry {
+            ewma: Ewma::of(label),
+            ..Entry::new()
+        }
+    }
+
+    fn new() -> Self {
+        Entry {
+            stats: LabelStats::default(),
+            threads: BTreeMap::new(),
+            reservoir: Reservoir::new(RESERVOIR_SIZE.load(Ordering::Relaxed)),
+            recent: Recent::default(),
+            ewma: Arc::new(Ewma::new()),
+            #[cfg(feature = "histogram")]
+            histogram: Histogram::new(HISTOGRAM_PRECISION.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn add(&mut self, duration: Duration, self_duration: Duration, weight: u64) {
+        self.stats.add_sampled(duration, self_duration, weight);
+        let thread = THREAD_KEY
+            .try_with(|key| *key)
+            .unwrap_or_else(|_| thread_key(&crate::record::thread_name()));
+        self.threads.entry(thread).or_default().add_sampled(duration, self_duration, weight);
+        #[cfg(feature = "histogram")]
+        self.histogram.record_n(duration, weight);
+        self.reservoir.add_n(duration, weight);
+        self.ewma.add(duration, weight);
+        let retention = WINDOW_RETENTION.load(Ordering::Relaxed);
+        if retention > 0 {
+            let capacity = WINDOW_CAPACITY.load(Ordering::Relaxed);
+            self.recent.add(crate::clock::now(), duration, weight, Duration::from_nanos(retention), capacity);
+        }
+    }
+
+    /// Returns a copy of the entry to read, with the moving average of the
+    /// label in all shards.
+    fn snapshot(&self) -> Entry {
+        let mut entry = self.clone();
+        if let Some(ewma) = self.ewma.get() {
+            entry.stats.ewma = ewma;
+        }
+        entry
+    }
+
+    /// Returns the statistics of each thread, by the names from
+    /// [`thread_names`], ordered by total time.
+    fn threads(&self, names: &[String]) -> Vec<(String, LabelStats)> {
+        let mut threads: Vec<_> = self
+            .threads
+            .iter()
+            .map(|(&thread, stats)| (names[thread].clone(), *stats))
+            .collect();
+        threads.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total));
+        threads
+    }
+
+    fn merge(&mut self, other: &Entry) {
+        self.stats.merge(&other.stats);
+        // Entries of the same label share their moving average; only those of
+        // separate registries are weighted by count, like `LabelStats::merge`
+        if Arc::ptr_eq(&self.ewma, &other.ewma)
+            && let Some(ewma) = self.ewma.get()
+        {
+            self.stats.ewma = ewma;
+        }
+        self.reservoir.merge(&other.reservoir);
+        for (thread, stats) in &other.threads {
+            self.threads.entry(*thread).or_default().merge(stats);
+        }
+        #[cfg(feature = "histogram")]
+        if self.histogram.significant_digits() == other.histogram.significant_digits() {
+            self.histogram.merge(&other.histogram);
+        } else if other.histogram.len() > self.histogram.len() {
+            // The precision changed between the two; keep the larger sample
+            self.histogram = other.histogram.clone();
+        }
+    }
+}
+
+/// Aggregated statistics for a single label.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LabelStats {
+    /// Number of recorded measurements
+    pub count: u64,
+    /// Sum of all recorded durations
+    pub total: Duration,
+    /// Sum of all recorded durations, excluding the time of labelled
+    /// measurements nested inside them
+    pub self_total: Duration,
+    /// Shortest recorded duration
+    pub min: Duration,
+    /// Longest recorded duration
+    pub max: Duration,
+    /// Exponentially weighted moving average of the recorded durations, which
+    /// follows recent changes in latency; see [`set_ewma_alpha`]. In the
+    /// registry, it follows the measurements of all threads in the order they
+    /// were recorded.
+    pub ewma: Duration,
+    /// Whether some measurements were sampled, so that the count and totals
+    /// are extrapolated from them
+    pub estimated: bool,
+}
+
+impl LabelStats {
+    /// Creates statistics from a single measurement.
+    pub fn new(duration: Duration) -> Self {
+        LabelStats {
+            count: 1,
+            total: duration,
+            self_total: duration,
+            min: duration,
+            max: duration,
+            ewma: duration,
+            estimated: false,
+        }
+    }
+
+    /// Adds a measurement to these statistics.
+    pub fn add(&mut self, duration: Duration) {
+        self.add_nested(duration, duration);
+    }
+
+    /// Adds a measurement that spent only `self_duration` of its `duration`
+    /// outside of nested measurements.
+    pub fn add_nested(&mut self, duration: Duration, self_duration: Duration) {
+        self.add_sampled(duration, self_duration, 1);
+    }
+
+    /// Adds a sampled measurement that stands for `weight` measurements of the
+    /// same duration.
+    pub fn add_sampled(&mut self, duration: Duration, self_duration: Duration, weight: u64) {
+        if weight == 0 {
+            return;
+        }
+        if self.count == 0 {
+            self.min = duration;
+            self.max = duration;
+            self.ewma = duration;
+        } else {
+            self.ewma = smooth(self.ewma, duration, weight);
+        }
+        let factor = u32::try_from(weight).unwrap_or(u32::MAX);
+        self.count += weight;
+        self.total += duration.saturating_mul(factor);
+        self.self_total += self_duration.saturating_mul(factor);
+        self.min = self.min.min(duration);
+        self.max = self.max.max(duration);
+        self.estimated |= weight > 1;
+    }
+
+    /// Adds all measurements of `other` to these statistics.
+    pub fn merge(&mut self, other: &LabelStats) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        // The averages of two sequences cannot be interleaved; weigh them by count instead
+        let ewma = (self.ewma.as_nanos() as f64 * self.count as f64 + other.ewma.as_nanos() as f64 * other.count as f64)
+            / (self.count + other.count) as f64;
+        self.ewma = Duration::from_nanos(ewma.round() as u64);
+        self.count += other.count;
+        self.total += other.total;
+        self.self_total += other.self_total;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.estimated |= other.estimated;
+    }
+
+    /// Returns the mean duration of all measurements.
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => self.total.div_f64(count as f64),
+        }
+    }
+
+    /// Returns the mean self time of all measurements.
+    pub fn self_mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => self.self_total.div_f64(count as f64),
+        }
+    }
+}
+
+/// A snapshot of the registry, ordered by total time (largest first).
+///
+/// Formatting a report with `{}` produces an aligned summary table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    entries: Vec<(String, LabelStats)>,
+    #[cfg(feature = "histogram")]
+    histograms: BTreeMap<String, Histogram>,
+    #[cfg(feature = "histogram")]
+    percentiles: BTreeMap<String, Percentiles>,
+    threads: BTreeMap<String, Vec<(String, LabelStats)>>,
+    reservoirs: BTreeMap<String, Reservoir>,
+    runtime: Option<Duration>,
+}
+
+impl Report {
+    /// Sets the runtime of the program, adding a column with the share of it
+    /// that each label took.
+    pub fn with_runtime(mut self, runtime: Duration) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Returns the runtime set with [`with_runtime`](Self::with_runtime).
+    pub fn runtime(&self) -> Option<Duration> {
+        self.runtime
+    }
+
+    /// Returns all labels and their statistics, ordered by total time.
+    pub fn entries(&self) -> &[(String, LabelStats)] {
+        &self.entries
+    }
+
+    /// Returns the statistics of a single label.
+    pub fn get(&self, label: &str) -> Option<&LabelStats> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == label)
+            .map(|(_, stats)| stats)
+    }
+
+    /// Returns the percentiles of a single label.
+    #[cfg(feature = "histogram")]
+    pub fn percentiles(&self, label: &str) -> Option<&Percentiles> {
+        self.percentiles.get(label)
+    }
+
+    /// Returns the histogram of a single label.
+    #[cfg(feature = "histogram")]
+    pub fn histogram(&self, label: &str) -> Option<&Histogram> {
+        self.histograms.get(label)
+    }
+
+    /// Returns the reservoir of raw samples of a single label, if reservoirs
+    /// were on when it was recorded, see [`set_reservoir_size`].
+    pub fn reservoir(&self, label: &str) -> Option<&Reservoir> {
+        self.reservoirs.get(label)
+    }
+
+    /// Returns the statistics of a single label for each thread that
+    /// recorded it, by thread name (or [`UNNAMED_THREAD`] if unnamed),
+    /// ordered by total time.
+    pub fn threads(&self, label: &str) -> &[(String, LabelStats)] {
+        self.threads.get(label).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns a table of how much time each thread spent in each label,
+    /// to find imbalanced thread pools.
+    ///
+    /// ```text
+    /// Label   Thread       Count         Total          Mean  % of Label
+    /// render  worker-0        40        1.21 s       30.2 ms       80.1%
+    /// render  worker-1        10      300.5 ms       30.1 ms       19.9%
+    /// ```
+    pub fn by_thread(&self) -> ThreadBreakdown<'_> {
+        ThreadBreakdown { report: self }
+    }
+
+    /// Returns `true` if no labels were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds all measurements of `other` to this report, e.g. to combine the
+    /// reports of several processes or runs.
+    ///
+    /// Labels and threads with the same name are merged, as are the
+    /// histograms of labels, so percentiles cover both reports. If both
+    /// reports have a runtime, the merged report has their sum.
+    ///
+    /// ```rust
+    /// use arbitime::registry::{self, Report};
+    /// use std::time::Duration;
+    ///
+    /// let mut total = Report::default();
+    /// for worker in 0..2 {
+    ///     registry::record("job", Duration::from_millis(10 * (worker + 1)));
+    ///     total.merge(&registry::take());
+    /// }
+    /// assert_eq!(total.get("job").unwrap().total, Duration::from_millis(30));
+    /// ```
+    pub fn merge(&mut self, other: &Report) {
+        for (label, stats) in &other.entries {
+            match self.entries.iter_mut().find(|(name, _)| name == label) {
+                Some((_, merged)) => merged.merge(stats),
+                None => self.entries.push((label.clone(), *stats)),
+            }
+        }
+        self.entries.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total));
+        for (label, threads) in &other.threads {
+            let merged = self.threads.entry(label.clone()).or_default();
+            for (thread, stats) in threads {
+                match merged.iter_mut().find(|(name, _)| name == thread) {
+                    Some((_, merged)) => merged.merge(stats),
+                    None => merged.push((thread.clone(), *stats)),
+                }
+            }
+            merged.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total));
+        }
+        #[cfg(feature = "histogram")]
+        for (label, histogram) in &other.histograms {
+            match self.histograms.get_mut(label) {
+                Some(merged) if merged.significant_digits() == histogram.significant_digits() => merged.merge(histogram),
+                // Histograms of different precisions cannot be merged; keep the larger sample
+                Some(merged) if merged.len() >= histogram.len() => continue,
+                _ => {
+                    self.histograms.insert(label.clone(), histogram.clone());
+                }
+            }
+            self.percentiles.insert(label.clone(), self.histograms[label].percentiles());
+        }
+        for (label, reservoir) in &other.reservoirs {
+            self.reservoirs.entry(label.clone()).or_default().merge(reservoir);
+        }
+        self.runtime = match (self.runtime, other.runtime) {
+            (Some(runtime), Some(other)) => Some(runtime + other),
+            (runtime, other) => runtime.or(other),
+        };
+    }
+
+    /// Compares this report against a `baseline`, e.g. the report of a
+    /// previous run, returning the change of every label in either of them.
+    ///
+    /// The labels of this report come first, ordered by total time, followed
+    /// by those only in the baseline.
+    ///
+    /// ```rust
+    /// use arbitime::registry;
+    /// use std::time::Duration;
+    ///
+    /// registry::record("render", Duration::from_millis(40));
+    /// let baseline = registry::take();
+    /// registry::record("render", Duration::from_millis(50));
+    ///
+    /// for diff in registry::take().diff(&baseline) {
+    ///     // Prints: "render - mean 50 ms (+25.0%), 1 calls (+0)"
+    ///     println!("{}", diff);
+    /// }
+    /// ```
+    pub fn diff(&self, baseline: &Report) -> Vec<LabelDiff> {
+        let current = self.entries.iter().map(|(label, stats)| LabelDiff {
+            label: label.clone(),
+            baseline: baseline.get(label).copied(),
+            current: Some(*stats),
+        });
+        let removed = baseline
+            .entries
+            .iter()
+            .filter(|(label, _)| self.get(label).is_none())
+            .map(|(label, stats)| LabelDiff {
+                label: label.clone(),
+                baseline: Some(*stats),
+                current: None,
+            });
+        current.chain(removed).collect()
+    }
+
+    /// Formats the report as one JSON object per label, each on its own line.
+    ///
+    /// ```text
+    /// {"label":"parse","count":3,"total_ns":1200,"self_ns":900,"mean_ns":400,"ewma_ns":410,"min_ns":300,"max_ns":500}
+    /// ```
+    ///
+    /// Labels with [`estimated`](LabelStats::estimated) statistics also have
+    /// an `"estimated":true` field.
+    pub fn to_json(&self) -> String {
+        let lines: Vec<String> = self
+            .entries
+            .iter()
+            .map(|(label, stats)| {
+                format!(
+                    r#"{{"label":{},"count":{},"total_ns":{},"self_ns":{},"mean_ns":{},"ewma_ns":{},"min_ns":{},"max_ns":{}{}}}"#,
+                    escape_json(label),
+                    stats.count,
+                    stats.total.as_nanos(),
+                    stats.self_total.as_nanos(),
+                    stats.mean().as_nanos(),
+                    stats.ewma.as_nanos(),
+                    stats.min.as_nanos(),
+                    stats.max.as_nanos(),
+                    if stats.estimated { r#","estimated":true"# } else { "" }
+                )
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// Formats the report as a Markdown table, to paste into pull requests
+    /// or issues.
+    ///
+    /// ```text
+    /// | Label | Count | Mean | p95 | Max | % of Total |
+    /// |:------|------:|------:|------:|------:|------:|
+    /// | parse | 3 | 400 ns | 500 ns | 500 ns | 75.0% |
+    /// ```
+    ///
+    /// The `p95` column is only present with the `histogram` feature. The
+    /// last column is the share of the [runtime](Self::with_runtime) if it is
+    /// set, and of the sum of all labels' totals otherwise.
+    pub fn to_markdown(&self) -> String {
+        let (header, rows) = self.summary_rows();
+        let mut table = format!("| {} |\n", header.join(" | "));
+        let align: Vec<&str> = header
+            .iter()
+            .map(|column| match *column {
+                "Label" => ":------",
+                _ => "------:",
+            })
+            .collect();
+        table.push_str(&format!("|{}|\n", align.join("|")));
+        for mut row in rows {
+            row[0] = row[0].replace('|', "\\|");
+            table.push_str(&format!("| {} |\n", row.join(" | ")));
+        }
+        table
+    }
+
+    /// Formats the report as a table with borders, for terminals and logs.
+    ///
+    /// ```text
+    /// +-------+-------+--------+--------+--------+------------+
+    /// | Label | Count |   Mean |    p95 |    Max | % of Total |
+    /// +-------+-------+--------+--------+--------+------------+
+    /// | parse |     3 | 400 ns | 500 ns | 500 ns |      75.0% |
+    /// +-------+-------+--------+--------+--------+------------+
+    /// ```
+    ///
+    /// The columns are those of [`to_markdown`](Self::to_markdown).
+    pub fn to_ascii_table(&self) -> String {
+        let (header, rows) = self.summary_rows();
+        let widths: Vec<usize> = header
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                rows.iter()
+                    .map(|row| row[i].chars().count())
+                    .fold(column.len(), usize::max)
+            })
+            .collect();
+        let border: String = widths.iter().map(|&width| format!("+{}", "-".repeat(width + 2))).collect();
+        let border = format!("{}+\n", border);
+        let line = |cells: &[&str]| {
+            let cells: String = cells
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(i, (cell, &width))| match i {
+                    0 => format!("| {:<width$} ", cell),
+                    _ => format!("| {:>width$} ", cell),
+                })
+                .collect();
+            format!("{}|\n", cells)
+        };
+        let mut table = border.clone();
+        table.push_str(&line(&header));
+        table.push_str(&border);
+        for row in &rows {
+            let cells: Vec<&str> = row.iter().map(String::as_str).collect();
+            table.push_str(&line(&cells));
+        }
+        table.push_str(&border);
+        table
+    }
+
+    /// Returns the header and the rows of the summary tables.
+    fn summary_rows(&self) -> (Vec<&'static str>, Vec<Vec<String>>) {
+        let mut header = vec!["Label", "Count", "Mean"];
+        #[cfg(feature = "histogram")]
+        header.push("p95");
+        header.push("Max");
+        header.push(if self.runtime.is_some() { "% Runtime" } else { "% of Total" });
+        let whole = match self.runtime {
+            Some(runtime) => runtime,
+            None => self.entries.iter().map(|(_, stats)| stats.total).sum(),
+        };
+        let rows = self
+            .entries
+            .iter()
+            .map(|(label, stats)| {
+                let mut row = vec![
+                    label.clone(),
+                    match stats.estimated {
+                        true => format!("~{}", stats.count),
+                        false => stats.count.to_string(),
+                    },
+                    HumanDuration(stats.mean()).to_string(),
+                ];
+                #[cfg(feature = "histogram")]
+                row.push(match self.percentiles.get(label) {
+                    Some(percentiles) => HumanDuration(percentiles.p95).to_string(),
+                    None => "-".to_string(),
+                });
+                row.push(HumanDuration(stats.max).to_string());
+                let share = match whole.as_nanos() {
+                    0 => 0.0,
+                    nanos => stats.total.as_nanos() as f64 / nanos as f64 * 100.0,
+                };
+                row.push(format!("{:.1}%", share));
+                row
+            })
+            .collect();
+        (header, rows)
+    }
+}
+
+/// The change of a label between two reports, see [`Report::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelDiff {
+    /// The label
+    pub label: String,
+    /// The statistics in the baseline, if the label was recorded there
+    pub baseline: Option<LabelStats>,
+    /// The current statistics, if the label was recorded
+    pub current: Option<LabelStats>,
+}
+
+impl LabelDiff {
+    /// Returns the change of the mean duration, in percent of the baseline,
+    /// or `None` if the label is missing from either report.
+    pub fn mean_change(&self) -> Option<f64> {
+        Some(change(self.baseline?.mean(), self.current?.mean()))
+    }
+
+    /// Returns the change of the total duration, in percent of the baseline,
+    /// or `None` if the label is missing from either report.
+    pub fn total_change(&self) -> Option<f64> {
+        Some(change(self.baseline?.total, self.current?.total))
+    }
+
+    /// Returns how many more measurements the label has than in the baseline,
+    /// negative if it has fewer.
+    pub fn count_change(&self) -> i128 {
+        let count = |stats: Option<LabelStats>| stats.map_or(0, |stats| i128::from(stats.count));
+        count(self.current) - count(self.baseline)
+    }
+}
+
+impl fmt::Display for LabelDiff {
+    /// Formats the change as e.g. `"parse - mean 5 µs (+25.0%), 12 calls (+2)"`,
+    /// `"parse - new, mean 5 µs, 12 calls"` or `"parse - removed"`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.baseline, self.current) {
+            (Some(baseline), Some(current)) => write!(
+                f,
+                "{} - mean {} ({:+.1}%), {} calls ({:+})",
+                self.label,
+                HumanDuration(current.mean()),
+                change(baseline.mean(), current.mean()),
+                current.count,
+                self.count_change()
+            ),
+            (None, Some(current)) => write!(
+                f,
+                "{} - new, mean {}, {} calls",
+                self.label,
+                HumanDuration(current.mean()),
+                current.count
+            ),
+            _ => write!(f, "{} - removed", self.label),
+        }
+    }
+}
+
+/// Returns the change from `baseline` to `current`, in percent of `baseline`.
+pub(crate) fn change(baseline: Duration, current: Duration) -> f64 {
+    match baseline.as_nanos() {
+        0 if current.is_zero() => 0.0,
+        0 => f64::INFINITY,
+        baseline => (current.as_nanos() as f64 / baseline as f64 - 1.0) * 100.0,
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .entries
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .chain(["Label".len()])
+            .max()
+            .unwrap_or_default();
+        write!(
+            f,
+            "{:<width$}  {:>8}  {:>12}  {:>12}  {:>12}  {:>12}  {:>12}",
+            "Label", "Count", "Total", "Self", "Mean", "Min", "Max"
+        )?;
+        #[cfg(feature = "histogram")]
+        write!(f, "  {:>12}  {:>12}  {:>12}  {:>12}", "p50", "p90", "p99", "p99.9")?;
+        if self.runtime.is_some() {
+            write!(f, "  {:>9}", "% Runtime")?;
+        }
+        writeln!(f)?;
+        for (label, stats) in &self.entries {
+            write!(
+                f,
+                "{:<width$}  {:>8}  {:>12}  {:>12}  {:>12}  {:>12}  {:>12}",
+                label,
+                match stats.estimated {
+                    true => format!("~{}", stats.count),
+                    false => stats.count.to_string(),
+                },
+                HumanDuration(stats.total),
+                HumanDuration(stats.self_total),
+                HumanDuration(stats.mean()),
+                HumanDuration(stats.min),
+                HumanDuration(stats.max)
+            )?;
+            #[cfg(feature = "histogram")]
+            if let Some(percentiles) = self.percentiles.get(label) {
+                write!(
+                    f,
+                    "  {:>12}  {:>12}  {:>12}  {:>12}",
+                    HumanDuration(percentiles.p50),
+                    HumanDuration(percentiles.p90),
+                    HumanDuration(percentiles.p99),
+                    HumanDuration(percentiles.p999)
+                )?;
+            }
+            if let Some(runtime) = self.runtime {
+                let share = match runtime.as_nanos() {
+                    0 => 0.0,
+                    nanos => stats.total.as_nanos() as f64 / nanos as f64 * 100.0,
+                };
+                write!(f, "  {:>8.1}%", share)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The time each thread spent in each label of a [`Report`].
+///
+/// Returned by [`Report::by_thread`]; formatting it with `{}` produces an
+/// aligned table.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadBreakdown<'a> {
+    report: &'a Report,
+}
+
+impl fmt::Display for ThreadBreakdown<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = || {
+            self.report.entries.iter().flat_map(|(label, stats)| {
+                self.report
+                    .threads(label)
+                    .iter()
+                    .map(move |(thread, thread_stats)| (label, stats, thread, thread_stats))
+            })
+        };
+        let label_width = rows()
+            .map(|(label, ..)| label.chars().count())
+            .fold("Label".len(), usize::max);
+        let thread_width = rows()
+            .map(|(_, _, thread, _)| thread.chars().count())
+            .fold("Thread".len(), usize::max);
+        writeln!(
+            f,
+            "{:<label_width$}  {:<thread_width$}  {:>8}  {:>12}  {:>12}  {:>10}",
+            "Label", "Thread", "Count", "Total", "Mean", "% of Label"
+        )?;
+        for (label, stats, thread, thread_stats) in rows() {
+            let share = match stats.total.as_nanos() {
+                0 => 0.0,
+                nanos => thread_stats.total.as_nanos() as f64 / nanos as f64 * 100.0,
+            };
+            writeln!(
+                f,
+                "{:<label_width$}  {:<thread_width$}  {:>8}  {:>12}  {:>12}  {:>9.1}%",
+                label,
+                thread,
+                thread_stats.count,
+                HumanDuration(thread_stats.total),
+                HumanDuration(thread_stats.mean()),
+                share
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Records a measurement for `label`.
+pub fn record(label: &str, duration: Duration) {
+    record_nested(label, duration, duration);
+}
+
+/// Records a measurement for `label` that spent only `self_duration` of its
+/// `duration` outside of nested measurements.
+pub fn record_nested(label: &str, duration: Duration, self_duration: Duration) {
+    record_sampled(label, duration, self_duration, 1);
+}
+
+/// Records a sampled measurement for `label` that stands for `weight`
+/// measurements, of which only one was timed.
+pub fn record_sampled(label: &str, duration: Duration, self_duration: Duration, weight: u64) {
+    {
+        let mut registry = own_shard();
+        match registry.get_mut(label) {
+            Some(entry) => entry.add(duration, self_duration, weight),
+            None => {
+                let mut entry = Entry::of(label);
+                entry.add(duration, self_duration, weight);
+                registry.insert(label.to_string(), entry);
+            }
+        }
+    }
+    let observer = *OBSERVER.read().unwrap_or_else(PoisonError::into_inner);
+    if let Some(observer) = observer {
+        observer(label, duration);
+    }
+}
+
+/// An open labelled measurement, collecting the time of the labelled
+/// measurements nested inside it on the current thread.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Nested {
+    depth: usize,
+    weight: u64,
+}
+
+/// Opens a labelled measurement that is about to start.
+#[doc(hidden)]
+pub fn enter() -> Nested {
+    let depth = CHILD_TIME.with_borrow_mut(|stack| {
+        stack.push(Duration::ZERO);
+        stack.len() - 1
+    });
+    Nested { depth, weight: 1 }
+}
+
+impl Nested {
+    /// Marks the measurement as a sample standing for `weight` executions.
+    pub fn sampled(mut self, weight: u64) -> Self {
+        self.weight = weight.max(1);
+        self
+    }
+
+    /// Returns how many executions the measurement stands for.
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+
+    /// Closes the measurement, which took `duration`, and returns its self
+    /// time. The duration counts towards the enclosing measurement only if
+    /// `recorded`, since the time of unrecorded children stays self time of
+    /// their parent.
+    pub fn finish(self, duration: Duration, recorded: bool) -> Duration {
+        let children = self.close();
+        if recorded && self.depth > 0 {
+            CHILD_TIME.with_borrow_mut(|stack| {
+                if let Some(parent) = stack.get_mut(self.depth - 1) {
+                    *parent += duration;
+                }
+            });
+        }
+        duration.saturating_sub(children)
+    }
+
+    /// Removes this measurement (and any left open inside it) from the stack,
+    /// returning the time of its children.
+    fn close(&self) -> Duration {
+        CHILD_TIME.with_borrow_mut(|stack| {
+            if stack.len() <= self.depth {
+                return Duration::ZERO;
+            }
+            stack.drain(self.depth..).next().unwrap_or_default()
+        })
+    }
+}
+
+impl Drop for Nested {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Calls `observer` with every labelled measurement recorded from now on, to
+/// forward timings to a metrics system.
+///
+/// Used by `install_metrics!` with the `metrics` feature. Replaces the
+/// previous observer.
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// fn export(label: &str, duration: Duration) {
+///     println!("timing.{}:{}|ms", label, duration.as_millis());
+/// }
+///
+/// arbitime::registry::set_observer(export);
+/// arbitime::log_time!("db" => (1..=100).sum::<u32>()); // Also prints "timing.db:0|ms"
+/// arbitime::registry::clear_observer();
+/// ```
+pub fn set_observer(observer: Observer) {
+    *OBSERVER.write().unwrap_or_else(PoisonError::into_inner) = Some(observer);
+}
+
+/// Removes the observer set with [`set_observer`].
+pub fn clear_observer() {
+    *OBSERVER.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Returns the statistics recorded for `label`, if any.
+pub fn stats(label: &str) -> Option<LabelStats> {
+    entry(label).map(|entry| entry.stats)
+}
+
+/// Returns the statistics recorded for `label` by each thread, by thread name
+/// (or [`UNNAMED_THREAD`] if unnamed), ordered by total time.
+pub fn thread_stats(label: &str) -> Vec<(String, LabelStats)> {
+    entry(label).map(|entry| entry.threads(&thread_names())).unwrap_or_default()
+}
+
+/// Returns a copy of the histogram recorded for `label`, if any.
+#[cfg(feature = "histogram")]
+pub fn histogram(label: &str) -> Option<Histogram> {
+    entry(label).map(|entry| entry.histogram)
+}
+
+/// Returns the p50, p90, p95, p99 and p99.9 recorded for `label`, if any.
+#[cfg(feature = "histogram")]
+pub fn percentiles(label: &str) -> Option<Percentiles> {
+    entry(label).map(|entry| entry.histogram.percentiles())
+}
+
+/// Sets the number of significant decimal digits (`1..=5`, default 3) of the
+/// histograms of labels recorded from now on.
+///
+/// More digits give more precise percentiles at the cost of more memory per
+/// label.
+#[cfg(feature = "histogram")]
+pub fn set_histogram_precision(significant_digits: u8) {
+    HISTOGRAM_PRECISION.store(significant_digits.clamp(1, 5), Ordering::Relaxed);
+}
+
+/// Sets the smoothing factor `alpha` of [`LabelStats::ewma`], clamped to
+/// `0.0..=1.0`, default [`DEFAULT_EWMA_ALPHA`].
+///
+/// Each measurement moves the average by `alpha` of its difference from it,
+/// so a higher `alpha` follows changes faster and a lower one smooths out
+/// more noise. The average reflects roughly the last `2 / alpha`
+/// measurements.
+///
+/// ```rust
+/// use arbitime::{log_time, registry};
+/// use std::time::Duration;
+///
+/// registry::set_ewma_alpha(0.3);
+/// log_time!("fetch" => std::thread::sleep(Duration::from_millis(1)));
+/// if registry::stats("fetch").is_some_and(|stats| stats.ewma > Duration::from_millis(100)) {
+///     // Throttle work while the recent latency is high
+/// }
+/// ```
+pub fn set_ewma_alpha(alpha: f64) {
+    let alpha = if alpha.is_nan() { DEFAULT_EWMA_ALPHA } else { alpha.clamp(0.0, 1.0) };
+    EWMA_ALPHA.store(alpha.to_bits(), Ordering::Relaxed);
+}
+
+/// Returns the smoothing factor set with [`set_ewma_alpha`].
+pub fn ewma_alpha() -> f64 {
+    f64::from_bits(EWMA_ALPHA.load(Ordering::Relaxed))
+}
+
+/// A label of the registry, to read its statistics repeatedly, such as
+/// those of the last minute in a live service.
+///
+/// ```rust
+/// use arbitime::{log_time, registry};
+/// use std::time::Duration;
+///
+/// registry::set_window(Duration::from_secs(300), 10_000);
+/// log_time!(threshold: Duration::MAX, "handle_request" => (1..=20).product::<u64>());
+///
+/// let requests = registry::label("handle_request");
+/// if let Some(last_minute) = requests.window(Duration::from_secs(60)) {
+///     println!("{} requests in the last minute", last_minute.stats.count);
+/// }
+/// # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+/// assert_eq!(requests.stats().unwrap().count, 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label<'a> {
+    name: &'a str,
+}
+
+/// Returns a handle to the statistics of `name`, whether it was recorded yet
+/// or not.
+pub fn label(name: &str) -> Label<'_> {
+    Label { name }
+}
+
+impl<'a> Label<'a> {
+    /// Returns the name of the label.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the statistics of all measurements of the label, like
+    /// [`stats`].
+    pub fn stats(&self) -> Option<LabelStats> {
+        stats(self.name)
+    }
+
+    /// Returns the statistics of the measurements of the label recorded in
+    /// the `last` period, such as the last minute, if the label was recorded.
+    ///
+    /// Recent measurements are only kept after [`set_window`], and for as
+    /// long as its retention. The result is marked as not
+    /// [`complete`](WindowStats::complete) if some measurements of the period
+    /// were not kept.
+    pub fn window(&self, last: Duration) -> Option<WindowStats> {
+        let since = crate::clock::now().saturating_sub(window::nanos(last));
+        let mut samples = Vec::new();
+        let mut complete = window::nanos(last) <= WINDOW_RETENTION.load(Ordering::Relaxed);
+        let mut found = false;
+        for shard in &REGISTRY {
+            if let Some(entry) = lock(shard).get(self.name) {
+                found = true;
+                complete &= entry.recent.collect(since, &mut samples);
+            }
+        }
+        found.then(|| WindowStats::new(samples, complete))
+    }
+}
+
+/// Returns the statistics of the measurements of `label` recorded in the
+/// `last` period; shorthand for [`Label::window`].
+pub fn window(label: &str, last: Duration) -> Option<WindowStats> {
+    self::label(label).window(last)
+}
+
+/// Keeps the measurements of every label recorded in the last `retention`,
+/// up to `capacity` of them per label and thread shard, for [`Label::window`].
+///
+/// Each kept measurement takes 32 bytes. A zero `retention`, the default,
+/// stops keeping new measurements.
+pub fn set_window(retention: Duration, capacity: usize) {
+    WINDOW_CAPACITY.store(capacity, Ordering::Relaxed);
+    WINDOW_RETENTION.store(window::nanos(retention), Ordering::Relaxed);
+}
+
+/// Returns a copy of the reservoir of raw samples recorded for `label`, if
+/// reservoirs were on when it was recorded.
+pub fn reservoir(label: &str) -> Option<Reservoir> {
+    entry(label).map(|entry| entry.reservoir).filter(|reservoir| reservoir.capacity() > 0)
+}
+
+/// Keeps a reservoir of up to `size` raw samples for each label recorded
+/// from now on, or none if `size` is zero, the default.
+///
+/// The samples are a uniformly random subset of all measurements of the
+/// label, so exact percentiles of labels recorded millions of times can be
+/// computed from them in bounded memory, at the cost of up to 16 bytes per
+/// sample and label in each thread's shard of the registry.
+/// [`reservoir::DEFAULT_SIZE`](crate::reservoir::DEFAULT_SIZE) is a good
+/// starting point.
+///
+/// ```rust
+/// use arbitime::{log_time, registry, reservoir};
+///
+/// registry::set_reservoir_size(reservoir::DEFAULT_SIZE);
+/// for _ in 0..10_000 {
+///     log_time!(threshold: std::time::Duration::MAX, "hash" => (1..=30).product::<u128>());
+/// }
+/// # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+/// let hash = registry::reservoir("hash").unwrap();
+/// # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+/// assert_eq!(hash.samples().len(), 1024);
+/// # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+/// println!("p99: {:?}", hash.percentile(99.0));
+/// ```
+pub fn set_reservoir_size(size: usize) {
+    RESERVOIR_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Takes a snapshot of all recorded labels.
+pub fn snapshot() -> Report {
+    report_of(&merged(&all_shards()))
+}
+
+/// Takes a snapshot of all recorded labels and removes them, in one step, so
+/// that no measurement is lost between the two.
+///
+/// ```rust
+/// use arbitime::{log_time, registry};
+///
+/// log_time!("tick" => ());
+/// let report = registry::take();
+/// # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+/// assert_eq!(report.get("tick").unwrap().count, 1);
+/// assert!(registry::snapshot().is_empty());
+/// ```
+pub fn take() -> Report {
+    let mut shards = all_shards();
+    let report = report_of(&merged(&shards));
+    clear_shards(&mut shards);
+    report
+}
+
+fn report_of(registry: &BTreeMap<String, Entry>) -> Report {
+    let names = thread_names();
+    let mut entries: Vec<_> = registry
+        .iter()
+        .map(|(label, entry)| (label.clone(), entry.stats))
+        .collect();
+    entries.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total));
+    Report {
+        entries,
+        #[cfg(feature = "histogram")]
+        histograms: registry
+            .iter()
+            .map(|(label, entry)| (label.clone(), entry.histogram.clone()))
+            .collect(),
+        #[cfg(feature = "histogram")]
+        percentiles: registry
+            .iter()
+            .map(|(label, entry)| (label.clone(), entry.histogram.percentiles()))
+            .collect(),
+        threads: registry
+            .iter()
+            .map(|(label, entry)| (label.clone(), entry.threads(&names)))
+            .collect(),
+        reservoirs: registry
+            .iter()
+            .filter(|(_, entry)| entry.reservoir.capacity() > 0)
+            .map(|(label, entry)| (label.clone(), entry.reservoir.clone()))
+            .collect(),
+        runtime: None,
+    }
+}
+
+/// Removes all recorded labels.
+pub fn clear() {
+    clear_shards(&mut all_shards());
+}
+
+/// Empties the locked shards, and forgets the moving averages of their labels.
+fn clear_shards(shards: &mut [MutexGuard<'static, BTreeMap<String, Entry>>]) {
+    for shard in shards {
+        shard.clear();
+    }
+    EWMAS.lock().unwrap_or_else(PoisonError::into_inner).clear();
+}
+
+/// Sends a summary report of all recorded labels to the installed
+/// [sink](crate::sink), or prints it to stderr as a table if there is none.
+///
+/// Nothing is sent while timing output is [disabled](crate::set_enabled).
+pub fn report() {
+    crate::sink::dispatch_report(&snapshot());
+}
+
+/// Returns a guard that sends a summary report of all recorded labels to the
+/// [sink](crate::sink) when it is dropped, typically at the end of `main`.
+/// Without a sink, it is printed to stderr as a table.
+///
+/// Besides the columns of [`report`], the table shows the share of the
+/// runtime, measured from the creation of the guard, that each label took.
+/// The guard is also dropped when `main` panics, but not on
+/// [`std::process::exit`]. Nothing is printed if no labels were recorded.
+#[must_use = "the report is sent when the guard is dropped"]
+pub fn report_on_exit() -> ReportGuard {
+    ReportGuard {
+        start: crate::clock::now(),
+    }
+}
+
+/// Sends a summary report to the sink when dropped.
+///
+/// Returned by [`report_on_exit`].
+#[derive(Debug)]
+#[must_use = "the report is sent when the guard is dropped"]
+pub struct ReportGuard {
+    start: u64,
+}
+
+impl ReportGuard {
+    /// Returns the report that would be printed now.
+    pub fn report(&self) -> Report {
+        snapshot().with_runtime(crate::clock::elapsed_since(self.start))
+    }
+}
+
+impl Drop for ReportGuard {
+    fn drop(&mut self) {
+        crate::sink::dispatch_report(&self.report());
+    }
+}
+
+/// Starts a thread that sends the report of each `interval` to the
+/// [sink](crate::sink), then clears the registry for the next interval.
+///
+/// This gives daemons a rolling summary, e.g. per minute, with each label's
+/// share of the interval. Because the registry is cleared, [`stats`],
+/// [`snapshot`] and [`report`] only see the current interval while the
+/// reporter runs. Intervals without any labels are not reported. The reporter
+/// runs until the returned [`Reporter`] is stopped or dropped.
+///
+/// # Panics
+///
+/// If `interval` is zero, or the thread can't be spawned.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// let _reporter = arbitime::spawn_reporter(Duration::from_secs(60));
+/// loop {
+///     arbitime::log_time!("handle request" => (1..=100).sum::<u32>());
+/// }
+/// ```
+#[must_use = "the reporter stops when dropped"]
+pub fn spawn_reporter(interval: Duration) -> Reporter {
+    assert!(!interval.is_zero(), "the report interval must not be zero");
+    let (stop, stopped) = mpsc::channel::<()>();
+    let thread = std::thread::Builder::new()
+        .name("arbitime-reporter".to_string())
+        .spawn(move || {
+            let mut start = crate::clock::now();
+            loop {
+                let last = stopped.recv_timeout(interval);
+                let end = crate::clock::now();
+                let runtime = Duration::from_nanos(end.saturating_sub(start));
+                start = end;
+                crate::sink::dispatch_report(&take().with_runtime(runtime));
+                if last != Err(RecvTimeoutError::Timeout) {
+                    break;
+                }
+            }
+        })
+        .expect("failed to spawn the reporter thread");
+    Reporter {
+        stop: Some(stop),
+        thread: Some(thread),
+    }
+}
+
+/// A background thread sending periodic reports to the sink.
+///
+/// Returned by [`spawn_reporter`]. Stopping or dropping it reports the last,
+/// partial interval and waits for the thread to finish.
+///
+/// ```rust
+/// use arbitime::{log_time, registry};
+/// use std::time::Duration;
+///
+/// let reporter = arbitime::spawn_reporter(Duration::from_secs(3600));
+/// log_time!("startup" => (1..=100).sum::<u32>());
+/// reporter.stop(); // Reports "startup" right away instead of in an hour
+/// assert!(registry::stats("startup").is_none());
+/// ```
+#[derive(Debug)]
+#[must_use = "the reporter stops when dropped"]
+pub struct Reporter {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Reporter {
+    /// Stops the reporter after reporting the current interval.
+    pub fn stop(self) {}
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes the thread
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_by_label() {
+        record("registry-test", Duration::from_millis(10));
+        record("registry-test", Duration::from_millis(30));
+        record("registry-test", Duration::from_millis(20));
+
+        let stats = stats("registry-test").unwrap();
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.total, Duration::from_millis(60));
+        assert_eq!(stats.min, Duration::from_millis(10));
+        assert_eq!(stats.max, Duration::from_millis(30));
+        assert_eq!(stats.mean(), Duration::from_millis(20));
+
+        let report = snapshot();
+        assert_eq!(report.get("registry-test"), Some(&stats));
+        assert!(report.to_string().contains("registry-test"));
+    }
+
+    #[test]
+    fn smooths_recent_durations() {
+        let mut stats = LabelStats::new(Duration::from_millis(100));
+        stats.add(Duration::from_millis(200));
+        assert_eq!(stats.ewma, Duration::from_millis(110));
+
+        let mut merged = stats;
+        merged.merge(&LabelStats::new(Duration::from_millis(50)));
+        assert_eq!(merged.ewma, Duration::from_millis(90));
+
+        stats.add_sampled(Duration::from_millis(200), Duration::from_millis(200), 2);
+        assert_eq!(stats.ewma, Duration::from_micros(127_100));
+    }
+
+    #[test]
+    fn separates_self_time() {
+        let outer = enter();
+        let inner = enter();
+        let innermost = enter();
+        assert_eq!(innermost.finish(Duration::from_millis(5), true), Duration::from_millis(5));
+        assert_eq!(inner.finish(Duration::from_millis(8), true), Duration::from_millis(3));
+        let unrecorded = enter();
+        assert_eq!(unrecorded.finish(Duration::from_millis(4), false), Duration::from_millis(4));
+        assert_eq!(outer.finish(Duration::from_millis(20), true), Duration::from_millis(12));
+        assert!(CHILD_TIME.with_borrow(Vec::is_empty));
+
+        record_nested("registry-self", Duration::from_millis(20), Duration::from_millis(12));
+        record("registry-self", Duration::from_millis(10));
+        let stats = stats("registry-self").unwrap();
+        assert_eq!(stats.total, Duration::from_millis(30));
+        assert_eq!(stats.self_total, Duration::from_millis(22));
+        assert_eq!(stats.self_mean(), Duration::from_millis(11));
+        assert!(snapshot().to_json().contains(r#""label":"registry-self","count":2,"total_ns":30000000,"self_ns":22000000,"#));
+    }
+
+    #[test]
+    fn merges_threads() {
+        let threads: Vec<_> = (1..=20)
+            .map(|millis| std::thread::spawn(move || record("registry-threads", Duration::from_millis(millis))))
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let stats = stats("registry-threads").unwrap();
+        assert_eq!(stats.count, 20);
+        assert_eq!(stats.total, Duration::from_millis(210));
+        assert_eq!((stats.min, stats.max), (Duration::from_millis(1), Duration::from_millis(20)));
+        assert_eq!(snapshot().get("registry-threads"), Some(&stats));
+    }
+
+    #[test]
+    fn merges_more_threads_than_shards() {
+        const THREADS: usize = SHARDS * 3 + 1;
+        let start = std::sync::Arc::new(std::sync::Barrier::new(THREADS));
+        let threads: Vec<_> = (0..THREADS)
+            .map(|i| {
+                let start = std::sync::Arc::clone(&start);
+                std::thread::Builder::new()
+                    .name(format!("registry-contended-{}", i))
+                    .spawn(move || {
+                        start.wait();
+                        for _ in 0..1_000 {
+                            record("registry-contended", Duration::from_micros(1));
+                        }
+                    })
+                    .unwrap()
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let stats = stats("registry-contended").unwrap();
+        assert_eq!(stats.count, THREADS as u64 * 1_000);
+        assert_eq!(stats.total, Duration::from_micros(THREADS as u64 * 1_000));
+        assert_eq!(thread_stats("registry-contended").len(), THREADS);
+    }
+
+    #[test]
+    fn groups_unnamed_threads() {
+        for _ in 0..10 {
+            std::thread::spawn(|| record("registry-unnamed", Duration::from_millis(1))).join().unwrap();
+        }
+        assert!(!THREAD_KEYS.lock().unwrap().keys().any(|name| name.starts_with("ThreadId")));
+        let threads = thread_stats("registry-unnamed");
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].0, UNNAMED_THREAD);
+        assert_eq!(threads[0].1.count, 10);
+    }
+
+    #[test]
+    fn breaks_down_by_thread() {
+        let worker = |name: &str, millis: &'static [u64]| {
+            std::thread::Builder::new()
+                .name(name.to_string())
+                .spawn(move || {
+                    for &millis in millis {
+                        record("registry-by-thread", Duration::from_millis(millis));
+                    }
+                })
+                .unwrap()
+        };
+        for thread in [worker("worker-a", &[10, 20]), worker("worker-b", &[10])] {
+            thread.join().unwrap();
+        }
+
+        let threads = thread_stats("registry-by-thread");
+        let totals: Vec<_> = threads.iter().map(|(name, stats)| (name.as_str(), stats.count, stats.total.as_millis())).collect();
+        assert_eq!(totals, [("worker-a", 2, 30), ("worker-b", 1, 10)]);
+
+        let report = snapshot();
+        assert_eq!(report.threads("registry-by-thread"), threads.as_slice());
+        let table = report.by_thread().to_string();
+        assert!(table.lines().next().unwrap().ends_with("% of Label"));
+        let line = table.lines().find(|line| line.contains("worker-b")).unwrap();
+        assert!(line.starts_with("registry-by-thread"), "{}", line);
+        assert!(line.ends_with("25.0%"), "{}", line);
+
+        // Threads with the same name, such as respawned pool workers, share a row
+        for _ in 0..2 {
+            worker("worker-a", &[5]).join().unwrap();
+        }
+        let threads = thread_stats("registry-by-thread");
+        let counts: Vec<_> = threads.iter().map(|(name, stats)| (name.as_str(), stats.count)).collect();
+        assert_eq!(counts, [("worker-a", 4), ("worker-b", 1)]);
+    }
+
+    #[test]
+    fn formats_summary_tables() {
+        let mut parse = LabelStats::new(Duration::from_micros(300));
+        parse.add(Duration::from_micros(500));
+        let report = Report {
+            entries: vec![
+                ("parse|lex".to_string(), parse),
+                ("load".to_string(), LabelStats::new(Duration::from_micros(200))),
+            ],
+            ..Report::default()
+        };
+        #[cfg(not(feature = "histogram"))]
+        {
+            assert_eq!(
+                report.to_markdown(),
+                "| Label | Count | Mean | Max | % of Total |\n\
+                 |:------|------:|------:|------:|------:|\n\
+                 | parse\\|lex | 2 | 400 µs | 500 µs | 80.0% |\n\
+                 | load | 1 | 200 µs | 200 µs | 20.0% |\n"
+            );
+            assert_eq!(
+                report.with_runtime(Duration::from_millis(2)).to_ascii_table(),
+                "+-----------+-------+--------+--------+-----------+\n\
+                 | Label     | Count |   Mean |    Max | % Runtime |\n\
+                 +-----------+-------+--------+--------+-----------+\n\
+                 | parse|lex |     2 | 400 µs | 500 µs |     40.0% |\n\
+                 | load      |     1 | 200 µs | 200 µs |     10.0% |\n\
+                 +-----------+-------+--------+--------+-----------+\n"
+            );
+        }
+        #[cfg(feature = "histogram")]
+        {
+            assert!(report.to_markdown().starts_with("| Label | Count | Mean | p95 | Max | % of Total |\n"));
+            assert!(report.to_ascii_table().contains("| parse|lex |     2 | 400 µs |   - | 500 µs |      80.0% |"));
+        }
+    }
+
+    #[test]
+    fn extrapolates_samples() {
+        record_sampled("registry-sampled", Duration::from_millis(2), Duration::from_millis(1), 100);
+        record("registry-sampled", Duration::from_millis(4));
+        let stats = stats("registry-sampled").unwrap();
+        assert_eq!(stats.count, 101);
+        assert_eq!(stats.total, Duration::from_millis(204));
+        assert_eq!(stats.self_total, Duration::from_millis(104));
+        assert_eq!((stats.min, stats.max), (Duration::from_millis(2), Duration::from_millis(4)));
+        assert!(stats.estimated);
+
+        let text = snapshot().to_string();
+        let line = text.lines().find(|line| line.starts_with("registry-sampled")).unwrap();
+        assert!(line.contains(" ~101 "), "{}", line);
+        assert!(snapshot().to_json().contains(r#""max_ns":4000000,"estimated":true}"#));
+    }
+
+    #[test]
+    fn reports_share_of_runtime() {
+        record("registry-runtime", Duration::from_millis(25));
+        let report = snapshot().with_runtime(Duration::from_millis(100));
+        assert_eq!(report.runtime(), Some(Duration::from_millis(100)));
+
+        let text = report.to_string();
+        assert!(text.lines().next().unwrap().ends_with("% Runtime"));
+        let line = text.lines().find(|line| line.starts_with("registry-runtime")).unwrap();
+        assert!(line.ends_with("25.0%"), "{}", line);
+    }
+
+    #[test]
+    #[cfg(feature = "histogram")]
+    fn reports_percentiles() {
+        for millis in 1..=100 {
+            record("registry-percentiles", Duration::from_millis(millis));
+        }
+        let percentiles = percentiles("registry-percentiles").unwrap();
+        assert!(percentiles.p50 >= Duration::from_millis(50));
+        assert!(percentiles.p90 < Duration::from_millis(91));
+        assert_eq!(percentiles.p999, Duration::from_millis(100));
+        assert_eq!(histogram("registry-percentiles").unwrap().len(), 100);
+
+        let report = snapshot();
+        assert_eq!(report.percentiles("registry-percentiles"), Some(&percentiles));
+        assert!(report.to_string().lines().next().unwrap().ends_with("p99.9"));
+    }
+
+    /// Builds a report of one thread's measurements, without the global registry.
+    fn report(thread: &str, measurements: &[(&str, u64)]) -> Report {
+        let mut entries: BTreeMap<String, Entry> = BTreeMap::new();
+        for &(label, millis) in measurements {
+            entries.entry(label.to_string()).or_insert_with(Entry::new).add(Duration::from_millis(millis), Duration::from_millis(millis), 1);
+        }
+        let mut report = report_of(&entries);
+        for threads in report.threads.values_mut() {
+            threads[0].0 = thread.to_string();
+        }
+        report
+    }
+
+    #[test]
+    fn merges_reports() {
+        let mut merged = report("worker-a", &[("parse", 10), ("load", 30)]).with_runtime(Duration::from_millis(50));
+        merged.merge(&report("worker-b", &[("parse", 30), ("parse", 50), ("save", 5)]).with_runtime(Duration::from_millis(100)));
+
+        let labels: Vec<_> = merged.entries().iter().map(|(label, stats)| (label.as_str(), stats.count)).collect();
+        assert_eq!(labels, [("parse", 3), ("load", 1), ("save", 1)]);
+        let parse = merged.get("parse").unwrap();
+        assert_eq!((parse.total, parse.min, parse.max), (Duration::from_millis(90), Duration::from_millis(10), Duration::from_millis(50)));
+        let threads: Vec<_> = merged.threads("parse").iter().map(|(thread, stats)| (thread.as_str(), stats.count)).collect();
+        assert_eq!(threads, [("worker-b", 2), ("worker-a", 1)]);
+        assert_eq!(merged.runtime(), Some(Duration::from_millis(150)));
+        #[cfg(feature = "histogram")]
+        {
+            assert_eq!(merged.histogram("parse").unwrap().len(), 3);
+            assert_eq!(merged.percentiles("parse").unwrap().p999, Duration::from_millis(50));
+        }
+
+        let mut empty = Report::default();
+        empty.merge(&merged);
+        assert_eq!(empty, merged);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn round_trips_reports_through_serde() {
+        let mut sent = report("worker-a", &[("parse", 10), ("parse", 30), ("load", 5)]).with_runtime(Duration::from_millis(50));
+        sent.reservoirs.insert("parse".to_string(), Reservoir::new(4));
+        let json = serde_json::to_string(&sent).unwrap();
+        let received: Report = serde_json::from_str(&json).unwrap();
+        assert_eq!(received, sent);
+        assert_eq!(received.get("parse").unwrap().mean(), Duration::from_millis(20));
+
+        let stats = serde_json::from_str::<LabelStats>(&serde_json::to_string(&sent.get("load").unwrap()).unwrap()).unwrap();
+        assert_eq!(&stats, sent.get("load").unwrap());
+    }
+
+    #[test]
+    fn diffs_reports() {
+        let baseline = report("main", &[("parse", 40), ("load", 10), ("load", 10)]);
+        let current = report("main", &[("parse", 50), ("save", 5)]);
+
+        let diffs = current.diff(&baseline);
+        let labels: Vec<_> = diffs.iter().map(|diff| diff.label.as_str()).collect();
+        assert_eq!(labels, ["parse", "save", "load"]);
+        assert_eq!(diffs[0].mean_change(), Some(25.0));
+        assert_eq!(diffs[0].count_change(), 0);
+        assert_eq!(diffs[0].to_string(), "parse - mean 50 ms (+25.0%), 1 calls (+0)");
+        assert_eq!(diffs[1].mean_change(), None);
+        assert_eq!(diffs[1].to_string(), "save - new, mean 5 ms, 1 calls");
+        assert_eq!(diffs[2].total_change(), None);
+        assert_eq!(diffs[2].count_change(), -2);
+        assert_eq!(diffs[2].to_string(), "load - removed");
+    }
+
+    #[test]
+    fn shares_moving_averages_between_shards() {
+        // One shard sees old slow measurements, another recent fast ones
+        let mut stale = Entry::of("registry-ewma");
+        let mut recent = Entry::of("registry-ewma");
+        let mut expected = LabelStats::default();
+        for (entry, millis, count) in [(&mut stale, 100, 100), (&mut recent, 1, 10)] {
+            for _ in 0..count {
+                entry.add(Duration::from_millis(millis), Duration::from_millis(millis), 1);
+                expected.add(Duration::from_millis(millis));
+            }
+        }
+
+        for (first, second) in [(&stale, &recent), (&recent, &stale)] {
+            let mut merged = first.snapshot();
+            merged.merge(second);
+            assert_eq!(merged.stats.ewma, expected.ewma);
+            assert_eq!(merged.stats.count, 110);
+        }
+        assert_eq!(stale.snapshot().stats.ewma, expected.ewma);
+        assert!(expected.ewma < Duration::from_millis(40));
+
+        // Entries of separate registries are weighted by count
+        let mut separate = Entry::new();
+        separate.add(Duration::from_millis(100), Duration::from_millis(100), 1);
+        let mut merged = recent.snapshot();
+        merged.merge(&separate);
+        assert_eq!(merged.stats.count, 11);
+        assert!(merged.stats.ewma > expected.ewma);
+    }
+
+    #[test]
+    fn reads_windows_through_label_handles() {
+        let clock = crate::test::MockClock::new();
+        let _guard = clock.install();
+        // Windows are global, so they are turned back off even if an assertion fails
+        struct WindowOff;
+
+        impl Drop for WindowOff {
+            fn drop(&mut self) {
+                set_window(Duration::ZERO, 0);
+            }
+        }
+
+        let _window = WindowOff;
+        set_window(Duration::from_secs(300), 1_000);
+        record("registry-window", Duration::from_millis(50));
+        clock.advance(Duration::from_secs(120));
+        record("registry-window", Duration::from_millis(10));
+
+        let requests = label("registry-window");
+        let last_minute = requests.window(Duration::from_secs(60)).unwrap();
+        assert_eq!((last_minute.stats.count, last_minute.stats.max), (1, Duration::from_millis(10)));
+        assert!(last_minute.complete);
+        assert_eq!(requests.window(Duration::from_secs(180)).unwrap().stats.count, 2);
+        assert_eq!(window("registry-window", Duration::from_secs(60)), Some(last_minute));
+        assert_eq!(requests.stats().unwrap().count, 2);
+        assert_eq!(label("registry-unrecorded").window(Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn keeps_reservoirs_of_samples() {
+        let mut shards = [BTreeMap::new(), BTreeMap::new()];
+        for (shard, millis) in shards.iter_mut().zip([1, 2]) {
+            let mut entry = Entry::new();
+            entry.reservoir = Reservoir::new(4);
+            for _ in 0..10 {
+                entry.add(Duration::from_millis(millis), Duration::from_millis(millis), 1);
+            }
+            shard.insert("sampled".to_string(), entry);
+            shard.insert("unsampled".to_string(), Entry::new());
+        }
+        let mut report = report_of(&shards[0]);
+        report.merge(&report_of(&shards[1]));
+
+        let reservoir = report.reservoir("sampled").unwrap();
+        assert_eq!((reservoir.samples().len(), reservoir.seen()), (4, 20));
+        assert_eq!(reservoir.percentile(0.0), Duration::from_millis(1));
+        assert_eq!(reservoir.percentile(100.0), Duration::from_millis(2));
+        assert_eq!(report.reservoir("unsampled"), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Bounded reservoirs of raw samples.
+//!
+//! A [`Reservoir`] keeps a uniformly random subset of a fixed number of the
+//! durations it is given, however many there are (Vitter's algorithm R), so
+//! exact percentiles or a custom histogram of labels recorded millions of
+//! times can be computed from their samples in bounded memory.
+//!
+//! Reservoirs are off in the [`registry`](crate::registry) by default.
+//! [`set_reservoir_size`](crate::registry::set_reservoir_size) turns them on
+//! for labels recorded from then on, and
+//! [`registry::reservoir`](crate::registry::reservoir) returns the samples of
+//! a label.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::reservoir::Reservoir;
+//! use std::time::Duration;
+//!
+//! let mut reservoir = Reservoir::new(1024);
+//! for micros in 1..=100_000 {
+//!     reservoir.add(Duration::from_micros(micros));
+//! }
+//!
+//! assert_eq!(reservoir.samples().len(), 1024);
+//! assert_eq!(reservoir.seen(), 100_000);
+//! let p50 = reservoir.percentile(50.0);
+//! assert!(p50 > Duration::from_millis(40) && p50 < Duration::from_millis(60));
+//! ```
+
+use std::time::Duration;
+
+/// The default number of samples kept per label by
+/// [`set_reservoir_size`](crate::registry::set_reservoir_size).
+pub const DEFAULT_SIZE: usize = 1024;
+
+/// A uniformly random sample of a bounded number of durations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reservoir {
+    samples: Vec<Duration>,
+    capacity: usize,
+    /// Number of durations offered to the reservoir
+    seen: u64,
+}
+
+impl Reservoir {
+    /// Creates an empty reservoir that keeps up to `capacity` samples.
+    pub fn new(capacity: usize) -> Self {
+        Reservoir {
+            samples: Vec::new(),
+            capacity,
+            seen: 0,
+        }
+    }
+
+    /// Returns the maximum number of samples kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of durations added, kept or not.
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Returns the samples, in no particular order.
+    pub fn samples(&self) -> &[Duration] {
+        &self.samples
+    }
+
+    /// Returns `true` if no samples are kept.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Offers a duration to the reservoir.
+    pub fn add(&mut self, duration: Duration) {
+        self.add_n(duration, 1);
+    }
+
+    /// Offers a duration that stands for `count` measurements of the same
+    /// duration, such as a sampled one, which is kept with a proportionally
+    /// higher probability.
+    pub fn add_n(&mut self, duration: Duration, count: u64) {
+        if count == 0 || self.capacity == 0 {
+            return;
+        }
+        self.seen += count;
+        if self.samples.len() < self.capacity {
+            self.samples.push(duration);
+            return;
+        }
+        // Keep the duration with probability capacity * count / seen, in a random slot
+        let draw = mix(self.seen) % self.seen;
+        if u128::from(draw) < self.capacity as u128 * u128::from(count) {
+            self.samples[(draw % self.capacity as u64) as usize] = duration;
+        }
+    }
+
+    /// Adds the samples of `other`, keeping each reservoir's share of the
+    /// result proportional to the number of durations it has seen.
+    pub fn merge(&mut self, other: &Reservoir) {
+        if self.capacity == 0 {
+            *self = other.clone();
+            return;
+        }
+        let seen = self.seen + other.seen;
+        if self.samples.len() + other.samples.len() > self.capacity && seen > 0 {
+            let share = (self.capacity as u128 * u128::from(other.seen) / u128::from(seen)) as usize;
+            let theirs = share.min(other.samples.len());
+            let ours = (self.capacity - theirs).min(self.samples.len());
+            let mut others = other.samples.clone();
+            keep_random(&mut self.samples, ours, self.seen);
+            keep_random(&mut others, self.capacity - ours, other.seen);
+            self.samples.append(&mut others);
+        } else {
+            self.samples.extend_from_slice(&other.samples);
+        }
+        self.seen = seen;
+    }
+
+    /// Returns the sample below which `percentile` percent of the samples
+    /// fall, e.g. `percentile(99.0)` for p99, or zero if the reservoir is
+    /// empty.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let fraction = (percentile / 100.0).clamp(0.0, 1.0);
+        let rank = ((fraction * sorted.len() as f64).ceil() as usize).max(1);
+        sorted[rank - 1]
+    }
+}
+
+/// Keeps `count` of the samples, picked at random with the given seed.
+fn keep_random(samples: &mut Vec<Duration>, count: usize, seed: u64) {
+    let count = count.min(samples.len());
+    for i in 0..count {
+        let j = i + (mix(seed.wrapping_add(i as u64)) % (samples.len() - i) as u64) as usize;
+        samples.swap(i, j);
+    }
+    samples.truncate(count);
+}
+
+/// Scrambles the bits of `value` (SplitMix64), to draw reservoir slots.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_uniformly() {
+        let mut reservoir = Reservoir::new(100);
+        for millis in 0..10_000 {
+            reservoir.add(Duration::from_millis(millis));
+        }
+        assert_eq!((reservoir.samples().len(), reservoir.seen()), (100, 10_000));
+        let late = reservoir.samples().iter().filter(|&&sample| sample >= Duration::from_secs(5)).count();
+        assert!((30..=70).contains(&late), "{} of 100 samples from the second half", late);
+        assert_eq!(Reservoir::new(100).percentile(50.0), Duration::ZERO);
+
+        let mut off = Reservoir::new(0);
+        off.add(Duration::from_millis(1));
+        assert!(off.is_empty());
+    }
+
+    #[test]
+    fn merges_in_proportion() {
+        let mut small = Reservoir::new(10);
+        for _ in 0..100 {
+            small.add(Duration::from_millis(1));
+        }
+        let mut large = Reservoir::new(10);
+        for _ in 0..300 {
+            large.add_n(Duration::from_millis(2), 3);
+        }
+        small.merge(&large);
+        assert_eq!((small.samples().len(), small.seen()), (10, 1000));
+        let from_large = small.samples().iter().filter(|&&sample| sample == Duration::from_millis(2)).count();
+        assert_eq!(from_large, 9);
+        assert_eq!(small.percentile(10.0), Duration::from_millis(1));
+        assert_eq!(small.percentile(50.0), Duration::from_millis(2));
+    }
+}
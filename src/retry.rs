@@ -0,0 +1,216 @@
+//! Retries of fallible code, with the duration of every attempt.
+
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+use crate::callsite::CallSite;
+use crate::{HumanDuration, Level, Outcome, TimingRecord};
+
+/// A single attempt of [`retry_timed!`](crate::retry_timed).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    /// The number of the attempt, starting at 1
+    pub number: u32,
+    /// The time the attempt took
+    pub duration: Duration,
+    /// Whether the attempt succeeded, or its error
+    pub outcome: Outcome,
+    /// The time waited after the attempt before the next one, zero for the
+    /// last attempt
+    pub backoff: Duration,
+}
+
+impl Attempt {
+    /// Returns `true` if the attempt succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.outcome == Outcome::Ok
+    }
+}
+
+impl Display for Attempt {
+    /// Formats the attempt as e.g. `attempt 1: failed in 1.1 s (error: timed out)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            Outcome::Ok => write!(f, "attempt {}: succeeded in {}", self.number, HumanDuration(self.duration)),
+            Outcome::Err(error) => write!(
+                f,
+                "attempt {}: failed in {} (error: {})",
+                self.number,
+                HumanDuration(self.duration),
+                error
+            ),
+        }
+    }
+}
+
+/// How long [`retry_timed!`](crate::retry_timed) waits before the next
+/// attempt.
+///
+/// Implemented for a fixed [`Duration`], and for closures that take the
+/// number of the failed attempt, starting at 1, e.g. for exponential backoff:
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// let backoff = |attempt: u32| Duration::from_millis(100) * 2u32.pow(attempt - 1);
+/// ```
+pub trait Backoff {
+    /// Returns the time to wait after the given failed attempt.
+    fn delay(&mut self, attempt: u32) -> Duration;
+}
+
+impl Backoff for Duration {
+    fn delay(&mut self, _attempt: u32) -> Duration {
+        *self
+    }
+}
+
+impl<F: FnMut(u32) -> Duration> Backoff for F {
+    fn delay(&mut self, attempt: u32) -> Duration {
+        self(attempt)
+    }
+}
+
+/// Runs `f` up to `attempts` times until it succeeds, sleeping for the
+/// backoff between attempts.
+pub fn retry<T, E: Display>(
+    attempts: u32,
+    mut backoff: impl Backoff,
+    mut f: impl FnMut() -> Result<T, E>,
+) -> (Result<T, E>, Vec<Attempt>) {
+    let attempts = attempts.max(1);
+    let mut log = Vec::new();
+    let mut number = 0;
+    loop {
+        number += 1;
+        let start = if cfg!(feature = "disable") { 0 } else { crate::clock::now() };
+        let result = f();
+        let duration = if cfg!(feature = "disable") {
+            Duration::ZERO
+        } else {
+            crate::calibration::correct(crate::clock::elapsed_since(start))
+        };
+        let outcome = match &result {
+            Ok(_) => Outcome::Ok,
+            Err(error) => Outcome::Err(error.to_string()),
+        };
+        let last = result.is_ok() || number >= attempts;
+        let wait = if last { Duration::ZERO } else { backoff.delay(number) };
+        log.push(Attempt {
+            number,
+            duration,
+            outcome,
+            backoff: wait,
+        });
+        if last {
+            return (result, log);
+        }
+        std::thread::sleep(wait);
+    }
+}
+
+/// Records every attempt under `label` and returns the record of the
+/// summary, e.g. `"fetch - succeeded on attempt 2 of 3 after 1.25 s"` at
+/// [`Level::Info`] or a failure at [`Level::Warn`], with the total time of
+/// the attempts and backoffs as its duration, or `None` if the label is
+/// disabled.
+pub fn summary(label: &dyn Display, attempts: u32, log: &[Attempt], site: &'static CallSite) -> Option<TimingRecord> {
+    if cfg!(feature = "disable") || !crate::is_enabled() {
+        return None;
+    }
+    let label = label.to_string();
+    if !crate::filter::enabled(Some(&label)) {
+        return None;
+    }
+    for attempt in log {
+        crate::registry::record(&label, attempt.duration);
+    }
+    let total: Duration = log.iter().map(|attempt| attempt.duration + attempt.backoff).sum();
+    let last = log.last()?;
+    let (level, summary) = match &last.outcome {
+        Outcome::Ok => (
+            Level::Info,
+            format!(
+                "{} - succeeded on attempt {} of {} after {}",
+                label,
+                last.number,
+                attempts.max(1),
+                HumanDuration(total)
+            ),
+        ),
+        Outcome::Err(error) => (
+            Level::Warn,
+            format!(
+                "{} - failed {} attempts after {} (error: {})",
+                label,
+                log.len(),
+                HumanDuration(total),
+                error
+            ),
+        ),
+    };
+    let mut record = crate::__private::summary_record(Some(label), total, summary, site);
+    record.level = level;
+    record.outcome = Some(last.outcome.clone());
+    Some(record)
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+
+    static SITE: CallSite = CallSite::new(file!(), line!(), module_path!());
+
+    #[test]
+    fn retries_until_success() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let mut calls = 0;
+        let (result, log) = retry(3, |attempt: u32| Duration::from_nanos(attempt.into()), || {
+            calls += 1;
+            clock.advance(Duration::from_millis(calls));
+            if calls < 2 { Err("busy") } else { Ok(calls) }
+        });
+        assert_eq!(result, Ok(2));
+        let failed = Attempt {
+            number: 1,
+            duration: Duration::from_millis(1),
+            outcome: Outcome::Err("busy".to_string()),
+            backoff: Duration::from_nanos(1),
+        };
+        let succeeded = Attempt {
+            number: 2,
+            duration: Duration::from_millis(2),
+            outcome: Outcome::Ok,
+            backoff: Duration::ZERO,
+        };
+        assert_eq!(log, [failed.clone(), succeeded]);
+        assert_eq!(failed.to_string(), "attempt 1: failed in 1 ms (error: busy)");
+
+        let record = summary(&"retry-flaky", 3, &log, &SITE).unwrap();
+        assert_eq!(record.level, Level::Info);
+        assert_eq!(record.to_string(), "retry-flaky - succeeded on attempt 2 of 3 after 3 ms");
+        assert_eq!(record.duration, Duration::from_nanos(3_000_001));
+        assert_eq!(crate::registry::stats("retry-flaky").unwrap().count, 2);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_attempt() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let (result, log) = retry(2, Duration::ZERO, || {
+            clock.advance(Duration::from_millis(5));
+            Err::<(), _>("down")
+        });
+        assert_eq!(result, Err("down"));
+        assert_eq!(log.len(), 2);
+        assert!(log.iter().all(|attempt| !attempt.is_ok()));
+        let record = summary(&"retry-down", 2, &log, &SITE).unwrap();
+        assert_eq!(record.level, Level::Warn);
+        assert_eq!(record.to_string(), "retry-down - failed 2 attempts after 10 ms (error: down)");
+        assert_eq!(record.outcome, Some(Outcome::Err("down".to_string())));
+    }
+}
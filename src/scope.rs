@@ -0,0 +1,210 @@
+//! RAII guard that times a scope.
+
+use std::borrow::Cow;
+use std::panic::Location;
+use std::time::Duration;
+
+use crate::callsite::CallSite;
+use crate::checkpoint::{self, Splits};
+use crate::nesting::{self, Frame};
+use crate::registry;
+use crate::{Level, TimingRecord};
+
+/// A guard that measures the time from its creation until it is dropped.
+///
+/// When the guard goes out of scope, the elapsed time is logged in
+/// the same format as [`log_time!`](crate::log_time) and, if the timer has a
+/// label, recorded in the [`registry`](crate::registry). Because the measurement
+/// happens in [`Drop`], every exit path is covered: early `return`s, the `?`
+/// operator and panics.
+///
+/// Timers nested inside each other are printed as a tree; see [`nesting`].
+///
+/// Usually created through the [`time_scope!`](crate::time_scope) macro.
+/// Unless a [`sink`](crate::sink) is installed, it prints to stderr, or logs
+/// through the `log` crate when the `log` feature is enabled.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::ScopeTimer;
+///
+/// fn parse(input: &str) -> Result<u32, std::num::ParseIntError> {
+///     let _timer = ScopeTimer::new("parse");
+///     let value = input.trim().parse::<u32>()?;
+///     Ok(value * 2)
+/// } // Prints: "parse - Execution time: ..."
+///
+/// assert_eq!(parse("21"), Ok(42));
+/// assert!(parse("oops").is_err());
+/// ```
+#[derive(Debug)]
+pub struct ScopeTimer {
+    label: Option<Cow<'static, str>>,
+    threshold: Option<Duration>,
+    budget: Option<Duration>,
+    backtrace: bool,
+    level: Level,
+    frame: Option<Frame>,
+    splits: Option<Splits>,
+    nested: Option<registry::Nested>,
+    location: &'static Location<'static>,
+    site: Option<&'static CallSite>,
+    #[cfg(feature = "watchdog")]
+    watch: Option<crate::watchdog::Watch>,
+    /// A reading of the global [`clock`](crate::clock)
+    start: u64,
+}
+
+impl ScopeTimer {
+    /// Starts a timer that logs with the given label when dropped.
+    #[track_caller]
+    pub fn new(label: impl Into<Cow<'static, str>>) -> Self {
+        Self::start(Some(label.into()), Location::caller())
+    }
+
+    /// Starts a timer that logs without a label when dropped.
+    #[track_caller]
+    pub fn unlabeled() -> Self {
+        Self::start(None, Location::caller())
+    }
+
+    /// Enters the nesting, checkpoint and registry frames and reads the clock,
+    /// unless the `disable` feature compiles timing out. Only labelled timers
+    /// track the time of their nested timers.
+    fn start(label: Option<Cow<'static, str>>, location: &'static Location<'static>) -> Self {
+        let timed = cfg!(not(feature = "disable"));
+        ScopeTimer {
+            nested: (timed && label.is_some()).then(registry::enter),
+            label,
+            threshold: None,
+            budget: None,
+            backtrace: false,
+            level: Level::Info,
+            frame: timed.then(nesting::enter),
+            splits: timed.then(checkpoint::enter),
+            location,
+            site: None,
+            #[cfg(feature = "watchdog")]
+            watch: None,
+            start: if timed { crate::clock::now() } else { 0 },
+        }
+    }
+
+    /// Only log when the scope took at least `threshold`.
+    pub fn threshold(mut self, threshold: Duration) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Reports the scope from the [`watchdog`](crate::watchdog) thread if it
+    /// is still running after `threshold`, instead of only logging it once it
+    /// ends.
+    #[cfg(feature = "watchdog")]
+    pub fn watchdog(mut self, threshold: Duration) -> Self {
+        if cfg!(not(feature = "disable")) && crate::is_enabled() && crate::filter::enabled(self.label.as_deref()) {
+            let label = self.label.as_ref().map(|label| label.to_string());
+            let watch = crate::watchdog::enter(label, threshold, self.location.file(), self.location.line(), crate::sink::emit_message);
+            self.watch = Some(watch);
+        }
+        self
+    }
+
+    /// Follow the message with a backtrace of the scope's caller when it is
+    /// logged, typically because it reached the [`threshold`](Self::threshold).
+    pub fn backtrace(mut self, capture: bool) -> Self {
+        self.backtrace = capture;
+        self
+    }
+
+    /// Log at `level` instead of [`Level::Info`].
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Panics when the timer is dropped after more than `budget`, unless the
+    /// thread is already panicking, for `#[timed_test]`.
+    #[doc(hidden)]
+    pub fn __budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Sets the counters of the macro expansion creating the timer, which
+    /// also provide the module path that `#[track_caller]` cannot.
+    #[doc(hidden)]
+    pub fn __callsite(mut self, site: &'static CallSite) -> Self {
+        self.site = Some(site);
+        self
+    }
+
+    /// Returns the label of this timer, if any.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Returns the time elapsed since the timer was created, or zero with the
+    /// `disable` feature.
+    pub fn elapsed(&self) -> Duration {
+        if cfg!(feature = "disable") {
+            return Duration::ZERO;
+        }
+        crate::clock::elapsed_since(self.start)
+    }
+}
+
+impl Drop for ScopeTimer {
+    fn drop(&mut self) {
+        #[cfg(feature = "watchdog")]
+        self.watch.take();
+        // Like `assert_time!`, a budget is enforced even while output is disabled
+        let enabled = crate::is_enabled();
+        if cfg!(feature = "disable") || (!enabled && self.budget.is_none()) {
+            return;
+        }
+        let duration = crate::calibration::correct(crate::clock::elapsed_since(self.start));
+        // A filtered-out timer is not recorded, but nested timings still are
+        let measured = enabled && crate::filter::enabled(self.label.as_deref());
+        let self_duration = match self.nested.take() {
+            Some(nested) => nested.finish(duration, measured),
+            None => duration,
+        };
+        if let Some(label) = self.label.as_ref().filter(|_| measured) {
+            registry::record_nested(label, duration, self_duration);
+        }
+        if let Some(site) = self.site.filter(|_| measured) {
+            site.record(self.label.as_deref(), duration, 1);
+        }
+        let mut record = TimingRecord::new(
+            self.label.as_ref().map(|label| label.to_string()),
+            duration,
+            self.location.file(),
+            self.location.line(),
+        );
+        record.module_path = self.site.map_or("", CallSite::module_path).into();
+        record.level = self.level;
+        record.panicked = std::thread::panicking();
+        if let Some(splits) = self.splits.take() {
+            record.splits = splits.finish(duration);
+        }
+        if measured {
+            crate::trace::record(&record);
+            crate::slow::check(&record);
+        }
+        let over_budget = self.budget.filter(|&budget| duration > budget && !record.panicked);
+        let message = over_budget.map(|budget| {
+            format!("{}, exceeding its budget of {}", record.message(), crate::HumanDuration(budget))
+        });
+        if let Some(frame) = self.frame.take() {
+            let logged = measured && self.threshold.is_none_or(|threshold| duration >= threshold);
+            if logged && self.backtrace {
+                record.backtrace = Some(std::backtrace::Backtrace::force_capture().to_string());
+            }
+            crate::sink::dispatch(frame.finish(logged.then_some(record)), crate::sink::emit_message);
+        }
+        if let Some(message) = message {
+            panic!("{}", message);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Per-iteration timings of loops, for [`time_iterations!`](crate::time_iterations).
+
+use std::time::Duration;
+
+use crate::registry::LabelStats;
+use crate::reservoir::Reservoir;
+use crate::HumanDuration;
+
+/// The duration of every iteration of a loop timed with
+/// [`time_iterations!`](crate::time_iterations).
+///
+/// A series keeps running statistics of all iterations, and optionally a
+/// reservoir of raw samples: a uniformly random subset of the durations, of a
+/// fixed size however many iterations ran, e.g. to plot their distribution.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::Series;
+/// use std::time::Duration;
+///
+/// let mut series = Series::new(Some("parse".into())).with_samples(2);
+/// for millis in [1, 2, 3] {
+///     series.add(Duration::from_millis(millis));
+/// }
+/// assert_eq!(series.stats().count, 3);
+/// assert_eq!(series.mean(), Duration::from_millis(2));
+/// assert_eq!(series.samples().len(), 2);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Series {
+    label: Option<String>,
+    stats: LabelStats,
+    /// The running mean and sum of squared deviations of the durations, in
+    /// nanoseconds, by Welford's algorithm
+    mean_ns: f64,
+    squares_ns: f64,
+    reservoir: Reservoir,
+    /// Whether iterations are recorded in the registry
+    recorded: bool,
+}
+
+impl Series {
+    /// Creates an empty series, without samples.
+    ///
+    /// The iterations of a labelled series are also recorded in the
+    /// [`registry`](crate::registry) under its label, unless the label is
+    /// filtered out.
+    pub fn new(label: Option<String>) -> Self {
+        let recorded = !cfg!(feature = "disable")
+            && crate::is_enabled()
+            && label.as_deref().is_some_and(|label| crate::filter::enabled(Some(label)));
+        Series {
+            label,
+            recorded,
+            ..Series::default()
+        }
+    }
+
+    /// Keeps a reservoir of up to `capacity` raw samples.
+    pub fn with_samples(mut self, capacity: usize) -> Self {
+        self.reservoir = Reservoir::new(capacity);
+        self
+    }
+
+    /// Returns the label of the series, if it has one.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Returns the count, total, minimum and maximum of the iterations.
+    pub fn stats(&self) -> &LabelStats {
+        &self.stats
+    }
+
+    /// Returns the mean duration of the iterations.
+    pub fn mean(&self) -> Duration {
+        self.stats.mean()
+    }
+
+    /// Returns the standard deviation of the durations of the iterations.
+    pub fn std_dev(&self) -> Duration {
+        match self.stats.count {
+            0 => Duration::ZERO,
+            count => Duration::from_nanos((self.squares_ns / count as f64).sqrt() as u64),
+        }
+    }
+
+    /// Returns the raw samples in the reservoir, in no particular order.
+    pub fn samples(&self) -> &[Duration] {
+        self.reservoir.samples()
+    }
+
+    /// Returns the reservoir of raw samples, e.g. for their percentiles.
+    pub fn reservoir(&self) -> &Reservoir {
+        &self.reservoir
+    }
+
+    /// Adds the duration of an iteration.
+    pub fn add(&mut self, duration: Duration) {
+        self.stats.add(duration);
+        let count = self.stats.count;
+        let nanos = duration.as_nanos() as f64;
+        let delta = nanos - self.mean_ns;
+        self.mean_ns += delta / count as f64;
+        self.squares_ns += delta * (nanos - self.mean_ns);
+        self.reservoir.add(duration);
+        if self.recorded
+            && let Some(label) = &self.label
+        {
+            crate::registry::record(label, duration);
+        }
+    }
+
+    /// Starts timing an iteration, which ends when the returned guard is
+    /// dropped.
+    pub fn iteration(&mut self) -> Iteration<'_> {
+        let start = if cfg!(feature = "disable") { 0 } else { crate::clock::now() };
+        Iteration { series: self, start }
+    }
+
+    /// Returns the summary message of the series, e.g.
+    /// `"parse - 1000 iterations, mean: 1.2 ms, std dev: 300 µs, min: 1 ms, max: 3 ms, total: 1.2 s"`,
+    /// or `None` if it is disabled.
+    pub fn summary(&self) -> Option<String> {
+        if cfg!(feature = "disable")
+            || !crate::is_enabled()
+            || !crate::filter::enabled(self.label.as_deref())
+        {
+            return None;
+        }
+        let iterations = match self.stats.count {
+            1 => "1 iteration".to_string(),
+            count => format!("{} iterations", count),
+        };
+        let prefix = match &self.label {
+            Some(label) => format!("{} - {}", label, iterations),
+            None => iterations,
+        };
+        if self.stats.count == 0 {
+            return Some(prefix);
+        }
+        Some(format!(
+            "{}, mean: {}, std dev: {}, min: {}, max: {}, total: {}",
+            prefix,
+            HumanDuration(self.mean()),
+            HumanDuration(self.std_dev()),
+            HumanDuration(self.stats.min),
+            HumanDuration(self.stats.max),
+            HumanDuration(self.stats.total)
+        ))
+    }
+}
+
+/// An iteration of a [`Series`] being timed, see [`Series::iteration`].
+#[derive(Debug)]
+pub struct Iteration<'a> {
+    series: &'a mut Series,
+    /// A reading of the [`clock`](crate::clock) when the iteration started
+    start: u64,
+}
+
+impl Drop for Iteration<'_> {
+    fn drop(&mut self) {
+        if !cfg!(feature = "disable") {
+            let duration = crate::calibration::correct(crate::clock::elapsed_since(self.start));
+            self.series.add(duration);
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+
+    #[test]
+    fn summarizes_iterations() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let mut series = Series::new(Some("series-loop".into()));
+        for millis in [2, 4, 4, 4, 5, 5, 7, 9] {
+            let _iteration = series.iteration();
+            clock.advance(Duration::from_millis(millis));
+        }
+        assert_eq!(series.mean(), Duration::from_millis(5));
+        assert_eq!(series.std_dev(), Duration::from_millis(2));
+        assert_eq!(
+            series.summary().unwrap(),
+            "series-loop - 8 iterations, mean: 5 ms, std dev: 2 ms, min: 2 ms, max: 9 ms, total: 40 ms"
+        );
+        assert_eq!(crate::registry::stats("series-loop").unwrap().count, 8);
+        assert_eq!(Series::new(None).summary().unwrap(), "0 iterations");
+    }
+
+    #[test]
+    fn keeps_a_reservoir_of_samples() {
+        let mut series = Series::new(None).with_samples(10);
+        for millis in 0..1000 {
+            series.add(Duration::from_millis(millis));
+        }
+        assert_eq!(series.stats().count, 1000);
+        assert_eq!(series.samples().len(), 10);
+        // A uniform sample is not stuck on the first durations
+        assert!(series.samples().iter().any(|&sample| sample >= Duration::from_millis(10)));
+        assert!(Series::new(None).samples().is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+//! Dumping the timing report on `SIGUSR1`.
+//!
+//! Long-running services can't easily call [`report`](crate::report) from
+//! outside. With the `signal` feature on Unix, [`report_on_sigusr1`] installs
+//! a handler so that
+//!
+//! ```sh
+//! kill -USR1 <pid>
+//! ```
+//!
+//! sends the current [`registry`](crate::registry) report to the installed
+//! [`TimeSink`](crate::TimeSink), or to stderr, without restarting the process.
+//! The signal handler itself only wakes a background thread named
+//! `arbitime-signal`, which takes the snapshot and writes the report.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{log_time, signal};
+//!
+//! signal::report_on_sigusr1().expect("failed to install the SIGUSR1 handler");
+//!
+//! loop {
+//!     log_time!("handle request" => (1..=100).sum::<u32>());
+//!     # break;
+//! }
+//! ```
+
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::os::raw::{c_int, c_void};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+#[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    not(any(target_arch = "mips", target_arch = "mips64"))
+))]
+const SIGUSR1: c_int = 10;
+#[cfg(any(
+    target_os = "solaris",
+    target_os = "illumos",
+    all(
+        any(target_os = "linux", target_os = "android"),
+        any(target_arch = "mips", target_arch = "mips64")
+    )
+))]
+const SIGUSR1: c_int = 16;
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "solaris", target_os = "illumos")))]
+const SIGUSR1: c_int = 30;
+
+/// `SIG_ERR`, returned by `signal` on failure
+const SIG_ERR: usize = usize::MAX;
+
+unsafe extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    fn write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+}
+
+/// The write end of the pipe waking the reporting thread, or -1
+static WAKE_FD: AtomicI32 = AtomicI32::new(-1);
+/// The write end itself, kept open once the handler is installed
+static WAKE: Mutex<Option<UnixStream>> = Mutex::new(None);
+
+/// Installs a `SIGUSR1` handler that dumps the current timing report to the
+/// installed sink.
+///
+/// Installing it more than once has no further effect. Replaces any handler
+/// that was installed for `SIGUSR1` before.
+///
+/// # Errors
+///
+/// If the wake-up socket, the reporting thread or the handler can't be
+/// created.
+pub fn report_on_sigusr1() -> io::Result<()> {
+    let mut wake = WAKE.lock().unwrap_or_else(PoisonError::into_inner);
+    if wake.is_some() {
+        return Ok(());
+    }
+    let (writer, mut reader) = UnixStream::pair()?;
+    // The handler must never block
+    writer.set_nonblocking(true)?;
+    std::thread::Builder::new()
+        .name("arbitime-signal".to_string())
+        .spawn(move || {
+            let mut buffer = [0; 64];
+            // Several signals in a row are reported once
+            while let Ok(1..) = reader.read(&mut buffer) {
+                crate::sink::dispatch_report(&crate::registry::snapshot());
+            }
+        })?;
+    WAKE_FD.store(writer.as_raw_fd(), Ordering::Release);
+    // SAFETY: `on_signal` only performs async-signal-safe operations
+    if unsafe { signal(SIGUSR1, on_signal) } == SIG_ERR {
+        WAKE_FD.store(-1, Ordering::Release);
+        return Err(io::Error::last_os_error());
+    }
+    *wake = Some(writer);
+    Ok(())
+}
+
+extern "C" fn on_signal(_signum: c_int) {
+    let fd = WAKE_FD.load(Ordering::Acquire);
+    if fd >= 0 {
+        // SAFETY: `fd` is the write end of the socket pair, which stays open
+        // once the handler is installed. A full socket means a report is
+        // already pending, so the result is ignored.
+        let _ = unsafe { write(fd, [1u8].as_ptr().cast(), 1) };
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+
+    unsafe extern "C" {
+        fn raise(signum: c_int) -> c_int;
+    }
+
+    #[test]
+    fn survives_sigusr1() {
+        report_on_sigusr1().unwrap();
+        report_on_sigusr1().unwrap();
+        crate::registry::record("signal-report", std::time::Duration::from_millis(1));
+        // Without the handler, SIGUSR1 would terminate the test process
+        // SAFETY: raising a signal with a handler installed
+        assert_eq!(unsafe { raise(SIGUSR1) }, 0);
+    }
+}
@@ -0,0 +1,440 @@
+//! Pluggable output destinations for timings.
+//!
+//! Every timing that is logged — by [`log_time!`](crate::log_time),
+//! [`json_time!`](crate::json_time), [`time_scope!`](crate::time_scope),
+//! [`#[timed]`](crate::timed) or the async macros — is handed to the global
+//! [`TimeSink`] as a [`TimingRecord`]. Until a sink is installed with
+//! [`set_sink`], arbitime prints to stderr, or through the `log` crate when the
+//! `log` feature is enabled.
+//!
+//! Nested timings reach the sink once the outermost one finishes, parents
+//! before their children, with [`TimingRecord::depth`] set accordingly.
+//! Summary reports of the [`registry`](crate::registry) that are requested at
+//! runtime, for example with a signal, go to [`TimeSink::report`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::sink::{self, NullSink, StdoutSink};
+//! use arbitime::{log_time, TimingRecord};
+//!
+//! // Print to stdout instead of stderr
+//! arbitime::set_sink(StdoutSink);
+//! log_time!("to stdout" => (1..=1000).sum::<u32>());
+//!
+//! // Any closure taking a record is a sink
+//! arbitime::set_sink(|record: &TimingRecord| {
+//!     println!("{} took {} ns", record.label.as_deref().unwrap_or("?"), record.duration.as_nanos());
+//! });
+//!
+//! // Silence all output
+//! arbitime::set_sink(NullSink);
+//!
+//! // Go back to the default output
+//! sink::reset_sink();
+//! ```
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use crate::registry::Report;
+use crate::{Level, TimingRecord};
+
+static SINK: RwLock<Option<Arc<dyn TimeSink>>> = RwLock::new(None);
+
+/// A destination for finished timings.
+///
+/// Implement this trait to send timings anywhere: a file, a metrics system, a
+/// channel, a test buffer. Closures taking a `&TimingRecord` implement it too.
+pub trait TimeSink: Send + Sync {
+    /// Outputs a finished timing.
+    fn record(&self, record: &TimingRecord);
+
+    /// Outputs a summary report of all labels.
+    ///
+    /// Prints the report table to stderr unless overridden.
+    fn report(&self, report: &Report) {
+        eprint!("{}", report);
+    }
+}
+
+impl<F> TimeSink for F
+where
+    F: Fn(&TimingRecord) + Send + Sync,
+{
+    fn record(&self, record: &TimingRecord) {
+        self(record)
+    }
+}
+
+/// Replaces the global sink that all timings are sent to.
+pub fn set_sink(sink: impl TimeSink + 'static) {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(sink));
+}
+
+/// Replaces the global sink with one that is already shared.
+pub(crate) fn install(sink: Arc<dyn TimeSink>) {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) = Some(sink);
+}
+
+/// Removes the sink installed by [`set_sink`], restoring the default output
+/// to stderr (or the `log` crate with the `log` feature).
+pub fn reset_sink() {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Returns the installed sink, if any.
+fn current() -> Option<Arc<dyn TimeSink>> {
+    SINK.read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Emits a message at `level` through the `log` crate with the `log`
+/// feature, or prints it to stderr, the default `emit` function of the
+/// timing types of the crate.
+pub(crate) fn emit_message(level: Level, msg: &str) {
+    crate::__emit!(level, msg);
+}
+
+/// Sends records to the installed sink, or to `emit` as text lines if there is none.
+#[doc(hidden)]
+pub fn dispatch(mut records: Vec<TimingRecord>, emit: fn(Level, &str)) {
+    if cfg!(feature = "disable") || records.is_empty() || !crate::is_enabled() {
+        return;
+    }
+    records.retain_mut(|record| crate::config::apply(record) && crate::filter::allows(record.label.as_deref(), record.level));
+    if records.is_empty() {
+        return;
+    }
+    match current() {
+        Some(sink) => records.iter().for_each(|record| sink.record(record)),
+        None => records.iter().for_each(|record| emit(record.level, &record.to_terminal_text())),
+    }
+}
+
+/// Sends a summary report to the installed sink, or prints it to stderr if
+/// there is none.
+///
+/// Nothing is sent if the report is empty or timing output is disabled.
+pub fn dispatch_report(report: &Report) {
+    if cfg!(feature = "disable") || report.is_empty() || !crate::is_enabled() {
+        return;
+    }
+    match current() {
+        Some(sink) => sink.report(report),
+        None => eprint!("{}", report),
+    }
+}
+
+/// Sends a record to the installed sink, or to `emit` as a JSON line if there is none.
+#[doc(hidden)]
+pub fn dispatch_json(mut record: TimingRecord, emit: fn(Level, &str)) {
+    if cfg!(feature = "disable") || !crate::is_enabled() {
+        return;
+    }
+    if !crate::config::apply(&mut record) || !crate::filter::allows(record.label.as_deref(), record.level) {
+        return;
+    }
+    match current() {
+        Some(sink) => sink.record(&record),
+        None => emit(record.level, &record.to_json()),
+    }
+}
+
+/// Prints each timing as a line of text to stderr.
+///
+/// With the `color` feature, durations are colored as described in
+/// [`color`](crate::color).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrSink;
+
+impl TimeSink for StderrSink {
+    fn record(&self, record: &TimingRecord) {
+        eprintln!("{}", record.to_terminal_text());
+    }
+}
+
+/// Prints each timing as a line of text to stdout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdoutSink;
+
+impl TimeSink for StdoutSink {
+    fn record(&self, record: &TimingRecord) {
+        println!("{}", record.to_text());
+    }
+
+    fn report(&self, report: &Report) {
+        print!("{}", report);
+    }
+}
+
+/// Discards all timings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullSink;
+
+impl TimeSink for NullSink {
+    fn record(&self, _record: &TimingRecord) {}
+
+    fn report(&self, _report: &Report) {}
+}
+
+/// Writes each timing as a line to any [`Write`] implementation, such as a
+/// file or a `Vec<u8>`.
+///
+/// Lines are text by default; use [`WriterSink::json`] to write one JSON
+/// object per line instead. Reports are written as a table, or as one JSON
+/// object per label. Write errors are ignored.
+///
+/// ```rust
+/// use arbitime::sink::WriterSink;
+/// use std::fs::File;
+///
+/// # fn main() -> std::io::Result<()> {
+/// # let path = std::env::temp_dir().join("arbitime-writer-sink.jsonl");
+/// arbitime::set_sink(WriterSink::json(File::create(&path)?));
+/// # arbitime::sink::reset_sink();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct WriterSink<W> {
+    writer: Mutex<W>,
+    json: bool,
+}
+
+impl<W: Write> WriterSink<W> {
+    /// Creates a sink that writes text lines to `writer`.
+    pub fn new(writer: W) -> Self {
+        WriterSink {
+            writer: Mutex::new(writer),
+            json: false,
+        }
+    }
+
+    /// Creates a sink that writes JSON lines to `writer`.
+    pub fn json(writer: W) -> Self {
+        WriterSink {
+            writer: Mutex::new(writer),
+            json: true,
+        }
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&self) -> io::Result<()> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner).flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<W: Write + Send> TimeSink for WriterSink<W> {
+    fn record(&self, record: &TimingRecord) {
+        let line = if self.json {
+            record.to_json()
+        } else {
+            record.to_text()
+        };
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let _ = writeln!(writer, "{}", line);
+    }
+
+    fn report(&self, report: &Report) {
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let _ = if self.json {
+            writeln!(writer, "{}", report.to_json())
+        } else {
+            write!(writer, "{}", report)
+        };
+    }
+}
+
+/// Sends each labelled timing as a statsd timer to a UDP address, such as a
+/// statsd, Datadog or Telegraf agent.
+///
+/// Every record becomes one `label:12.345|ms` datagram, with the label
+/// prefixed by [`prefix`](Self::prefix) and `:`, `|`, `@`, `#`, `,` and
+/// whitespace replaced by `_`. Tags added with [`tag`](Self::tag) are appended
+/// in the Datadog format (`|#key:value`) or, with [`TagFormat::Telegraf`],
+/// inserted in the name (`label,key=value:12.345|ms`). Unlabelled timings are
+/// skipped, and send errors are ignored.
+///
+/// ```rust
+/// use arbitime::sink::StatsdSink;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let sink = StatsdSink::new("127.0.0.1:8125")?
+///     .prefix("myapp.")
+///     .tag("env", "prod");
+/// arbitime::set_sink(sink);
+///
+/// arbitime::log_time!("db query" => (1..=100).sum::<u32>()); // Sends "myapp.db_query:0.004|ms|#env:prod"
+/// # arbitime::sink::reset_sink();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct StatsdSink {
+    socket: UdpSocket,
+    prefix: String,
+    tags: Vec<(String, String)>,
+    tag_format: TagFormat,
+}
+
+/// How [`StatsdSink`] writes tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TagFormat {
+    /// `name:value|ms|#key:value,key2:value2`, used by Datadog (the default)
+    #[default]
+    Datadog,
+    /// `name,key=value,key2=value2:value|ms`, used by Telegraf
+    Telegraf,
+}
+
+impl StatsdSink {
+    /// Creates a sink sending to `address`, e.g. `"127.0.0.1:8125"`.
+    ///
+    /// # Errors
+    ///
+    /// If the address can't be resolved or no UDP socket can be opened.
+    pub fn new(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to send timings to"))?;
+        let local = if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(address)?;
+        Ok(StatsdSink {
+            socket,
+            prefix: String::new(),
+            tags: Vec::new(),
+            tag_format: TagFormat::default(),
+        })
+    }
+
+    /// Prefixes all metric names, e.g. with `"myapp."`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Adds a tag to all metrics.
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets how tags are written.
+    pub fn tag_format(mut self, format: TagFormat) -> Self {
+        self.tag_format = format;
+        self
+    }
+
+    /// Formats the datagram for a record, or `None` for unlabelled records.
+    fn datagram(&self, record: &TimingRecord) -> Option<String> {
+        let label = record.label.as_deref()?;
+        let mut datagram: String = self
+            .prefix
+            .chars()
+            .chain(label.chars())
+            .map(|c| match c {
+                ':' | '|' | '@' | '#' | ',' => '_',
+                c if c.is_whitespace() => '_',
+                c => c,
+            })
+            .collect();
+        let millis = record.duration.as_secs_f64() * 1e3;
+        match self.tag_format {
+            TagFormat::Datadog => {
+                let _ = write!(datagram, ":{:.3}|ms", millis);
+                for (i, (key, value)) in self.tags.iter().enumerate() {
+                    let separator = if i == 0 { "|#" } else { "," };
+                    let _ = write!(datagram, "{}{}:{}", separator, key, value);
+                }
+            }
+            TagFormat::Telegraf => {
+                for (key, value) in &self.tags {
+                    let _ = write!(datagram, ",{}={}", key, value);
+                }
+                let _ = write!(datagram, ":{:.3}|ms", millis);
+            }
+        }
+        Some(datagram)
+    }
+}
+
+impl TimeSink for StatsdSink {
+    fn record(&self, record: &TimingRecord) {
+        if let Some(datagram) = self.datagram(record) {
+            let _ = self.socket.send(datagram.as_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn writer_sink_writes_lines() {
+        let record = TimingRecord::new(Some("write".into()), Duration::from_millis(3), "src/lib.rs", 1);
+
+        let sink = WriterSink::new(Vec::new());
+        sink.record(&record);
+        sink.record(&record);
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(output, "write - Execution time: 3 ms\n".repeat(2));
+
+        let sink = WriterSink::json(Vec::new());
+        sink.record(&record);
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(output, format!("{}\n", record.to_json()));
+    }
+
+    #[test]
+    fn statsd_sink_sends_datagrams() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let receive = || {
+            let mut buffer = [0; 512];
+            let len = agent.recv(&mut buffer).unwrap();
+            String::from_utf8(buffer[..len].to_vec()).unwrap()
+        };
+        let record = TimingRecord::new(Some("db query".into()), Duration::from_micros(12_345), "src/lib.rs", 1);
+
+        let sink = StatsdSink::new(agent.local_addr().unwrap()).unwrap().prefix("app.");
+        sink.record(&TimingRecord::new(None, Duration::from_millis(1), "src/lib.rs", 1));
+        sink.record(&record);
+        assert_eq!(receive(), "app.db_query:12.345|ms");
+
+        let sink = sink.tag("env", "prod").tag("host", "a");
+        sink.record(&record);
+        assert_eq!(receive(), "app.db_query:12.345|ms|#env:prod,host:a");
+
+        let sink = sink.tag_format(TagFormat::Telegraf);
+        sink.record(&record);
+        assert_eq!(receive(), "app.db_query,env=prod,host=a:12.345|ms");
+    }
+
+    #[test]
+    fn writer_sink_writes_reports() {
+        crate::registry::record("sink-report", Duration::from_millis(4));
+        let report = crate::registry::snapshot();
+
+        let sink = WriterSink::new(Vec::new());
+        sink.report(&report);
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(output, report.to_string());
+
+        let sink = WriterSink::json(Vec::new());
+        sink.report(&report);
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        assert!(output.lines().all(|line| line.starts_with(r#"{"label":"#)));
+        assert!(output.contains(r#"{"label":"sink-report","count":1,"#));
+    }
+}
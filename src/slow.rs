@@ -0,0 +1,106 @@
+//! Callbacks for slow measurements.
+//!
+//! [`on_slow`] registers a closure that is called with the
+//! [`TimingRecord`] of every measurement that takes longer than its
+//! threshold, whether it is logged or not: [`format_time!`](crate::format_time),
+//! [`log_time!`](crate::log_time), [`json_time!`](crate::json_time), their
+//! async variants, [`time_scope!`](crate::time_scope) and
+//! [`timed`](crate::timed). Applications can use it to capture a backtrace,
+//! increment an error metric or page someone, independently of the output.
+//!
+//! Hooks run on the thread that took the measurement, right after it
+//! finished, so slow hooks slow down the timed code. Measurements that are
+//! [disabled](crate::set_enabled) or [filtered out](crate::filter) do not
+//! call them. Without any hook, a measurement costs one atomic load.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{log_time, TimingRecord};
+//! use std::time::Duration;
+//!
+//! arbitime::on_slow(Duration::from_millis(500), |record: &TimingRecord| {
+//!     eprintln!("slow: {} at {}", record.label.as_deref().unwrap_or("?"), record.location());
+//! });
+//!
+//! log_time!("fast" => (1..=100).sum::<u32>()); // Too fast to call the hook
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+use crate::TimingRecord;
+
+type Hook = Arc<dyn Fn(&TimingRecord) + Send + Sync>;
+
+static HOOKS: RwLock<Vec<(Duration, Hook)>> = RwLock::new(Vec::new());
+/// The lowest threshold of all hooks in nanoseconds, or `u64::MAX` without
+/// hooks, to skip the lock for most measurements
+static MIN_THRESHOLD_NS: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Registers a closure that is called with every measurement that takes
+/// longer than `threshold`.
+///
+/// Hooks are kept until [`clear_hooks`] is called, and each hook whose
+/// threshold is exceeded is called, in the order they were registered.
+pub fn on_slow(threshold: Duration, hook: impl Fn(&TimingRecord) + Send + Sync + 'static) {
+    let mut hooks = HOOKS.write().unwrap_or_else(PoisonError::into_inner);
+    hooks.push((threshold, Arc::new(hook)));
+    let nanos = u64::try_from(threshold.as_nanos()).unwrap_or(u64::MAX);
+    MIN_THRESHOLD_NS.fetch_min(nanos, Ordering::Relaxed);
+}
+
+/// Removes all hooks registered with [`on_slow`].
+pub fn clear_hooks() {
+    let mut hooks = HOOKS.write().unwrap_or_else(PoisonError::into_inner);
+    hooks.clear();
+    MIN_THRESHOLD_NS.store(u64::MAX, Ordering::Relaxed);
+}
+
+/// Calls the hooks whose threshold the record exceeds.
+pub(crate) fn check(record: &TimingRecord) {
+    let nanos = u64::try_from(record.duration.as_nanos()).unwrap_or(u64::MAX);
+    if nanos <= MIN_THRESHOLD_NS.load(Ordering::Relaxed) {
+        return;
+    }
+    // Hooks are called without the lock, so that they can time code and
+    // register hooks themselves
+    let hooks: Vec<Hook> = HOOKS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .filter(|(threshold, _)| record.duration > *threshold)
+        .map(|(_, hook)| Arc::clone(hook))
+        .collect();
+    for hook in hooks {
+        hook(record);
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn calls_hooks_over_their_threshold() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        for millis in [10, 100] {
+            let collected = Arc::clone(&seen);
+            on_slow(Duration::from_millis(millis), move |record: &TimingRecord| {
+                if record.label.as_deref().is_some_and(|label| label.starts_with("slow-")) {
+                    collected.lock().unwrap().push((millis, record.label.clone().unwrap()));
+                }
+            });
+        }
+
+        for (label, millis) in [("slow-fast", 10), ("slow-medium", 50), ("slow-slowest", 200)] {
+            check(&TimingRecord::new(Some(label.into()), Duration::from_millis(millis), "", 0));
+        }
+
+        let seen = seen.lock().unwrap();
+        let expected = [(10, "slow-medium"), (10, "slow-slowest"), (100, "slow-slowest")];
+        assert_eq!(*seen, expected.map(|(millis, label)| (millis, label.to_string())));
+    }
+}
@@ -0,0 +1,136 @@
+//! Spans begun and ended by id, for work that cannot be wrapped in a block.
+//!
+//! GPU submissions, completion callbacks and other asynchronous work start in
+//! one place and finish in another, possibly on another thread, so neither a
+//! block nor a guard can time them. [`begin`](crate::begin) opens a span and
+//! returns its [`SpanId`], and [`end`](crate::end) closes it. The measurement
+//! then goes through the same pipeline as [`time_scope!`](crate::time_scope):
+//! it is recorded in the [`registry`](crate::registry), the
+//! [`trace`](crate::trace) and the [`on_slow`](crate::on_slow) hooks, and
+//! logged through the [`sink`](crate::sink), to stderr or the `log` crate if
+//! none is installed.
+
+use std::collections::HashMap;
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use crate::TimingRecord;
+
+/// The id of a span opened with [`begin`], to [`end`] it with.
+///
+/// Ids are unique for the lifetime of the process, and can be sent to other
+/// threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanId(u64);
+
+/// A span that was begun but not ended yet.
+#[derive(Debug)]
+struct OpenSpan {
+    label: String,
+    /// The correlation ID of the [context](crate::context) the span began in
+    context: Option<Arc<str>>,
+    location: &'static Location<'static>,
+    /// A reading of the [`clock`](crate::clock) when the span began
+    start: u64,
+}
+
+/// The id of the next span, starting at 1 so that 0 is never a valid id
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+static OPEN: Mutex<Option<HashMap<u64, OpenSpan>>> = Mutex::new(None);
+
+fn open() -> MutexGuard<'static, Option<HashMap<u64, OpenSpan>>> {
+    OPEN.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Begins a span with the given label, which lasts until [`end`] is called
+/// with the returned id.
+///
+/// A span that is never ended is never recorded, and its label is kept until
+/// the end of the process.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::{begin, end, registry};
+///
+/// let upload = begin("upload");
+/// // Submit the upload, and end the span in its completion callback
+/// let on_complete = move || end(upload);
+///
+/// let duration = on_complete();
+/// # #[cfg(not(feature = "disable"))]
+/// assert!(duration.is_some());
+/// assert_eq!(end(upload), None); // Already ended
+/// # #[cfg(not(feature = "disable"))]
+/// assert_eq!(registry::stats("upload").unwrap().count, 1);
+/// ```
+#[track_caller]
+pub fn begin(label: impl Into<String>) -> SpanId {
+    if cfg!(feature = "disable") {
+        return SpanId(0);
+    }
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let span = OpenSpan {
+        label: label.into(),
+        context: crate::context::current(),
+        location: Location::caller(),
+        start: crate::clock::now(),
+    };
+    open().get_or_insert_with(HashMap::new).insert(id, span);
+    SpanId(id)
+}
+
+/// Ends the span with the given id, and returns how long it lasted, or
+/// `None` if it was already ended.
+///
+/// The span is attributed to the source location and the
+/// [context](crate::context) of its [`begin`], and to the thread that ends
+/// it.
+pub fn end(id: SpanId) -> Option<Duration> {
+    let span = open().as_mut()?.remove(&id.0)?;
+    let duration = crate::calibration::correct(crate::clock::elapsed_since(span.start));
+    if !crate::is_enabled() || !crate::filter::enabled(Some(&span.label)) {
+        return Some(duration);
+    }
+    crate::registry::record(&span.label, duration);
+    let mut record = TimingRecord::new(Some(span.label), duration, span.location.file(), span.location.line());
+    record.context = span.context;
+    crate::trace::record(&record);
+    crate::slow::check(&record);
+    crate::sink::dispatch(vec![record], crate::sink::emit_message);
+    Some(duration)
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+
+    #[test]
+    fn ends_spans_across_threads() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let first = begin("span-upload");
+        let second = begin("span-upload");
+        assert_ne!(first, second);
+        clock.advance(Duration::from_millis(3));
+        let callback = {
+            let clock = clock.clone();
+            move || {
+                let _guard = clock.install();
+                end(second)
+            }
+        };
+        assert_eq!(std::thread::spawn(callback).join().unwrap(), Some(Duration::from_millis(3)));
+        clock.advance(Duration::from_millis(2));
+        assert_eq!(end(first), Some(Duration::from_millis(5)));
+        assert_eq!(end(first), None);
+
+        let stats = crate::registry::stats("span-upload").unwrap();
+        assert_eq!((stats.count, stats.total), (2, Duration::from_millis(8)));
+    }
+}
@@ -0,0 +1,151 @@
+//! Imperative stopwatch for timing phases of long-running code.
+
+use std::time::Duration;
+
+/// A stopwatch that can be paused, resumed and split into labelled laps.
+///
+/// Unlike the timing macros, a `Stopwatch` is controlled explicitly, which is
+/// convenient for measuring phases of a loop or of code that does not fit in
+/// a single block. Time spent while paused is not counted.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::Stopwatch;
+///
+/// let mut stopwatch = Stopwatch::start_new();
+///
+/// let data: Vec<u32> = (1..=1000).collect();
+/// stopwatch.lap("load");
+///
+/// stopwatch.pause();
+/// // Not measured
+/// stopwatch.resume();
+///
+/// let sum: u32 = data.iter().sum();
+/// stopwatch.lap("sum");
+///
+/// for (label, duration) in stopwatch.laps() {
+///     println!("{}: {:?}", label, duration);
+/// }
+/// assert_eq!(stopwatch.laps().len(), 2);
+/// assert!(stopwatch.elapsed() >= stopwatch.laps()[0].1 + stopwatch.laps()[1].1);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Stopwatch {
+    /// Time accumulated before the current run
+    accumulated: Duration,
+    /// When the current run started, as a reading of the global
+    /// [`clock`](crate::clock), if running
+    running_since: Option<u64>,
+    /// Total elapsed time when the last lap was recorded
+    last_lap: Duration,
+    laps: Vec<(String, Duration)>,
+}
+
+impl Stopwatch {
+    /// Creates a stopped stopwatch with no elapsed time.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a stopwatch and starts it immediately.
+    pub fn start_new() -> Self {
+        let mut stopwatch = Self::new();
+        stopwatch.start();
+        stopwatch
+    }
+
+    /// Resets the stopwatch, discarding all laps, and starts it.
+    pub fn start(&mut self) {
+        self.reset();
+        self.running_since = Some(crate::clock::now());
+    }
+
+    /// Stops the stopwatch and returns the total elapsed time.
+    ///
+    /// The elapsed time and laps are kept until the next [`start`](Self::start)
+    /// or [`reset`](Self::reset).
+    pub fn stop(&mut self) -> Duration {
+        self.pause();
+        self.accumulated
+    }
+
+    /// Pauses the stopwatch. Does nothing if it is not running.
+    pub fn pause(&mut self) {
+        if let Some(since) = self.running_since.take() {
+            self.accumulated += crate::clock::elapsed_since(since);
+        }
+    }
+
+    /// Resumes a paused stopwatch. Does nothing if it is already running.
+    pub fn resume(&mut self) {
+        if self.running_since.is_none() {
+            self.running_since = Some(crate::clock::now());
+        }
+    }
+
+    /// Stops the stopwatch and clears the elapsed time and all laps.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Returns `true` if the stopwatch is running.
+    pub fn is_running(&self) -> bool {
+        self.running_since.is_some()
+    }
+
+    /// Returns the total elapsed time, excluding time spent paused.
+    pub fn elapsed(&self) -> Duration {
+        match self.running_since {
+            Some(since) => self.accumulated + crate::clock::elapsed_since(since),
+            None => self.accumulated,
+        }
+    }
+
+    /// Records a lap ending now and returns its duration.
+    ///
+    /// A lap covers the time since the previous lap, or since the stopwatch
+    /// was started for the first lap.
+    pub fn lap(&mut self, label: impl Into<String>) -> Duration {
+        let elapsed = self.elapsed();
+        let duration = elapsed - self.last_lap;
+        self.last_lap = elapsed;
+        self.laps.push((label.into(), duration));
+        duration
+    }
+
+    /// Returns all recorded laps in order.
+    pub fn laps(&self) -> &[(String, Duration)] {
+        &self.laps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pausing_excludes_time() {
+        let mut stopwatch = Stopwatch::start_new();
+        assert!(stopwatch.is_running());
+        stopwatch.pause();
+        let paused_at = stopwatch.elapsed();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(stopwatch.elapsed(), paused_at);
+
+        stopwatch.resume();
+        let lap = stopwatch.lap("first");
+        assert!(lap >= paused_at);
+        assert_eq!(stopwatch.laps()[0], ("first".to_string(), lap));
+
+        let total = stopwatch.stop();
+        assert!(!stopwatch.is_running());
+        assert!(total >= lap);
+        assert!(total < Duration::from_millis(5));
+
+        stopwatch.reset();
+        assert_eq!(stopwatch.elapsed(), Duration::ZERO);
+        assert!(stopwatch.laps().is_empty());
+    }
+}
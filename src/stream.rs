@@ -0,0 +1,272 @@
+//! Per-item timing of asynchronous streams.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::HumanDuration;
+
+/// The items of a stream timed by a [`StreamTimer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct StreamStats {
+    /// The number of items the stream produced
+    pub items: u64,
+    /// The total time the stream took to produce its items, from the request
+    /// for an item until it was ready
+    pub latency: Duration,
+    /// The longest time the stream took to produce an item
+    pub max_latency: Duration,
+    /// The total time the consumer spent on the items, from receiving an item
+    /// until requesting the next one
+    pub processing: Duration,
+    /// The longest time the consumer spent on an item
+    pub max_processing: Duration,
+}
+
+impl StreamStats {
+    /// Returns the mean time the stream took to produce an item.
+    pub fn mean_latency(&self) -> Duration {
+        mean(self.latency, self.items)
+    }
+
+    /// Returns the mean time the consumer spent on an item.
+    pub fn mean_processing(&self) -> Duration {
+        mean(self.processing, self.items)
+    }
+}
+
+fn mean(total: Duration, count: u64) -> Duration {
+    match u32::try_from(count) {
+        Ok(0) => Duration::ZERO,
+        Ok(count) => total / count,
+        Err(_) => Duration::from_secs_f64(total.as_secs_f64() / count as f64),
+    }
+}
+
+impl fmt::Display for StreamStats {
+    /// Formats the statistics as e.g.
+    /// `10 items, latency: mean 1.2 ms, max 3 ms, processing: mean 400 µs, max 1 ms`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} items, latency: mean {}, max {}, processing: mean {}, max {}",
+            self.items,
+            HumanDuration(self.mean_latency()),
+            HumanDuration(self.max_latency),
+            HumanDuration(self.mean_processing()),
+            HumanDuration(self.max_processing)
+        )
+    }
+}
+
+/// Times the items of a stream, for use in its `poll_next`.
+///
+/// Usually used through [`timed_stream!`](crate::timed_stream), which wraps a
+/// [`futures::Stream`](https://docs.rs/futures) with a `StreamTimer`. The
+/// timer itself does not depend on any stream trait, so that hand-written
+/// streams and other stream traits can be timed too, by calling
+/// [`poll_next`](StreamTimer::poll_next) with their own `poll_next`.
+///
+/// For each item, the timer measures its *latency*, the time from the first
+/// poll requesting it until the stream produced it, and its *processing
+/// time*, the time from handing it out until the consumer polls for the next
+/// one. Timers created with a [label](StreamTimer::labeled) record each
+/// latency in the [`registry`](crate::registry) under the label.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::StreamTimer;
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll};
+///
+/// /// A stream of numbers, with its own `poll_next`
+/// struct Countdown(u32);
+///
+/// impl Countdown {
+///     fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<u32>> {
+///         let this = self.get_mut();
+///         this.0 = this.0.saturating_sub(1);
+///         Poll::Ready((this.0 > 0).then_some(this.0))
+///     }
+/// }
+///
+/// let mut stream = Countdown(4);
+/// let mut timer = StreamTimer::labeled("countdown");
+/// let mut cx = Context::from_waker(std::task::Waker::noop());
+/// while let Poll::Ready(Some((latency, n))) = timer.poll_next(Pin::new(&mut stream), &mut cx, Countdown::poll_next) {
+///     println!("{} after {:?}", n, latency);
+/// }
+/// # #[cfg(not(feature = "disable"))]
+/// assert_eq!(timer.stats().items, 3);
+/// # #[cfg(not(feature = "disable"))]
+/// println!("{}", timer.summary().unwrap()); // "countdown - 3 items, latency: mean ..."
+/// ```
+#[derive(Debug)]
+pub struct StreamTimer {
+    label: Option<Cow<'static, str>>,
+    stats: StreamStats,
+    /// Readings of the [`clock`](crate::clock) at the first poll for the
+    /// next item, and when the last item was handed out
+    requested: Option<u64>,
+    yielded: Option<u64>,
+    finished: bool,
+    summarized: bool,
+}
+
+impl StreamTimer {
+    /// Creates a timer that only measures.
+    pub fn new() -> Self {
+        StreamTimer {
+            label: None,
+            stats: StreamStats::default(),
+            requested: None,
+            yielded: None,
+            finished: false,
+            summarized: false,
+        }
+    }
+
+    /// Creates a timer that records the latency of each item in the registry
+    /// under `label`, and has a [`summary`](StreamTimer::summary) once the
+    /// stream ends.
+    pub fn labeled(label: impl Into<Cow<'static, str>>) -> Self {
+        StreamTimer {
+            label: Some(label.into()),
+            ..StreamTimer::new()
+        }
+    }
+
+    /// Returns the label of the timer, if any.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Polls `stream` for its next item with `poll_next`, and returns the
+    /// item with its latency once it is ready.
+    pub fn poll_next<S: ?Sized, T>(
+        &mut self,
+        stream: Pin<&mut S>,
+        cx: &mut Context<'_>,
+        poll_next: impl FnOnce(Pin<&mut S>, &mut Context<'_>) -> Poll<Option<T>>,
+    ) -> Poll<Option<(Duration, T)>> {
+        if cfg!(feature = "disable") {
+            return poll_next(stream, cx).map(|item| item.map(|item| (Duration::ZERO, item)));
+        }
+        let requested = *self.requested.get_or_insert_with(|| {
+            if let Some(yielded) = self.yielded.take() {
+                let processing = crate::clock::elapsed_since(yielded);
+                self.stats.processing += processing;
+                self.stats.max_processing = self.stats.max_processing.max(processing);
+            }
+            crate::clock::now()
+        });
+        let item = match poll_next(stream, cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(None) => {
+                self.finished = true;
+                return Poll::Ready(None);
+            }
+            Poll::Ready(Some(item)) => item,
+        };
+        let latency = crate::calibration::correct(crate::clock::elapsed_since(requested));
+        self.requested = None;
+        self.stats.items += 1;
+        self.stats.latency += latency;
+        self.stats.max_latency = self.stats.max_latency.max(latency);
+        if let Some(label) = &self.label
+            && crate::is_enabled()
+            && crate::filter::enabled(Some(label))
+        {
+            crate::registry::record(label, latency);
+        }
+        self.yielded = Some(crate::clock::now());
+        Poll::Ready(Some((latency, item)))
+    }
+
+    /// Returns the statistics of the items so far.
+    pub fn stats(&self) -> StreamStats {
+        self.stats
+    }
+
+    /// Returns the summary message of a labelled timer once the stream has
+    /// ended, e.g.
+    /// `"events - 10 items, latency: mean 1.2 ms, max 3 ms, processing: mean 400 µs, max 1 ms"`,
+    /// and `None` afterwards, before the end, and if the label is disabled.
+    pub fn summary(&mut self) -> Option<String> {
+        let label = self.label.as_ref()?;
+        if !self.finished || self.summarized || !crate::is_enabled() || !crate::filter::enabled(Some(label)) {
+            return None;
+        }
+        self.summarized = true;
+        Some(format!("{} - {}", label, self.stats))
+    }
+}
+
+impl Default for StreamTimer {
+    fn default() -> Self {
+        StreamTimer::new()
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::registry;
+    use crate::test::MockClock;
+
+    /// Yields 1, 2 and 3, pending once before each, taking 1 ms per poll.
+    struct Numbers<'a> {
+        clock: &'a MockClock,
+        next: u32,
+        ready: bool,
+    }
+
+    impl Numbers<'_> {
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<u32>> {
+            self.clock.advance(Duration::from_millis(1));
+            self.ready = !self.ready;
+            if self.ready {
+                return Poll::Pending;
+            }
+            self.next += 1;
+            Poll::Ready((self.next <= 3).then_some(self.next))
+        }
+    }
+
+    #[test]
+    fn times_items() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+
+        let mut stream = Numbers { clock: &clock, next: 0, ready: false };
+        let mut timer = StreamTimer::labeled("stream-numbers");
+        assert_eq!(timer.label(), Some("stream-numbers"));
+        let mut items = Vec::new();
+        loop {
+            match timer.poll_next(Pin::new(&mut stream), &mut cx, Numbers::poll_next) {
+                Poll::Pending => clock.advance(Duration::from_millis(3)),
+                Poll::Ready(Some(item)) => {
+                    assert_eq!(timer.summary(), None);
+                    items.push(item);
+                    clock.advance(Duration::from_millis(item.1.into()));
+                }
+                Poll::Ready(None) => break,
+            }
+        }
+
+        let latency = Duration::from_millis(5);
+        assert_eq!(items, [(latency, 1), (latency, 2), (latency, 3)]);
+        let stats = timer.stats();
+        assert_eq!((stats.items, stats.latency, stats.processing), (3, latency * 3, Duration::from_millis(6)));
+        assert_eq!(
+            timer.summary().unwrap(),
+            "stream-numbers - 3 items, latency: mean 5 ms, max 5 ms, processing: mean 2 ms, max 3 ms"
+        );
+        assert_eq!(timer.summary(), None);
+        assert_eq!(registry::stats("stream-numbers").unwrap().count, 3);
+    }
+}
@@ -0,0 +1,35 @@
+//! Runtime switch for timing output.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turns timing output on or off at runtime. Enabled by default.
+///
+/// While disabled, [`log_time!`](crate::log_time), [`json_time!`](crate::json_time),
+/// [`time_scope!`](crate::time_scope), [`#[timed]`](crate::timed) and the
+/// async macros still run their code, but emit nothing and record nothing in
+/// the [`registry`](crate::registry) or [`trace`](crate::trace). Macros that
+/// return their measurement, such as [`time!`](crate::time) and
+/// [`format_time!`](crate::format_time), keep working.
+///
+/// Unlike the `disable` feature, this needs no recompilation, e.g. to enable
+/// timing only when a `--verbose` flag is passed:
+///
+/// ```rust
+/// use arbitime::{log_time, registry};
+///
+/// let verbose = std::env::args().any(|arg| arg == "--verbose");
+/// arbitime::set_enabled(verbose);
+///
+/// log_time!("startup" => (1..=1000).sum::<u32>()); // Only printed with --verbose
+/// assert_eq!(registry::stats("startup").is_some(), verbose);
+/// ```
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether timing output is enabled; see [`set_enabled`].
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,547 @@
+//! Locks and channels that measure contention.
+//!
+//! # Locks
+//!
+//! The timing macros can only time a whole `lock()` call and everything done
+//! while holding the lock together. [`TimedMutex`] and [`TimedRwLock`] wrap
+//! the standard locks and record, for every acquisition, how long the caller
+//! waited for the lock and how long it then held it, in the
+//! [`registry`](crate::registry) under labels made of the lock's name, the
+//! kind of measurement and the call site:
+//!
+//! ```text
+//! cache wait (src/cache.rs:42)
+//! cache hold (src/cache.rs:42)
+//! config read wait (src/config.rs:17)
+//! config write hold (src/config.rs:30)
+//! ```
+//!
+//! Long waits point to contention, and long holds to the critical sections
+//! causing it. The measurements appear in the [`report`](crate::report) like
+//! any other label, and [`filter`](crate::filter) patterns such as
+//! `ARBITIME=cache*` select them. Nothing is logged per acquisition.
+//!
+//! ```rust
+//! use arbitime::sync::TimedMutex;
+//! use std::sync::Arc;
+//!
+//! let counter = Arc::new(TimedMutex::new("counter", 0));
+//! let threads: Vec<_> = (0..4)
+//!     .map(|_| {
+//!         let counter = Arc::clone(&counter);
+//!         std::thread::spawn(move || {
+//!             for _ in 0..100 {
+//!                 *counter.lock().unwrap() += 1;
+//!             }
+//!         })
+//!     })
+//!     .collect();
+//! for thread in threads {
+//!     thread.join().unwrap();
+//! }
+//! assert_eq!(*counter.lock().unwrap(), 400);
+//!
+//! arbitime::report(); // Includes "counter wait (src/main.rs:10)" and "counter hold (src/main.rs:10)"
+//! ```
+//!
+//! # Channels
+//!
+//! Time spent by a message in a queue is invisible to the timing of either
+//! the producer or the consumer. [`channel`] and [`sync_channel`] create
+//! [`std::sync::mpsc`] channels that stamp every message when it is sent,
+//! and record how long it waited in the queue when it is received in the
+//! registry under `"<name> queue"`. The distribution of the delays is
+//! reported like any other label, with percentiles when the `histogram`
+//! feature is enabled. Messages that are never received are not recorded.
+//!
+//! ```rust
+//! use arbitime::registry;
+//! use arbitime::sync;
+//!
+//! let (tx, rx) = sync::channel("jobs");
+//! let producer = std::thread::spawn(move || {
+//!     for job in 0..10 {
+//!         tx.send(job).unwrap();
+//!     }
+//! });
+//! let total: u32 = rx.iter().sum();
+//! producer.join().unwrap();
+//!
+//! assert_eq!(total, 45);
+//! # #[cfg(not(feature = "disable"))]
+//! assert_eq!(registry::stats("jobs queue").unwrap().count, 10);
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use std::sync::mpsc::{self, RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+use std::sync::{
+    LockResult, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
+    TryLockResult,
+};
+use std::time::Duration;
+
+/// A [`Mutex`] that records how long each caller waited for it and held it.
+///
+/// See the [module documentation](self) for the recorded labels.
+#[derive(Debug, Default)]
+pub struct TimedMutex<T: ?Sized> {
+    name: Cow<'static, str>,
+    inner: Mutex<T>,
+}
+
+/// A [`RwLock`] that records how long each reader or writer waited for it
+/// and held it.
+///
+/// See the [module documentation](self) for the recorded labels.
+#[derive(Debug, Default)]
+pub struct TimedRwLock<T: ?Sized> {
+    name: Cow<'static, str>,
+    inner: RwLock<T>,
+}
+
+/// A guard of a [`TimedMutex`] or [`TimedRwLock`] that records the time the
+/// lock was held when it is dropped.
+pub struct TimedGuard<'a, G> {
+    guard: ManuallyDrop<G>,
+    name: &'a str,
+    kind: &'static str,
+    location: &'static Location<'static>,
+    /// A reading of the [`clock`](crate::clock) when the lock was acquired
+    acquired: u64,
+}
+
+/// The guard returned by [`TimedMutex::lock`].
+pub type TimedMutexGuard<'a, T> = TimedGuard<'a, MutexGuard<'a, T>>;
+/// The guard returned by [`TimedRwLock::read`].
+pub type TimedReadGuard<'a, T> = TimedGuard<'a, RwLockReadGuard<'a, T>>;
+/// The guard returned by [`TimedRwLock::write`].
+pub type TimedWriteGuard<'a, T> = TimedGuard<'a, RwLockWriteGuard<'a, T>>;
+
+impl<T> TimedMutex<T> {
+    /// Creates an unlocked mutex whose measurements are labelled with `name`.
+    pub fn new(name: impl Into<Cow<'static, str>>, value: T) -> Self {
+        TimedMutex {
+            name: name.into(),
+            inner: Mutex::new(value),
+        }
+    }
+
+    /// Returns the wrapped value, like [`Mutex::into_inner`].
+    pub fn into_inner(self) -> LockResult<T> {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> TimedMutex<T> {
+    /// Returns the name labelling the measurements of the mutex.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Acquires the mutex like [`Mutex::lock`], recording the time spent
+    /// waiting, and the time it is held once the guard is dropped.
+    #[track_caller]
+    pub fn lock(&self) -> LockResult<TimedMutexGuard<'_, T>> {
+        let location = Location::caller();
+        let start = crate::clock::now();
+        let result = self.inner.lock();
+        record(&self.name, "wait", location, crate::clock::elapsed_since(start));
+        map_result(result, |guard| TimedGuard::new(guard, &self.name, "hold", location))
+    }
+
+    /// Acquires the mutex if it is free, like [`Mutex::try_lock`], recording
+    /// the time it is held once the guard is dropped.
+    #[track_caller]
+    pub fn try_lock(&self) -> TryLockResult<TimedMutexGuard<'_, T>> {
+        let location = Location::caller();
+        map_try_result(self.inner.try_lock(), |guard| TimedGuard::new(guard, &self.name, "hold", location))
+    }
+
+    /// Returns a mutable reference to the value, like [`Mutex::get_mut`].
+    /// No locking takes place, so nothing is recorded.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.inner.get_mut()
+    }
+}
+
+impl<T> TimedRwLock<T> {
+    /// Creates an unlocked lock whose measurements are labelled with `name`.
+    pub fn new(name: impl Into<Cow<'static, str>>, value: T) -> Self {
+        TimedRwLock {
+            name: name.into(),
+            inner: RwLock::new(value),
+        }
+    }
+
+    /// Returns the wrapped value, like [`RwLock::into_inner`].
+    pub fn into_inner(self) -> LockResult<T> {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> TimedRwLock<T> {
+    /// Returns the name labelling the measurements of the lock.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Acquires shared read access like [`RwLock::read`], recording the time
+    /// spent waiting, and the time it is held once the guard is dropped.
+    #[track_caller]
+    pub fn read(&self) -> LockResult<TimedReadGuard<'_, T>> {
+        let location = Location::caller();
+        let start = crate::clock::now();
+        let result = self.inner.read();
+        record(&self.name, "read wait", location, crate::clock::elapsed_since(start));
+        map_result(result, |guard| TimedGuard::new(guard, &self.name, "read hold", location))
+    }
+
+    /// Acquires exclusive write access like [`RwLock::write`], recording the
+    /// time spent waiting, and the time it is held once the guard is dropped.
+    #[track_caller]
+    pub fn write(&self) -> LockResult<TimedWriteGuard<'_, T>> {
+        let location = Location::caller();
+        let start = crate::clock::now();
+        let result = self.inner.write();
+        record(&self.name, "write wait", location, crate::clock::elapsed_since(start));
+        map_result(result, |guard| TimedGuard::new(guard, &self.name, "write hold", location))
+    }
+
+    /// Returns a mutable reference to the value, like [`RwLock::get_mut`].
+    /// No locking takes place, so nothing is recorded.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.inner.get_mut()
+    }
+}
+
+impl<'a, G> TimedGuard<'a, G> {
+    fn new(guard: G, name: &'a str, kind: &'static str, location: &'static Location<'static>) -> Self {
+        TimedGuard {
+            guard: ManuallyDrop::new(guard),
+            name,
+            kind,
+            location,
+            acquired: crate::clock::now(),
+        }
+    }
+}
+
+impl<G: Deref> Deref for TimedGuard<'_, G> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &G::Target {
+        &self.guard
+    }
+}
+
+impl<G: DerefMut> DerefMut for TimedGuard<'_, G> {
+    fn deref_mut(&mut self) -> &mut G::Target {
+        &mut self.guard
+    }
+}
+
+impl<G: fmt::Debug> fmt::Debug for TimedGuard<'_, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.guard, f)
+    }
+}
+
+impl<G> Drop for TimedGuard<'_, G> {
+    fn drop(&mut self) {
+        let held = crate::clock::elapsed_since(self.acquired);
+        // SAFETY: the guard is not used after this point. Releasing the lock
+        // first keeps the registry update out of the critical section.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        record(self.name, self.kind, self.location, held);
+    }
+}
+
+/// A message stamped with the [`clock`](crate::clock) reading when it was sent.
+#[derive(Debug)]
+struct Stamped<T> {
+    sent: u64,
+    value: T,
+}
+
+impl<T> Stamped<T> {
+    fn new(value: T) -> Self {
+        Stamped {
+            sent: crate::clock::now(),
+            value,
+        }
+    }
+}
+
+/// Creates an unbounded channel, like [`mpsc::channel`], that records how
+/// long its messages wait in the queue under `"<name> queue"`.
+///
+/// See the [module documentation](self#channels) for an example.
+pub fn channel<T>(name: impl AsRef<str>) -> (TimedSender<T>, TimedReceiver<T>) {
+    let (sender, receiver) = mpsc::channel();
+    (TimedSender { inner: sender }, TimedReceiver::new(name.as_ref(), receiver))
+}
+
+/// Creates a channel holding up to `bound` messages, like
+/// [`mpsc::sync_channel`], that records how long its messages wait in the
+/// queue under `"<name> queue"`.
+///
+/// The time senders spend blocked on a full channel is not part of the
+/// queueing delay.
+pub fn sync_channel<T>(name: impl AsRef<str>, bound: usize) -> (TimedSyncSender<T>, TimedReceiver<T>) {
+    let (sender, receiver) = mpsc::sync_channel(bound);
+    (TimedSyncSender { inner: sender }, TimedReceiver::new(name.as_ref(), receiver))
+}
+
+/// The sending half of a [`channel`], like [`mpsc::Sender`].
+#[derive(Debug)]
+pub struct TimedSender<T> {
+    inner: mpsc::Sender<Stamped<T>>,
+}
+
+impl<T> TimedSender<T> {
+    /// Sends a message, like [`mpsc::Sender::send`].
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.inner
+            .send(Stamped::new(value))
+            .map_err(|SendError(message)| SendError(message.value))
+    }
+}
+
+impl<T> Clone for TimedSender<T> {
+    fn clone(&self) -> Self {
+        TimedSender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// The sending half of a [`sync_channel`], like [`mpsc::SyncSender`].
+#[derive(Debug)]
+pub struct TimedSyncSender<T> {
+    inner: mpsc::SyncSender<Stamped<T>>,
+}
+
+impl<T> TimedSyncSender<T> {
+    /// Sends a message, blocking while the channel is full, like
+    /// [`mpsc::SyncSender::send`].
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.inner
+            .send(Stamped::new(value))
+            .map_err(|SendError(message)| SendError(message.value))
+    }
+
+    /// Sends a message if the channel has room, like
+    /// [`mpsc::SyncSender::try_send`].
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.inner.try_send(Stamped::new(value)).map_err(|error| match error {
+            TrySendError::Full(message) => TrySendError::Full(message.value),
+            TrySendError::Disconnected(message) => TrySendError::Disconnected(message.value),
+        })
+    }
+}
+
+impl<T> Clone for TimedSyncSender<T> {
+    fn clone(&self) -> Self {
+        TimedSyncSender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// The receiving half of a [`channel`] or [`sync_channel`], like
+/// [`mpsc::Receiver`], that records the queueing delay of every message it
+/// receives.
+#[derive(Debug)]
+pub struct TimedReceiver<T> {
+    inner: mpsc::Receiver<Stamped<T>>,
+    label: String,
+}
+
+impl<T> TimedReceiver<T> {
+    fn new(name: &str, inner: mpsc::Receiver<Stamped<T>>) -> Self {
+        TimedReceiver {
+            inner,
+            label: format!("{} queue", name),
+        }
+    }
+
+    /// Returns the label the queueing delays are recorded under.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Waits for a message, like [`mpsc::Receiver::recv`].
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.inner.recv().map(|message| self.dequeue(message))
+    }
+
+    /// Returns a message if one is queued, like
+    /// [`mpsc::Receiver::try_recv`].
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.inner.try_recv().map(|message| self.dequeue(message))
+    }
+
+    /// Waits for a message for at most `timeout`, like
+    /// [`mpsc::Receiver::recv_timeout`].
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.inner.recv_timeout(timeout).map(|message| self.dequeue(message))
+    }
+
+    /// Returns an iterator waiting for messages until all senders are
+    /// dropped, like [`mpsc::Receiver::iter`].
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// Returns an iterator over the queued messages, like
+    /// [`mpsc::Receiver::try_iter`].
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
+    }
+
+    /// Records the time the message spent in the queue.
+    fn dequeue(&self, message: Stamped<T>) -> T {
+        if !cfg!(feature = "disable") && crate::is_enabled() && crate::filter::enabled(Some(&self.label)) {
+            crate::registry::record(&self.label, crate::clock::elapsed_since(message.sent));
+        }
+        message.value
+    }
+}
+
+impl<T> IntoIterator for TimedReceiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+/// An iterator waiting for the messages of a [`TimedReceiver`] until all
+/// senders are dropped.
+#[derive(Debug)]
+pub struct IntoIter<T> {
+    receiver: TimedReceiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Records a wait or hold time of the lock `name` acquired at `location`.
+fn record(name: &str, kind: &str, location: &Location<'_>, duration: Duration) {
+    if cfg!(feature = "disable") || !crate::is_enabled() {
+        return;
+    }
+    let label = format!("{} {} ({}:{})", name, kind, location.file(), location.line());
+    if crate::filter::enabled(Some(&label)) {
+        crate::registry::record(&label, duration);
+    }
+}
+
+fn map_result<G, U>(result: LockResult<G>, f: impl FnOnce(G) -> U) -> LockResult<U> {
+    match result {
+        Ok(guard) => Ok(f(guard)),
+        Err(poisoned) => Err(PoisonError::new(f(poisoned.into_inner()))),
+    }
+}
+
+fn map_try_result<G, U>(result: TryLockResult<G>, f: impl FnOnce(G) -> U) -> TryLockResult<U> {
+    match result {
+        Ok(guard) => Ok(f(guard)),
+        Err(TryLockError::Poisoned(poisoned)) => Err(TryLockError::Poisoned(PoisonError::new(f(poisoned.into_inner())))),
+        Err(TryLockError::WouldBlock) => Err(TryLockError::WouldBlock),
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::registry;
+    use crate::test::MockClock;
+
+    fn label(name: &str, line: u32) -> String {
+        format!("{} ({}:{})", name, file!(), line)
+    }
+
+    #[test]
+    fn records_wait_and_hold_times() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let mutex = TimedMutex::new("sync-mutex", vec![1]);
+        let line = line!() + 1;
+        let mut guard = mutex.lock().unwrap();
+        guard.push(2);
+        assert!(mutex.try_lock().is_err());
+        clock.advance(Duration::from_millis(5));
+        drop(guard);
+        assert_eq!(mutex.into_inner().unwrap(), [1, 2]);
+
+        let hold = registry::stats(&label("sync-mutex hold", line)).unwrap();
+        assert_eq!((hold.count, hold.total), (1, Duration::from_millis(5)));
+        assert_eq!(registry::stats(&label("sync-mutex wait", line)).unwrap().total, Duration::ZERO);
+
+        let lock = TimedRwLock::new("sync-rwlock", 1);
+        let line = line!() + 1;
+        let (first, second) = (lock.read().unwrap(), lock.read().unwrap());
+        clock.advance(Duration::from_millis(3));
+        assert_eq!(*first + *second, 2);
+        drop((first, second));
+        *lock.write().unwrap() += 1;
+        assert_eq!(*lock.read().unwrap(), 2);
+
+        let read = registry::stats(&label("sync-rwlock read hold", line)).unwrap();
+        assert_eq!((read.count, read.total), (2, Duration::from_millis(6)));
+        assert_eq!(registry::stats(&label("sync-rwlock write hold", line + 4)).unwrap().count, 1);
+    }
+
+    #[test]
+    fn records_queueing_delays() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let (tx, rx) = channel("sync-jobs");
+        tx.send(1).unwrap();
+        clock.advance(Duration::from_millis(4));
+        tx.clone().send(2).unwrap();
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [1, 2]);
+        assert!(rx.try_recv().is_err());
+        drop(tx);
+        assert_eq!(rx.label(), "sync-jobs queue");
+        assert!(rx.recv().is_err());
+
+        let stats = registry::stats("sync-jobs queue").unwrap();
+        assert_eq!((stats.count, stats.min, stats.max), (2, Duration::from_millis(1), Duration::from_millis(5)));
+
+        let (tx, rx) = sync_channel("sync-bounded", 1);
+        tx.try_send('a').unwrap();
+        assert!(matches!(tx.try_send('b'), Err(TrySendError::Full('b'))));
+        assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Ok('a'));
+        drop(rx);
+        assert!(matches!(tx.send('c'), Err(SendError('c'))));
+        assert_eq!(registry::stats("sync-bounded queue").unwrap().count, 1);
+    }
+
+    #[test]
+    fn keeps_poisoned_guards() {
+        let mutex = std::sync::Arc::new(TimedMutex::new("sync-poisoned", 0));
+        let clone = std::sync::Arc::clone(&mutex);
+        let _ = std::thread::spawn(move || {
+            let _guard = clone.lock().unwrap();
+            panic!("poison");
+        })
+        .join();
+        let guard = mutex.lock().unwrap_err().into_inner();
+        assert_eq!(*guard, 0);
+    }
+}
@@ -0,0 +1,203 @@
+//! Nested timings in async tasks.
+//!
+//! [`nesting`](crate::nesting) keeps the open timings of each thread, which
+//! breaks down in async code: a multi-threaded executor moves a task between
+//! threads at every `.await`, and runs other tasks on the same thread in
+//! between. [`scope`] gives a future a stack of open timings of its own,
+//! swapped in whenever it is polled, so that inside it:
+//!
+//! - [`log_time_async!`](crate::log_time_async) takes part in nesting, with
+//!   the timings inside the future printed below it, across `.await`s.
+//! - [`log_time!`](crate::log_time) and [`time_scope!`](crate::time_scope)
+//!   blocks held across `.await`s nest correctly too.
+//! - A task spawned inside a timed block, wrapped in its own [`scope`], hands
+//!   its timings to that block, which prints them below itself once it
+//!   finishes. If the block finished first, the task's timings are printed
+//!   on their own when they finish, still indented below it.
+//! - The correlation ID of the [`context`](crate::context) the scope was
+//!   created in is attached to the timings taken inside it.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use arbitime::{log_time, log_time_async, task};
+//!
+//! async fn handle(request: Request) -> Response {
+//!     log_time_async!("handle" => async {
+//!         let user = log_time_async!("load user" => db.user(request.user)).await;
+//!         tokio::spawn(task::scope(async move {
+//!             log_time_async!("audit" => audit.log(user.id)).await;
+//!         }));
+//!         log_time!("render" => render(&user))
+//!     })
+//! }
+//!
+//! tokio::spawn(task::scope(handle(request)));
+//! // Prints:
+//! // handle - Execution time: 12 ms
+//! //   load user - Execution time: 8 ms
+//! //   render - Execution time: 3 ms
+//! //   audit - Execution time: 1 ms
+//! ```
+
+use std::future::Future;
+use std::mem::ManuallyDrop;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::nesting::Stack;
+
+/// Runs `future` with its own stack of open timings, nested in the timed
+/// block it is created in, if any, and in the [`context`](crate::context) it
+/// is created in.
+///
+/// Create the scope of a spawned task before spawning it, e.g.
+/// `tokio::spawn(task::scope(future))`, so that it is created inside the
+/// spawning task.
+pub fn scope<F: Future>(future: F) -> TaskScope<F> {
+    TaskScope {
+        future: ManuallyDrop::new(future),
+        stack: Stack::task(),
+        context: crate::context::current(),
+    }
+}
+
+/// A future with its own stack of open timings.
+///
+/// Created by [`scope`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct TaskScope<F> {
+    future: ManuallyDrop<F>,
+    stack: Stack,
+    context: Option<Arc<str>>,
+}
+
+impl<F: Future> Future for TaskScope<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        // SAFETY: `future` is pinned along with `self` and only dropped in
+        // place; `stack` is not structurally pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let _context = this.context.clone().map(crate::context::enter);
+        let entered = Entered::new(&mut this.stack);
+        let future = unsafe { Pin::new_unchecked(&mut *this.future) };
+        let poll = future.poll(cx);
+        drop(entered);
+        poll
+    }
+}
+
+impl<F> Drop for TaskScope<F> {
+    fn drop(&mut self) {
+        // Timed blocks inside a cancelled future close on its own stack
+        let _context = self.context.clone().map(crate::context::enter);
+        let _entered = Entered::new(&mut self.stack);
+        // SAFETY: `future` is never used again.
+        unsafe { ManuallyDrop::drop(&mut self.future) };
+    }
+}
+
+/// Swaps the stack of a task in for the current thread's, and back when
+/// dropped, even if polling panics.
+struct Entered<'a> {
+    stack: &'a mut Stack,
+}
+
+impl<'a> Entered<'a> {
+    fn new(stack: &'a mut Stack) -> Self {
+        stack.swap();
+        Entered { stack }
+    }
+}
+
+impl Drop for Entered<'_> {
+    fn drop(&mut self) {
+        self.stack.swap();
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::{nesting, TimingRecord};
+    use std::sync::{Arc, Mutex};
+    use std::task::Waker;
+    use std::time::Duration;
+
+    type Output = Arc<Mutex<Vec<String>>>;
+
+    /// A future that yields once.
+    struct Yield(bool);
+
+    impl Future for Yield {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    /// Times `body` across a yield like `log_time_async!`, writing the lines
+    /// it outputs to `output`.
+    async fn timed(label: &'static str, output: Output, body: impl Future<Output = ()>) {
+        let frame = nesting::enter_task().expect("not inside a task scope");
+        Yield(false).await;
+        body.await;
+        let record = TimingRecord::new(Some(label.into()), Duration::from_millis(1), file!(), line!());
+        let lines = frame.finish(Some(record)).into_iter().map(|record| {
+            format!("{:indent$}{}", "", record.label.unwrap(), indent = record.depth * 2)
+        });
+        output.lock().unwrap().extend(lines);
+    }
+
+    fn poll<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn nests_across_awaits_and_tasks() {
+        let output = Output::default();
+        let mut first = Box::pin(scope(timed("first", output.clone(), timed("first-inner", output.clone(), async {}))));
+        let mut second = Box::pin(scope(timed("second", output.clone(), async {})));
+        // Interleave the tasks on the same thread
+        assert!(poll(first.as_mut()).is_pending());
+        assert!(poll(second.as_mut()).is_pending());
+        assert!(poll(first.as_mut()).is_pending());
+        assert!(poll(second.as_mut()).is_ready());
+        assert!(poll(first.as_mut()).is_ready());
+        assert_eq!(*output.lock().unwrap(), ["second", "first", "  first-inner"]);
+        assert_eq!(nesting::depth(), 0);
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&first);
+
+        // A task created inside a timed block hands its timings to it
+        output.lock().unwrap().clear();
+        let spawned = Arc::new(Mutex::new(None));
+        let parent = {
+            let (output, spawned) = (output.clone(), spawned.clone());
+            timed("parent", output.clone(), async move {
+                let child = scope(timed("child", output, async {}));
+                *spawned.lock().unwrap() = Some(Box::pin(child));
+                Yield(false).await;
+            })
+        };
+        let mut parent = Box::pin(scope(parent));
+        assert!(poll(parent.as_mut()).is_pending());
+        assert!(poll(parent.as_mut()).is_pending());
+        let mut child = spawned.lock().unwrap().take().unwrap();
+        while poll(child.as_mut()).is_pending() {}
+        while poll(parent.as_mut()).is_pending() {}
+        assert_eq!(*output.lock().unwrap(), ["parent", "  child"]);
+
+        let mut request = Box::pin(crate::with_context("task-req", || scope(async { crate::context::current() })));
+        assert_eq!(poll(request.as_mut()), Poll::Ready(Some("task-req".into())));
+    }
+}
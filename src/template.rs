@@ -0,0 +1,336 @@
+//! Custom formats for timing messages.
+//!
+//! A [`Template`] replaces the default `"label - Execution time: 12 ms"`
+//! message with any text containing placeholders in braces:
+//!
+//! | Placeholder            | Replaced with                                          |
+//! |------------------------|--------------------------------------------------------|
+//! | `{label}`              | The label, or nothing for unlabelled timings           |
+//! | `{duration}`           | The duration as a [`HumanDuration`], e.g. `12.35 ms`   |
+//! | `{duration:.3}`        | The same with a fixed number of decimal places         |
+//! | `{duration:ms}`        | The duration in `ns`, `us`/`µs`, `ms` or `s`           |
+//! | `{duration:.2ms}`      | The same with a fixed number of decimal places         |
+//! | `{timestamp}`          | When the timing finished, as an RFC 3339 UTC timestamp |
+//! | `{thread}`             | The name or id of the thread                           |
+//! | `{context}`            | The correlation ID of the [`context`](crate::context)  |
+//! | `{level}`              | The level, e.g. `INFO`                                 |
+//! | `{file}` / `{line}`    | The call site of the timing                            |
+//! | `{module}`             | The module path of the call site                       |
+//! | `{location}`           | Shorthand for `{file}:{line}`                          |
+//! | `{throughput}`         | Items or bytes per second, e.g. `1.2 M items/s`        |
+//! | `{splits}`             | Phases between checkpoints, e.g. `read: 3 ms, 25.0%`   |
+//! | `{fields}`             | The fields of the timing, e.g. `rows=42 table=users`   |
+//!
+//! `{throughput}` is empty except for [`throughput_time!`](crate::throughput_time),
+//! `{splits}` is empty unless the code reached a [`checkpoint!`](crate::checkpoint),
+//! and `{fields}` is empty unless the timing was given `fields:`.
+//! Literal braces are written as `{{` and `}}`. A template set with
+//! [`set_template`] applies to every text message, and
+//! [`format_time!`](crate::format_time) and [`log_time!`](crate::log_time)
+//! also accept a `template:` option for a single call. JSON output is not
+//! affected.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{format_time, log_time};
+//!
+//! arbitime::set_template("{label}: {duration:.2ms} ({file}:{line})").unwrap();
+//! log_time!("parsing" => "42".parse::<u32>()); // Prints: "parsing: 0.01 ms (src/main.rs:4)"
+//!
+//! let (message, _) = format_time!(template: "[{thread}] {label} took {duration}", "sum" => 2 + 2);
+//! # #[cfg(not(feature = "disable"))]
+//! assert!(message.starts_with("[main] sum took "));
+//! ```
+
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::{HumanDuration, TimingRecord};
+
+static TEMPLATE: RwLock<Option<Arc<Template>>> = RwLock::new(None);
+
+/// A parsed message template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Label,
+    Duration {
+        unit: Option<Unit>,
+        precision: Option<usize>,
+    },
+    Timestamp,
+    Thread,
+    Context,
+    Level,
+    File,
+    Line,
+    Module,
+    Location,
+    Throughput,
+    Splits,
+    Fields,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Nanos,
+    Micros,
+    Millis,
+    Secs,
+}
+
+impl Unit {
+    fn parse(unit: &str) -> Option<Unit> {
+        match unit {
+            "ns" => Some(Unit::Nanos),
+            "us" | "µs" => Some(Unit::Micros),
+            "ms" => Some(Unit::Millis),
+            "s" => Some(Unit::Secs),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of nanoseconds in one unit, and its symbol.
+    fn scale(self) -> (f64, &'static str) {
+        match self {
+            Unit::Nanos => (1.0, "ns"),
+            Unit::Micros => (1e3, "µs"),
+            Unit::Millis => (1e6, "ms"),
+            Unit::Secs => (1e9, "s"),
+        }
+    }
+}
+
+impl Template {
+    /// Parses a template, as described in the [module documentation](self).
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let invalid = |reason: String| TemplateError {
+            template: template.to_string(),
+            reason,
+        };
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => return Err(invalid("unmatched `}`".to_string())),
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(invalid("unclosed `{`".to_string())),
+                        }
+                    }
+                    let part = parse_placeholder(&placeholder)
+                        .ok_or_else(|| invalid(format!("unknown placeholder `{{{}}}`", placeholder)))?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(part);
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template { parts })
+    }
+
+    /// Formats a record with this template.
+    pub fn render(&self, record: &TimingRecord) -> String {
+        let mut message = String::new();
+        let _ = self.write(&mut message, record, HumanDuration(record.duration));
+        message
+    }
+
+    /// Writes a record with this template, using `duration` for `{duration}`
+    /// placeholders without a unit.
+    pub(crate) fn write(
+        &self,
+        out: &mut impl Write,
+        record: &TimingRecord,
+        duration: impl fmt::Display,
+    ) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.write_str(text)?,
+                Part::Label => out.write_str(record.label.as_deref().unwrap_or_default())?,
+                Part::Duration { unit: None, precision: None } => write!(out, "{}", duration)?,
+                Part::Duration { unit: None, precision: Some(precision) } => {
+                    write!(out, "{:.*}", precision, duration)?
+                }
+                Part::Duration { unit: Some(unit), precision } => {
+                    let (scale, symbol) = unit.scale();
+                    let value = record.duration.as_nanos() as f64 / scale;
+                    out.write_str(&crate::format::format_value(value, symbol, *precision))?
+                }
+                Part::Timestamp => out.write_str(&crate::format::rfc3339(record.timestamp))?,
+                Part::Thread => out.write_str(&record.thread)?,
+                Part::Context => out.write_str(record.context.as_deref().unwrap_or_default())?,
+                Part::Level => out.write_str(record.level.as_str())?,
+                Part::File => out.write_str(&record.file)?,
+                Part::Line => write!(out, "{}", record.line)?,
+                Part::Module => out.write_str(&record.module_path)?,
+                Part::Location => write!(out, "{}:{}", record.file, record.line)?,
+                Part::Throughput => {
+                    if let Some(throughput) = record.throughput {
+                        out.write_str(&throughput.rate(record.duration))?
+                    }
+                }
+                Part::Splits => record.write_splits(out)?,
+                Part::Fields => record.write_fields(out)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the contents of a `{...}` placeholder.
+fn parse_placeholder(placeholder: &str) -> Option<Part> {
+    let (name, spec) = match placeholder.split_once(':') {
+        Some((name, spec)) => (name.trim(), Some(spec.trim())),
+        None => (placeholder.trim(), None),
+    };
+    let part = match name {
+        "label" => Part::Label,
+        "duration" => {
+            let Some(spec) = spec else {
+                return Some(Part::Duration {
+                    unit: None,
+                    precision: None,
+                });
+            };
+            let (precision, unit) = match spec.strip_prefix('.') {
+                Some(rest) => {
+                    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                    let (digits, unit) = rest.split_at(digits);
+                    (Some(digits.parse().ok()?), unit)
+                }
+                None => (None, spec),
+            };
+            let unit = match unit {
+                "" => None,
+                unit => Some(Unit::parse(unit)?),
+            };
+            return Some(Part::Duration { unit, precision });
+        }
+        "timestamp" => Part::Timestamp,
+        "thread" => Part::Thread,
+        "context" => Part::Context,
+        "level" => Part::Level,
+        "file" => Part::File,
+        "line" => Part::Line,
+        "module" => Part::Module,
+        "location" => Part::Location,
+        "throughput" => Part::Throughput,
+        "splits" => Part::Splits,
+        "fields" => Part::Fields,
+        _ => return None,
+    };
+    // Only `{duration}` takes a format spec
+    spec.is_none().then_some(part)
+}
+
+/// An invalid message template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError {
+    template: String,
+    reason: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid template `{}`: {}", self.template, self.reason)
+    }
+}
+
+impl Error for TemplateError {}
+
+/// Parses `template` and uses it for all text messages.
+///
+/// Returns an error, and keeps the current format, if the template is invalid.
+pub fn set_template(template: &str) -> Result<(), TemplateError> {
+    install(Template::parse(template)?);
+    Ok(())
+}
+
+/// Uses an already parsed template for all text messages.
+pub(crate) fn install(template: Template) {
+    *TEMPLATE.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(template));
+}
+
+/// Restores the default message format.
+pub fn clear_template() {
+    *TEMPLATE.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// Returns the template set with [`set_template`], if any.
+pub fn current() -> Option<Arc<Template>> {
+    TEMPLATE.read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn renders_placeholders() {
+        let mut record = TimingRecord::new(Some("parse".into()), Duration::from_micros(12_345), "src/parser.rs", 88);
+        record.thread = "main".into();
+        record.module_path = "app::parser".into();
+
+        let render = |template: &str, record: &TimingRecord| Template::parse(template).unwrap().render(record);
+        assert_eq!(
+            render("{label}: {duration:.2ms} ({file}:{line})", &record),
+            "parse: 12.35 ms (src/parser.rs:88)"
+        );
+        assert_eq!(
+            render("{duration} / {duration:.1} / {duration:us}", &record),
+            "12.35 ms / 12.3 ms / 12345 µs"
+        );
+        assert_eq!(
+            render("{{{level}}} [{thread}] {module} at {location}", &record),
+            "{INFO} [main] app::parser at src/parser.rs:88"
+        );
+
+        assert_eq!(render("[{throughput}] [{splits}] [{context}] [{fields}]", &record), "[] [] [] []");
+        record.throughput = Some(crate::Throughput::Items(12_345));
+        assert_eq!(render("{throughput}", &record), "1 M items/s");
+        record.context = Some("req-123".into());
+        assert_eq!(render("{context}: {label}", &record), "req-123: parse");
+
+        record.label = None;
+        assert_eq!(render("<{label}> {duration:s}", &record), "<> 0.01 s");
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert_eq!(
+            Template::parse("{label} {elapsed}").unwrap_err().to_string(),
+            "invalid template `{label} {elapsed}`: unknown placeholder `{elapsed}`"
+        );
+        assert!(Template::parse("{duration:.2h}").is_err());
+        assert!(Template::parse("{label:x}").is_err());
+        assert!(Template::parse("{label").is_err());
+        assert!(Template::parse("label}").is_err());
+    }
+}
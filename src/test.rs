@@ -0,0 +1,129 @@
+//! Utilities for testing code that is timed with arbitime.
+//!
+//! [`MockClock`] is a [`Clock`] that only moves when told to, so that code
+//! using [`time!`](crate::time), [`ScopeTimer`](crate::ScopeTimer),
+//! [`assert_time!`](crate::assert_time) and the other macros can be tested
+//! deterministically, without sleeping.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::test::MockClock;
+//! use arbitime::time;
+//! use std::time::Duration;
+//!
+//! fn slow_operation(clock: &MockClock) -> u32 {
+//!     clock.advance(Duration::from_millis(250)); // Instead of real work
+//!     42
+//! }
+//!
+//! let clock = MockClock::new();
+//! let _guard = clock.install();
+//!
+//! let (duration, result) = time!(slow_operation(&clock));
+//! # #[cfg(not(feature = "disable"))]
+//! assert_eq!(duration, Duration::from_millis(250));
+//! assert_eq!(result, 42);
+//! ```
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::clock::{self, Clock};
+
+/// A clock that stands still until it is advanced by hand.
+///
+/// Clones share the same time, so a clone can be handed to the code under
+/// test while the test keeps the original. A mock clock is either installed
+/// on the current thread with [`install`](Self::install), which keeps tests
+/// running in parallel independent of each other, or globally with
+/// [`clock::set_clock`].
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl MockClock {
+    /// Creates a mock clock reading zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        // Saturate instead of wrapping around
+        let _ = self
+            .nanos
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |now| Some(now.saturating_add(nanos)));
+    }
+
+    /// Returns the total time the clock has been advanced by.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Acquire))
+    }
+
+    /// Makes all measurements on the current thread read this clock until the
+    /// returned guard is dropped.
+    #[must_use = "the mock clock is uninstalled when the guard is dropped"]
+    pub fn install(&self) -> MockClockGuard {
+        MockClockGuard {
+            previous: clock::set_thread_clock(Some(Arc::new(self.clone()))),
+        }
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> u64 {
+        self.nanos.load(Ordering::Acquire)
+    }
+}
+
+/// Restores the previous clock of the thread when dropped.
+///
+/// Returned by [`MockClock::install`].
+#[must_use = "the mock clock is uninstalled when the guard is dropped"]
+pub struct MockClockGuard {
+    previous: Option<Arc<dyn Clock>>,
+}
+
+impl std::fmt::Debug for MockClockGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockClockGuard").finish_non_exhaustive()
+    }
+}
+
+impl Drop for MockClockGuard {
+    fn drop(&mut self) {
+        clock::set_thread_clock(self.previous.take());
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::{ScopeTimer, Stopwatch};
+
+    #[test]
+    fn mock_clock_drives_measurements() {
+        let clock = MockClock::new();
+        {
+            let _guard = clock.install();
+            let (duration, _) = crate::time!(clock.advance(Duration::from_millis(5)));
+            assert_eq!(duration, Duration::from_millis(5));
+
+            let timer = ScopeTimer::new("mock clock");
+            let mut stopwatch = Stopwatch::start_new();
+            clock.advance(Duration::from_secs(2));
+            assert_eq!(timer.elapsed(), Duration::from_secs(2));
+            assert_eq!(stopwatch.lap("mock"), Duration::from_secs(2));
+            drop(timer);
+        }
+        assert_eq!(clock.elapsed(), Duration::from_millis(2005));
+
+        // Other threads and code after the guard is dropped use the real clock
+        let (duration, _) = crate::time!(std::thread::sleep(Duration::from_millis(1)));
+        assert!(duration >= Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,154 @@
+//! Structured results of [`time_ex!`](crate::time_ex).
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::callsite::CallSite;
+use crate::HumanDuration;
+
+/// A measurement returned by [`time_ex!`](crate::time_ex), with the result of
+/// the timed code.
+///
+/// Unlike the `(Duration, T)` of [`time!`](crate::time), a `Timing` keeps when
+/// the code ran, its label and where it was timed, so it can be stored or
+/// forwarded without losing context. Formatting it with `{}` produces the
+/// default message, e.g. `"parse - Execution time: 12 ms"`.
+///
+/// # Examples
+///
+/// ```rust
+/// use arbitime::time_ex;
+///
+/// let timing = time_ex!("parse" => "42".parse::<u32>());
+/// assert_eq!(timing.label(), Some("parse"));
+/// assert_eq!(timing.end() - timing.start(), timing.duration());
+/// assert_eq!(timing.call_site().file(), file!());
+/// assert_eq!(timing.into_result(), Ok(42));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Timing<T> {
+    duration: Duration,
+    start: Instant,
+    end: Instant,
+    label: Option<String>,
+    site: &'static CallSite,
+    result: T,
+}
+
+impl<T> Timing<T> {
+    /// Creates a measurement that ended now.
+    pub(crate) fn new(duration: Duration, label: Option<String>, site: &'static CallSite, result: T) -> Self {
+        let end = Instant::now();
+        Timing {
+            duration,
+            start: end.checked_sub(duration).unwrap_or(end),
+            end,
+            label,
+            site,
+            result,
+        }
+    }
+
+    /// Returns the time the code took.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns when the code started running.
+    ///
+    /// The duration is read from the global [`clock`](crate::clock), so the
+    /// start is derived from [`end`](Self::end) and always lies exactly
+    /// [`duration`](Self::duration) before it.
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
+    /// Returns when the code finished running.
+    pub fn end(&self) -> Instant {
+        self.end
+    }
+
+    /// Returns the label of the measurement, if it has one.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Returns where in the source code the measurement was taken.
+    pub fn call_site(&self) -> &'static CallSite {
+        self.site
+    }
+
+    /// Returns a reference to the result of the code.
+    pub fn result(&self) -> &T {
+        &self.result
+    }
+
+    /// Returns a mutable reference to the result of the code.
+    pub fn result_mut(&mut self) -> &mut T {
+        &mut self.result
+    }
+
+    /// Returns the result of the code, discarding the measurement.
+    pub fn into_result(self) -> T {
+        self.result
+    }
+
+    /// Returns the `(Duration, T)` tuple of [`time!`](crate::time).
+    pub fn into_tuple(self) -> (Duration, T) {
+        (self.duration, self.result)
+    }
+
+    /// Replaces the result with `f` applied to it, keeping the measurement.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Timing<U> {
+        Timing {
+            duration: self.duration,
+            start: self.start,
+            end: self.end,
+            label: self.label,
+            site: self.site,
+            result: f(self.result),
+        }
+    }
+}
+
+impl<T> fmt::Display for Timing<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.label {
+            Some(label) => write!(f, "{} - Execution time: {}", label, HumanDuration(self.duration)),
+            None => write!(f, "Execution time: {}", HumanDuration(self.duration)),
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+    use crate::test::MockClock;
+
+    static SITE: CallSite = CallSite::new(file!(), line!(), module_path!());
+
+    #[test]
+    fn keeps_measurement_context() {
+        let clock = MockClock::new();
+        let _guard = clock.install();
+
+        let timing = crate::time_ex!("timing-{}", 1 => {
+            clock.advance(Duration::from_millis(12));
+            "12"
+        });
+        assert_eq!(timing.duration(), Duration::from_millis(12));
+        assert_eq!(timing.end() - timing.start(), timing.duration());
+        assert_eq!(timing.label(), Some("timing-1"));
+        assert_eq!(timing.call_site().module_path(), module_path!());
+        assert_eq!(timing.to_string(), "timing-1 - Execution time: 12 ms");
+
+        let timing = timing.map(|text| text.parse::<u32>().unwrap());
+        assert_eq!(*timing.result(), 12);
+        assert_eq!(timing.into_tuple(), (Duration::from_millis(12), 12));
+
+        let mut timing = Timing::new(Duration::from_micros(5), None, &SITE, vec![1]);
+        timing.result_mut().push(2);
+        assert_eq!(timing.to_string(), "Execution time: 5 µs");
+        assert_eq!(timing.into_result(), [1, 2]);
+    }
+}
@@ -0,0 +1,328 @@
+//! Chrome Trace Event exporter.
+//!
+//! While enabled, every labelled timing is collected as a Chrome tracing
+//! complete (`"X"`) event. The collected events can be written to a
+//! `trace.json` file that opens in `chrome://tracing`, [Perfetto](https://ui.perfetto.dev)
+//! or [speedscope](https://www.speedscope.app), showing the timed sections of
+//! every thread on a timeline.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use arbitime::{log_time, trace};
+//!
+//! // Collect events until the guard is dropped, then write them to trace.json
+//! let _trace = trace::save_on_drop("trace.json");
+//!
+//! log_time!("load" => std::fs::read_to_string("input.txt"));
+//! log_time!("process" => (1..=1000).sum::<u32>());
+//! ```
+//!
+//! Events can also be written on demand:
+//!
+//! ```rust,no_run
+//! use arbitime::{log_time, trace};
+//!
+//! trace::enable();
+//! log_time!("work" => (1..=1000).sum::<u32>());
+//! trace::save("trace.json").unwrap();
+//! ```
+//!
+//! # Flamegraphs
+//!
+//! The same events can be written as folded stacks with [`save_folded`], one
+//! line per stack of nested labels with its self time in microseconds:
+//!
+//! ```text
+//! frame 1200
+//! frame;physics 4100
+//! frame;render 9500
+//! frame;render;upload 2300
+//! ```
+//!
+//! This is the input format of [`flamegraph.pl`](https://github.com/brendangregg/FlameGraph),
+//! [inferno](https://github.com/jonhoo/inferno) and speedscope, which show
+//! where the time goes as a flamegraph. Nesting is reconstructed from the
+//! events of each thread: a timing that started and ended within another is
+//! its child. The stacks of all threads are merged.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::TimingRecord;
+use crate::record::escape_json;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static EVENTS: Mutex<Vec<TraceEvent>> = Mutex::new(Vec::new());
+static NEXT_TID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// A small numeric id for the current thread, as Chrome tracing expects.
+    static TID: u64 = NEXT_TID.fetch_add(1, Ordering::Relaxed);
+}
+
+fn events() -> MutexGuard<'static, Vec<TraceEvent>> {
+    EVENTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A collected complete event.
+#[derive(Debug, Clone)]
+struct TraceEvent {
+    name: String,
+    /// Start time in microseconds since the Unix epoch
+    ts: f64,
+    /// Duration in microseconds
+    dur: f64,
+    tid: u64,
+    thread: String,
+    file: String,
+    line: u32,
+}
+
+/// Starts collecting trace events.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Stops collecting trace events. Already collected events are kept.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Returns `true` if trace events are being collected.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Discards all collected events.
+pub fn clear() {
+    events().clear();
+}
+
+/// Adds a finished timing to the trace, if collection is enabled.
+pub fn record(record: &TimingRecord) {
+    if !is_enabled() {
+        return;
+    }
+    let end = record.unix_time().as_secs_f64() * 1e6;
+    let dur = record.duration.as_secs_f64() * 1e6;
+    let event = TraceEvent {
+        name: record.label.clone().unwrap_or_else(|| "time".to_string()),
+        ts: end - dur,
+        dur,
+        tid: TID.with(|tid| *tid),
+        thread: record.thread.clone(),
+        file: record.file.to_string(),
+        line: record.line,
+    };
+    events().push(event);
+}
+
+/// Writes all collected events in the Chrome Trace Event JSON format.
+pub fn write(mut writer: impl Write) -> io::Result<()> {
+    let events = events().clone();
+    let pid = std::process::id();
+
+    writeln!(writer, "{{\"traceEvents\":[")?;
+    let mut threads: Vec<(u64, &str)> = events
+        .iter()
+        .map(|event| (event.tid, event.thread.as_str()))
+        .collect();
+    threads.sort_unstable();
+    threads.dedup_by_key(|(tid, _)| *tid);
+
+    let mut first = true;
+    let mut separator = |writer: &mut dyn Write| -> io::Result<()> {
+        if !std::mem::take(&mut first) {
+            writeln!(writer, ",")?;
+        }
+        Ok(())
+    };
+    for (tid, name) in threads {
+        separator(&mut writer)?;
+        write!(
+            writer,
+            r#"{{"name":"thread_name","ph":"M","pid":{},"tid":{},"args":{{"name":{}}}}}"#,
+            pid,
+            tid,
+            escape_json(name)
+        )?;
+    }
+    for event in &events {
+        separator(&mut writer)?;
+        write!(
+            writer,
+            r#"{{"name":{},"cat":"arbitime","ph":"X","ts":{:.3},"dur":{:.3},"pid":{},"tid":{},"args":{{"file":{},"line":{}}}}}"#,
+            escape_json(&event.name),
+            event.ts,
+            event.dur,
+            pid,
+            event.tid,
+            escape_json(&event.file),
+            event.line
+        )?;
+    }
+    writeln!(writer, "\n],\"displayTimeUnit\":\"ms\"}}")?;
+    writer.flush()
+}
+
+/// Writes all collected events to a file, typically `trace.json`.
+pub fn save(path: impl AsRef<Path>) -> io::Result<()> {
+    write(BufWriter::new(File::create(path)?))
+}
+
+/// Writes all collected events as folded stacks, e.g. `frame;render 9500`,
+/// with the self time of each stack in whole microseconds.
+///
+/// Stacks are sorted by name, and those with less than a microsecond of self
+/// time are left out. See the [module documentation](self#flamegraphs).
+pub fn write_folded(mut writer: impl Write) -> io::Result<()> {
+    for (stack, micros) in fold(&events()) {
+        let micros = micros.round();
+        if micros >= 1.0 {
+            writeln!(writer, "{} {}", stack, micros)?;
+        }
+    }
+    writer.flush()
+}
+
+/// Writes all collected events as folded stacks to a file, typically
+/// `trace.folded`.
+pub fn save_folded(path: impl AsRef<Path>) -> io::Result<()> {
+    write_folded(BufWriter::new(File::create(path)?))
+}
+
+/// Sums the self time of the events by their stack of nested names, in
+/// microseconds.
+fn fold(events: &[TraceEvent]) -> BTreeMap<String, f64> {
+    let mut events: Vec<&TraceEvent> = events.iter().collect();
+    // Parents start first, or at the same time but last longer
+    events.sort_by(|a, b| {
+        a.tid
+            .cmp(&b.tid)
+            .then(a.ts.total_cmp(&b.ts))
+            .then(b.dur.total_cmp(&a.dur))
+    });
+
+    let mut stacks = BTreeMap::new();
+    // The open events of the current thread: their stack, end and self time
+    let mut open: Vec<(String, f64, f64)> = Vec::new();
+    let mut tid = None;
+    for event in events {
+        if tid != Some(event.tid) {
+            close(&mut open, 0, &mut stacks);
+            tid = Some(event.tid);
+        }
+        let end = event.ts + event.dur;
+        let depth = open
+            .iter()
+            .rposition(|(_, parent_end, _)| end <= *parent_end)
+            .map_or(0, |parent| parent + 1);
+        close(&mut open, depth, &mut stacks);
+        let name = event.name.replace(';', ":").replace('\n', " ");
+        let stack = match open.last_mut() {
+            Some((parent, _, parent_self)) => {
+                *parent_self -= event.dur;
+                format!("{};{}", parent, name)
+            }
+            None => name,
+        };
+        open.push((stack, end, event.dur));
+    }
+    close(&mut open, 0, &mut stacks);
+    stacks
+}
+
+/// Closes the open events from `depth` on, adding their self time to `stacks`.
+fn close(open: &mut Vec<(String, f64, f64)>, depth: usize, stacks: &mut BTreeMap<String, f64>) {
+    for (stack, _, self_time) in open.drain(depth.min(open.len())..) {
+        *stacks.entry(stack).or_default() += self_time.max(0.0);
+    }
+}
+
+/// Enables collection and returns a guard that saves the trace to `path`
+/// when dropped.
+///
+/// Place the guard at the top of `main` to write the trace when the program
+/// exits.
+pub fn save_on_drop(path: impl Into<PathBuf>) -> TraceGuard {
+    enable();
+    TraceGuard { path: path.into() }
+}
+
+/// Saves the trace when dropped. Created by [`save_on_drop`].
+#[derive(Debug)]
+#[must_use = "the trace is saved when the guard is dropped"]
+pub struct TraceGuard {
+    path: PathBuf,
+}
+
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        if let Err(error) = save(&self.path) {
+            eprintln!("arbitime: failed to write trace to {}: {}", self.path.display(), error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn writes_complete_events() {
+        enable();
+        let record = TimingRecord::new(
+            Some("trace-test".into()),
+            Duration::from_micros(250),
+            "src/lib.rs",
+            1,
+        );
+        super::record(&record);
+
+        let mut output = Vec::new();
+        write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("{\"traceEvents\":["));
+        assert!(output.contains(r#""name":"trace-test","cat":"arbitime","ph":"X""#));
+        assert!(output.contains(r#""dur":250.000"#));
+        assert!(output.contains(r#""ph":"M""#));
+        assert!(output.trim_end().ends_with("\"displayTimeUnit\":\"ms\"}"));
+    }
+
+    #[test]
+    fn folds_nested_events() {
+        let event = |name: &str, tid, ts, dur| TraceEvent {
+            name: name.to_string(),
+            ts,
+            dur,
+            tid,
+            thread: String::new(),
+            file: String::new(),
+            line: 0,
+        };
+        let events = [
+            event("render", 1, 140.0, 50.0),
+            event("frame", 1, 100.0, 100.0),
+            event("physics", 1, 110.0, 20.0),
+            event("upload", 1, 150.0, 30.0),
+            event("frame", 1, 300.0, 10.0),
+            event("a;b", 2, 100.0, 5.0),
+            event("frame", 2, 120.0, 50.0),
+        ];
+        let stacks: Vec<(String, f64)> = fold(&events).into_iter().collect();
+        let expected = [
+            ("a:b", 5.0),
+            ("frame", 30.0 + 10.0 + 50.0),
+            ("frame;physics", 20.0),
+            ("frame;render", 20.0),
+            ("frame;render;upload", 30.0),
+        ];
+        assert_eq!(stacks, expected.map(|(stack, micros)| (stack.to_string(), micros)));
+    }
+}
@@ -0,0 +1,247 @@
+//! Live warnings for timed sections that are still running.
+//!
+//! A timed block is only logged once it finishes, so a block that hangs never
+//! shows up. With the `watchdog` feature, [`log_time!`](crate::log_time)
+//! accepts a `watchdog:` option and [`ScopeTimer`](crate::ScopeTimer) a
+//! [`watchdog`](crate::ScopeTimer::watchdog) threshold. While such a section
+//! runs, a background thread named `arbitime-watchdog` checks it, and once it
+//! has been running for longer than its threshold, logs a warning such as
+//!
+//! ```text
+//! sync - still running after 5 s on thread worker-1 (src/sync.rs:42)
+//! ```
+//!
+//! at [`Level::Warn`], or calls the handler installed with [`set_handler`].
+//! Each section is reported at most once, and the section itself is never
+//! interrupted. The thread is started with the first watched section and
+//! sleeps until the next deadline, so unwatched code pays nothing.
+//!
+//! The watchdog measures real time with [`Instant`], not the
+//! [`clock`](crate::clock), since it runs on another thread.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{log_time, watchdog};
+//! use std::time::Duration;
+//!
+//! watchdog::set_handler(|stalled: &watchdog::Stalled| {
+//!     eprintln!("possible hang: {}", stalled);
+//! });
+//!
+//! log_time!(watchdog: Duration::from_millis(10), "sync" => {
+//!     std::thread::sleep(Duration::from_millis(50));
+//! });
+//! // Prints "possible hang: sync - still running after 10 ms on thread main (src/main.rs:8)"
+//! // while the block runs, then "sync - Execution time: 50.1 ms"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Once, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::{HumanDuration, Level};
+
+type Handler = Arc<dyn Fn(&Stalled) + Send + Sync>;
+
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+static SECTIONS: Mutex<Sections> = Mutex::new(Sections {
+    next_id: 0,
+    watched: BTreeMap::new(),
+});
+/// Wakes the watchdog thread when a section starts
+static WAKE: Condvar = Condvar::new();
+static START: Once = Once::new();
+
+/// The sections currently watched, by id.
+struct Sections {
+    next_id: u64,
+    watched: BTreeMap<u64, Section>,
+}
+
+/// A watched section that has not finished yet.
+struct Section {
+    stalled: Stalled,
+    start: Instant,
+    emit: fn(Level, &str),
+    reported: bool,
+}
+
+/// A watched section that has been running for longer than its threshold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stalled {
+    /// The label of the section, if any
+    pub label: Option<String>,
+    /// The name of the thread running the section, or its id if unnamed
+    pub thread: String,
+    /// The source file of the section
+    pub file: &'static str,
+    /// The line in [`file`](Self::file) of the section
+    pub line: u32,
+    /// The threshold of the section
+    pub threshold: Duration,
+    /// How long the section had been running when it was reported
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Stalled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.label {
+            Some(label) => write!(f, "{} - still running", label)?,
+            None => f.write_str("Still running")?,
+        }
+        write!(
+            f,
+            " after {} on thread {} ({}:{})",
+            HumanDuration(self.elapsed),
+            self.thread,
+            self.file,
+            self.line
+        )
+    }
+}
+
+/// Installs a handler that is called from the watchdog thread for each
+/// section that runs longer than its threshold, instead of logging a warning.
+///
+/// The handler should return quickly, since it delays the reports of other
+/// sections.
+pub fn set_handler(handler: impl Fn(&Stalled) + Send + Sync + 'static) {
+    *HANDLER.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(handler));
+}
+
+/// Restores the default handler, which logs a warning like
+/// [`log_time!`](crate::log_time) output.
+pub fn reset_handler() {
+    *HANDLER.write().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// A guard that watches a section until it is dropped.
+#[derive(Debug)]
+pub struct Watch {
+    id: u64,
+}
+
+/// Starts watching a section that is about to run, reporting it once it has
+/// been running for `threshold`. `emit` logs the default warning.
+#[doc(hidden)]
+pub fn enter(
+    label: Option<String>,
+    threshold: Duration,
+    file: &'static str,
+    line: u32,
+    emit: fn(Level, &str),
+) -> Watch {
+    START.call_once(|| {
+        // Without the thread, sections are simply never reported
+        let _ = std::thread::Builder::new()
+            .name("arbitime-watchdog".to_string())
+            .spawn(run);
+    });
+    let section = Section {
+        stalled: Stalled {
+            label,
+            thread: crate::record::thread_name().to_string(),
+            file,
+            line,
+            threshold,
+            elapsed: Duration::ZERO,
+        },
+        start: Instant::now(),
+        emit,
+        reported: false,
+    };
+    let mut sections = lock();
+    let id = sections.next_id;
+    sections.next_id += 1;
+    sections.watched.insert(id, section);
+    WAKE.notify_one();
+    Watch { id }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        lock().watched.remove(&self.id);
+    }
+}
+
+fn lock() -> MutexGuard<'static, Sections> {
+    SECTIONS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The loop of the watchdog thread.
+fn run() {
+    let mut sections = lock();
+    loop {
+        let now = Instant::now();
+        let mut stalled = Vec::new();
+        let mut next_deadline: Option<Instant> = None;
+        for section in sections.watched.values_mut().filter(|section| !section.reported) {
+            let deadline = section.start + section.stalled.threshold;
+            if deadline <= now {
+                section.reported = true;
+                section.stalled.elapsed = now - section.start;
+                stalled.push((section.stalled.clone(), section.emit));
+            } else {
+                next_deadline = Some(next_deadline.map_or(deadline, |next| next.min(deadline)));
+            }
+        }
+        if !stalled.is_empty() {
+            drop(sections);
+            for (stalled, emit) in stalled {
+                report(&stalled, emit);
+            }
+            sections = lock();
+            continue;
+        }
+        sections = match next_deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(now);
+                WAKE.wait_timeout(sections, timeout)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+            None => WAKE.wait(sections).unwrap_or_else(PoisonError::into_inner),
+        };
+    }
+}
+
+fn report(stalled: &Stalled, emit: fn(Level, &str)) {
+    let handler = HANDLER.read().unwrap_or_else(PoisonError::into_inner).clone();
+    match handler {
+        Some(handler) => handler(stalled),
+        None => emit(Level::Warn, &stalled.to_string()),
+    }
+}
+
+#[cfg(all(test, not(feature = "disable")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_stalled_sections_once() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&seen);
+        set_handler(move |stalled: &Stalled| {
+            collected.lock().unwrap().push(stalled.clone());
+        });
+        let ignore = |_: Level, _: &str| {};
+
+        let finished = enter(Some("watchdog-fast".into()), Duration::from_secs(60), "src/fast.rs", 1, ignore);
+        drop(finished);
+        let stalled = enter(Some("watchdog-slow".into()), Duration::from_millis(10), "src/slow.rs", 7, ignore);
+        std::thread::sleep(Duration::from_millis(200));
+        drop(stalled);
+        reset_handler();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].label.as_deref(), Some("watchdog-slow"));
+        assert!(seen[0].elapsed >= Duration::from_millis(10));
+        let message = seen[0].to_string();
+        assert!(message.starts_with("watchdog-slow - still running after "), "{}", message);
+        assert!(message.ends_with(&format!(" on thread {} (src/slow.rs:7)", seen[0].thread)), "{}", message);
+    }
+}
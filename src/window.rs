@@ -0,0 +1,193 @@
+//! Sliding-window statistics of recent measurements.
+//!
+//! The [`registry`](crate::registry) aggregates every measurement since the
+//! start of the program, which hides how a live service behaves right now.
+//! After [`set_window`](crate::registry::set_window), each label also keeps
+//! its recent measurements in a ring buffer of timestamped samples, and
+//! [`Label::window`](crate::registry::Label::window) returns the statistics
+//! and percentiles of those taken in the last N seconds.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use arbitime::{log_time, registry};
+//! use std::time::Duration;
+//!
+//! registry::set_window(Duration::from_secs(300), 10_000);
+//! for _ in 0..100 {
+//!     log_time!(threshold: Duration::MAX, "handle_request" => (1..=20).product::<u64>());
+//! }
+//!
+//! # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+//! let last_minute = registry::label("handle_request").window(Duration::from_secs(60)).unwrap();
+//! # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+//! assert_eq!(last_minute.stats.count, 100);
+//! # #[cfg(not(any(feature = "disable", feature = "defmt")))]
+//! println!("p99 over the last minute: {:?}", last_minute.percentile(99.0));
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+#[cfg(feature = "histogram")]
+use crate::histogram::Percentiles;
+use crate::registry::LabelStats;
+
+/// A measurement in a ring buffer of [`Recent`] ones.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// A reading of the [`clock`](crate::clock) when the measurement was recorded
+    at: u64,
+    duration: Duration,
+    /// The number of measurements the sample stands for
+    weight: u64,
+}
+
+/// The recent measurements of a label, oldest first.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Recent {
+    samples: VecDeque<Sample>,
+    /// The reading of the newest sample dropped to make room, if any
+    evicted: Option<u64>,
+}
+
+impl Recent {
+    /// Adds a measurement recorded at `at`, dropping samples older than
+    /// `retention` and the oldest ones beyond `capacity`.
+    pub(crate) fn add(&mut self, at: u64, duration: Duration, weight: u64, retention: Duration, capacity: usize) {
+        let oldest = at.saturating_sub(nanos(retention));
+        while self.samples.front().is_some_and(|sample| sample.at < oldest) {
+            self.samples.pop_front();
+        }
+        if capacity == 0 {
+            return;
+        }
+        while self.samples.len() >= capacity {
+            self.evicted = self.samples.pop_front().map(|sample| sample.at);
+        }
+        self.samples.push_back(Sample { at, duration, weight });
+    }
+
+    /// Adds the samples recorded at or after `since` to `window`, and returns
+    /// `false` if some of them were dropped to make room.
+    pub(crate) fn collect(&self, since: u64, window: &mut Vec<(Duration, u64)>) -> bool {
+        let start = self.samples.partition_point(|sample| sample.at < since);
+        window.extend(self.samples.range(start..).map(|sample| (sample.duration, sample.weight)));
+        self.evicted.is_none_or(|evicted| evicted < since)
+    }
+}
+
+/// The statistics of the measurements of a label in a recent time window,
+/// see [`Label::window`](crate::registry::Label::window).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowStats {
+    /// The count, total, minimum and maximum of the measurements in the
+    /// window. Self time is not kept for recent measurements, so it equals
+    /// the total.
+    pub stats: LabelStats,
+    /// Whether every measurement of the window is included: `false` if the
+    /// window is longer than the retention set with
+    /// [`set_window`](crate::registry::set_window), or if measurements were
+    /// dropped because the ring buffer was full
+    pub complete: bool,
+    /// The durations in the window and how many measurements each stands for,
+    /// shortest first
+    samples: Vec<(Duration, u64)>,
+}
+
+impl WindowStats {
+    pub(crate) fn new(mut samples: Vec<(Duration, u64)>, complete: bool) -> Self {
+        samples.sort_unstable();
+        let mut stats = LabelStats::default();
+        for &(duration, weight) in &samples {
+            stats.add_sampled(duration, duration, weight);
+        }
+        WindowStats {
+            stats,
+            complete,
+            samples,
+        }
+    }
+
+    /// Returns the mean duration in the window.
+    pub fn mean(&self) -> Duration {
+        self.stats.mean()
+    }
+
+    /// Returns the duration below which `percentile` percent of the
+    /// measurements in the window fall, e.g. `percentile(99.0)` for p99, or
+    /// zero if the window is empty.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let fraction = (percentile / 100.0).clamp(0.0, 1.0);
+        let rank = ((fraction * self.stats.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for &(duration, weight) in &self.samples {
+            seen += weight;
+            if seen >= rank {
+                return duration;
+            }
+        }
+        Duration::ZERO
+    }
+
+    /// Returns the p50, p90, p95, p99 and p99.9 of the window.
+    #[cfg(feature = "histogram")]
+    pub fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            p50: self.percentile(50.0),
+            p90: self.percentile(90.0),
+            p95: self.percentile(95.0),
+            p99: self.percentile(99.0),
+            p999: self.percentile(99.9),
+        }
+    }
+}
+
+/// Returns the whole nanoseconds of `duration`, saturating at `u64::MAX`.
+pub(crate) fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn keeps_recent_samples() {
+        let retention = Duration::from_secs(60);
+        let mut recent = Recent::default();
+        for (second, millis) in [(0, 50), (30, 10), (70, 20), (80, 30)] {
+            recent.add(second * SECOND, Duration::from_millis(millis), 1, retention, 10);
+        }
+
+        let mut samples = Vec::new();
+        assert!(recent.collect(60 * SECOND, &mut samples));
+        let window = WindowStats::new(samples, true);
+        assert_eq!(window.stats.count, 2);
+        assert_eq!(window.mean(), Duration::from_millis(25));
+        assert_eq!(window.percentile(50.0), Duration::from_millis(20));
+        assert_eq!(window.percentile(99.0), Duration::from_millis(30));
+
+        // The sample at 0 s is past the retention, the one at 30 s is still kept
+        let mut samples = Vec::new();
+        recent.collect(0, &mut samples);
+        assert_eq!(samples.len(), 3);
+    }
+
+    #[test]
+    fn reports_evicted_samples() {
+        let mut recent = Recent::default();
+        for second in 0..5 {
+            recent.add(second * SECOND, Duration::from_millis(second + 1), 2, Duration::from_secs(60), 3);
+        }
+        assert!(!recent.collect(0, &mut Vec::new()));
+        let mut samples = Vec::new();
+        assert!(recent.collect(2 * SECOND, &mut samples));
+        let window = WindowStats::new(samples, true);
+        assert_eq!(window.stats.count, 6);
+        assert_eq!(window.percentile(100.0), Duration::from_millis(5));
+        assert_eq!(WindowStats::default().percentile(50.0), Duration::ZERO);
+    }
+}